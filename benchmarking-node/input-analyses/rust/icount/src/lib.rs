@@ -79,31 +79,31 @@ enum Instruction {
 }
 
 advice! {
-    if_then_else       (c: PathContinuation, _ic: IfThenElseInputCount, _ia: IfThenElseArity   , loc: Location) { inc_instr(loc, Instruction::If);               c                         }
-    if_then_else_post  (                                                                         loc: Location) { inc_instr(loc, Instruction::IfPost);                                     }
-    if_then            (c: PathContinuation, _ic: IfThenInputCount, _ia: IfThenArity           , loc: Location) { inc_instr(loc, Instruction::IfThen);           c                         }
-    if_then_post       (                                                                         loc: Location) { inc_instr(loc, Instruction::IfThenPost);                                 }
-    br                 (_l: BranchTargetLabel                                                  , loc: Location) { inc_instr(loc, Instruction::Br);                                         }
-    br_if              (c : ParameterBrIfCondition, _l : ParameterBrIfLabel                    , loc: Location) { inc_instr(loc, Instruction::BrIf);             c                         }
-    br_table           (bt: BranchTableTarget, _e: BranchTableEffective, _d: BranchTableDefault, loc: Location) { inc_instr(loc, Instruction::BrIfTable);        bt                        }
-    select             (c: PathContinuation                                                    , loc: Location) { inc_instr(loc, Instruction::Select);           c                         }
-    call pre           (_t : FunctionIndex                                                     , loc: Location) { inc_instr(loc, Instruction::CallPre);                                    }
-    call post          (_t : FunctionIndex                                                     , loc: Location) { inc_instr(loc, Instruction::CallPost);                                   }
-    call_indirect pre  (t: FunctionTableIndex, _f: FunctionTable                               , loc: Location) { inc_instr(loc, Instruction::CallIndirectPre);  t                         }
-    call_indirect post (_t: FunctionTable                                                      , loc: Location) { inc_instr(loc, Instruction::CallIndirectPost);                           }
-    unary              (opt: UnaryOperator, opnd: WasmValue                                    , loc: Location) { inc_instr(loc, Instruction::Unary);            opt.apply(opnd)           }
-    binary             ( opt: BinaryOperator, l_opnd: WasmValue, r_opnd: WasmValue             , loc: Location) { inc_instr(loc, Instruction::Binary);           opt.apply(l_opnd, r_opnd) }
-    drop               (                                                                         loc: Location) { inc_instr(loc, Instruction::Drop);                                       }
-    return_            (                                                                         loc: Location) { inc_instr(loc, Instruction::Return);                                     }
-    const_             (v: WasmValue                                                           , loc: Location) { inc_instr(loc, Instruction::Const);            v                         }
-    local              (v: WasmValue, _i: LocalIndex, _l: LocalOp                              , loc: Location) { inc_instr(loc, Instruction::Local);            v                         }
-    global             (v: WasmValue, _i: GlobalIndex, _g: GlobalOp                            , loc: Location) { inc_instr(loc, Instruction::Global);           v                         }
-    load               (i: LoadIndex, o: LoadOffset, op: LoadOperation                         , loc: Location) { inc_instr(loc, Instruction::Load);             op.perform(&i, &o)        }
-    store              (i: StoreIndex, v: WasmValue, o: StoreOffset, op: StoreOperation        , loc: Location) { inc_instr(loc, Instruction::Store);            op.perform(&i, &v, &o);   }
-    memory_size        (s: WasmValue, _i: MemoryIndex                                          , loc: Location) { inc_instr(loc, Instruction::MemorySize);       s                         }
-    memory_grow        (a: WasmValue, i: MemoryIndex                                           , loc: Location) { inc_instr(loc, Instruction::MemoryGrow);       i.grow(a)                 }
-    block pre          (_bi: BlockInputCount, _ba: BlockArity                                  , loc: Location) { inc_instr(loc, Instruction::BlockPre);                                   }
-    block post         (                                                                         loc: Location) { inc_instr(loc, Instruction::BlockPost);                                  }
-    loop_ pre          (_li: LoopInputCount, _la: LoopArity                                    , loc: Location) { inc_instr(loc, Instruction::LoopPre);                                    }
-    loop_ post         (                                                                         loc: Location) { inc_instr(loc, Instruction::LoopPost);                                   }
+    if_then_else       (c: PathContinuation, _ic: IfThenElseInputCount, _ia: IfThenElseArity           , loc: Location) { inc_instr(loc, Instruction::If);               c                           }
+    if_then_else_post  (                                                                                 loc: Location) { inc_instr(loc, Instruction::IfPost);                                       }
+    if_then            (c: PathContinuation, _ic: IfThenInputCount, _ia: IfThenArity                   , loc: Location) { inc_instr(loc, Instruction::IfThen);           c                           }
+    if_then_post       (                                                                                 loc: Location) { inc_instr(loc, Instruction::IfThenPost);                                   }
+    br                 (_l: BranchTargetLabel                                                          , loc: Location) { inc_instr(loc, Instruction::Br);                                           }
+    br_if              (c : ParameterBrIfCondition, _l : ParameterBrIfLabel                            , loc: Location) { inc_instr(loc, Instruction::BrIf);             c                           }
+    br_table           (bt: BranchTableTarget, _e: BranchTableEffective, _d: BranchTableDefault        , loc: Location) { inc_instr(loc, Instruction::BrIfTable);        bt                          }
+    select             (c: PathContinuation                                                            , loc: Location) { inc_instr(loc, Instruction::Select);           c                           }
+    call pre           (_t : FunctionIndex                                                             , loc: Location) { inc_instr(loc, Instruction::CallPre);                                      }
+    call post          (_t : FunctionIndex                                                             , loc: Location) { inc_instr(loc, Instruction::CallPost);                                     }
+    call_indirect pre  (t: FunctionTableIndex, _f: FunctionTable                                       , loc: Location) { inc_instr(loc, Instruction::CallIndirectPre);  t                           }
+    call_indirect post (_t: FunctionTable                                                              , loc: Location) { inc_instr(loc, Instruction::CallIndirectPost);                             }
+    unary              (opt: UnaryOperator, opnd: WasmValue                                            , loc: Location) { inc_instr(loc, Instruction::Unary);            opt.apply(opnd)             }
+    binary             ( opt: BinaryOperator, l_opnd: WasmValue, r_opnd: WasmValue                     , loc: Location) { inc_instr(loc, Instruction::Binary);           opt.apply(l_opnd, r_opnd)   }
+    drop               (                                                                                 loc: Location) { inc_instr(loc, Instruction::Drop);                                         }
+    return_            (                                                                                 loc: Location) { inc_instr(loc, Instruction::Return);                                       }
+    const_             (v: WasmValue                                                                   , loc: Location) { inc_instr(loc, Instruction::Const);            v                           }
+    local              (v: WasmValue, _i: LocalIndex, _l: LocalOp                                      , loc: Location) { inc_instr(loc, Instruction::Local);            v                           }
+    global             (v: WasmValue, _i: GlobalIndex, _g: GlobalOp                                    , loc: Location) { inc_instr(loc, Instruction::Global);           v                           }
+    load               (i: LoadIndex, o: LoadOffset, op: LoadOperation, m: MemoryIndex                 , loc: Location) { inc_instr(loc, Instruction::Load);             op.perform(&i, &o, &m)      }
+    store              (i: StoreIndex, v: WasmValue, o: StoreOffset, op: StoreOperation, m: MemoryIndex, loc: Location) { inc_instr(loc, Instruction::Store);            op.perform(&i, &v, &o, &m); }
+    memory_size        (s: WasmValue, _i: MemoryIndex                                                  , loc: Location) { inc_instr(loc, Instruction::MemorySize);       s                           }
+    memory_grow        (a: WasmValue, i: MemoryIndex                                                   , loc: Location) { inc_instr(loc, Instruction::MemoryGrow);       i.grow(a)                   }
+    block pre          (_bi: BlockInputCount, _ba: BlockArity                                          , loc: Location) { inc_instr(loc, Instruction::BlockPre);                                     }
+    block post         (                                                                                 loc: Location) { inc_instr(loc, Instruction::BlockPost);                                    }
+    loop_ pre          (_li: LoopInputCount, _la: LoopArity                                            , loc: Location) { inc_instr(loc, Instruction::LoopPre);                                      }
+    loop_ post         (                                                                                 loc: Location) { inc_instr(loc, Instruction::LoopPost);                                     }
 }
@@ -23,28 +23,28 @@ fn alignment_check(index: i64, size: i64) {
     assert!(index & (size - 1) == 0); // size == 4 (_32) | 8 (_64)
 }
 
-fn safe_load(i: LoadIndex, o: LoadOffset, op: LoadOperation) -> WasmValue {
+fn safe_load(i: LoadIndex, o: LoadOffset, op: LoadOperation, m: MemoryIndex) -> WasmValue {
     // Bound check: check for reading past valid memory: if pointer + offset + bytes
     bounds_check(i.value() as i64, op.target_value_size() as i64, o.value());
     // Alignment check
     alignment_check(i.value() as i64, op.target_value_size() as i64);
     // Perform
-    op.perform(&i, &o)
+    op.perform(&i, &o, &m)
 }
 
-fn safe_store(i: StoreIndex, v: WasmValue, o: StoreOffset, op: StoreOperation) -> () {
+fn safe_store(i: StoreIndex, v: WasmValue, o: StoreOffset, op: StoreOperation, m: MemoryIndex) -> () {
     // Bound check: check for reading past valid memory: if pointer + offset + bytes
     bounds_check(i.value() as i64, op.target_value_size() as i64, o.value());
     // Alignment check
     alignment_check(i.value() as i64, op.target_value_size() as i64);
     // Perform
-    op.perform(&i, &v, &o);
+    op.perform(&i, &v, &o, &m);
 }
 
 // Target program events: loads & stores
 advice! {
-    load (i: LoadIndex, o: LoadOffset, op: LoadOperation, _l: Location) { safe_load(i, o, op)  }
-    store (i: StoreIndex, v: WasmValue, o: StoreOffset, op: StoreOperation, _l: Location) { safe_store(i, v, o, op); }
+    load (i: LoadIndex, o: LoadOffset, op: LoadOperation, m: MemoryIndex, _l: Location) { safe_load(i, o, op, m)  }
+    store (i: StoreIndex, v: WasmValue, o: StoreOffset, op: StoreOperation, m: MemoryIndex, _l: Location) { safe_store(i, v, o, op, m); }
 }
 
 // In `SafeHeap.cpp` the inspiration source for `fn bounds_check` is `makeBoundsCheck`
@@ -5,6 +5,9 @@ use clio::*;
 use rust_to_wasm_compiler::WasiSupport;
 use serde::Deserialize;
 use wastrumentation::compiler::Compiles;
+use wastrumentation::site_id::SiteIds;
+use wastrumentation::site_predicate::SitePredicate;
+use wastrumentation::target_selector::{target_functions, TargetSelector};
 use wastrumentation::{Configuration, Wastrumenter};
 use wastrumentation_lang_rust::compile::compiler::Compiler as RustCompiler;
 use wastrumentation_lang_rust::compile::options::RustSource;
@@ -19,16 +22,46 @@ struct Args {
     input_program_path: Input,
 
     /// Path to rust analysis TOML file
-    #[arg(short, long)]
-    rust_analysis_toml_path: Input,
+    #[arg(short, long, required_unless_present = "map_site_ids")]
+    rust_analysis_toml_path: Option<Input>,
 
-    /// Hooks to instrument
+    /// Hooks to instrument, all but the opt-in ones by default
     #[arg(long, num_args = 1..)]
     hooks: Option<Vec<Hook>>,
 
-    // Target functions of interest
+    /// Target functions of interest, by index or as `index:n`, `export:glob`
+    /// or `name:glob`, where a leading `!` excludes the functions matched,
+    /// e.g. `export:run_*` `!name:alloc::*`
     #[arg(long, required = false, num_args = 1..)]
-    targets: Option<Vec<u32>>,
+    targets: Option<Vec<TargetSelector>>,
+
+    /// Sites to instrument within the targets, clauses `key=values` joined by
+    /// `&&` with keys `function`, `instr`, `opcode` and `hook`, e.g.
+    /// "function=12 && instr=40..=90 && opcode=i32.load". Hooks are named
+    /// after their traps, e.g. `trap_i32_load`.
+    #[arg(long)]
+    filter: Option<SitePredicate>,
+
+    /// Map every instruction of the targets onto its location in the input
+    /// program, its code section offset and, given DWARF, file and line
+    #[arg(long)]
+    source_locations: bool,
+
+    /// Identify every instruction of the targets by a site id that survives
+    /// rebuilds of the input program, along with a manifest of the ids
+    #[arg(long)]
+    site_ids: bool,
+
+    /// Keep the DWARF of the input program, rewritten to match the
+    /// instrumented code, rather than stripping it
+    #[arg(long)]
+    debug_info: bool,
+
+    /// Rather than instrumenting, write how the site ids of this earlier build
+    /// of the input program, or the manifest of its site ids, map onto those of
+    /// the input program, a line `old new` in hexadecimal per site
+    #[arg(long, value_name = "OLD")]
+    map_site_ids: Option<Input>,
 
     /// Output path for the instrumented module
     #[arg(short, long)]
@@ -42,6 +75,13 @@ enum Hook {
     CallPost,
     CallIndirectPre,
     CallIndirectPost,
+    CallValuesPre,
+    CallValuesPost,
+    FunctionEnter,
+    FunctionExit,
+    ModuleInit,
+    ModuleFinalize,
+    BasicBlock,
     IfThen,
     IfThenPost,
     IfThenElse,
@@ -54,6 +94,13 @@ enum Hook {
     Binary,
     Drop,
     Return,
+    ReturnValues,
+    DropValue,
+    Unreachable,
+    Nop,
+    RefNull,
+    RefIsNull,
+    RefFunc,
     Const,
     Local,
     Global,
@@ -61,10 +108,23 @@ enum Hook {
     Load,
     MemorySize,
     MemoryGrow,
+    MemoryFill,
+    MemoryCopy,
+    MemoryInit,
+    DataDrop,
+    TableGet,
+    TableSet,
+    TableSize,
+    TableGrow,
+    TableFill,
+    TableCopy,
+    TableInit,
+    ElemDrop,
     BlockPre,
     BlockPost,
     LoopPre,
     LoopPost,
+    LoopIteration,
 }
 
 impl From<&Hook> for AnalysisHook {
@@ -75,6 +135,13 @@ impl From<&Hook> for AnalysisHook {
             Hook::CallPost => AnalysisHook::CallPost,
             Hook::CallIndirectPre => AnalysisHook::CallIndirectPre,
             Hook::CallIndirectPost => AnalysisHook::CallIndirectPost,
+            Hook::CallValuesPre => AnalysisHook::CallValuesPre,
+            Hook::CallValuesPost => AnalysisHook::CallValuesPost,
+            Hook::FunctionEnter => AnalysisHook::FunctionEnter,
+            Hook::FunctionExit => AnalysisHook::FunctionExit,
+            Hook::ModuleInit => AnalysisHook::ModuleInit,
+            Hook::ModuleFinalize => AnalysisHook::ModuleFinalize,
+            Hook::BasicBlock => AnalysisHook::BasicBlock,
             Hook::IfThen => AnalysisHook::IfThen,
             Hook::IfThenPost => AnalysisHook::IfThenPost,
             Hook::IfThenElse => AnalysisHook::IfThenElse,
@@ -87,6 +154,13 @@ impl From<&Hook> for AnalysisHook {
             Hook::Binary => AnalysisHook::Binary,
             Hook::Drop => AnalysisHook::Drop,
             Hook::Return => AnalysisHook::Return,
+            Hook::ReturnValues => AnalysisHook::ReturnValues,
+            Hook::DropValue => AnalysisHook::DropValue,
+            Hook::Unreachable => AnalysisHook::Unreachable,
+            Hook::Nop => AnalysisHook::Nop,
+            Hook::RefNull => AnalysisHook::RefNull,
+            Hook::RefIsNull => AnalysisHook::RefIsNull,
+            Hook::RefFunc => AnalysisHook::RefFunc,
             Hook::Const => AnalysisHook::Const,
             Hook::Local => AnalysisHook::Local,
             Hook::Global => AnalysisHook::Global,
@@ -94,10 +168,23 @@ impl From<&Hook> for AnalysisHook {
             Hook::Load => AnalysisHook::Load,
             Hook::MemorySize => AnalysisHook::MemorySize,
             Hook::MemoryGrow => AnalysisHook::MemoryGrow,
+            Hook::MemoryFill => AnalysisHook::MemoryFill,
+            Hook::MemoryCopy => AnalysisHook::MemoryCopy,
+            Hook::MemoryInit => AnalysisHook::MemoryInit,
+            Hook::DataDrop => AnalysisHook::DataDrop,
+            Hook::TableGet => AnalysisHook::TableGet,
+            Hook::TableSet => AnalysisHook::TableSet,
+            Hook::TableSize => AnalysisHook::TableSize,
+            Hook::TableGrow => AnalysisHook::TableGrow,
+            Hook::TableFill => AnalysisHook::TableFill,
+            Hook::TableCopy => AnalysisHook::TableCopy,
+            Hook::TableInit => AnalysisHook::TableInit,
+            Hook::ElemDrop => AnalysisHook::ElemDrop,
             Hook::BlockPre => AnalysisHook::BlockPre,
             Hook::BlockPost => AnalysisHook::BlockPost,
             Hook::LoopPre => AnalysisHook::LoopPre,
             Hook::LoopPost => AnalysisHook::LoopPost,
+            Hook::LoopIteration => AnalysisHook::LoopIteration,
         }
     }
 }
@@ -109,11 +196,44 @@ fn main() -> anyhow::Result<()> {
         mut output_path,
         hooks,
         targets,
+        filter,
+        source_locations,
+        site_ids,
+        debug_info,
+        map_site_ids,
     } = Args::parse();
 
     let mut wasm_module = Vec::new();
     input_program_path.read_to_end(&mut wasm_module)?;
 
+    if let Some(mut old_path) = map_site_ids {
+        let mut old = Vec::new();
+        old_path.read_to_end(&mut old)?;
+        let old = if old.starts_with(b"\0asm") {
+            SiteIds::of_program(&old)?
+        } else {
+            SiteIds::from_manifest(&old)?
+        };
+        let new = SiteIds::of_program(&wasm_module)?;
+        for (old_id, new_id) in old.map_onto(&new) {
+            writeln!(output_path, "{old_id:016x} {new_id:016x}")?;
+        }
+        return Ok(());
+    }
+    let rust_analysis_toml_path =
+        rust_analysis_toml_path.expect("Required without `--map-site-ids`");
+
+    if targets.is_some() {
+        // On stderr, the instrumented program may be written to stdout
+        for target in target_functions(&wasm_module, &None, &targets)? {
+            let name = target.name.as_deref().unwrap_or("<unnamed>");
+            eprintln!(
+                "Targeting function {} {name} exported as {:?}",
+                target.index, target.export
+            );
+        }
+    }
+
     let hooks = match hooks {
         None => AnalysisHook::all_hooks(),
         Some(hooks) => hooks.iter().map(From::from).collect(),
@@ -131,7 +251,12 @@ fn main() -> anyhow::Result<()> {
     let instrumentation_language_compiler = RustCompiler::setup_compiler()?;
     let analysis_language_compiler = RustCompiler::setup_compiler()?;
     let configuration = Configuration {
-        target_indices: targets,
+        target_indices: None,
+        target_selectors: targets,
+        site_predicate: filter,
+        source_locations,
+        site_ids,
+        debug_info,
         primary_selection: None,
     };
 
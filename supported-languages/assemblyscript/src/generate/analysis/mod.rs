@@ -452,6 +452,8 @@ impl Display for ASTrapBrIf<'_> {
             export function {FUNCTION_NAME_SPECIALIZED_BR_IF}(
                 path_kontinuation: i32,
                 low_level_label: i32,
+                target_instr_index: i64,
+                target_kind: i32,
                 func_index: i64,
                 istr_index: i64,
             ): i32 {{
@@ -481,6 +483,8 @@ impl Display for ASTrapBrTable<'_> {
                 br_table_target: i32,
                 effective_label: i32,
                 br_table_default: i32,
+                target_instr_index: i64,
+                target_kind: i32,
                 func_index: i64,
                 istr_index: i64,
             ): i32 {{
@@ -540,6 +544,7 @@ impl Display for ASTrapCallIndirectPre<'_> {
             export function {FUNCTION_NAME_SPECIALIZED_CALL_INDIRECT_PRE}(
                 function_table_index: i32, // NOTE: index first, eases transformation!
                 function_table: i32,
+                resolved_function: i32,
                 func_index: i64,
                 istr_index: i64,
             ): i32 {{
@@ -566,6 +571,7 @@ impl Display for ASTrapCallIndirectPost<'_> {
             "
             export function {FUNCTION_NAME_SPECIALIZED_CALL_INDIRECT_POST}(
                 function_table: i32,
+                resolved_function: i32,
                 func_index: i64,
                 istr_index: i64,
             ): void {{
@@ -51,7 +51,7 @@ impl WaspSignature<'_> {
                 .return_types
                 .iter()
                 .chain(self.argument_types.iter())
-                .map(ToString::to_string)
+                .map(|ty| ty.stack_type().to_string())
                 .collect::<Vec<String>>()
                 .join(", ");
             format!("<{comma_separated_types}>")
@@ -154,7 +154,7 @@ fn generate_allocate_specialized(signature: &WaspSignature) -> String {
     let signature_args_typs_ident = signature_args
         .iter()
         .enumerate()
-        .map(|(index, ty)| format!("a{index}: {ty}"))
+        .map(|(index, ty)| format!("a{index}: {}", ty.stack_type()))
         .collect::<Vec<String>>()
         .join(", ");
 
@@ -259,6 +259,7 @@ fn generate_load_specialized(signature: &WaspSignature) -> String {
         .iter()
         .enumerate()
         .map(|(index, arg_i_ret_type)| {
+            let arg_i_ret_type = arg_i_ret_type.stack_type();
             let mangled_name = signature.generate_load_name(SignatureSide::Argument, index);
             format!(
                 "
@@ -271,6 +272,7 @@ export function {mangled_name}(stack_ptr: usize): {arg_i_ret_type} {{
         .iter()
         .enumerate()
         .map(|(index, ret_i_ret_type)| {
+            let ret_i_ret_type = ret_i_ret_type.stack_type();
             let mangled_name = signature.generate_load_name(SignatureSide::Return, index);
             format!(
                 "
@@ -330,6 +332,7 @@ fn generate_store_specialized(signature: &WaspSignature) -> String {
         .iter()
         .enumerate()
         .map(|(index, arg_i_ret_type)| {
+            let arg_i_ret_type = arg_i_ret_type.stack_type();
             let mangled_name = signature.generate_store_name(SignatureSide::Argument, index);
             format!(
                 "
@@ -342,6 +345,7 @@ export function {mangled_name}(stack_ptr: usize, a{index}: {arg_i_ret_type}): vo
         .iter()
         .enumerate()
         .map(|(index, ret_i_ret_type)| {
+            let ret_i_ret_type = ret_i_ret_type.stack_type();
             let mangled_name = signature.generate_store_name(SignatureSide::Return, index);
             format!(
                 "
@@ -460,7 +464,7 @@ fn generate_store_rets_specialized(signature: &WaspSignature) -> String {
     let rets_signature = signature_rets
         .iter()
         .enumerate()
-        .map(|(index, ty)| format!("a{index}: {ty}"));
+        .map(|(index, ty)| format!("a{index}: {}", ty.stack_type()));
     // eg: `stack_ptr: usize, a0: R0, a1: R1`
     let total_signature = (vec![String::from("stack_ptr: usize")])
         .into_iter()
@@ -127,6 +127,17 @@ pub extern \"C\" fn allocate_ret_f64_f32_arg_i32_i64(a0: i32, a1: i64) -> usize
 pub extern \"C\" fn allocate_ret_f64_f32_i32_i64_arg_i64_i32_f32_f64(a0: i64, a1: i32, a2: f32, a3: f64) -> usize {
     allocate_ret_4_arg_4(WasmValue::new_i64(a0), WasmValue::new_i32(a1), WasmValue::new_f32(a2), WasmValue::new_f64(a3))
 }
+");
+
+    let signature_with_references = Signature {
+        return_types: vec![WasmType::Ref(RefType::ExternRef)],
+        argument_types: vec![WasmType::Ref(RefType::FuncRef), WasmType::I64],
+    };
+    assert_eq!(generate_allocate_specialized(&RustSignature(&signature_with_references)), "
+#[no_mangle]
+pub extern \"C\" fn allocate_ret_ref_extern_arg_ref_func_i64(a0: i32, a1: i64) -> usize {
+    allocate_ret_1_arg_2(WasmValue::new_func_ref(a0), WasmValue::new_i64(a1))
+}
 ");
 }
 
@@ -28,6 +28,13 @@ pub enum Hook {
     CallPost,
     CallIndirectPre,
     CallIndirectPost,
+    CallValuesPre,
+    CallValuesPost,
+    FunctionEnter,
+    FunctionExit,
+    ModuleInit,
+    ModuleFinalize,
+    BasicBlock,
     IfThen,
     IfThenPost,
     IfThenElse,
@@ -40,6 +47,13 @@ pub enum Hook {
     Binary,
     Drop,
     Return,
+    ReturnValues,
+    DropValue,
+    Unreachable,
+    Nop,
+    RefNull,
+    RefIsNull,
+    RefFunc,
     Const,
     Local,
     Global,
@@ -47,10 +61,23 @@ pub enum Hook {
     Load,
     MemorySize,
     MemoryGrow,
+    MemoryFill,
+    MemoryCopy,
+    MemoryInit,
+    DataDrop,
+    TableGet,
+    TableSet,
+    TableSize,
+    TableGrow,
+    TableFill,
+    TableCopy,
+    TableInit,
+    ElemDrop,
     BlockPre,
     BlockPost,
     LoopPre,
     LoopPost,
+    LoopIteration,
 }
 
 impl Hook {
@@ -87,6 +114,41 @@ impl Hook {
             LoopPost,
         ])
     }
+
+    /// The hooks an analysis has to request explicitly, as they are not part
+    /// of [`Hook::all_hooks`].
+    pub fn opt_in_hooks() -> HashSet<Self> {
+        use Hook::*;
+        HashSet::from([
+            CallValuesPre,
+            CallValuesPost,
+            FunctionEnter,
+            FunctionExit,
+            ModuleInit,
+            ModuleFinalize,
+            BasicBlock,
+            ReturnValues,
+            DropValue,
+            Unreachable,
+            Nop,
+            RefNull,
+            RefIsNull,
+            RefFunc,
+            MemoryFill,
+            MemoryCopy,
+            MemoryInit,
+            DataDrop,
+            TableGet,
+            TableSet,
+            TableSize,
+            TableGrow,
+            TableFill,
+            TableCopy,
+            TableInit,
+            ElemDrop,
+            LoopIteration,
+        ])
+    }
 }
 
 pub fn interface_from(hooks: &HashSet<Hook>) -> AnalysisInterface {
@@ -110,6 +172,27 @@ pub fn interface_from(hooks: &HashSet<Hook>) -> AnalysisInterface {
                 interface.post_trap_call_indirect =
                     Some(AnalysisInterface::interface_call_indirect_post())
             }
+            Hook::CallValuesPre => {
+                interface.call_values_pre = Some(AnalysisInterface::interface_call_values_pre())
+            }
+            Hook::CallValuesPost => {
+                interface.call_values_post = Some(AnalysisInterface::interface_call_values_post())
+            }
+            Hook::FunctionEnter => {
+                interface.function_enter = Some(AnalysisInterface::interface_function_enter())
+            }
+            Hook::FunctionExit => {
+                interface.function_exit = Some(AnalysisInterface::interface_function_exit())
+            }
+            Hook::ModuleInit => {
+                interface.module_init = Some(AnalysisInterface::interface_module_init())
+            }
+            Hook::ModuleFinalize => {
+                interface.module_finalize = Some(AnalysisInterface::interface_module_finalize())
+            }
+            Hook::BasicBlock => {
+                interface.basic_block = Some(AnalysisInterface::interface_basic_block())
+            }
             Hook::IfThen => interface.if_then_trap = Some(AnalysisInterface::interface_if_then()),
             Hook::IfThenPost => {
                 interface.if_then_post_trap = Some(AnalysisInterface::interface_if_then_post())
@@ -163,6 +246,24 @@ pub fn interface_from(hooks: &HashSet<Hook>) -> AnalysisInterface {
             }
             Hook::Drop => interface.drop_trap = Some(AnalysisInterface::interface_drop()),
             Hook::Return => interface.return_trap = Some(AnalysisInterface::interface_return()),
+            Hook::ReturnValues => {
+                interface.return_values = Some(AnalysisInterface::interface_return_values())
+            }
+            Hook::DropValue => {
+                interface.drop_i32 = Some(AnalysisInterface::interface_drop_i32());
+                interface.drop_f32 = Some(AnalysisInterface::interface_drop_f32());
+                interface.drop_i64 = Some(AnalysisInterface::interface_drop_i64());
+                interface.drop_f64 = Some(AnalysisInterface::interface_drop_f64());
+            }
+            Hook::Unreachable => {
+                interface.unreachable_trap = Some(AnalysisInterface::interface_unreachable())
+            }
+            Hook::Nop => interface.nop_trap = Some(AnalysisInterface::interface_nop()),
+            Hook::RefNull => interface.ref_null = Some(AnalysisInterface::interface_ref_null()),
+            Hook::RefIsNull => {
+                interface.ref_is_null = Some(AnalysisInterface::interface_ref_is_null())
+            }
+            Hook::RefFunc => interface.ref_func = Some(AnalysisInterface::interface_ref_func()),
             Hook::Const => {
                 interface.const_i32_trap = Some(AnalysisInterface::interface_const_i32());
                 interface.const_f32_trap = Some(AnalysisInterface::interface_const_f32());
@@ -211,6 +312,34 @@ pub fn interface_from(hooks: &HashSet<Hook>) -> AnalysisInterface {
             Hook::MemoryGrow => {
                 interface.memory_grow = Some(AnalysisInterface::interface_memory_grow())
             }
+            Hook::MemoryFill => {
+                interface.memory_fill = Some(AnalysisInterface::interface_memory_fill())
+            }
+            Hook::MemoryCopy => {
+                interface.memory_copy = Some(AnalysisInterface::interface_memory_copy())
+            }
+            Hook::MemoryInit => {
+                interface.memory_init = Some(AnalysisInterface::interface_memory_init())
+            }
+            Hook::DataDrop => interface.data_drop = Some(AnalysisInterface::interface_data_drop()),
+            Hook::TableGet => interface.table_get = Some(AnalysisInterface::interface_table_get()),
+            Hook::TableSet => interface.table_set = Some(AnalysisInterface::interface_table_set()),
+            Hook::TableSize => {
+                interface.table_size = Some(AnalysisInterface::interface_table_size())
+            }
+            Hook::TableGrow => {
+                interface.table_grow = Some(AnalysisInterface::interface_table_grow())
+            }
+            Hook::TableFill => {
+                interface.table_fill = Some(AnalysisInterface::interface_table_fill())
+            }
+            Hook::TableCopy => {
+                interface.table_copy = Some(AnalysisInterface::interface_table_copy())
+            }
+            Hook::TableInit => {
+                interface.table_init = Some(AnalysisInterface::interface_table_init())
+            }
+            Hook::ElemDrop => interface.elem_drop = Some(AnalysisInterface::interface_elem_drop()),
             Hook::BlockPre => {
                 interface.pre_block = Some(AnalysisInterface::interface_pre_block());
             }
@@ -223,6 +352,9 @@ pub fn interface_from(hooks: &HashSet<Hook>) -> AnalysisInterface {
             Hook::LoopPost => {
                 interface.post_loop = Some(AnalysisInterface::interface_post_loop());
             }
+            Hook::LoopIteration => {
+                interface.loop_iteration = Some(AnalysisInterface::interface_loop_iteration());
+            }
         }
     }
     interface
@@ -48,13 +48,13 @@ impl WatSignature<'_> {
         let ret_types = self
             .return_types
             .iter()
-            .map(WasmType::wat_type)
+            .map(ToString::to_string)
             .collect::<Vec<_>>()
             .join("_");
         let arg_types = self
             .argument_types
             .iter()
-            .map(WasmType::wat_type)
+            .map(ToString::to_string)
             .collect::<Vec<_>>()
             .join("_");
 
@@ -80,7 +80,7 @@ impl WatSignature<'_> {
         let params = self
             .argument_types
             .iter()
-            .map(|t| format!("(param {})", t.wat_type()))
+            .map(|t| format!("(param {})", t.stack_type().wat_type()))
             .collect::<Vec<_>>()
             .join(" ");
 
@@ -91,7 +91,7 @@ impl WatSignature<'_> {
                 " (result {})",
                 self.return_types
                     .iter()
-                    .map(|t| t.wat_type())
+                    .map(|t| t.stack_type().wat_type())
                     .collect::<Vec<_>>()
                     .join(" ")
             )
@@ -357,7 +357,10 @@ fn generate_specialized_functions_wat(signature: &WatSignature) -> String {
     functions.push_str(&format!("{DEF}(func (export \"allocate_{mangled_name}\") "));
 
     for (i, arg_type) in signature.argument_types.iter().enumerate() {
-        functions.push_str(&format!("(param $a{i} {}) ", arg_type.wat_type()));
+        functions.push_str(&format!(
+            "(param $a{i} {}) ",
+            arg_type.stack_type().wat_type()
+        ));
     }
     functions.push_str("(result i32)\n");
 
@@ -377,7 +380,7 @@ fn generate_specialized_functions_wat(signature: &WatSignature) -> String {
     // Load functions for arguments
     for (i, arg_type) in signature.argument_types.iter().enumerate() {
         let conversion = i64_to_wasm_type_conversion(arg_type);
-        let arg_ty_wasm = arg_type.wat_type();
+        let arg_ty_wasm = arg_type.stack_type().wat_type();
 
         functions.push_str(&formatdoc!(
             "
@@ -393,7 +396,7 @@ fn generate_specialized_functions_wat(signature: &WatSignature) -> String {
     // Load functions for returns
     for (i, ret_type) in signature.return_types.iter().enumerate() {
         let conversion = i64_to_wasm_type_conversion(ret_type);
-        let ret_type_wasm = ret_type.wat_type();
+        let ret_type_wasm = ret_type.stack_type().wat_type();
 
         functions.push_str(&formatdoc!(
             "
@@ -409,7 +412,7 @@ fn generate_specialized_functions_wat(signature: &WatSignature) -> String {
     // Store functions for arguments
     for (i, arg_type) in signature.argument_types.iter().enumerate() {
         let conversion = wasm_type_to_i64_conversion(arg_type);
-        let arg_type_wasm = arg_type.wat_type();
+        let arg_type_wasm = arg_type.stack_type().wat_type();
 
         functions.push_str(&formatdoc!(
             "
@@ -429,7 +432,7 @@ fn generate_specialized_functions_wat(signature: &WatSignature) -> String {
     // Store functions for returns
     for (i, ret_type) in signature.return_types.iter().enumerate() {
         let conversion = wasm_type_to_i64_conversion(ret_type);
-        let ret_type_wasm = ret_type.wat_type();
+        let ret_type_wasm = ret_type.stack_type().wat_type();
 
         functions.push_str(&formatdoc!(
             "
@@ -456,7 +459,10 @@ fn generate_specialized_functions_wat(signature: &WatSignature) -> String {
     } else {
         // Add parameters for each return type
         for (i, ret_type) in signature.return_types.iter().enumerate() {
-            functions.push_str(&format!(" (param $ret{i} {})", ret_type.wat_type()));
+            functions.push_str(&format!(
+                " (param $ret{i} {})",
+                ret_type.stack_type().wat_type()
+            ));
         }
         functions.push('\n');
 
@@ -48,6 +48,7 @@ pub struct MergeOptions<'a> {
     // Options:
     pub no_validation: options::NoValidate,
     pub rename_export_conflicts: options::RenameExportConflicts,
+    pub debug_info: options::DebugInfo,
 
     // Features:
     pub bulk_memory: options::BulkMemory,
@@ -154,6 +155,7 @@ impl MergeOptions<'_> {
             self,
             no_validation,
             rename_export_conflicts,
+            debug_info,
             sign_ext,
             threads,
             mutable_globals,
@@ -26,6 +26,8 @@ macro_rules! option {
 option!(NoValidate, "--no-validation");
 // Rename exports to avoid conflicts (rather than error)
 option!(RenameExportConflicts, "--rename-export-conflicts");
+// Keep the names section and other debug info (rather than strip it)
+option!(DebugInfo, "--debuginfo");
 
 // FEATURES
 // sign extension operations
@@ -80,6 +80,28 @@ fn test_merge() {
     assert_eq!(odd.call(&mut store, 12346).unwrap(), 0);
 }
 
+#[test]
+fn test_merge_debug_info() {
+    let wat_named = parse_str(r#"(module (func $a_function_name (export "f")))"#).unwrap();
+    let merged_wasm = |debug_info| {
+        MergeOptions {
+            primary: None,
+            input_modules: vec![InputModule {
+                module: &wat_named,
+                namespace: String::from("named"),
+            }],
+            debug_info,
+            ..Default::default()
+        }
+        .merge()
+        .unwrap()
+    };
+    let has_name = |wasm: &[u8]| wasm.windows(15).any(|w| w == b"a_function_name");
+
+    assert!(has_name(&merged_wasm(options::DebugInfo::Enable)));
+    assert!(!has_name(&merged_wasm(options::DebugInfo::Disable)));
+}
+
 #[test]
 fn test_merge_fail() {
     let merge_options = MergeOptions {
@@ -116,6 +138,7 @@ fn test_debug() {
             input_modules: [],
             no_validation: Disable,
             rename_export_conflicts: Disable,
+            debug_info: Disable,
             bulk_memory: Disable,
             bulk_memory_opt: Disable,
             call_indirect_overlong: Disable,
@@ -0,0 +1,193 @@
+//! A coverage-guided fuzz loop over programs instrumented with
+//! [`wastrumentation::instrument_edge_coverage`]. Every input runs in a fresh
+//! instance, after which the exported coverage map is compared against all
+//! coverage seen so far; inputs that reach new edges, or reach edges a new
+//! number of times, join the corpus to be mutated further.
+
+use wasmtime::{Engine, Instance, Linker, Module, Store};
+use wastrumentation::edge_coverage::{EXPORT_COVERAGE_MAP, MAP_SIZE};
+
+#[cfg(test)]
+mod tests;
+
+/// Runs a single input against a fresh instance of the program
+pub type Harness<'a, T> = dyn FnMut(&mut Store<T>, &Instance, &[u8]) -> anyhow::Result<()> + 'a;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Outcome {
+    /// The input reached coverage no input before it did
+    NewCoverage,
+    /// The input reached no new coverage
+    Uninteresting,
+    /// The harness failed, e.g. because the program trapped
+    Crash,
+}
+
+pub struct Fuzzer<T> {
+    engine: Engine,
+    linker: Linker<T>,
+    module: Module,
+    corpus: Vec<Vec<u8>>,
+    crashes: Vec<Vec<u8>>,
+    /// Per edge, the buckets of hit counts seen so far
+    seen: Vec<u8>,
+    max_len: usize,
+    rng: XorShift,
+}
+
+impl<T: Default> Fuzzer<T> {
+    /// Instruments `program` for edge coverage. The `linker` must provide the
+    /// imports of the program, `seed` makes the mutations reproducible.
+    ///
+    /// # Errors
+    /// Errors upon failing to instrument or compile the program.
+    pub fn new(
+        engine: &Engine,
+        linker: Linker<T>,
+        program: &[u8],
+        seed: u64,
+    ) -> anyhow::Result<Self> {
+        let instrumented = wastrumentation::instrument_edge_coverage(program, false)?;
+        let module = Module::new(engine, instrumented)?;
+        Ok(Self {
+            engine: engine.clone(),
+            linker,
+            module,
+            corpus: Vec::new(),
+            crashes: Vec::new(),
+            seen: vec![0; usize::try_from(MAP_SIZE).unwrap()],
+            max_len: 64,
+            rng: XorShift::new(seed),
+        })
+    }
+
+    /// Limits the length of mutated inputs, 64 bytes by default.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    pub fn corpus(&self) -> &[Vec<u8>] {
+        &self.corpus
+    }
+
+    pub fn crashes(&self) -> &[Vec<u8>] {
+        &self.crashes
+    }
+
+    /// Runs `input`, keeping it in the corpus when it reaches new coverage.
+    ///
+    /// # Errors
+    /// Errors upon failing to instantiate the program or to find its map.
+    pub fn run(&mut self, input: &[u8], harness: &mut Harness<T>) -> anyhow::Result<Outcome> {
+        let mut store = Store::new(&self.engine, T::default());
+        let instance = self.linker.instantiate(&mut store, &self.module)?;
+        let result = harness(&mut store, &instance, input);
+
+        let map = instance
+            .get_memory(&mut store, EXPORT_COVERAGE_MAP)
+            .ok_or_else(|| anyhow::anyhow!("The program exports no coverage map"))?;
+        let mut new_coverage = false;
+        for (seen, count) in self.seen.iter_mut().zip(map.data(&store)) {
+            let bucket = bucket(*count);
+            if bucket & !*seen != 0 {
+                *seen |= bucket;
+                new_coverage = true;
+            }
+        }
+
+        if result.is_err() {
+            self.crashes.push(input.to_vec());
+            Ok(Outcome::Crash)
+        } else if new_coverage {
+            self.corpus.push(input.to_vec());
+            Ok(Outcome::NewCoverage)
+        } else {
+            Ok(Outcome::Uninteresting)
+        }
+    }
+
+    /// Mutates inputs from the corpus `iterations` times, starting from the
+    /// empty input if the corpus is empty. Stops early at the first crash.
+    ///
+    /// # Errors
+    /// Errors upon failing to instantiate the program or to find its map.
+    pub fn fuzz(&mut self, iterations: usize, harness: &mut Harness<T>) -> anyhow::Result<()> {
+        if self.corpus.is_empty() {
+            self.run(&[], harness)?;
+        }
+        for _ in 0..iterations {
+            let input = match self.corpus.len() {
+                0 => Vec::new(),
+                len => self.corpus[self.rng.below(len)].clone(),
+            };
+            let input = self.mutate(input);
+            if self.run(&input, harness)? == Outcome::Crash {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn mutate(&mut self, mut input: Vec<u8>) -> Vec<u8> {
+        let rng = &mut self.rng;
+        let len = input.len();
+        if len == 0 {
+            if self.max_len > 0 {
+                input.push(rng.byte());
+            }
+            return input;
+        }
+        match rng.below(4) {
+            0 if len < self.max_len => input.insert(rng.below(len + 1), rng.byte()),
+            0 | 1 => input[rng.below(len)] ^= 1 << rng.below(8),
+            2 => input[rng.below(len)] = rng.byte(),
+            _ => {
+                input.remove(rng.below(len));
+            }
+        }
+        input
+    }
+}
+
+/// The bucket of a hit count as AFL has them: 1, 2, 3, 4-7, 8-15, 16-31,
+/// 32-127 and 128-255, so that loops iterating slightly more often are
+/// not all considered new.
+fn bucket(count: u8) -> u8 {
+    match count {
+        0 => 0,
+        1 => 1 << 0,
+        2 => 1 << 1,
+        3 => 1 << 2,
+        4..=7 => 1 << 3,
+        8..=15 => 1 << 4,
+        16..=31 => 1 << 5,
+        32..=127 => 1 << 6,
+        128..=255 => 1 << 7,
+    }
+}
+
+/// Deterministic, which keeps fuzz runs reproducible from their seed
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // The state must never be zero
+        Self(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        usize::try_from(self.next() % u64::try_from(bound).unwrap()).unwrap()
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.next().to_le_bytes()[0]
+    }
+}
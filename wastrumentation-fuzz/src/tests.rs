@@ -0,0 +1,62 @@
+use wasmtime::{Engine, Linker};
+
+use crate::{Fuzzer, Outcome};
+
+// Traps on inputs starting with "FUZ", one comparison per byte
+const MAGIC_BYTES_PROGRAM: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "check") (param $len i32)
+    (if (i32.ge_u (local.get $len) (i32.const 3))
+      (then
+        (if (i32.eq (i32.load8_u (i32.const 0)) (i32.const 70))
+          (then
+            (if (i32.eq (i32.load8_u (i32.const 1)) (i32.const 85))
+              (then
+                (if (i32.eq (i32.load8_u (i32.const 2)) (i32.const 90))
+                  (then unreachable))))))))))"#;
+
+fn check(
+    store: &mut wasmtime::Store<()>,
+    instance: &wasmtime::Instance,
+    input: &[u8],
+) -> anyhow::Result<()> {
+    let memory = instance.get_memory(&mut *store, "memory").unwrap();
+    memory.write(&mut *store, 0, input)?;
+    let check = instance.get_typed_func::<i32, ()>(&mut *store, "check")?;
+    check.call(&mut *store, i32::try_from(input.len())?)
+}
+
+#[test]
+fn test_new_coverage_joins_corpus() {
+    let program = wat::parse_str(MAGIC_BYTES_PROGRAM).unwrap();
+    let engine = Engine::default();
+    let mut fuzzer = Fuzzer::new(&engine, Linker::new(&engine), &program, 0).unwrap();
+
+    assert_eq!(fuzzer.run(b"", &mut check).unwrap(), Outcome::NewCoverage);
+    assert_eq!(fuzzer.run(b"", &mut check).unwrap(), Outcome::Uninteresting);
+    assert_eq!(
+        fuzzer.run(b"abc", &mut check).unwrap(),
+        Outcome::NewCoverage
+    );
+    assert_eq!(
+        fuzzer.run(b"Fbc", &mut check).unwrap(),
+        Outcome::NewCoverage
+    );
+    assert_eq!(fuzzer.run(b"FUZ", &mut check).unwrap(), Outcome::Crash);
+    assert_eq!(fuzzer.corpus().len(), 3);
+    assert_eq!(fuzzer.crashes(), [b"FUZ".to_vec()]);
+}
+
+#[test]
+fn test_finds_magic_bytes() {
+    let program = wat::parse_str(MAGIC_BYTES_PROGRAM).unwrap();
+    let engine = Engine::default();
+    let mut fuzzer = Fuzzer::new(&engine, Linker::new(&engine), &program, 42)
+        .unwrap()
+        .with_max_len(8);
+
+    fuzzer.fuzz(500_000, &mut check).unwrap();
+    let crash = &fuzzer.crashes()[0];
+    assert!(crash.starts_with(b"FUZ"));
+}
@@ -53,9 +53,11 @@ advice! { if_then_post (
 
 advice! { br (
         branch_target_label: BranchTargetLabel,
+        branch_target: BranchTarget,
         location: Location,
     ) {
         let _ = branch_target_label;
+        let _ = branch_target;
         let _ = location;
     }
 }
@@ -63,9 +65,11 @@ advice! { br (
 advice! { br_if (
         path_continuation : ParameterBrIfCondition,
         target_label : ParameterBrIfLabel,
+        branch_target : BranchTarget,
         location: Location,
     ) {
         let _ = target_label;
+        let _ = branch_target;
         let _ = location;
         path_continuation
     }
@@ -75,9 +79,11 @@ advice! { br_table (
         branch_table_target: BranchTableTarget,
         branch_table_effective: BranchTableEffective,
         branch_table_default: BranchTableDefault,
+        branch_target: BranchTarget,
         location: Location,
     ) {
         let _ = branch_table_effective;
+        let _ = branch_target;
         let _ = branch_table_default;
         let _ = location;
         branch_table_target
@@ -111,12 +117,38 @@ advice! { call post (
     }
 }
 
+advice! { call_values pre (
+        function: FunctionIndex,
+        args: MutDynArgs,
+        location: Location,
+    ) {
+        let _ = function;
+        let _ = args;
+        let _ = location;
+    }
+}
+
+advice! { call_values post (
+        function: FunctionIndex,
+        args: MutDynArgs,
+        results: MutDynResults,
+        location: Location,
+    ) {
+        let _ = function;
+        let _ = args;
+        let _ = results;
+        let _ = location;
+    }
+}
+
 advice! { call_indirect pre (
         target_func: FunctionTableIndex,
         func_table_ident: FunctionTable,
+        resolved: FunctionIndex,
         location: Location,
     ) {
         let _ = func_table_ident;
+        let _ = resolved;
         let _ = location;
         target_func
     }
@@ -124,9 +156,47 @@ advice! { call_indirect pre (
 
 advice! { call_indirect post (
         target_func: FunctionTable,
+        resolved: FunctionIndex,
         location: Location,
     ) {
         let _ = target_func;
+        let _ = resolved;
+        let _ = location;
+    }
+}
+
+advice! { function enter (
+        args: MutDynArgs,
+        location: Location,
+    ) {
+        let _ = args;
+        let _ = location;
+    }
+}
+
+advice! { function exit (
+        results: MutDynResults,
+        location: Location,
+    ) {
+        let _ = results;
+        let _ = location;
+    }
+}
+
+advice! { module init () {} }
+
+advice! { module finalize (
+        function: FunctionIndex,
+    ) {
+        let _ = function;
+    }
+}
+
+advice! { basic block (
+        block: BasicBlockIndex,
+        location: Location,
+    ) {
+        let _ = block;
         let _ = location;
     }
 }
@@ -166,6 +236,65 @@ advice! { return_ (
     }
 }
 
+advice! { return_values (
+        results: MutDynResults,
+        location: Location,
+    ) {
+        let _ = results;
+        let _ = location;
+    }
+}
+
+advice! { drop_value (
+        value: WasmValue,
+        location: Location,
+    ) {
+        let _ = value;
+        let _ = location;
+    }
+}
+
+advice! { unreachable (
+        location: Location,
+    ) {
+        let _ = location;
+    }
+}
+
+advice! { nop (
+        location: Location,
+    ) {
+        let _ = location;
+    }
+}
+
+advice! { ref_null (
+        ref_type: WasmType,
+        location: Location,
+    ) {
+        let _ = ref_type;
+        let _ = location;
+    }
+}
+
+advice! { ref_is_null (
+        is_null: ReferenceIsNull,
+        location: Location,
+    ) {
+        let _ = location;
+        is_null
+    }
+}
+
+advice! { ref_func (
+        function_index: FunctionIndex,
+        location: Location,
+    ) {
+        let _ = function_index;
+        let _ = location;
+    }
+}
+
 advice! { const_(
         value: WasmValue,
         location: Location,
@@ -205,10 +334,11 @@ advice! { load(
         store_index: LoadIndex,
         offset: LoadOffset,
         operation: LoadOperation,
+        memory_index: MemoryIndex,
         location: Location,
     ) {
         let _ = location;
-        operation.perform(&store_index, &offset)
+        operation.perform(&store_index, &offset, &memory_index)
     }
 }
 
@@ -217,10 +347,11 @@ advice! { store(
         value: WasmValue,
         offset: StoreOffset,
         operation: StoreOperation,
+        memory_index: MemoryIndex,
         location: Location,
     ) {
         let _ = location;
-        operation.perform(&store_index, &value, &offset);
+        operation.perform(&store_index, &value, &offset, &memory_index);
     }
 }
 
@@ -245,6 +376,153 @@ advice! { memory_grow (
     }
 }
 
+advice! { memory_fill (
+        destination: MemoryAddress,
+        value: WasmValue,
+        length: MemoryLength,
+        index: MemoryIndex,
+        location: Location,
+    ) {
+        let _ = location;
+        index.fill(&destination, &value, &length);
+    }
+}
+
+advice! { memory_copy (
+        destination: MemoryAddress,
+        source: MemoryAddress,
+        length: MemoryLength,
+        destination_index: MemoryIndex,
+        source_index: MemoryIndex,
+        location: Location,
+    ) {
+        let _ = location;
+        destination_index.copy(&destination, &source_index, &source, &length);
+    }
+}
+
+advice! { memory_init (
+        destination: MemoryAddress,
+        offset: DataOffset,
+        length: MemoryLength,
+        data_index: DataIndex,
+        index: MemoryIndex,
+        location: Location,
+    ) {
+        let _ = location;
+        index.init(&destination, &data_index, &offset, &length);
+    }
+}
+
+advice! { data_drop (
+        data_index: DataIndex,
+        location: Location,
+    ) {
+        let _ = location;
+        data_index.drop_segment();
+    }
+}
+
+advice! { table_get (
+        slot: TableSlot,
+        value: TableValue,
+        index: TableIndex,
+        location: Location,
+    ) {
+        let _ = slot;
+        let _ = index;
+        let _ = location;
+        value
+    }
+}
+
+advice! { table_set (
+        slot: TableSlot,
+        value: TableValue,
+        index: TableIndex,
+        location: Location,
+    ) {
+        let _ = value;
+        let _ = index;
+        let _ = location;
+        slot
+    }
+}
+
+advice! { table_size (
+        size: TableLength,
+        index: TableIndex,
+        location: Location,
+    ) {
+        let _ = index;
+        let _ = location;
+        size
+    }
+}
+
+advice! { table_grow (
+        init: TableValue,
+        delta: TableLength,
+        index: TableIndex,
+        location: Location,
+    ) {
+        let _ = init;
+        let _ = index;
+        let _ = location;
+        delta
+    }
+}
+
+advice! { table_fill (
+        slot: TableSlot,
+        value: TableValue,
+        length: TableLength,
+        index: TableIndex,
+        location: Location,
+    ) {
+        let _ = slot;
+        let _ = value;
+        let _ = length;
+        let _ = index;
+        let _ = location;
+    }
+}
+
+advice! { table_copy (
+        destination: TableSlot,
+        source: TableSlot,
+        length: TableLength,
+        destination_index: TableIndex,
+        source_index: TableIndex,
+        location: Location,
+    ) {
+        let _ = location;
+        destination_index.copy(&destination, &source_index, &source, &length);
+    }
+}
+
+advice! { table_init (
+        destination: TableSlot,
+        offset: ElementOffset,
+        length: TableLength,
+        element_index: ElementIndex,
+        index: TableIndex,
+        location: Location,
+    ) {
+        let _ = location;
+        index.init(&destination, &element_index, &offset, &length);
+    }
+}
+
+advice! { elem_drop (
+        element_index: ElementIndex,
+        location: Location,
+    ) {
+        let _ = location;
+        element_index.drop_segment();
+    }
+}
+
 advice! { block pre (
         block_input_count: BlockInputCount,
         block_arity: BlockArity,
@@ -280,3 +558,12 @@ advice! { loop_ post (
         let _ = location;
     }
 }
+
+advice! { loop_ iteration (
+        iteration: LoopIteration,
+        location: Location,
+    ) {
+        let _ = iteration;
+        let _ = location;
+    }
+}
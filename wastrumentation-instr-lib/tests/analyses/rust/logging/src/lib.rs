@@ -49,18 +49,20 @@ advice! { if_then_post (
 
 advice! { br (
         branch_target_label: BranchTargetLabel,
+        branch_target: BranchTarget,
         location: Location,
     ) {
-        println!("[ANALYSIS:] br {branch_target_label:#?}, location: {location:?}");
+        println!("[ANALYSIS:] br {branch_target_label:#?} to {branch_target:?}, location: {location:?}");
     }
 }
 
 advice! { br_if (
         path_continuation : ParameterBrIfCondition,
         target_label : ParameterBrIfLabel,
+        branch_target : BranchTarget,
         location: Location,
     ) {
-        println!("[ANALYSIS:] br_if {path_continuation:#?} to {target_label:#?}, location: {location:?}");
+        println!("[ANALYSIS:] br_if {path_continuation:#?} to {target_label:#?} ({branch_target:?}), location: {location:?}");
         path_continuation
     }
 }
@@ -69,9 +71,10 @@ advice! { br_table (
         branch_table_target: BranchTableTarget,
         branch_table_effective: BranchTableEffective,
         branch_table_default: BranchTableDefault,
+        branch_target: BranchTarget,
         location: Location,
     ) {
-        println!("[ANALYSIS:] br_table {branch_table_target:#?} (effective: {branch_table_effective:#?}) (default: {branch_table_default:#?}), location: {location:?}");
+        println!("[ANALYSIS:] br_table {branch_table_target:#?} (effective: {branch_table_effective:#?}) (default: {branch_table_default:#?}) to {branch_target:?}, location: {location:?}");
         branch_table_target
     }
 }
@@ -101,21 +104,78 @@ advice! { call post (
     }
 }
 
+advice! { call_values pre (
+        function: FunctionIndex,
+        args: MutDynArgs,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] call values pre {function:#?}, args: {args:?}, location: {location:?}");
+    }
+}
+
+advice! { call_values post (
+        function: FunctionIndex,
+        args: MutDynArgs,
+        results: MutDynResults,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] call values post {function:#?}, args: {args:?}, results: {results:?}, location: {location:?}");
+    }
+}
+
 advice! { call_indirect pre (
         target_func: FunctionTableIndex,
         func_table_ident: FunctionTable,
+        resolved: FunctionIndex,
         location: Location,
     ) {
-        println!("[ANALYSIS:] call_indirect pre {target_func:#?} {func_table_ident:#?}, location: {location:?}");
+        println!("[ANALYSIS:] call_indirect pre {target_func:#?} {func_table_ident:#?} {resolved:#?}, location: {location:?}");
         target_func
     }
 }
 
 advice! { call_indirect post (
         target_func: FunctionTable,
+        resolved: FunctionIndex,
         location: Location,
     ) {
-        println!("[ANALYSIS:] call_indirect post {target_func:#?}, location: {location:?}");
+        println!("[ANALYSIS:] call_indirect post {target_func:#?} {resolved:#?}, location: {location:?}");
+    }
+}
+
+advice! { function enter (
+        args: MutDynArgs,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] function enter {args:?}, location: {location:?}");
+    }
+}
+
+advice! { function exit (
+        results: MutDynResults,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] function exit {results:?}, location: {location:?}");
+    }
+}
+
+advice! { module init () {
+        println!("[ANALYSIS:] module init");
+    }
+}
+
+advice! { module finalize (
+        function: FunctionIndex,
+    ) {
+        println!("[ANALYSIS:] module finalize {function:#?}");
+    }
+}
+
+advice! { basic block (
+        block: BasicBlockIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] basic block {block:#?}, location: {location:?}");
     }
 }
 
@@ -154,6 +214,61 @@ advice! { return_ (
     }
 }
 
+advice! { return_values (
+        results: MutDynResults,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] return values {results:?}, location: {location:?}");
+    }
+}
+
+advice! { drop_value (
+        value: WasmValue,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] drop value {value:?}, location: {location:?}");
+    }
+}
+
+advice! { unreachable (
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] Unreachable called! location: {location:?}");
+    }
+}
+
+advice! { nop (
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] Nop called! location: {location:?}");
+    }
+}
+
+advice! { ref_null (
+        ref_type: WasmType,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] ref_null {ref_type:?}, location: {location:?}");
+    }
+}
+
+advice! { ref_is_null (
+        is_null: ReferenceIsNull,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] ref_is_null {is_null:#?}, location: {location:?}");
+        is_null
+    }
+}
+
+advice! { ref_func (
+        function_index: FunctionIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] ref_func {function_index:#?}, location: {location:?}");
+    }
+}
+
 advice! { const_ (
         value: WasmValue,
         location: Location,
@@ -189,9 +304,10 @@ advice! { load (
         store_index: LoadIndex,
         offset: LoadOffset,
         operation: LoadOperation,
+        memory_index: MemoryIndex,
         location: Location,
     ) {
-        let value = operation.perform(&store_index, &offset);
+        let value = operation.perform(&store_index, &offset, &memory_index);
         println!("[ANALYSIS:] load generic {operation:#?} @ (CONST {offset:#?} + {store_index:#?}) -> {value:#?}, location: {location:?}");
         value
     }
@@ -202,10 +318,11 @@ advice! { store (
         value: WasmValue,
         offset: StoreOffset,
         operation: StoreOperation,
+        memory_index: MemoryIndex,
         location: Location,
     ) {
         println!("[ANALYSIS:] store generic {operation:#?} @ (CONST {offset:#?} + {store_index:#?}) <- {value:#?}, location: {location:?}");
-        operation.perform(&store_index, &value, &offset);
+        operation.perform(&store_index, &value, &offset, &memory_index);
     }
 }
 
@@ -229,6 +346,142 @@ advice! { memory_grow (
     }
 }
 
+advice! { memory_fill (
+        destination: MemoryAddress,
+        value: WasmValue,
+        length: MemoryLength,
+        index: MemoryIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] memory_fill {value:#?} @ ({destination:#?}, {length:#?}) @ {index:#?}, location: {location:?}");
+        index.fill(&destination, &value, &length);
+    }
+}
+
+advice! { memory_copy (
+        destination: MemoryAddress,
+        source: MemoryAddress,
+        length: MemoryLength,
+        destination_index: MemoryIndex,
+        source_index: MemoryIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] memory_copy ({source:#?} @ {source_index:#?}) -> ({destination:#?} @ {destination_index:#?}), {length:#?}, location: {location:?}");
+        destination_index.copy(&destination, &source_index, &source, &length);
+    }
+}
+
+advice! { memory_init (
+        destination: MemoryAddress,
+        offset: DataOffset,
+        length: MemoryLength,
+        data_index: DataIndex,
+        index: MemoryIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] memory_init ({offset:#?} @ {data_index:#?}) -> ({destination:#?} @ {index:#?}), {length:#?}, location: {location:?}");
+        index.init(&destination, &data_index, &offset, &length);
+    }
+}
+
+advice! { data_drop (
+        data_index: DataIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] data_drop {data_index:#?}, location: {location:?}");
+        data_index.drop_segment();
+    }
+}
+
+advice! { table_get (
+        slot: TableSlot,
+        value: TableValue,
+        index: TableIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] table_get {slot:#?} -> {value:#?} @ {index:#?}, location: {location:?}");
+        value
+    }
+}
+
+advice! { table_set (
+        slot: TableSlot,
+        value: TableValue,
+        index: TableIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] table_set {slot:#?} <- {value:#?} @ {index:#?}, location: {location:?}");
+        slot
+    }
+}
+
+advice! { table_size (
+        size: TableLength,
+        index: TableIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] table_size {size:#?} @ {index:#?}, location: {location:?}");
+        size
+    }
+}
+
+advice! { table_grow (
+        init: TableValue,
+        delta: TableLength,
+        index: TableIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] table_grow ({init:#?}, {delta:#?}) @ {index:#?}, location: {location:?}");
+        delta
+    }
+}
+
+advice! { table_fill (
+        slot: TableSlot,
+        value: TableValue,
+        length: TableLength,
+        index: TableIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] table_fill ({slot:#?}, {value:#?}, {length:#?}) @ {index:#?}, location: {location:?}");
+    }
+}
+
+advice! { table_copy (
+        destination: TableSlot,
+        source: TableSlot,
+        length: TableLength,
+        destination_index: TableIndex,
+        source_index: TableIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] table_copy ({source:#?} @ {source_index:#?}) -> ({destination:#?} @ {destination_index:#?}), {length:#?}, location: {location:?}");
+        destination_index.copy(&destination, &source_index, &source, &length);
+    }
+}
+
+advice! { table_init (
+        destination: TableSlot,
+        offset: ElementOffset,
+        length: TableLength,
+        element_index: ElementIndex,
+        index: TableIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] table_init ({offset:#?} @ {element_index:#?}) -> ({destination:#?} @ {index:#?}), {length:#?}, location: {location:?}");
+        index.init(&destination, &element_index, &offset, &length);
+    }
+}
+
+advice! { elem_drop (
+        element_index: ElementIndex,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] elem_drop {element_index:#?}, location: {location:?}");
+        element_index.drop_segment();
+    }
+}
+
 advice! { block pre (
         block_input_count: BlockInputCount,
         block_arity: BlockArity,
@@ -260,3 +513,11 @@ advice! { loop_ post (
         println!("[ANALYSIS:] loop_ post, location: {location:?}");
     }
 }
+
+advice! { loop_ iteration (
+        iteration: LoopIteration,
+        location: Location,
+    ) {
+        println!("[ANALYSIS:] loop_ iteration {iteration:?}, location: {location:?}");
+    }
+}
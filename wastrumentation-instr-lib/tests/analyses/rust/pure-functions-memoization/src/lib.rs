@@ -33,6 +33,9 @@ fn to_bit_pattern(value: WasmValue) -> BitPattern {
         WasmValue::F32(v) => pattern[..4].copy_from_slice(&v.to_le_bytes()),
         WasmValue::I64(v) => pattern[..].copy_from_slice(&v.to_le_bytes()),
         WasmValue::F64(v) => pattern[..].copy_from_slice(&v.to_le_bytes()),
+        WasmValue::FuncRef(v) | WasmValue::ExternRef(v) => {
+            pattern[..4].copy_from_slice(&v.to_le_bytes())
+        }
     }
     pattern
 }
@@ -0,0 +1,39 @@
+/// The construct that introduces the label a branch targets
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum BranchTargetKind {
+    Block,
+    Loop,
+    If,
+    /// The body of the function, branching to it returns
+    Function,
+}
+
+/// Where a branch continues, resolved from its relative label
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct BranchTarget {
+    instr_index: i64,
+    kind: BranchTargetKind,
+}
+
+impl BranchTarget {
+    pub fn new(instr_index: i64, kind: i32) -> Self {
+        let kind = match kind {
+            0 => BranchTargetKind::Block,
+            1 => BranchTargetKind::Loop,
+            2 => BranchTargetKind::If,
+            3 => BranchTargetKind::Function,
+            _ => panic!("Unknown branch target kind {kind}"),
+        };
+        Self { instr_index, kind }
+    }
+
+    /// The index of the `block`, `loop` or `if` that introduces the label,
+    /// `None` for the label of the function body.
+    pub fn instruction_index(&self) -> Option<i64> {
+        (self.kind != BranchTargetKind::Function).then_some(self.instr_index)
+    }
+
+    pub fn kind(&self) -> BranchTargetKind {
+        self.kind
+    }
+}
@@ -14,10 +14,16 @@ pub use binary::BinaryOperator;
 
 mod memory;
 pub use memory::{
-    base_memory_size, Deserialize, LoadIndex, LoadOffset, LoadOperation, MemoryIndex, StoreIndex,
-    StoreOffset, StoreOperation,
+    base_memory_size, DataIndex, DataOffset, Deserialize, LoadIndex, LoadOffset, LoadOperation,
+    MemoryAddress, MemoryIndex, MemoryLength, StoreIndex, StoreOffset, StoreOperation,
 };
 
+mod table;
+pub use table::{ElementIndex, ElementOffset, TableIndex, TableLength, TableSlot, TableValue};
+
+mod branch;
+pub use branch::{BranchTarget, BranchTargetKind};
+
 extern crate alloc;
 use alloc::vec::Vec;
 use core::mem::size_of;
@@ -35,33 +41,72 @@ extern "C" {
     // Base apply
     fn call_base(f_apply: i32, sigv: i32);
     // Base load
-    fn instrumented_base_load_i32(ptr: i32, offset: i32) -> i32;
-    fn instrumented_base_load_i64(ptr: i32, offset: i32) -> i64;
-    fn instrumented_base_load_f32(ptr: i32, offset: i32) -> f32;
-    fn instrumented_base_load_f64(ptr: i32, offset: i32) -> f64;
-    fn instrumented_base_load_i32_8S(ptr: i32, offset: i32) -> i32;
-    fn instrumented_base_load_i32_8U(ptr: i32, offset: i32) -> i32;
-    fn instrumented_base_load_i32_16S(ptr: i32, offset: i32) -> i32;
-    fn instrumented_base_load_i32_16U(ptr: i32, offset: i32) -> i32;
-    fn instrumented_base_load_i64_8S(ptr: i32, offset: i32) -> i64;
-    fn instrumented_base_load_i64_8U(ptr: i32, offset: i32) -> i64;
-    fn instrumented_base_load_i64_16S(ptr: i32, offset: i32) -> i64;
-    fn instrumented_base_load_i64_16U(ptr: i32, offset: i32) -> i64;
-    fn instrumented_base_load_i64_32S(ptr: i32, offset: i32) -> i64;
-    fn instrumented_base_load_i64_32U(ptr: i32, offset: i32) -> i64;
+    fn instrumented_base_load_i32(ptr: i32, offset: i32, memory: i32) -> i32;
+    fn instrumented_base_load_i64(ptr: i32, offset: i32, memory: i32) -> i64;
+    fn instrumented_base_load_f32(ptr: i32, offset: i32, memory: i32) -> f32;
+    fn instrumented_base_load_f64(ptr: i32, offset: i32, memory: i32) -> f64;
+    fn instrumented_base_load_i32_8S(ptr: i32, offset: i32, memory: i32) -> i32;
+    fn instrumented_base_load_i32_8U(ptr: i32, offset: i32, memory: i32) -> i32;
+    fn instrumented_base_load_i32_16S(ptr: i32, offset: i32, memory: i32) -> i32;
+    fn instrumented_base_load_i32_16U(ptr: i32, offset: i32, memory: i32) -> i32;
+    fn instrumented_base_load_i64_8S(ptr: i32, offset: i32, memory: i32) -> i64;
+    fn instrumented_base_load_i64_8U(ptr: i32, offset: i32, memory: i32) -> i64;
+    fn instrumented_base_load_i64_16S(ptr: i32, offset: i32, memory: i32) -> i64;
+    fn instrumented_base_load_i64_16U(ptr: i32, offset: i32, memory: i32) -> i64;
+    fn instrumented_base_load_i64_32S(ptr: i32, offset: i32, memory: i32) -> i64;
+    fn instrumented_base_load_i64_32U(ptr: i32, offset: i32, memory: i32) -> i64;
     // Base store
-    fn instrumented_base_store_i32(ptr: i32, value: i32, offset: i32);
-    fn instrumented_base_store_i64(ptr: i32, value: i64, offset: i32);
-    fn instrumented_base_store_f32(ptr: i32, value: f32, offset: i32);
-    fn instrumented_base_store_f64(ptr: i32, value: f64, offset: i32);
-    fn instrumented_base_store_i32_8(ptr: i32, value: i32, offset: i32);
-    fn instrumented_base_store_i32_16(ptr: i32, value: i32, offset: i32);
-    fn instrumented_base_store_i64_8(ptr: i32, value: i64, offset: i32);
-    fn instrumented_base_store_i64_16(ptr: i32, value: i64, offset: i32);
-    fn instrumented_base_store_i64_32(ptr: i32, value: i64, offset: i32);
+    fn instrumented_base_store_i32(ptr: i32, value: i32, offset: i32, memory: i32);
+    fn instrumented_base_store_i64(ptr: i32, value: i64, offset: i32, memory: i32);
+    fn instrumented_base_store_f32(ptr: i32, value: f32, offset: i32, memory: i32);
+    fn instrumented_base_store_f64(ptr: i32, value: f64, offset: i32, memory: i32);
+    fn instrumented_base_store_i32_8(ptr: i32, value: i32, offset: i32, memory: i32);
+    fn instrumented_base_store_i32_16(ptr: i32, value: i32, offset: i32, memory: i32);
+    fn instrumented_base_store_i64_8(ptr: i32, value: i64, offset: i32, memory: i32);
+    fn instrumented_base_store_i64_16(ptr: i32, value: i64, offset: i32, memory: i32);
+    fn instrumented_base_store_i64_32(ptr: i32, value: i64, offset: i32, memory: i32);
     // Base memory grow
     fn instrumented_memory_grow(amount: i32, idx: i32) -> i32;
     fn instrumented_memory_size(idx: i32) -> i32;
+    // Base bulk memory
+    fn instrumented_base_memory_fill(destination: i32, value: i32, length: i32, memory: i32);
+    fn instrumented_base_memory_copy(
+        destination: i32,
+        source: i32,
+        length: i32,
+        destination_memory: i32,
+        source_memory: i32,
+    );
+    fn instrumented_base_memory_init(
+        destination: i32,
+        offset: i32,
+        length: i32,
+        data: i32,
+        memory: i32,
+    );
+    fn instrumented_base_data_drop(data: i32);
+    // Base bulk table
+    fn instrumented_base_table_copy(
+        destination: i32,
+        source: i32,
+        length: i32,
+        destination_table: i32,
+        source_table: i32,
+    );
+    fn instrumented_base_table_init(
+        destination: i32,
+        offset: i32,
+        length: i32,
+        element: i32,
+        table: i32,
+    );
+    fn instrumented_base_elem_drop(element: i32);
+    // Source locations, only exported when instrumenting with source locations
+    fn instrumented_source_offset(funct_index: i32, instr_index: i32) -> i32;
+    fn instrumented_source_line(funct_index: i32, instr_index: i32) -> i32;
+    fn instrumented_source_column(funct_index: i32, instr_index: i32) -> i32;
+    // Site ids, only exported when instrumenting with site ids
+    fn instrumented_site_id(funct_index: i32, instr_index: i32) -> i64;
 }
 
 #[link(wasm_import_module = "wastrumentation_stack")]
@@ -113,12 +158,44 @@ impl Location {
     pub fn function_index(&self) -> i64 {
         self.funct_index
     }
+
+    /// The byte offset of the instruction from the start of the code section
+    /// of the input program. The program must have been instrumented with
+    /// source locations, the file of a location is in its side table.
+    pub fn code_offset(&self) -> Option<u32> {
+        let (funct_index, instr_index) = (self.funct_index as i32, self.instr_index as i32);
+        u32::try_from(unsafe { instrumented_source_offset(funct_index, instr_index) }).ok()
+    }
+
+    /// The line of the instruction, if the input program carries DWARF.
+    pub fn source_line(&self) -> Option<u32> {
+        let (funct_index, instr_index) = (self.funct_index as i32, self.instr_index as i32);
+        u32::try_from(unsafe { instrumented_source_line(funct_index, instr_index) }).ok()
+    }
+
+    /// The column of the instruction, if the input program carries DWARF.
+    pub fn source_column(&self) -> Option<u32> {
+        let (funct_index, instr_index) = (self.funct_index as i32, self.instr_index as i32);
+        u32::try_from(unsafe { instrumented_source_column(funct_index, instr_index) }).ok()
+    }
+
+    /// An id of the instruction that, unlike its index, survives changes to
+    /// the input program elsewhere. The program must have been instrumented
+    /// with site ids, their paths are in its side table.
+    pub fn site_id(&self) -> Option<u64> {
+        let (funct_index, instr_index) = (self.funct_index as i32, self.instr_index as i32);
+        u64::try_from(unsafe { instrumented_site_id(funct_index, instr_index) }).ok()
+    }
 }
 
 const TYPE_I32: i32 = 0;
 const TYPE_F32: i32 = 1;
 const TYPE_I64: i32 = 2;
 const TYPE_F64: i32 = 3;
+const TYPE_FUNC_REF: i32 = 4;
+const TYPE_EXTERN_REF: i32 = 5;
+
+const NULL_REF_HANDLE: i32 = 0;
 
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub enum WasmType {
@@ -126,6 +203,8 @@ pub enum WasmType {
     F32,
     I64,
     F64,
+    FuncRef,
+    ExternRef,
 }
 
 // TODO: can I wrap this i32 into MaterializedWasmType to ensure type safety?
@@ -136,6 +215,8 @@ impl From<&i32> for WasmType {
             TYPE_F32 => Self::F32,
             TYPE_I64 => Self::I64,
             TYPE_F64 => Self::F64,
+            TYPE_FUNC_REF => Self::FuncRef,
+            TYPE_EXTERN_REF => Self::ExternRef,
             _ => panic!(),
         }
     }
@@ -148,6 +229,7 @@ impl WasmType {
             WasmType::F32 => size_of::<f32>(),
             WasmType::I64 => size_of::<i64>(),
             WasmType::F64 => size_of::<f64>(),
+            WasmType::FuncRef | WasmType::ExternRef => size_of::<i32>(),
         }
     }
 
@@ -174,6 +256,14 @@ impl WasmType {
                 let res = wastrumentation_stack_load_f64(ptr, offset);
                 WasmValue::F64(res)
             },
+            WasmType::FuncRef => unsafe {
+                let res = wastrumentation_stack_load_i32(ptr, offset);
+                WasmValue::FuncRef(res)
+            },
+            WasmType::ExternRef => unsafe {
+                let res = wastrumentation_stack_load_i32(ptr, offset);
+                WasmValue::ExternRef(res)
+            },
         }
     }
 }
@@ -184,6 +274,12 @@ pub enum WasmValue {
     F32(f32),
     I64(i64),
     F64(f64),
+    /// A handle to a reference, valid for the duration of the observed call.
+    /// Handle `0` stands for `ref.null`.
+    FuncRef(i32),
+    /// A handle to a reference, valid for the duration of the observed call.
+    /// Handle `0` stands for `ref.null`.
+    ExternRef(i32),
 }
 
 impl PartialEq for WasmValue {
@@ -193,6 +289,8 @@ impl PartialEq for WasmValue {
             (Self::F32(l), Self::F32(r)) => l.to_be_bytes() == r.to_be_bytes(),
             (Self::I64(l), Self::I64(r)) => l == r,
             (Self::F64(l), Self::F64(r)) => l.to_be_bytes() == r.to_be_bytes(),
+            (Self::FuncRef(l), Self::FuncRef(r)) => l == r,
+            (Self::ExternRef(l), Self::ExternRef(r)) => l == r,
             _ => false,
         }
     }
@@ -238,6 +336,9 @@ impl WasmValue {
             WasmValue::F64(value) => unsafe {
                 wastrumentation_stack_store_f64(ptr, *value, offset)
             },
+            WasmValue::FuncRef(handle) | WasmValue::ExternRef(handle) => unsafe {
+                wastrumentation_stack_store_i32(ptr, *handle, offset)
+            },
         }
     }
 
@@ -275,6 +376,8 @@ impl WasmValue {
             WasmValue::F32(_) => WasmType::F32,
             WasmValue::I64(_) => WasmType::I64,
             WasmValue::F64(_) => WasmType::F64,
+            WasmValue::FuncRef(_) => WasmType::FuncRef,
+            WasmValue::ExternRef(_) => WasmType::ExternRef,
         }
     }
 
@@ -293,6 +396,8 @@ impl WasmValue {
             WasmType::I64 => Self::I64(0),
             WasmType::F32 => Self::F32(0.0),
             WasmType::F64 => Self::F64(0.0),
+            WasmType::FuncRef => Self::FuncRef(NULL_REF_HANDLE),
+            WasmType::ExternRef => Self::ExternRef(NULL_REF_HANDLE),
         }
     }
 
@@ -303,6 +408,7 @@ impl WasmValue {
             WasmValue::F32(v) => v.to_le_bytes().to_vec(),
             WasmValue::I64(v) => v.to_le_bytes().to_vec(),
             WasmValue::F64(v) => v.to_le_bytes().to_vec(),
+            WasmValue::FuncRef(v) | WasmValue::ExternRef(v) => v.to_le_bytes().to_vec(),
         }
         .into_iter()
         .map(|v| format!("{v}"))
@@ -558,6 +664,7 @@ where
 }
 
 generate_wrapper!(FunctionIndex          wrapping i32 accessed-using .value());
+generate_wrapper!(BasicBlockIndex        wrapping i32 accessed-using .value());
 generate_wrapper!(FunctionTableIndex     wrapping i32 accessed-using .value());
 generate_wrapper!(FunctionTable          wrapping i32 accessed-using .value());
 generate_wrapper!(PathContinuation       wrapping i32 accessed-using .value());
@@ -576,7 +683,9 @@ generate_wrapper!(BlockArity             wrapping i32 accessed-using .value());
 generate_wrapper!(BlockInputCount        wrapping i32 accessed-using .value());
 generate_wrapper!(LoopArity              wrapping i32 accessed-using .value());
 generate_wrapper!(LoopInputCount         wrapping i32 accessed-using .value());
+generate_wrapper!(LoopIteration          wrapping i64 accessed-using .value());
 generate_wrapper!(GlobalIndex            wrapping i64 accessed-using .value());
+generate_wrapper!(ReferenceIsNull        wrapping i32 accessed-using .value());
 
 impl SerializedContinuation for PathContinuation {
     fn low_level_continuation(&self) -> &i32 {
@@ -615,6 +724,135 @@ pub enum GlobalOp {
 
 #[macro_export]
 macro_rules! advice {
+    (function enter
+        (
+            $args_ident: ident: MutDynArgs,
+            $location_ident: ident: Location $(,)?
+        ) $body:block
+    ) => {
+        #[no_mangle]
+        pub extern "C"
+        fn trap_function_enter (
+            argc: i32,
+            argv: i32,
+            argtypv: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let mut $args_ident = MutDynArgs::new(argc, 0, argv, argtypv);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body
+        }
+    };
+    (function exit
+        (
+            $results_ident: ident: MutDynResults,
+            $location_ident: ident: Location $(,)?
+        ) $body:block
+    ) => {
+        #[no_mangle]
+        pub extern "C"
+        fn trap_function_exit (
+            resc: i32,
+            resv: i32,
+            restypv: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let mut $results_ident = MutDynResults::new(0, resc, resv, restypv);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body
+        }
+    };
+    (module init () $body:block) => {
+        #[no_mangle]
+        pub extern "C"
+        fn trap_module_init () {
+            $body
+        }
+    };
+    (module finalize
+        (
+            $func_ident: ident: FunctionIndex $(,)?
+        ) $body:block
+    ) => {
+        #[no_mangle]
+        pub extern "C"
+        fn trap_module_finalize (
+            func_ident: i32,
+        ) {
+            let $func_ident = FunctionIndex(func_ident);
+            $body
+        }
+    };
+    (basic block
+        (
+            $block_ident: ident: BasicBlockIndex,
+            $location_ident: ident: Location $(,)?
+        ) $body:block
+    ) => {
+        #[no_mangle]
+        pub extern "C"
+        fn trap_basic_block (
+            block_ident: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $block_ident = BasicBlockIndex(block_ident);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body
+        }
+    };
+    (call_values pre
+        (
+            $func_ident: ident: FunctionIndex,
+            $args_ident: ident: MutDynArgs,
+            $location_ident: ident: Location $(,)?
+        ) $body:block
+    ) => {
+        #[no_mangle]
+        pub extern "C"
+        fn trap_call_values_pre (
+            func_ident: i32,
+            argc: i32,
+            resc: i32,
+            sigv: i32,
+            sigtypv: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $func_ident = FunctionIndex(func_ident);
+            let mut $args_ident = MutDynArgs::new(argc, resc, sigv, sigtypv);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body
+        }
+    };
+    (call_values post
+        (
+            $func_ident: ident: FunctionIndex,
+            $args_ident: ident: MutDynArgs,
+            $ress_ident: ident: MutDynResults,
+            $location_ident: ident: Location $(,)?
+        ) $body:block
+    ) => {
+        #[no_mangle]
+        pub extern "C"
+        fn trap_call_values_post (
+            func_ident: i32,
+            argc: i32,
+            resc: i32,
+            sigv: i32,
+            sigtypv: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $func_ident = FunctionIndex(func_ident);
+            let $args_ident = MutDynArgs::new(argc, resc, sigv, sigtypv);
+            let mut $ress_ident = MutDynResults::new(argc, resc, sigv, sigtypv);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body
+        }
+    };
     (call pre
         (
             $func_ident: ident: FunctionIndex,
@@ -650,6 +888,32 @@ macro_rules! advice {
             $body
         }
     };
+    (call_indirect pre
+        (
+            $func_table_index_ident: ident: FunctionTableIndex,
+            $func_table_ident: ident: FunctionTable,
+            $resolved_ident: ident: FunctionIndex,
+            $location_ident: ident: Location $(,)?
+        ) $body:block
+    ) => {
+        #[no_mangle]
+        pub extern "C"
+        fn specialized_call_indirect_pre (
+            function_table_index: i32,
+            function_table: i32,
+            resolved_function: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) -> i32 {
+            let $func_table_index_ident = FunctionTableIndex(function_table_index);
+            let $func_table_ident = FunctionTable(function_table);
+            // `-1` when the slot is null, of another type, or cannot be resolved
+            let $resolved_ident = FunctionIndex(resolved_function);
+            let $location_ident = Location::new(funct_index, instr_index);
+            let FunctionTableIndex(final_index) = $body;
+            final_index
+        }
+    };
     (call_indirect pre
         (
             $func_table_index_ident: ident: FunctionTableIndex,
@@ -662,6 +926,7 @@ macro_rules! advice {
         fn specialized_call_indirect_pre (
             function_table_index: i32,
             function_table: i32,
+            _resolved_function: i32,
             funct_index: i64,
             instr_index: i64,
         ) -> i32 {
@@ -672,6 +937,28 @@ macro_rules! advice {
             final_index
         }
     };
+    (call_indirect post
+        (
+            $func_table_ident: ident: FunctionTable,
+            $resolved_ident: ident: FunctionIndex,
+            $location_ident: ident: Location $(,)?
+        ) $body:block
+    ) => {
+        #[no_mangle]
+        pub extern "C"
+        fn specialized_call_indirect_post (
+            function_table: i32,
+            resolved_function: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $func_table_ident = FunctionTable(function_table);
+            // `-1` when the slot is null, of another type, or cannot be resolved
+            let $resolved_ident = FunctionIndex(resolved_function);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body
+        }
+    };
     (call_indirect post
         (
             $func_table_ident: ident: FunctionTable,
@@ -682,6 +969,7 @@ macro_rules! advice {
         pub extern "C"
         fn specialized_call_indirect_post (
             function_table: i32,
+            _resolved_function: i32,
             funct_index: i64,
             instr_index: i64,
         ) {
@@ -717,6 +1005,7 @@ macro_rules! advice {
     (br
         (
             $target_label: ident: BranchTargetLabel,
+            $branch_target: ident: BranchTarget,
             $location_ident: ident: Location $(,)?
         ) $body:block
     ) => {
@@ -724,6 +1013,29 @@ macro_rules! advice {
         pub extern "C"
         fn specialized_br (
             low_level_label: i64,
+            target_instr_index: i64,
+            target_kind: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $target_label = BranchTargetLabel(low_level_label);
+            let $branch_target = BranchTarget::new(target_instr_index, target_kind);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body
+        }
+    };
+    (br
+        (
+            $target_label: ident: BranchTargetLabel,
+            $location_ident: ident: Location $(,)?
+        ) $body:block
+    ) => {
+        #[no_mangle]
+        pub extern "C"
+        fn specialized_br (
+            low_level_label: i64,
+            _target_instr_index: i64,
+            _target_kind: i32,
             funct_index: i64,
             instr_index: i64,
         ) {
@@ -809,6 +1121,7 @@ macro_rules! advice {
         (
             $path_continuation: ident: ParameterBrIfCondition,
             $target_label: ident: ParameterBrIfLabel,
+            $branch_target: ident: BranchTarget,
             $location_ident: ident: Location $(,)?
         ) $body:block
     ) => {
@@ -817,6 +1130,33 @@ macro_rules! advice {
         fn specialized_br_if (
             path_continuation: i32,
             low_level_label: i32,
+            target_instr_index: i64,
+            target_kind: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) -> i32 {
+            let $path_continuation = ParameterBrIfCondition(path_continuation);
+            let $target_label = ParameterBrIfLabel(low_level_label);
+            let $branch_target = BranchTarget::new(target_instr_index, target_kind);
+            let $location_ident = Location::new(funct_index, instr_index);
+            let ParameterBrIfCondition(path_continuation) = $body;
+            path_continuation
+        }
+    };
+    (br_if
+        (
+            $path_continuation: ident: ParameterBrIfCondition,
+            $target_label: ident: ParameterBrIfLabel,
+            $location_ident: ident: Location $(,)?
+        ) $body:block
+    ) => {
+        #[no_mangle]
+        pub extern "C"
+        fn specialized_br_if (
+            path_continuation: i32,
+            low_level_label: i32,
+            _target_instr_index: i64,
+            _target_kind: i32,
             funct_index: i64,
             instr_index: i64,
         ) -> i32 {
@@ -832,6 +1172,7 @@ macro_rules! advice {
             $branch_table_target: ident: BranchTableTarget,
             $branch_table_effective: ident: BranchTableEffective,
             $branch_table_default: ident: BranchTableDefault,
+            $branch_target: ident: BranchTarget,
             $location_ident: ident: Location $(,)?
         ) $body:block
     ) => {
@@ -841,6 +1182,37 @@ macro_rules! advice {
             br_table_target: i32,
             effective_label: i32,
             br_table_default: i32,
+            target_instr_index: i64,
+            target_kind: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) -> i32 {
+            let $branch_table_target = BranchTableTarget(br_table_target);
+            let $branch_table_effective = BranchTableEffective(effective_label);
+            let $branch_table_default = BranchTableDefault(br_table_default);
+            // Resolved from the effective label, before the analysis may change the target
+            let $branch_target = BranchTarget::new(target_instr_index, target_kind);
+            let $location_ident = Location::new(funct_index, instr_index);
+            let BranchTableTarget(br_table_target) = $body;
+            br_table_target
+        }
+    };
+    (br_table
+        (
+            $branch_table_target: ident: BranchTableTarget,
+            $branch_table_effective: ident: BranchTableEffective,
+            $branch_table_default: ident: BranchTableDefault,
+            $location_ident: ident: Location $(,)?
+        ) $body:block
+    ) => {
+        #[no_mangle]
+        pub extern "C"
+        fn specialized_br_table (
+            br_table_target: i32,
+            effective_label: i32,
+            br_table_default: i32,
+            _target_instr_index: i64,
+            _target_kind: i32,
             funct_index: i64,
             instr_index: i64,
         ) -> i32 {
@@ -1019,6 +1391,124 @@ macro_rules! advice {
             $body
         }
     };
+    (return_values (
+        $results_ident: ident: MutDynResults,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_return_values(
+            resc: i32,
+            resv: i32,
+            restypv: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let mut $results_ident = MutDynResults::new(0, resc, resv, restypv);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body
+        }
+    };
+    (drop_value (
+        $value: ident: WasmValue,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        fn generic_drop_trap(value: WasmValue, location: Location) {
+            let $value = value;
+            let $location_ident = location;
+            $body
+        }
+        advice!(drop_value specific @for generic_drop_trap trap_drop_i32 i32 I32);
+        advice!(drop_value specific @for generic_drop_trap trap_drop_f32 f32 F32);
+        advice!(drop_value specific @for generic_drop_trap trap_drop_i64 i64 I64);
+        advice!(drop_value specific @for generic_drop_trap trap_drop_f64 f64 F64);
+    };
+    (
+        drop_value specific @for $generic_drop_trap:ident
+        $function_name:ident
+        $value_type:ident
+        $value_type_wasm_value:ident
+    ) => {
+        #[no_mangle]
+        extern "C" fn $function_name(
+            operand: $value_type,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let operand = WasmValue::$value_type_wasm_value(operand);
+            let location = Location::new(funct_index, instr_index);
+            $generic_drop_trap(operand, location);
+        }
+    };
+    (unreachable (
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_unreachable(
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body
+        }
+    };
+    (nop (
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_nop(
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body
+        }
+    };
+    (ref_null (
+        $ref_type: ident: WasmType,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_ref_null(
+            ref_type: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $ref_type = WasmType::from(&ref_type);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body
+        }
+    };
+    (ref_is_null (
+        $is_null: ident: ReferenceIsNull,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_ref_is_null(
+            is_null: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) -> i32 {
+            let $is_null = ReferenceIsNull(is_null);
+            let $location_ident = Location::new(funct_index, instr_index);
+            let ReferenceIsNull(is_null) = $body;
+            is_null
+        }
+    };
+    (ref_func (
+        $func_ident: ident: FunctionIndex,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_ref_func(
+            func_ident: i32,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $func_ident = FunctionIndex(func_ident);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body
+        }
+    };
     (const_
         (
             $value: ident: WasmValue,
@@ -1180,17 +1670,20 @@ macro_rules! advice {
         $load_index: ident: LoadIndex,
         $offset: ident: LoadOffset,
         $operation: ident: LoadOperation,
+        $memory_index: ident: MemoryIndex,
         $location_ident: ident: Location $(,)?
     ) $body:block) => {
         fn generic_load_trap(
             load_index: LoadIndex,
             offset: LoadOffset,
             operation: LoadOperation,
+            memory_index: MemoryIndex,
             location: Location,
         ) -> WasmValue {
             let $load_index = load_index;
             let $offset = offset;
             let $operation = operation;
+            let $memory_index = memory_index;
             let $location_ident = location;
             $body
         }
@@ -1212,14 +1705,16 @@ macro_rules! advice {
             load_idx: i32,
             offset: i64,
             operation: i32,
+            memory_idx: i64,
             funct_index: i64,
             instr_index: i64,
         ) -> $load_type {
             let load_index = LoadIndex(load_idx);
             let offset = LoadOffset(offset);
             let operation = LoadOperation::deserialize(&operation);
+            let memory_index = MemoryIndex(memory_idx);
             let location = Location::new(funct_index, instr_index);
-            let outcome = $generic_load_trap(load_index, offset, operation, location);
+            let outcome = $generic_load_trap(load_index, offset, operation, memory_index, location);
             let WasmValue::$load_type_wasm_value(outcome) = outcome else {
                 panic!(concat!("Attempted to convert {:?} to ", stringify!($value_type_wasm_value)), outcome);
             };
@@ -1232,6 +1727,7 @@ macro_rules! advice {
         $value: ident: WasmValue,
         $offset: ident: StoreOffset,
         $operation: ident: StoreOperation,
+        $memory_index: ident: MemoryIndex,
         $location_ident: ident: Location $(,)?
     ) $body:block) => {
         fn generic_store_trap(
@@ -1239,12 +1735,14 @@ macro_rules! advice {
             value: WasmValue,
             offset: StoreOffset,
             operation: StoreOperation,
+            memory_index: MemoryIndex,
             location: Location,
         ) {
             let $store_index = store_index;
             let $value = value;
             let $offset = offset;
             let $operation = operation;
+            let $memory_index = memory_index;
             let $location_ident = location;
             $body
         }
@@ -1268,6 +1766,7 @@ macro_rules! advice {
             value: $store_type,
             offset: i64,
             operation: i32,
+            memory_idx: i64,
             funct_index: i64,
             instr_index: i64,
         ) {
@@ -1275,8 +1774,9 @@ macro_rules! advice {
             let value = WasmValue::$store_type_wasm_value(value);
             let offset = StoreOffset(offset);
             let operation = StoreOperation::deserialize(&operation);
+            let memory_index = MemoryIndex(memory_idx);
             let location = Location::new(funct_index, instr_index);
-            $generic_store_trap(store_index, value, offset, operation, location);
+            $generic_store_trap(store_index, value, offset, operation, memory_index, location);
         }
     };
     (memory_size
@@ -1323,6 +1823,277 @@ macro_rules! advice {
             delta_or_neg_1.as_i32()
         }
     };
+    (memory_fill (
+        $destination: ident: MemoryAddress,
+        $value: ident: WasmValue,
+        $length: ident: MemoryLength,
+        $index: ident: MemoryIndex,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_memory_fill(
+            destination: i32,
+            value: i32,
+            length: i32,
+            idx: i64,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $destination = MemoryAddress(destination);
+            let $value = WasmValue::I32(value);
+            let $length = MemoryLength(length);
+            let $index = MemoryIndex(idx);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body;
+        }
+    };
+    (memory_copy (
+        $destination: ident: MemoryAddress,
+        $source: ident: MemoryAddress,
+        $length: ident: MemoryLength,
+        $destination_index: ident: MemoryIndex,
+        $source_index: ident: MemoryIndex,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_memory_copy(
+            destination: i32,
+            source: i32,
+            length: i32,
+            destination_idx: i64,
+            source_idx: i64,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $destination = MemoryAddress(destination);
+            let $source = MemoryAddress(source);
+            let $length = MemoryLength(length);
+            let $destination_index = MemoryIndex(destination_idx);
+            let $source_index = MemoryIndex(source_idx);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body;
+        }
+    };
+    (memory_init (
+        $destination: ident: MemoryAddress,
+        $offset: ident: DataOffset,
+        $length: ident: MemoryLength,
+        $data_index: ident: DataIndex,
+        $index: ident: MemoryIndex,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_memory_init(
+            destination: i32,
+            offset: i32,
+            length: i32,
+            data_idx: i64,
+            idx: i64,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $destination = MemoryAddress(destination);
+            let $offset = DataOffset(offset);
+            let $length = MemoryLength(length);
+            let $data_index = DataIndex(data_idx);
+            let $index = MemoryIndex(idx);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body;
+        }
+    };
+    (data_drop (
+        $data_index: ident: DataIndex,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_data_drop(
+            data_idx: i64,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $data_index = DataIndex(data_idx);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body;
+        }
+    };
+    (table_get (
+        $slot: ident: TableSlot,
+        $value: ident: TableValue,
+        $index: ident: TableIndex,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_table_get(
+            slot: i32,
+            value: i32,
+            idx: i64,
+            funct_index: i64,
+            instr_index: i64,
+        ) -> i32 {
+            let $slot = TableSlot(slot);
+            let $value = TableValue(value);
+            let $index = TableIndex(idx);
+            let $location_ident = Location::new(funct_index, instr_index);
+            let TableValue(value) = $body;
+            value
+        }
+    };
+    (table_set (
+        $slot: ident: TableSlot,
+        $value: ident: TableValue,
+        $index: ident: TableIndex,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_table_set(
+            slot: i32,
+            value: i32,
+            idx: i64,
+            funct_index: i64,
+            instr_index: i64,
+        ) -> i32 {
+            let $slot = TableSlot(slot);
+            let $value = TableValue(value);
+            let $index = TableIndex(idx);
+            let $location_ident = Location::new(funct_index, instr_index);
+            let TableSlot(slot) = $body;
+            slot
+        }
+    };
+    (table_size (
+        $size: ident: TableLength,
+        $index: ident: TableIndex,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_table_size(
+            size: i32,
+            idx: i64,
+            funct_index: i64,
+            instr_index: i64,
+        ) -> i32 {
+            let $size = TableLength(size);
+            let $index = TableIndex(idx);
+            let $location_ident = Location::new(funct_index, instr_index);
+            let TableLength(size) = $body;
+            size
+        }
+    };
+    (table_grow (
+        $init: ident: TableValue,
+        $delta: ident: TableLength,
+        $index: ident: TableIndex,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_table_grow(
+            init: i32,
+            delta: i32,
+            idx: i64,
+            funct_index: i64,
+            instr_index: i64,
+        ) -> i32 {
+            let $init = TableValue(init);
+            let $delta = TableLength(delta);
+            let $index = TableIndex(idx);
+            let $location_ident = Location::new(funct_index, instr_index);
+            let TableLength(delta) = $body;
+            delta
+        }
+    };
+    (table_fill (
+        $slot: ident: TableSlot,
+        $value: ident: TableValue,
+        $length: ident: TableLength,
+        $index: ident: TableIndex,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_table_fill(
+            slot: i32,
+            value: i32,
+            length: i32,
+            idx: i64,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $slot = TableSlot(slot);
+            let $value = TableValue(value);
+            let $length = TableLength(length);
+            let $index = TableIndex(idx);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body;
+        }
+    };
+    (table_copy (
+        $destination: ident: TableSlot,
+        $source: ident: TableSlot,
+        $length: ident: TableLength,
+        $destination_index: ident: TableIndex,
+        $source_index: ident: TableIndex,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_table_copy(
+            destination: i32,
+            source: i32,
+            length: i32,
+            destination_idx: i64,
+            source_idx: i64,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $destination = TableSlot(destination);
+            let $source = TableSlot(source);
+            let $length = TableLength(length);
+            let $destination_index = TableIndex(destination_idx);
+            let $source_index = TableIndex(source_idx);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body;
+        }
+    };
+    (table_init (
+        $destination: ident: TableSlot,
+        $offset: ident: ElementOffset,
+        $length: ident: TableLength,
+        $element_index: ident: ElementIndex,
+        $index: ident: TableIndex,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_table_init(
+            destination: i32,
+            offset: i32,
+            length: i32,
+            element_idx: i64,
+            idx: i64,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $destination = TableSlot(destination);
+            let $offset = ElementOffset(offset);
+            let $length = TableLength(length);
+            let $element_index = ElementIndex(element_idx);
+            let $index = TableIndex(idx);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body;
+        }
+    };
+    (elem_drop (
+        $element_index: ident: ElementIndex,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_elem_drop(
+            element_idx: i64,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $element_index = ElementIndex(element_idx);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body;
+        }
+    };
     (block pre (
         $block_input_c: ident: BlockInputCount,
         $block_arity: ident: BlockArity,
@@ -1383,6 +2154,21 @@ macro_rules! advice {
             $body
         }
     };
+    (loop_ iteration (
+        $iteration_ident: ident: LoopIteration,
+        $location_ident: ident: Location $(,)?
+    ) $body:block) => {
+        #[no_mangle]
+        extern "C" fn trap_loop_iteration(
+            iteration: i64,
+            funct_index: i64,
+            instr_index: i64,
+        ) {
+            let $iteration_ident = LoopIteration(iteration);
+            let $location_ident = Location::new(funct_index, instr_index);
+            $body
+        }
+    };
     // General pattern to allow multiple advices in a single `advice! {...}`
     ($(
         $($advice_keyword:ident)+ ($($formal_arg:ident : $formal_type:ident),* $(,)?) $body:block
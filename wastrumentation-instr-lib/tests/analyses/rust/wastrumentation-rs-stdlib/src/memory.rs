@@ -29,11 +29,15 @@ use crate::{
     instrumented_base_store_i64_8,
 };
 
-generate_wrapper!(LoadOffset  wrapping i64 accessed-using .value());
-generate_wrapper!(StoreIndex  wrapping i32 accessed-using .value());
-generate_wrapper!(StoreOffset wrapping i64 accessed-using .value());
-generate_wrapper!(LoadIndex   wrapping i32 accessed-using .value());
-generate_wrapper!(MemoryIndex wrapping i64 accessed-using .value());
+generate_wrapper!(LoadOffset    wrapping i64 accessed-using .value());
+generate_wrapper!(StoreIndex    wrapping i32 accessed-using .value());
+generate_wrapper!(StoreOffset   wrapping i64 accessed-using .value());
+generate_wrapper!(LoadIndex     wrapping i32 accessed-using .value());
+generate_wrapper!(MemoryIndex   wrapping i64 accessed-using .value());
+generate_wrapper!(MemoryAddress wrapping i32 accessed-using .value());
+generate_wrapper!(MemoryLength  wrapping i32 accessed-using .value());
+generate_wrapper!(DataIndex     wrapping i64 accessed-using .value());
+generate_wrapper!(DataOffset    wrapping i32 accessed-using .value());
 
 pub fn base_memory_size(index: i32) -> i32 {
     unsafe { crate::instrumented_memory_size(index) }
@@ -45,6 +49,65 @@ impl MemoryIndex {
         let index = self.value().try_into().unwrap();
         (unsafe { crate::instrumented_memory_grow(amount, index) }).into()
     }
+
+    pub fn fill(&self, destination: &MemoryAddress, value: &WasmValue, length: &MemoryLength) {
+        let index = self.value().try_into().unwrap();
+        unsafe {
+            crate::instrumented_base_memory_fill(
+                destination.value(),
+                value.as_i32(),
+                length.value(),
+                index,
+            )
+        }
+    }
+
+    pub fn copy(
+        &self,
+        destination: &MemoryAddress,
+        source_index: &MemoryIndex,
+        source: &MemoryAddress,
+        length: &MemoryLength,
+    ) {
+        let destination_index = self.value().try_into().unwrap();
+        let source_index = source_index.value().try_into().unwrap();
+        unsafe {
+            crate::instrumented_base_memory_copy(
+                destination.value(),
+                source.value(),
+                length.value(),
+                destination_index,
+                source_index,
+            )
+        }
+    }
+
+    pub fn init(
+        &self,
+        destination: &MemoryAddress,
+        data_index: &DataIndex,
+        offset: &DataOffset,
+        length: &MemoryLength,
+    ) {
+        let index = self.value().try_into().unwrap();
+        let data_index = data_index.value().try_into().unwrap();
+        unsafe {
+            crate::instrumented_base_memory_init(
+                destination.value(),
+                offset.value(),
+                length.value(),
+                data_index,
+                index,
+            )
+        }
+    }
+}
+
+impl DataIndex {
+    pub fn drop_segment(&self) {
+        let data_index = self.value().try_into().unwrap();
+        unsafe { crate::instrumented_base_data_drop(data_index) }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -100,7 +163,13 @@ impl Deserialize for StoreOperation {
 }
 
 impl StoreOperation {
-    pub fn perform(&self, store_index: &StoreIndex, value: &WasmValue, offset: &StoreOffset) {
+    pub fn perform(
+        &self,
+        store_index: &StoreIndex,
+        value: &WasmValue,
+        offset: &StoreOffset,
+        memory_index: &MemoryIndex,
+    ) {
         // Regular
         use StoreOperation::{F32Store, F64Store, I32Store, I64Store};
         // I32 Load
@@ -110,20 +179,31 @@ impl StoreOperation {
 
         let ptr = store_index.value();
         let offset = offset.value().try_into().unwrap();
+        let memory = memory_index.value().try_into().unwrap();
 
         match self {
             // Regular
-            F32Store => unsafe { instrumented_base_store_f32(ptr, value.as_f32(), offset) },
-            F64Store => unsafe { instrumented_base_store_f64(ptr, value.as_f64(), offset) },
-            I32Store => unsafe { instrumented_base_store_i32(ptr, value.as_i32(), offset) },
-            I64Store => unsafe { instrumented_base_store_i64(ptr, value.as_i64(), offset) },
+            F32Store => unsafe { instrumented_base_store_f32(ptr, value.as_f32(), offset, memory) },
+            F64Store => unsafe { instrumented_base_store_f64(ptr, value.as_f64(), offset, memory) },
+            I32Store => unsafe { instrumented_base_store_i32(ptr, value.as_i32(), offset, memory) },
+            I64Store => unsafe { instrumented_base_store_i64(ptr, value.as_i64(), offset, memory) },
             // I32 Load
-            I32Store16 => unsafe { instrumented_base_store_i32_16(ptr, value.as_i32(), offset) },
-            I32Store8 => unsafe { instrumented_base_store_i32_8(ptr, value.as_i32(), offset) },
+            I32Store16 => unsafe {
+                instrumented_base_store_i32_16(ptr, value.as_i32(), offset, memory)
+            },
+            I32Store8 => unsafe {
+                instrumented_base_store_i32_8(ptr, value.as_i32(), offset, memory)
+            },
             // I64 Load
-            I64Store16 => unsafe { instrumented_base_store_i64_16(ptr, value.as_i64(), offset) },
-            I64Store32 => unsafe { instrumented_base_store_i64_32(ptr, value.as_i64(), offset) },
-            I64Store8 => unsafe { instrumented_base_store_i64_8(ptr, value.as_i64(), offset) },
+            I64Store16 => unsafe {
+                instrumented_base_store_i64_16(ptr, value.as_i64(), offset, memory)
+            },
+            I64Store32 => unsafe {
+                instrumented_base_store_i64_32(ptr, value.as_i64(), offset, memory)
+            },
+            I64Store8 => unsafe {
+                instrumented_base_store_i64_8(ptr, value.as_i64(), offset, memory)
+            },
         }
     }
 
@@ -153,7 +233,12 @@ impl StoreOperation {
 }
 
 impl LoadOperation {
-    pub fn perform(&self, load_index: &LoadIndex, offset: &LoadOffset) -> WasmValue {
+    pub fn perform(
+        &self,
+        load_index: &LoadIndex,
+        offset: &LoadOffset,
+        memory_index: &MemoryIndex,
+    ) -> WasmValue {
         // Regular
         use LoadOperation::{F32Load, F64Load, I32Load, I64Load};
         // I32 Load
@@ -163,25 +248,26 @@ impl LoadOperation {
 
         let ptr = load_index.value();
         let offset = offset.value().try_into().unwrap();
+        let memory = memory_index.value().try_into().unwrap();
 
         match self {
             // Regular
-            F32Load => unsafe { instrumented_base_load_f32(ptr, offset).into() },
-            F64Load => unsafe { instrumented_base_load_f64(ptr, offset).into() },
-            I32Load => unsafe { instrumented_base_load_i32(ptr, offset).into() },
-            I64Load => unsafe { instrumented_base_load_i64(ptr, offset).into() },
+            F32Load => unsafe { instrumented_base_load_f32(ptr, offset, memory).into() },
+            F64Load => unsafe { instrumented_base_load_f64(ptr, offset, memory).into() },
+            I32Load => unsafe { instrumented_base_load_i32(ptr, offset, memory).into() },
+            I64Load => unsafe { instrumented_base_load_i64(ptr, offset, memory).into() },
             // I32 Load
-            I32Load16S => unsafe { instrumented_base_load_i32_16S(ptr, offset).into() },
-            I32Load16U => unsafe { instrumented_base_load_i32_16U(ptr, offset).into() },
-            I32Load8S => unsafe { instrumented_base_load_i32_8S(ptr, offset).into() },
-            I32Load8U => unsafe { instrumented_base_load_i32_8U(ptr, offset).into() },
+            I32Load16S => unsafe { instrumented_base_load_i32_16S(ptr, offset, memory).into() },
+            I32Load16U => unsafe { instrumented_base_load_i32_16U(ptr, offset, memory).into() },
+            I32Load8S => unsafe { instrumented_base_load_i32_8S(ptr, offset, memory).into() },
+            I32Load8U => unsafe { instrumented_base_load_i32_8U(ptr, offset, memory).into() },
             // I64 Load
-            I64Load16S => unsafe { instrumented_base_load_i64_16S(ptr, offset).into() },
-            I64Load16U => unsafe { instrumented_base_load_i64_16U(ptr, offset).into() },
-            I64Load32S => unsafe { instrumented_base_load_i64_32S(ptr, offset).into() },
-            I64Load32U => unsafe { instrumented_base_load_i64_32U(ptr, offset).into() },
-            I64Load8S => unsafe { instrumented_base_load_i64_8S(ptr, offset).into() },
-            I64Load8U => unsafe { instrumented_base_load_i64_8U(ptr, offset).into() },
+            I64Load16S => unsafe { instrumented_base_load_i64_16S(ptr, offset, memory).into() },
+            I64Load16U => unsafe { instrumented_base_load_i64_16U(ptr, offset, memory).into() },
+            I64Load32S => unsafe { instrumented_base_load_i64_32S(ptr, offset, memory).into() },
+            I64Load32U => unsafe { instrumented_base_load_i64_32U(ptr, offset, memory).into() },
+            I64Load8S => unsafe { instrumented_base_load_i64_8S(ptr, offset, memory).into() },
+            I64Load8U => unsafe { instrumented_base_load_i64_8U(ptr, offset, memory).into() },
         }
     }
 
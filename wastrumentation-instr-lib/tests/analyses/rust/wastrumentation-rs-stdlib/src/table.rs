@@ -0,0 +1,65 @@
+use crate::generate_wrapper;
+
+generate_wrapper!(TableIndex    wrapping i64 accessed-using .value());
+generate_wrapper!(TableSlot     wrapping i32 accessed-using .value());
+generate_wrapper!(TableLength   wrapping i32 accessed-using .value());
+generate_wrapper!(ElementIndex  wrapping i64 accessed-using .value());
+// A handle to the reference a table instruction reads or writes, valid for the
+// duration of the observed instruction. Handle `0` stands for `ref.null`.
+generate_wrapper!(TableValue    wrapping i32 accessed-using .handle());
+generate_wrapper!(ElementOffset wrapping i32 accessed-using .value());
+
+impl TableIndex {
+    pub fn copy(
+        &self,
+        destination: &TableSlot,
+        source_index: &TableIndex,
+        source: &TableSlot,
+        length: &TableLength,
+    ) {
+        let destination_index = self.value().try_into().unwrap();
+        let source_index = source_index.value().try_into().unwrap();
+        unsafe {
+            crate::instrumented_base_table_copy(
+                destination.value(),
+                source.value(),
+                length.value(),
+                destination_index,
+                source_index,
+            )
+        }
+    }
+
+    pub fn init(
+        &self,
+        destination: &TableSlot,
+        element_index: &ElementIndex,
+        offset: &ElementOffset,
+        length: &TableLength,
+    ) {
+        let index = self.value().try_into().unwrap();
+        let element_index = element_index.value().try_into().unwrap();
+        unsafe {
+            crate::instrumented_base_table_init(
+                destination.value(),
+                offset.value(),
+                length.value(),
+                element_index,
+                index,
+            )
+        }
+    }
+}
+
+impl TableValue {
+    pub fn is_null(&self) -> bool {
+        self.handle() == crate::NULL_REF_HANDLE
+    }
+}
+
+impl ElementIndex {
+    pub fn drop_segment(&self) {
+        let element_index = self.value().try_into().unwrap();
+        unsafe { crate::instrumented_base_elem_drop(element_index) }
+    }
+}
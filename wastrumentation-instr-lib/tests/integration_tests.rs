@@ -123,6 +123,11 @@ fn test_analysis_denan() {
 
     let configuration = Configuration {
         target_indices: None,
+        target_selectors: None,
+        site_predicate: None,
+        source_locations: false,
+        site_ids: false,
+        debug_info: false,
         primary_selection: Some(PrimaryTarget::Analysis),
     };
 
@@ -196,6 +201,11 @@ fn test_analysis_forward_guiicons() {
 
     let configuration = Configuration {
         target_indices: None,
+        target_selectors: None,
+        site_predicate: None,
+        source_locations: false,
+        site_ids: false,
+        debug_info: false,
         primary_selection: Some(PrimaryTarget::Target),
     };
 
@@ -245,6 +255,11 @@ fn test_analysis_safe_heap() {
 
     let configuration = Configuration {
         target_indices: None,
+        target_selectors: None,
+        site_predicate: None,
+        source_locations: false,
+        site_ids: false,
+        debug_info: false,
         primary_selection: Some(PrimaryTarget::Analysis),
     };
 
@@ -364,6 +379,11 @@ fn test_analysis_memory_introspection() {
 
     let configuration = Configuration {
         target_indices: None,
+        target_selectors: None,
+        site_predicate: None,
+        source_locations: false,
+        site_ids: false,
+        debug_info: false,
         // NOTE: here, the primary target [IS] important since
         // the reported `base_memory_size(0)` in the analysis
         // must target the input program!
@@ -442,11 +462,17 @@ fn test_analysis_forward_pacalc() {
 
     const PATH_INPUT_ANLYSIS: &str = "./tests/analyses/rust/forward/Cargo.toml";
     let source = Manifest(WasiSupport::Disabled, absolute(PATH_INPUT_ANLYSIS).unwrap());
-    let hooks = Hook::all_hooks();
+    let mut hooks = Hook::all_hooks();
+    hooks.extend(Hook::opt_in_hooks());
     let analysis = RustAnalysisSpec { source, hooks }.into();
 
     let configuration = Configuration {
         target_indices: None,
+        target_selectors: None,
+        site_predicate: None,
+        source_locations: false,
+        site_ids: false,
+        debug_info: false,
         primary_selection: Some(PrimaryTarget::Target),
     };
 
@@ -500,7 +526,7 @@ fn test_analysis_logging() {
         123,
     ) to ParameterBrIfLabel(
         0,
-    ), location: Location { instr_index: 0, funct_index: 2 }
+    ) (BranchTarget { instr_index: 0, kind: Block }), location: Location { instr_index: 0, funct_index: 2 }
     [ANALYSIS:] const_ generic I32(
         0,
     ), location: Location { instr_index: 0, funct_index: 6 }
@@ -614,6 +640,11 @@ fn test_analysis_logging() {
 
     let configuration = Configuration {
         target_indices: None,
+        target_selectors: None,
+        site_predicate: None,
+        source_locations: false,
+        site_ids: false,
+        debug_info: false,
         primary_selection: Some(PrimaryTarget::Analysis),
     };
 
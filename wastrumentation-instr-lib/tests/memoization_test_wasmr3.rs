@@ -242,6 +242,11 @@ fn report_memoization_benches_for(
 
     let configuration = Configuration {
         target_indices: Some(immutable_set.iter().copied().collect()),
+        target_selectors: None,
+        site_predicate: None,
+        source_locations: false,
+        site_ids: false,
+        debug_info: false,
         primary_selection: Some(PrimaryTarget::Analysis),
     };
 
@@ -302,6 +307,11 @@ fn report_memoization_benches_for(
 
     let configuration = Configuration {
         target_indices: Some(pure_functions_of_interest.clone()),
+        target_selectors: None,
+        site_predicate: None,
+        source_locations: false,
+        site_ids: false,
+        debug_info: false,
         primary_selection: Some(PrimaryTarget::Analysis),
     };
 
@@ -41,6 +41,17 @@ pub const TRAP_NAME_BINARY_F64_F64_TO_F64: &str = "binary_f64_f64_to_f64";
 
 pub const TRAP_NAME_DROP: &str = "drop_trap";
 pub const TRAP_NAME_RETURN: &str = "return_trap";
+pub const TRAP_NAME_RETURN_VALUES: &str = "trap_return_values";
+pub const TRAP_NAME_DROP_I32: &str = "trap_drop_i32";
+pub const TRAP_NAME_DROP_F32: &str = "trap_drop_f32";
+pub const TRAP_NAME_DROP_I64: &str = "trap_drop_i64";
+pub const TRAP_NAME_DROP_F64: &str = "trap_drop_f64";
+pub const TRAP_NAME_UNREACHABLE: &str = "trap_unreachable";
+pub const TRAP_NAME_NOP: &str = "trap_nop";
+
+pub const TRAP_NAME_REF_NULL: &str = "trap_ref_null";
+pub const TRAP_NAME_REF_IS_NULL: &str = "trap_ref_is_null";
+pub const TRAP_NAME_REF_FUNC: &str = "trap_ref_func";
 
 pub const TRAP_CONST_I32: &str = "trap_const_i32";
 pub const TRAP_CONST_F32: &str = "trap_const_f32";
@@ -79,11 +90,36 @@ pub const TRAP_NAME_I64_LOAD: &str = "trap_i64_load";
 
 pub const TRAP_NAME_MEMORY_SIZE: &str = "trap_memory_size";
 pub const TRAP_NAME_MEMORY_GROW: &str = "trap_memory_grow";
+pub const TRAP_NAME_MEMORY_FILL: &str = "trap_memory_fill";
+pub const TRAP_NAME_MEMORY_COPY: &str = "trap_memory_copy";
+pub const TRAP_NAME_MEMORY_INIT: &str = "trap_memory_init";
+pub const TRAP_NAME_DATA_DROP: &str = "trap_data_drop";
+
+pub const TRAP_NAME_TABLE_GET: &str = "trap_table_get";
+pub const TRAP_NAME_TABLE_SET: &str = "trap_table_set";
+pub const TRAP_NAME_TABLE_SIZE: &str = "trap_table_size";
+pub const TRAP_NAME_TABLE_GROW: &str = "trap_table_grow";
+pub const TRAP_NAME_TABLE_FILL: &str = "trap_table_fill";
+pub const TRAP_NAME_TABLE_COPY: &str = "trap_table_copy";
+pub const TRAP_NAME_TABLE_INIT: &str = "trap_table_init";
+pub const TRAP_NAME_ELEM_DROP: &str = "trap_elem_drop";
+
+pub const TRAP_NAME_CALL_VALUES_PRE: &str = "trap_call_values_pre";
+pub const TRAP_NAME_CALL_VALUES_POST: &str = "trap_call_values_post";
+
+pub const TRAP_NAME_FUNCTION_ENTER: &str = "trap_function_enter";
+pub const TRAP_NAME_FUNCTION_EXIT: &str = "trap_function_exit";
+
+pub const TRAP_NAME_MODULE_INIT: &str = "trap_module_init";
+pub const TRAP_NAME_MODULE_FINALIZE: &str = "trap_module_finalize";
+
+pub const TRAP_NAME_BASIC_BLOCK: &str = "trap_basic_block";
 
 pub const TRAP_NAME_PRE_BLOCK: &str = "trap_block_pre";
 pub const TRAP_NAME_POST_BLOCK: &str = "trap_block_post";
 pub const TRAP_NAME_PRE_LOOP: &str = "trap_loop_pre";
 pub const TRAP_NAME_POST_LOOP: &str = "trap_loop_post";
+pub const TRAP_NAME_LOOP_ITERATION: &str = "trap_loop_iteration";
 
 const SER_OPRTR_TYP: WasmType = I32;
 
@@ -129,13 +165,31 @@ pub struct AnalysisInterface {
     pub pre_trap_call_indirect: Option<WasmExport>,
     pub post_trap_call: Option<WasmExport>,
     pub post_trap_call_indirect: Option<WasmExport>,
+    pub call_values_pre: Option<WasmExport>,
+    pub call_values_post: Option<WasmExport>,
+    pub function_enter: Option<WasmExport>,
+    pub function_exit: Option<WasmExport>,
+    pub module_init: Option<WasmExport>,
+    pub module_finalize: Option<WasmExport>,
+    pub basic_block: Option<WasmExport>,
     pub pre_block: Option<WasmExport>,
     pub post_block: Option<WasmExport>,
     pub pre_loop: Option<WasmExport>,
     pub post_loop: Option<WasmExport>,
+    pub loop_iteration: Option<WasmExport>,
     pub select: Option<WasmExport>,
     pub drop_trap: Option<WasmExport>,
     pub return_trap: Option<WasmExport>,
+    pub return_values: Option<WasmExport>,
+    pub drop_i32: Option<WasmExport>,
+    pub drop_f32: Option<WasmExport>,
+    pub drop_i64: Option<WasmExport>,
+    pub drop_f64: Option<WasmExport>,
+    pub unreachable_trap: Option<WasmExport>,
+    pub nop_trap: Option<WasmExport>,
+    pub ref_null: Option<WasmExport>,
+    pub ref_is_null: Option<WasmExport>,
+    pub ref_func: Option<WasmExport>,
     pub const_i32_trap: Option<WasmExport>,
     pub const_f32_trap: Option<WasmExport>,
     pub const_i64_trap: Option<WasmExport>,
@@ -165,6 +219,18 @@ pub struct AnalysisInterface {
     pub binary_f64_f64_to_f64: Option<WasmExport>,
     pub memory_size: Option<WasmExport>,
     pub memory_grow: Option<WasmExport>,
+    pub memory_fill: Option<WasmExport>,
+    pub memory_copy: Option<WasmExport>,
+    pub memory_init: Option<WasmExport>,
+    pub data_drop: Option<WasmExport>,
+    pub table_get: Option<WasmExport>,
+    pub table_set: Option<WasmExport>,
+    pub table_size: Option<WasmExport>,
+    pub table_grow: Option<WasmExport>,
+    pub table_fill: Option<WasmExport>,
+    pub table_copy: Option<WasmExport>,
+    pub table_init: Option<WasmExport>,
+    pub elem_drop: Option<WasmExport>,
     pub local_get_i32: Option<WasmExport>,
     pub local_set_i32: Option<WasmExport>,
     pub local_tee_i32: Option<WasmExport>,
@@ -247,25 +313,38 @@ macro_rules! simple_interface {
     };
 }
 
-// TODO: add support for
-// - nop
-// - unreachable
-
 simple_interfaces! {
     interface_if_then               FUNCTION_NAME_SPECIALIZED_IF_THEN            :              /*cndt:*/ I32 /*inputs-len:*/ I32 /*results-len:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>           /*cont:*/ I32,
     interface_if_then_post          FUNCTION_NAME_SPECIALIZED_IF_THEN_POST       :                                                            /*void*/ /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
     interface_if_then_else          FUNCTION_NAME_SPECIALIZED_IF_THEN_ELSE       :              /*cndt:*/ I32 /*inputs-len:*/ I32 /*results-len:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>           /*cont:*/ I32,
     interface_if_then_else_post     FUNCTION_NAME_SPECIALIZED_IF_THEN_ELSE_POST  :                                                            /*void*/ /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
-    interface_br                    FUNCTION_NAME_SPECIALIZED_BR                 :                                                        /*lbl:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
-    interface_br_if                 FUNCTION_NAME_SPECIALIZED_BR_IF              :                                          /*cndt:*/ I32 /*lbl:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>           /*cont:*/ I32,
-    interface_br_table              FUNCTION_NAME_SPECIALIZED_BR_TABLE           :    /*br_tbl_tgt_idx:*/ I32 /*runtime_label:*/ I32 /*dflt_idx:*/ I32 /*fidx*/ I64 /*iidx*/ I64 => /*br_tbl_tgt_idx:*/ I32,
+    interface_br                    FUNCTION_NAME_SPECIALIZED_BR                 :                    /*lbl:*/ I64 /*tgt_iidx:*/ I64 /*tgt_kind:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_br_if                 FUNCTION_NAME_SPECIALIZED_BR_IF              :      /*cndt:*/ I32 /*lbl:*/ I32 /*tgt_iidx:*/ I64 /*tgt_kind:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>           /*cont:*/ I32,
+    interface_br_table              FUNCTION_NAME_SPECIALIZED_BR_TABLE           : /*br_tbl_tgt_idx:*/ I32 /*runtime_label:*/ I32 /*dflt_idx:*/ I32 /*tgt_iidx:*/ I64 /*tgt_kind:*/ I32 /*fidx*/ I64 /*iidx*/ I64 => /*br_tbl_tgt_idx:*/ I32,
     interface_call_pre              FUNCTION_NAME_SPECIALIZED_CALL_PRE           :                                                      /*f_tgt:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
     interface_call_post             FUNCTION_NAME_SPECIALIZED_CALL_POST          :                                                      /*f_tgt:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
-    interface_call_indirect_pre     FUNCTION_NAME_SPECIALIZED_CALL_INDIRECT_PRE  :                                 /*fn_tbl_idx:*/ I32 /*fn_tbl:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>    /*fn_tbl_idx:*/ I32 ,
-    interface_call_indirect_post    FUNCTION_NAME_SPECIALIZED_CALL_INDIRECT_POST :                                                     /*fn_tbl:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_call_indirect_pre     FUNCTION_NAME_SPECIALIZED_CALL_INDIRECT_PRE  :             /*fn_tbl_idx:*/ I32 /*fn_tbl:*/ I32 /*f_resolved:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>     /*fn_tbl_idx:*/ I32,
+    interface_call_indirect_post    FUNCTION_NAME_SPECIALIZED_CALL_INDIRECT_POST :                                 /*fn_tbl:*/ I32 /*f_resolved:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_call_values_pre       TRAP_NAME_CALL_VALUES_PRE                    : /*f_tgt:*/ I32 /*argc:*/ I32 /*resc:*/ I32 /*sigv:*/ I32 /*sigtypv:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>          /*void*/,
+    interface_call_values_post      TRAP_NAME_CALL_VALUES_POST                   : /*f_tgt:*/ I32 /*argc:*/ I32 /*resc:*/ I32 /*sigv:*/ I32 /*sigtypv:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>          /*void*/,
+    interface_function_enter        TRAP_NAME_FUNCTION_ENTER                     :                        /*argc:*/ I32 /*argv:*/ I32 /*argtypv:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_function_exit         TRAP_NAME_FUNCTION_EXIT                      :                        /*resc:*/ I32 /*resv:*/ I32 /*restypv:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_module_init           TRAP_NAME_MODULE_INIT                        :                                                                                      /*void*/ =>                /*void*/,
+    interface_module_finalize       TRAP_NAME_MODULE_FINALIZE                    :                                                                                /*f_idx:*/ I32 =>                /*void*/,
+    interface_basic_block           TRAP_NAME_BASIC_BLOCK                        :                                                        /*blk:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
     interface_select                FUNCTION_NAME_SELECT                         :                                                       /*cndt:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>           /*cont:*/ I32,
     interface_return                TRAP_NAME_RETURN                             :                                                            /*void*/ /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
     interface_drop                  TRAP_NAME_DROP                               :                                                            /*void*/ /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_return_values         TRAP_NAME_RETURN_VALUES                      :                        /*resc:*/ I32 /*resv:*/ I32 /*restypv:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_drop_i32              TRAP_NAME_DROP_I32                           :                                                      /*value:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_drop_f32              TRAP_NAME_DROP_F32                           :                                                      /*value:*/ F32 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_drop_i64              TRAP_NAME_DROP_I64                           :                                                      /*value:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_drop_f64              TRAP_NAME_DROP_F64                           :                                                      /*value:*/ F64 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_unreachable           TRAP_NAME_UNREACHABLE                        :                                                            /*void*/ /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_nop                   TRAP_NAME_NOP                                :                                                            /*void*/ /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_ref_null              TRAP_NAME_REF_NULL                           :                                                   /*ref_type:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_ref_is_null           TRAP_NAME_REF_IS_NULL                        :                                                    /*is_null:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>        /*is_null:*/ I32,
+    interface_ref_func              TRAP_NAME_REF_FUNC                           :                                                      /*f_tgt:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
     interface_const_i32             TRAP_CONST_I32                               :                                                      /*const:*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>            /*res:*/ I32,
     interface_const_f32             TRAP_CONST_F32                               :                                                      /*const:*/ F32 /*fidx*/ I64 /*iidx*/ I64 =>            /*res:*/ F32,
     interface_const_i64             TRAP_CONST_I64                               :                                                      /*const:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>            /*res:*/ I64,
@@ -313,18 +392,31 @@ simple_interfaces! {
     interface_global_set_i64        TRAP_NAME_GLOBAL_SET_I64                     :                                         /*value:*/ I64 /*idx:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>          /*value:*/ I64,
     interface_global_get_f64        TRAP_NAME_GLOBAL_GET_F64                     :                                         /*value:*/ F64 /*idx:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>          /*value:*/ F64,
     interface_global_set_f64        TRAP_NAME_GLOBAL_SET_F64                     :                                         /*value:*/ F64 /*idx:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>          /*value:*/ F64,
-    interface_f32_store             TRAP_NAME_F32_STORE                          : /*write_idx:*/ I32 /*val:*/ F32 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
-    interface_f64_store             TRAP_NAME_F64_STORE                          : /*write_idx:*/ I32 /*val:*/ F64 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
-    interface_i32_store             TRAP_NAME_I32_STORE                          : /*write_idx:*/ I32 /*val:*/ I32 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
-    interface_i64_store             TRAP_NAME_I64_STORE                          : /*write_idx:*/ I32 /*val:*/ I64 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
-    interface_f32_load              TRAP_NAME_F32_LOAD                           :               /*load_idx:*/ I32 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*fidx*/ I64 /*iidx*/ I64 =>            /*res:*/ F32,
-    interface_f64_load              TRAP_NAME_F64_LOAD                           :               /*load_idx:*/ I32 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*fidx*/ I64 /*iidx*/ I64 =>            /*res:*/ F64,
-    interface_i32_load              TRAP_NAME_I32_LOAD                           :               /*load_idx:*/ I32 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*fidx*/ I64 /*iidx*/ I64 =>            /*res:*/ I32,
-    interface_i64_load              TRAP_NAME_I64_LOAD                           :               /*load_idx:*/ I32 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*fidx*/ I64 /*iidx*/ I64 =>            /*res:*/ I64,
+    interface_f32_store             TRAP_NAME_F32_STORE                          : /*write_idx:*/ I32 /*val:*/ F32 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*mem:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>   /*void*/,
+    interface_f64_store             TRAP_NAME_F64_STORE                          : /*write_idx:*/ I32 /*val:*/ F64 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*mem:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>   /*void*/,
+    interface_i32_store             TRAP_NAME_I32_STORE                          : /*write_idx:*/ I32 /*val:*/ I32 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*mem:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>   /*void*/,
+    interface_i64_store             TRAP_NAME_I64_STORE                          : /*write_idx:*/ I32 /*val:*/ I64 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*mem:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>   /*void*/,
+    interface_f32_load              TRAP_NAME_F32_LOAD                           :  /*load_idx:*/ I32 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*mem:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>            /*res:*/ F32,
+    interface_f64_load              TRAP_NAME_F64_LOAD                           :  /*load_idx:*/ I32 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*mem:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>            /*res:*/ F64,
+    interface_i32_load              TRAP_NAME_I32_LOAD                           :  /*load_idx:*/ I32 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*mem:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>            /*res:*/ I32,
+    interface_i64_load              TRAP_NAME_I64_LOAD                           :  /*load_idx:*/ I32 /*offs:*/ I64 /*op:*/ SER_OPRTR_TYP /*mem:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>            /*res:*/ I64,
     interface_memory_size           TRAP_NAME_MEMORY_SIZE                        :                                          /*size:*/ I32 /*idx:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>           /*size:*/ I32,
     interface_memory_grow           TRAP_NAME_MEMORY_GROW                        :                                        /*amount:*/ I32 /*idx:*/ I64 /*fidx*/ I64 /*iidx*/ I64 => /*delta-or-neg-1:*/ I32,
+    interface_memory_fill           TRAP_NAME_MEMORY_FILL                        :                 /*dst:*/ I32 /*val:*/ I32 /*len:*/ I32 /*mem:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_memory_copy           TRAP_NAME_MEMORY_COPY                        : /*dst:*/ I32 /*src:*/ I32 /*len:*/ I32 /*dst_mem:*/ I64 /*src_mem:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>           /*void*/,
+    interface_memory_init           TRAP_NAME_MEMORY_INIT                        :  /*dst:*/ I32 /*offs:*/ I32 /*len:*/ I32 /*data:*/ I64 /*mem:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_data_drop             TRAP_NAME_DATA_DROP                          :                                                       /*data:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_table_get             TRAP_NAME_TABLE_GET                          :                           /*slot:*/ I32 /*value:*/ I32 /*tbl:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>          /*value:*/ I32,
+    interface_table_set             TRAP_NAME_TABLE_SET                          :                           /*slot:*/ I32 /*value:*/ I32 /*tbl:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>           /*slot:*/ I32,
+    interface_table_size            TRAP_NAME_TABLE_SIZE                         :                                          /*size:*/ I32 /*tbl:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>           /*size:*/ I32,
+    interface_table_grow            TRAP_NAME_TABLE_GROW                         :                           /*init:*/ I32 /*delta:*/ I32 /*tbl:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>          /*delta:*/ I32,
+    interface_table_fill            TRAP_NAME_TABLE_FILL                         :               /*slot:*/ I32 /*value:*/ I32 /*len:*/ I32 /*tbl:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_table_copy            TRAP_NAME_TABLE_COPY                         : /*dst:*/ I32 /*src:*/ I32 /*len:*/ I32 /*dst_tbl:*/ I64 /*src_tbl:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>           /*void*/,
+    interface_table_init            TRAP_NAME_TABLE_INIT                         :  /*dst:*/ I32 /*offs:*/ I32 /*len:*/ I32 /*elem:*/ I64 /*tbl:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
+    interface_elem_drop             TRAP_NAME_ELEM_DROP                          :                                                       /*elem:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>                /*void*/,
     interface_pre_block             TRAP_NAME_PRE_BLOCK                          :                                       /*input_c*/ I32 /*arity*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>              /* void */,
     interface_post_block            TRAP_NAME_POST_BLOCK                         :                                                          /* void */ /*fidx*/ I64 /*iidx*/ I64 =>              /* void */,
     interface_pre_loop              TRAP_NAME_PRE_LOOP                           :                                       /*input_c*/ I32 /*arity*/ I32 /*fidx*/ I64 /*iidx*/ I64 =>              /* void */,
     interface_post_loop             TRAP_NAME_POST_LOOP                          :                                                          /* void */ /*fidx*/ I64 /*iidx*/ I64 =>              /* void */,
+    interface_loop_iteration        TRAP_NAME_LOOP_ITERATION                     :                                                  /*iteration:*/ I64 /*fidx*/ I64 /*iidx*/ I64 =>              /* void */,
 }
@@ -0,0 +1,115 @@
+// Merging with binaryen keeps the names of the merged modules, but the custom
+// sections describing the input program, e.g. the toolchain that produced it,
+// and the side tables the instrumentation adds need not survive. They are
+// carried over onto the merged module instead, unless it already has them.
+//
+// Custom sections may occur anywhere in a module, so they are appended at the
+// end. Only the section framing is read, the module is otherwise untouched.
+
+const CUSTOM_SECTION_ID: u8 = 0;
+const HEADER_LENGTH: usize = 8; // magic number and version
+
+/// The custom sections that are carried over from the input program. Not
+/// `target_features`: the features of the input program need not be those of
+/// the merged module, which holds the analysis and instrumentation code too.
+pub const CARRIED_OVER_SECTIONS: [&str; 1] = ["producers"];
+/// The prefix of the side tables, e.g. `wastrumentation:basic_blocks`, which
+/// are carried over as well.
+pub const SIDE_TABLE_PREFIX: &str = "wastrumentation:";
+
+/// Appends the custom sections in [`CARRIED_OVER_SECTIONS`] and the side tables
+/// of `module` to `merged` if `merged` lacks them.
+pub fn carry_over(module: &[u8], merged: &[u8]) -> Vec<u8> {
+    let present: Vec<&str> = custom_sections(merged)
+        .map(|(name, _section)| name)
+        .collect();
+    let mut result = merged.to_vec();
+    for (name, section) in custom_sections(module) {
+        let carried = CARRIED_OVER_SECTIONS.contains(&name) || name.starts_with(SIDE_TABLE_PREFIX);
+        if carried && !present.contains(&name) {
+            result.extend_from_slice(section);
+        }
+    }
+    result
+}
+
+/// The name of every custom section in `module`, along with the bytes of the
+/// whole section, its framing included. Stops at the first malformed section.
+fn custom_sections(module: &[u8]) -> impl Iterator<Item = (&str, &[u8])> {
+    let mut offset = HEADER_LENGTH.min(module.len());
+    std::iter::from_fn(move || loop {
+        let start = offset;
+        let id = *module.get(offset)?;
+        let (size, content_start) = read_u32(module, offset + 1)?;
+        let end = content_start.checked_add(usize::try_from(size).ok()?)?;
+        let content = module.get(content_start..end)?;
+        offset = end;
+        if id != CUSTOM_SECTION_ID {
+            continue;
+        }
+        let (name_length, name_start) = read_u32(content, 0)?;
+        let name_end = name_start.checked_add(usize::try_from(name_length).ok()?)?;
+        let name = std::str::from_utf8(content.get(name_start..name_end)?).ok()?;
+        return Some((name, &module[start..end]));
+    })
+}
+
+/// Reads an unsigned LEB128 at `offset`, yielding it and the offset after it.
+fn read_u32(bytes: &[u8], mut offset: usize) -> Option<(u32, usize)> {
+    let mut value: u32 = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = *bytes.get(offset)?;
+        offset += 1;
+        value |= u32::from(byte & 0x7F).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some((value, offset));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{carry_over, custom_sections};
+
+    fn custom_section(name: &str, payload: &[u8]) -> Vec<u8> {
+        let content_length = u8::try_from(1 + name.len() + payload.len()).unwrap();
+        let mut section = vec![0, content_length, u8::try_from(name.len()).unwrap()];
+        section.extend_from_slice(name.as_bytes());
+        section.extend_from_slice(payload);
+        section
+    }
+
+    fn names(module: &[u8]) -> Vec<&str> {
+        custom_sections(module).map(|(name, _)| name).collect()
+    }
+
+    #[test]
+    fn test_carry_over_custom_sections() {
+        let mut input_program = wat::parse_str("(module (func (export \"f\")))").unwrap();
+        input_program.extend(custom_section("producers", b"\x01"));
+        input_program.extend(custom_section("target_features", b"\x02"));
+        input_program.extend(custom_section("unrelated", b"\x03"));
+        input_program.extend(custom_section("wastrumentation:side_table", b"\x05"));
+
+        let merged = wat::parse_str("(module (func (export \"g\")))").unwrap();
+        let carried_over = carry_over(&input_program, &merged);
+        assert_eq!(
+            names(&carried_over),
+            ["producers", "wastrumentation:side_table"]
+        );
+        assert!(wasmtime::Module::validate(&wasmtime::Engine::default(), &carried_over).is_ok());
+
+        let mut merged_with_producers = merged;
+        merged_with_producers.extend(custom_section("producers", b"\x04"));
+        let carried_over = carry_over(&input_program, &merged_with_producers);
+        assert_eq!(
+            names(&carried_over),
+            ["producers", "wastrumentation:side_table"]
+        );
+        assert_eq!(
+            custom_sections(&carried_over).next().unwrap().1,
+            custom_section("producers", b"\x04")
+        );
+    }
+}
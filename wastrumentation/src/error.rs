@@ -18,7 +18,7 @@ pub enum Error<AnalysisLangauge, InstrumentationLanguage> {
 
 #[derive(thiserror::Error, Debug)]
 pub enum InstrumentationError {
-    #[error("attempt to instrument an `import` function")]
+    #[error("failed to parse input module: {0}")]
     ParseModuleError(ParseError),
     #[error("attempt to instrument inner code of an `import` function")]
     AttemptInnerInstrumentImport,
@@ -26,4 +26,36 @@ pub enum InstrumentationError {
     LowToHighError { low_to_high_err: LowToHighError },
     #[error("Instrumentation Encode Error: {0}")]
     EncodeError(EncodeError),
+    #[error("failed to read the code offsets of the input module: {0}")]
+    ReadCodeOffsetsError(wasmparser::BinaryReaderError),
+    #[error("failed to read the DWARF of the input module: {0}")]
+    DwarfError(addr2line::gimli::Error),
+    #[error("failed to rewrite the DWARF of the input module: {0}")]
+    DwarfConvertError(gimli::write::ConvertError),
+    #[error("failed to encode the rewritten DWARF: {0}")]
+    DwarfWriteError(gimli::write::Error),
+    #[error("failed to add the memory of the instrumentation: {0}")]
+    AttachOwnedMemoryError(wasmparser::BinaryReaderError),
+    #[error("failed to read the site ids manifest: {0}")]
+    ReadSiteIdsManifestError(serde_json::Error),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SitePredicateError {
+    #[error("expected `key=values` in site predicate clause `{0}`")]
+    MalformedClause(String),
+    #[error("unknown site predicate key `{0}`, expected `function`, `instr`, `opcode` or `hook`")]
+    UnknownKey(String),
+    #[error("site predicate key `{0}` occurs more than once")]
+    DuplicateKey(String),
+    #[error("malformed index range `{0}`")]
+    MalformedRange(String),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum TargetSelectorError {
+    #[error("unknown target selector `{0}`, expected `index:`, `export:` or `name:` or an index")]
+    UnknownSelector(String),
+    #[error("malformed function index in target selector `{0}`")]
+    MalformedIndex(String),
 }
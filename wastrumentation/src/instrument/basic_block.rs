@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+
+use crate::error::InstrumentationError;
+use crate::parse_nesting::{
+    closing_index, BodyInner, ControlFlowGraph, HighLevelBody, HighLevelInstr as Instr,
+    TypedHighLevelInstr,
+};
+use wasabi_wasm::types::InferredInstructionType;
+use wasabi_wasm::{Function, Idx, Module, RawCustomSection, Val};
+
+/// The custom section mapping block ids to the instructions they cover, a JSON
+/// array holding, for every block in order of id, an object like
+/// `{"id":4,"function":1,"start":5,"end":7,"successors":[4,5]}`.
+pub const BASIC_BLOCKS_SECTION: &str = "wastrumentation:basic_blocks";
+
+/// The basic blocks of every function with code in the input program. The id
+/// of a block counts the blocks of all functions before it in index order, so
+/// it does not depend on which functions are targeted.
+pub struct BasicBlocks {
+    /// Per function index, the id of its first block and its graph
+    functions: BTreeMap<u32, (i32, ControlFlowGraph)>,
+}
+
+impl BasicBlocks {
+    /// Must be built before the instrumentation adds functions to `module`.
+    pub fn of(module: &Module) -> Result<Self, InstrumentationError> {
+        let mut functions = BTreeMap::new();
+        let mut next_id = 0;
+        for (index, function) in module.functions() {
+            let Some(code) = function.code() else {
+                continue;
+            };
+            let body: HighLevelBody = (module, function, code, &index)
+                .try_into()
+                .map_err(|e| InstrumentationError::LowToHighError { low_to_high_err: e })?;
+            let graph = ControlFlowGraph::build(&body);
+            let block_count = i32::try_from(graph.blocks.len()).unwrap();
+            functions.insert(index.to_u32(), (next_id, graph));
+            next_id += block_count;
+        }
+        Ok(Self { functions })
+    }
+
+    /// Calls the trap with the id of the block whenever control enters one,
+    /// before any of the instructions in it. Like the graph, this must be
+    /// applied to the body as parsed, before any other target transforms it.
+    pub fn transform(
+        &self,
+        high_level_body: &HighLevelBody,
+        function_index: Idx<Function>,
+        trap_idx: Idx<Function>,
+    ) -> HighLevelBody {
+        self.transform_with(high_level_body, function_index, |anchor, id| {
+            // STACK: []
+            let mut instrumentation = vec![anchor.instrument_with(Instr::Const(Val::I32(id)))];
+            // STACK: [block]
+            instrumentation.extend(anchor.to_trap_call(&trap_idx));
+            // STACK: []
+            instrumentation
+        })
+    }
+
+    /// Places the instructions `on_entry` yields for a block, given an anchor to
+    /// instrument with and the id of the block, wherever control enters it.
+    pub fn transform_with(
+        &self,
+        high_level_body: &HighLevelBody,
+        function_index: Idx<Function>,
+        on_entry: impl Fn(&TypedHighLevelInstr, i32) -> BodyInner,
+    ) -> HighLevelBody {
+        let HighLevelBody(body) = high_level_body;
+        let (first_id, graph) = &self.functions[&function_index.to_u32()];
+        let entries = Entries {
+            first_id: *first_id,
+            graph,
+            function_index,
+            on_entry,
+        };
+        HighLevelBody(entries.transform(body, 0))
+    }
+
+    pub fn install_side_table(&self, module: &mut Module) {
+        let blocks: Vec<String> = self
+            .functions
+            .iter()
+            .flat_map(|(function_index, (first_id, graph))| {
+                graph.blocks.iter().zip(*first_id..).map(move |(block, id)| {
+                    let successors: Vec<String> = block
+                        .successors
+                        .iter()
+                        .map(|position| (first_id + i32::try_from(*position).unwrap()).to_string())
+                        .collect();
+                    format!(
+                        r#"{{"id":{id},"function":{function_index},"start":{},"end":{},"successors":[{}]}}"#,
+                        block.start,
+                        block.end,
+                        successors.join(","),
+                    )
+                })
+            })
+            .collect();
+        module.custom_sections.push(RawCustomSection {
+            name: BASIC_BLOCKS_SECTION.to_string(),
+            content: format!("[{}]", blocks.join(",")).into_bytes(),
+            previous_section: None,
+        });
+    }
+}
+
+struct Entries<'a, OnEntry> {
+    first_id: i32,
+    graph: &'a ControlFlowGraph,
+    function_index: Idx<Function>,
+    on_entry: OnEntry,
+}
+
+impl<OnEntry: Fn(&TypedHighLevelInstr, i32) -> BodyInner> Entries<'_, OnEntry> {
+    /// `first_index` is the index of the first instruction of `body`.
+    fn transform(&self, body: &BodyInner, first_index: usize) -> BodyInner {
+        let mut result = Vec::new();
+
+        for typed_instr @ TypedHighLevelInstr {
+            instr, instr_index, ..
+        } in body
+        {
+            result.extend(self.enter(*instr_index));
+            let instr = match instr {
+                Instr::Block(type_, body) => {
+                    Instr::Block(*type_, self.transform(body, instr_index + 1))
+                }
+                Instr::Loop(type_, body) => {
+                    Instr::Loop(*type_, self.transform(body, instr_index + 1))
+                }
+                Instr::If(type_, then, else_) => {
+                    let else_index = closing_index(then, instr_index + 1);
+                    Instr::If(
+                        *type_,
+                        self.transform(then, instr_index + 1),
+                        else_
+                            .as_ref()
+                            .map(|else_| self.transform(else_, else_index + 1)),
+                    )
+                }
+                instr => instr.clone(),
+            };
+            result.push(typed_instr.place_untouched(instr));
+        }
+        // A block may start at the `else` or `end` closing the body
+        result.extend(self.enter(closing_index(body, first_index)));
+        result
+    }
+
+    fn enter(&self, instr_index: usize) -> BodyInner {
+        let Some(position) = self.graph.block_starting_at(instr_index) else {
+            return vec![];
+        };
+        let anchor = TypedHighLevelInstr::new_uninstrumented(
+            self.function_index.to_u32(),
+            instr_index,
+            InferredInstructionType::Unreachable, // Never inspected, only used to instrument with
+            Instr::Nop,
+        );
+        let id = self.first_id + i32::try_from(position).unwrap();
+        (self.on_entry)(&anchor, id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::{Engine, Instance, Module, Store};
+
+    use crate::parse_nesting::{HighLevelBody, LowLevelBody};
+
+    use super::BasicBlocks;
+
+    const LOOP_PROGRAM: &str = r#"
+    (module
+      (global $entries (mut i32) (i32.const 0))
+      (global $last (mut i32) (i32.const -1))
+
+      ;; Counts $n down to zero, entering the loop header and the block after
+      ;; the call once per iteration
+      (func $main (param $n i32) (result i32)
+        (loop $again
+          (local.set $n (call $decrement (local.get $n)))
+          (br_if $again (i32.gt_s (local.get $n) (i32.const 0))))
+        (local.get $n))
+
+      (func $block (param i32 i64 i64)
+        (global.set $entries (i32.add (global.get $entries) (i32.const 1)))
+        (global.set $last (local.get 0)))
+
+      (func $decrement (param i32) (result i32)
+        (i32.sub (local.get 0) (i32.const 1)))
+
+      (func (export "entries") (result i32) (global.get $entries))
+      (func (export "last") (result i32) (global.get $last))
+      (export "main" (func $main)))"#;
+
+    #[test]
+    fn test_basic_block_entries() {
+        let wasm_bytes = wat::parse_str(LOOP_PROGRAM).unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+        let basic_blocks = BasicBlocks::of(&wasm_module).unwrap();
+
+        let main = 0_u32.into();
+        let function = wasm_module.function(main);
+        let code = function.code().unwrap();
+        let high_level_body: HighLevelBody =
+            (&wasm_module, function, code, &main).try_into().unwrap();
+        let transformed = basic_blocks.transform(&high_level_body, main, 1_u32.into());
+        let LowLevelBody(low_level_body) = LowLevelBody::from(transformed);
+        wasm_module.function_mut(main).code_mut().unwrap().body = low_level_body;
+        basic_blocks.install_side_table(&mut wasm_module);
+
+        let instrumented = wasm_module.to_bytes().unwrap();
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let module = Module::new(&engine, &instrumented).unwrap();
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let main = instance
+            .get_typed_func::<i32, i32>(&mut store, "main")
+            .unwrap();
+        let entries = instance
+            .get_typed_func::<(), i32>(&mut store, "entries")
+            .unwrap();
+        let last = instance
+            .get_typed_func::<(), i32>(&mut store, "last")
+            .unwrap();
+
+        // The entry block, the loop header and the block after the call three
+        // times each, and the block after the loop
+        assert_eq!(main.call(&mut store, 3).unwrap(), 0);
+        assert_eq!(entries.call(&mut store, ()).unwrap(), 8);
+        assert_eq!(last.call(&mut store, ()).unwrap(), 3);
+
+        let (instrumented, _, _) = wasabi_wasm::Module::from_bytes(&instrumented).unwrap();
+        let side_table = instrumented
+            .custom_sections
+            .iter()
+            .find(|section| section.name == super::BASIC_BLOCKS_SECTION)
+            .unwrap();
+        assert!(std::str::from_utf8(&side_table.content)
+            .unwrap()
+            .starts_with(concat!(
+                r#"[{"id":0,"function":0,"start":0,"end":1,"successors":[1]},"#,
+                r#"{"id":1,"function":0,"start":1,"end":3,"successors":[2]},"#,
+                r#"{"id":2,"function":0,"start":3,"end":8,"successors":[1,3]},"#,
+                r#"{"id":3,"function":0,"start":8,"end":11,"successors":[]},"#,
+            )));
+    }
+}
@@ -1,9 +1,10 @@
 use crate::parse_nesting::{
     BodyInner, HighLevelBody, HighLevelInstr as Instr, TypedHighLevelInstr,
 };
-use wasabi_wasm::{Function, Idx, Module};
+use wasabi_wasm::{BinaryOp, Function, FunctionType, Idx, LocalOp, Module, Val, ValType};
 
 use super::TransformationStrategy;
+use crate::site_predicate::Sites;
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum Target {
@@ -11,22 +12,28 @@ pub enum Target {
     BlockPost(Idx<Function>),
     LoopPre(Idx<Function>),
     LoopPost(Idx<Function>),
+    LoopIteration(Idx<Function>),
     Select(Idx<Function>),
 }
 
 impl TransformationStrategy for Target {
-    fn transform(&self, high_level_body: &HighLevelBody, _: &mut Module) -> HighLevelBody {
+    fn transform(
+        &self,
+        high_level_body: &HighLevelBody,
+        module: &mut Module,
+        sites: &Sites,
+    ) -> HighLevelBody {
         let HighLevelBody(body) = high_level_body;
-        let transformed_body = transform(body, *self);
+        let transformed_body = transform(body, *self, sites, module);
         HighLevelBody(transformed_body)
     }
 }
 
-fn transform(body: &BodyInner, target: Target) -> BodyInner {
+fn transform(body: &BodyInner, target: Target, sites: &Sites, module: &mut Module) -> BodyInner {
     let mut result = Vec::new();
 
     for typed_instr @ TypedHighLevelInstr { instr, .. } in body {
-        if typed_instr.is_uninstrumented() {
+        if typed_instr.is_uninstrumented() && sites.selects(typed_instr) {
             match (target, instr) {
                 (Target::BlockPre(trap_idx), Instr::Block(type_, body)) => {
                     let mut injected_body = vec![
@@ -42,14 +49,14 @@ fn transform(body: &BodyInner, target: Target) -> BodyInner {
                     // STACK: [type_in, input_c:i32, arity:i32]
                     injected_body.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
                     // append rest of body
-                    injected_body.extend_from_slice(&transform(body, target));
+                    injected_body.extend_from_slice(&transform(body, target, sites, module));
                     // STACK: [type_in]
                     result.push(typed_instr.place_original(Instr::Block(*type_, injected_body)));
                     continue;
                 }
                 (Target::BlockPost(trap_idx), Instr::Block(type_, body)) => {
                     // STACK: [type_in]
-                    let mut injected_body = transform(body, target);
+                    let mut injected_body = transform(body, target, sites, module);
                     // append to rest of body
                     injected_body.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
                     // STACK: [type_in]
@@ -70,20 +77,59 @@ fn transform(body: &BodyInner, target: Target) -> BodyInner {
                     // STACK: [type_in, input_c:i32, arity:i32]
                     injected_body.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
                     // append rest of body
-                    injected_body.extend_from_slice(&transform(body, target));
+                    injected_body.extend_from_slice(&transform(body, target, sites, module));
                     // STACK: [type_in]
                     result.push(typed_instr.place_original(Instr::Loop(*type_, injected_body)));
                     continue;
                 }
                 (Target::LoopPost(trap_idx), Instr::Loop(type_, body)) => {
                     // STACK: [type_in]
-                    let mut injected_body = transform(body, target);
+                    let mut injected_body = transform(body, target, sites, module);
                     // append to rest of body
                     injected_body.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
                     // STACK: [type_in]
                     result.push(typed_instr.place_original(Instr::Loop(*type_, injected_body)));
                     continue;
                 }
+                (Target::LoopIteration(trap_idx), Instr::Loop(type_, body)) => {
+                    let iteration = module
+                        .function_mut(typed_instr.funct_index.into())
+                        .add_fresh_local(ValType::I64);
+                    let instrument = |instr| typed_instr.instrument_with(instr);
+                    // Every activation of the loop counts its iterations anew
+                    result.extend([
+                        instrument(Instr::Const(Val::I64(0))),
+                        instrument(Instr::Local(LocalOp::Set, iteration)),
+                    ]);
+                    // Control reaches the start of the body once upon entering
+                    // the loop and once through every back-edge, i.e. every
+                    // branch to the label of the loop. Only the latter find a
+                    // non-zero iteration count.
+                    let mut call_trap = vec![
+                        // STACK: [type_in]
+                        instrument(Instr::Local(LocalOp::Get, iteration)),
+                        // STACK: [type_in, iteration:i64]
+                    ];
+                    call_trap.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    let mut injected_body = vec![
+                        // STACK: [type_in]
+                        instrument(Instr::Local(LocalOp::Get, iteration)),
+                        instrument(Instr::Const(Val::I64(0))),
+                        instrument(Instr::Binary(BinaryOp::I64Ne)),
+                        // STACK: [type_in, back_edge:i32]
+                        instrument(Instr::if_then(FunctionType::new(&[], &[]), call_trap)),
+                        // STACK: [type_in]
+                        instrument(Instr::Local(LocalOp::Get, iteration)),
+                        instrument(Instr::Const(Val::I64(1))),
+                        instrument(Instr::Binary(BinaryOp::I64Add)),
+                        instrument(Instr::Local(LocalOp::Set, iteration)),
+                    ];
+                    // append rest of body
+                    injected_body.extend_from_slice(&transform(body, target, sites, module));
+                    // STACK: [type_in]
+                    result.push(typed_instr.place_original(Instr::Loop(*type_, injected_body)));
+                    continue;
+                }
                 (Target::Select(trap_idx), Instr::Select) => {
                     // STACK: [then_type_in, else_type_in, condition_i32]
                     result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
@@ -107,25 +153,31 @@ fn transform(body: &BodyInner, target: Target) -> BodyInner {
             (target, Instr::If(type_, then, None)) => {
                 result.push(typed_instr.place_untouched(Instr::If(
                     *type_,
-                    transform(then, target),
+                    transform(then, target, sites, module),
                     None,
                 )));
             }
             (target, Instr::If(type_, then, Some(else_))) => {
                 result.push(typed_instr.place_untouched(Instr::If(
                     *type_,
-                    transform(then, target),
-                    Some(transform(else_, target)),
+                    transform(then, target, sites, module),
+                    Some(transform(else_, target, sites, module)),
                 )))
             }
             (target, Instr::Loop(type_, body)) => {
                 result.push(
-                    typed_instr.place_untouched(Instr::Loop(*type_, transform(body, target))),
+                    typed_instr.place_untouched(Instr::Loop(
+                        *type_,
+                        transform(body, target, sites, module),
+                    )),
                 );
             }
             (target, Instr::Block(type_, body)) => {
                 result.push(
-                    typed_instr.place_untouched(Instr::Block(*type_, transform(body, target))),
+                    typed_instr.place_untouched(Instr::Block(
+                        *type_,
+                        transform(body, target, sites, module),
+                    )),
                 );
             }
             (_, instr) => result.push(typed_instr.place_untouched(instr.clone())),
@@ -136,5 +188,70 @@ fn transform(body: &BodyInner, target: Target) -> BodyInner {
 
 #[cfg(test)]
 mod tests {
-    // TODO:
+    use wasmtime::{Engine, Instance, Module, Store};
+
+    use crate::instrument::TransformationStrategy;
+    use crate::parse_nesting::{HighLevelBody, LowLevelBody};
+    use crate::site_predicate::Sites;
+
+    use super::Target;
+
+    const COUNTDOWN_PROGRAM: &str = r#"
+    (module
+      (global $back_edges (mut i32) (i32.const 0))
+      (global $last_iteration (mut i64) (i64.const 0))
+
+      (func $main (param $n i32) (result i32)
+        (loop $again
+          (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+          (br_if $again (i32.gt_s (local.get $n) (i32.const 0))))
+        (local.get $n))
+
+      (func $iteration (param i64 i64 i64)
+        (global.set $back_edges (i32.add (global.get $back_edges) (i32.const 1)))
+        (global.set $last_iteration (local.get 0)))
+
+      (func (export "back_edges") (result i32) (global.get $back_edges))
+      (func (export "last_iteration") (result i64) (global.get $last_iteration))
+      (export "main" (func $main)))"#;
+
+    #[test]
+    fn test_loop_iteration_per_back_edge() {
+        let wasm_bytes = wat::parse_str(COUNTDOWN_PROGRAM).unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+
+        let main = 0_u32.into();
+        let function = wasm_module.function(main);
+        let code = function.code().unwrap();
+        let high_level_body: HighLevelBody =
+            (&wasm_module, function, code, &main).try_into().unwrap();
+        let transformed = Target::LoopIteration(1_u32.into()).transform(
+            &high_level_body,
+            &mut wasm_module,
+            &Sites::default(),
+        );
+        let LowLevelBody(low_level_body) = LowLevelBody::from(transformed);
+        wasm_module.function_mut(main).code_mut().unwrap().body = low_level_body;
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let module = Module::new(&engine, wasm_module.to_bytes().unwrap()).unwrap();
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let main = instance
+            .get_typed_func::<i32, i32>(&mut store, "main")
+            .unwrap();
+        let back_edges = instance
+            .get_typed_func::<(), i32>(&mut store, "back_edges")
+            .unwrap();
+        let last_iteration = instance
+            .get_typed_func::<(), i64>(&mut store, "last_iteration")
+            .unwrap();
+
+        // Three iterations take two back-edges, counted anew on every call
+        for calls in 1..=2 {
+            assert_eq!(main.call(&mut store, 3).unwrap(), 0);
+            assert_eq!(back_edges.call(&mut store, ()).unwrap(), 2 * calls);
+            assert_eq!(last_iteration.call(&mut store, ()).unwrap(), 2);
+        }
+    }
 }
@@ -2,9 +2,10 @@ use crate::parse_nesting::{
     BodyInner, HighLevelBody, HighLevelInstr as Instr, TypedHighLevelInstr,
 };
 use generate_branch_table::{BrTable, Reified};
-use wasabi_wasm::{Function, FunctionType, Idx, Module, Val, ValType};
+use wasabi_wasm::{BinaryOp, Function, FunctionType, Idx, Label, LocalOp, Module, Val, ValType};
 
-use super::TransformationStrategy;
+use super::{Instrumentable, TransformationStrategy};
+use crate::site_predicate::Sites;
 
 mod generate_branch_table;
 
@@ -36,22 +37,86 @@ fn delta_to_instrument_instr(index_instr: &TypedHighLevelInstr) -> usize {
 
 // TODO: room for optimization - compute delta_to_instrument_body
 
+/// The kind of construct a branch targets, as passed to the analysis.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+enum LabelKind {
+    Block = 0,
+    Loop = 1,
+    If = 2,
+    Function = 3,
+}
+
+/// The construct a label refers to, by the index of the instruction that
+/// introduces it. The label of the function body has no such instruction.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+struct LabelTarget {
+    instr_index: i64,
+    kind: LabelKind,
+}
+
+impl LabelTarget {
+    const FUNCTION_BODY: Self = Self {
+        instr_index: -1,
+        kind: LabelKind::Function,
+    };
+
+    /// `labels` holds the enclosing labels, innermost last.
+    fn of(labels: &[Self], label: &Label) -> Self {
+        labels[labels.len() - 1 - usize::try_from(label.to_u32()).unwrap()]
+    }
+
+    fn instr_index(self) -> Instr {
+        Instr::Const(Val::I64(self.instr_index))
+    }
+
+    fn kind(self) -> Instr {
+        Instr::Const(Val::I32(self.kind as i32))
+    }
+}
+
+/// The labels enclosing the body of `typed_instr`, a block, loop or if.
+fn enclosed_by(labels: &[LabelTarget], typed_instr: &TypedHighLevelInstr) -> Vec<LabelTarget> {
+    let kind = match typed_instr.instr {
+        Instr::Loop(..) => LabelKind::Loop,
+        Instr::If(..) => LabelKind::If,
+        _ => LabelKind::Block,
+    };
+    let mut enclosed = labels.to_vec();
+    enclosed.push(LabelTarget {
+        instr_index: i64::try_from(typed_instr.instr_index).unwrap(),
+        kind,
+    });
+    enclosed
+}
+
 impl TransformationStrategy for Target {
-    fn transform(&self, high_level_body: &HighLevelBody, module: &mut Module) -> HighLevelBody {
+    fn transform(
+        &self,
+        high_level_body: &HighLevelBody,
+        module: &mut Module,
+        sites: &Sites,
+    ) -> HighLevelBody {
         let HighLevelBody(body) = high_level_body;
-        let transformed_body = transform(body, *self, module);
+        let transformed_body = transform(body, *self, sites, module, &[LabelTarget::FUNCTION_BODY]);
         HighLevelBody(transformed_body)
     }
 }
 
 /// # Panics
 /// When the index cannot be cast from u32 to i32
-fn transform(body: &BodyInner, target: Target, module: &mut Module) -> BodyInner {
+fn transform(
+    body: &BodyInner,
+    target: Target,
+    sites: &Sites,
+    module: &mut Module,
+    labels: &[LabelTarget],
+) -> BodyInner {
     let mut result: Vec<TypedHighLevelInstr> =
         Vec::with_capacity(body.iter().map(delta_to_instrument_instr).sum::<usize>() + body.len());
 
     for typed_instr @ TypedHighLevelInstr { instr, .. } in body {
-        if typed_instr.is_uninstrumented() {
+        let inner_labels = || enclosed_by(labels, typed_instr);
+        if typed_instr.is_uninstrumented() && sites.selects(typed_instr) {
             match (target, instr) {
                 (Target::Br(br_trap_idx), Instr::Br(label)) => {
                     // STACK: []
@@ -59,6 +124,12 @@ fn transform(body: &BodyInner, target: Target, module: &mut Module) -> BodyInner
                         typed_instr.instrument_with(Instr::Const(Val::I64(label.to_u32().into()))),
                     );
                     // STACK: [label]
+                    let label_target = LabelTarget::of(labels, label);
+                    result.extend_from_slice(&[
+                        typed_instr.instrument_with(label_target.instr_index()),
+                        typed_instr.instrument_with(label_target.kind()),
+                    ]);
+                    // STACK: [label, target_instr_index, target_kind]
                     result.extend_from_slice(&typed_instr.to_trap_call(&br_trap_idx));
                     // STACK: []
                     result.push(typed_instr.place_original(instr.clone()));
@@ -79,16 +150,53 @@ fn transform(body: &BodyInner, target: Target, module: &mut Module) -> BodyInner
                             .collect(),
                         reified_branch_table.body,
                     );
+                    module.name_generated(
+                        index_to_index_and_effective_target,
+                        &format!(
+                            "br_table_reified_{}_{}",
+                            typed_instr.funct_index, typed_instr.instr_index
+                        ),
+                    );
+                    let runtime_label = module
+                        .function_mut(typed_instr.funct_index.into())
+                        .add_fresh_local(ValType::I32);
                     result.extend_from_slice(&[
                         // STACK: [table_target_index]
                         typed_instr
                             .instrument_with(Instr::Call(index_to_index_and_effective_target)),
                         // STACK: [table_target_index, runtime_label]
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Tee, runtime_label)),
                         typed_instr.instrument_with(Instr::Const(Val::I32(
                             i32::try_from(default.to_u32()).expect("i32->u32"),
                         ))),
                     ]);
                     // STACK: [table_target_index, runtime_label, default]
+                    let mut labels_in_table = table.to_vec();
+                    labels_in_table.sort_by_key(|label| label.to_u32());
+                    labels_in_table.dedup();
+                    labels_in_table.retain(|label| label != default);
+                    // The value for the runtime label, falling back on the default
+                    let select_on_runtime_label = |value: fn(LabelTarget) -> Instr| {
+                        let mut selection =
+                            vec![typed_instr
+                                .instrument_with(value(LabelTarget::of(labels, default)))];
+                        for label in &labels_in_table {
+                            selection.extend([
+                                typed_instr.instrument_with(value(LabelTarget::of(labels, label))),
+                                typed_instr
+                                    .instrument_with(Instr::Local(LocalOp::Get, runtime_label)),
+                                typed_instr.instrument_with(Instr::Const(Val::I32(
+                                    i32::try_from(label.to_u32()).expect("i32->u32"),
+                                ))),
+                                typed_instr.instrument_with(Instr::Binary(BinaryOp::I32Eq)),
+                                typed_instr.instrument_with(Instr::Select),
+                            ]);
+                        }
+                        selection
+                    };
+                    result.extend(select_on_runtime_label(LabelTarget::instr_index));
+                    result.extend(select_on_runtime_label(LabelTarget::kind));
+                    // STACK: [table_target_index, runtime_label, default, target_iidx, target_kind]
                     result.extend_from_slice(&typed_instr.to_trap_call(&br_table_trap_idx));
                     // STACK: [table_target_index]
                     result.push(typed_instr.place_original(instr.clone()));
@@ -108,18 +216,17 @@ fn transform(body: &BodyInner, target: Target, module: &mut Module) -> BodyInner
                     // STACK: [type_in, condition, inputs-len:i32, results-len:i32]
                     result.extend_from_slice(&typed_instr.to_trap_call(&if_then_trap_idx));
                     // STACK: [type_in, kontinuation]
-                    result.push(
-                        typed_instr.place_original(Instr::if_then(
-                            *type_,
-                            transform(then, target, module),
-                        )),
-                    );
+                    result.push(typed_instr.place_original(Instr::if_then(
+                        *type_,
+                        transform(then, target, sites, module, &inner_labels()),
+                    )));
                     // STACK: [type_out]
                     continue;
                 }
                 (Target::IfThenPost(if_then_post_trap_idx), Instr::If(type_, then, None)) => {
                     // STACK: [type_in, continuation]
-                    let mut injected_then_body = transform(then, target, module);
+                    let mut injected_then_body =
+                        transform(then, target, sites, module, &inner_labels());
                     // append to rest of body
                     injected_then_body
                         .extend_from_slice(&typed_instr.to_trap_call(&if_then_post_trap_idx));
@@ -162,9 +269,9 @@ fn transform(body: &BodyInner, target: Target, module: &mut Module) -> BodyInner
                     result.push(typed_instr.place_original(Instr::if_then_else(
                         *type_,
                         // STACK: [type_in]
-                        transform(then, target, module),
+                        transform(then, target, sites, module, &inner_labels()),
                         // STACK: [type_in]
-                        transform(else_, target, module),
+                        transform(else_, target, sites, module, &inner_labels()),
                     )));
                     // STACK: [type_out]
                     continue;
@@ -174,12 +281,14 @@ fn transform(body: &BodyInner, target: Target, module: &mut Module) -> BodyInner
                     Instr::If(type_, then, Some(else_)),
                 ) => {
                     // Inject into then-body
-                    let mut injected_then_body = transform(then, target, module);
+                    let mut injected_then_body =
+                        transform(then, target, sites, module, &inner_labels());
                     // append to rest of body
                     injected_then_body
                         .extend_from_slice(&typed_instr.to_trap_call(&if_then_else_post_trap_idx));
                     // Inject into else-body
-                    let mut injected_else_body = transform(else_, target, module);
+                    let mut injected_else_body =
+                        transform(else_, target, sites, module, &inner_labels());
                     // append to rest of body
                     injected_else_body
                         .extend_from_slice(&typed_instr.to_trap_call(&if_then_else_post_trap_idx));
@@ -202,6 +311,12 @@ fn transform(body: &BodyInner, target: Target, module: &mut Module) -> BodyInner
                         i32::try_from(label.to_u32()).unwrap(),
                     ))));
                     // STACK: [condition, label]
+                    let label_target = LabelTarget::of(labels, label);
+                    result.extend_from_slice(&[
+                        typed_instr.instrument_with(label_target.instr_index()),
+                        typed_instr.instrument_with(label_target.kind()),
+                    ]);
+                    // STACK: [condition, label, target_instr_index, target_kind]
                     result.extend_from_slice(&typed_instr.to_trap_call(&br_if_trap_idx));
                     // STACK: [kontinuation]
                     result.push(typed_instr.place_original(instr.clone()));
@@ -217,28 +332,28 @@ fn transform(body: &BodyInner, target: Target, module: &mut Module) -> BodyInner
             (target, Instr::If(type_, then, None)) => {
                 result.push(typed_instr.place_untouched(Instr::If(
                     *type_,
-                    transform(then, target, module),
+                    transform(then, target, sites, module, &inner_labels()),
                     None,
                 )));
             }
             (target, Instr::If(type_, then, Some(else_))) => {
                 result.push(typed_instr.place_untouched(Instr::If(
                     *type_,
-                    transform(then, target, module),
-                    Some(transform(else_, target, module)),
+                    transform(then, target, sites, module, &inner_labels()),
+                    Some(transform(else_, target, sites, module, &inner_labels())),
                 )))
             }
             (target, Instr::Loop(type_, body)) => {
-                result.push(
-                    typed_instr
-                        .place_untouched(Instr::Loop(*type_, transform(body, target, module))),
-                );
+                result.push(typed_instr.place_untouched(Instr::Loop(
+                    *type_,
+                    transform(body, target, sites, module, &inner_labels()),
+                )));
             }
             (target, Instr::Block(type_, body)) => {
-                result.push(
-                    typed_instr
-                        .place_untouched(Instr::Block(*type_, transform(body, target, module))),
-                );
+                result.push(typed_instr.place_untouched(Instr::Block(
+                    *type_,
+                    transform(body, target, sites, module, &inner_labels()),
+                )));
             }
             (_, instr) => result.push(typed_instr.place_untouched(instr.clone())),
         }
@@ -473,8 +588,11 @@ mod tests {
         let code = function.code().unwrap();
         let high_level_body: HighLevelBody =
             (&wasm_module, function, code, &index).try_into().unwrap();
-        let transformed =
-            Target::IfThenElse(if_then_else_trap_idx).transform(&high_level_body, &mut wasm_module);
+        let transformed = Target::IfThenElse(if_then_else_trap_idx).transform(
+            &high_level_body,
+            &mut wasm_module,
+            &Sites::default(),
+        );
 
         let LowLevelBody(low_level_body) = LowLevelBody::from(transformed);
         wasm_module.function_mut(index).code_mut().unwrap().body = low_level_body;
@@ -660,6 +778,77 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_br_table_reports_target() {
+        use wasmtime::{Engine, Instance, Module, Store};
+
+        const BR_TABLE_PROGRAM: &str = r#"
+        (module
+          (global $target (mut i64) (i64.const -2))
+          (global $kind (mut i32) (i32.const -2))
+
+          (func $main (param $a i32) (result i32)
+            (block $outer (result i32)           ;; 0
+              (block $inner (result i32)         ;; 1
+                (i32.const 30)
+                (local.get $a)
+                (br_table $inner $outer 2))
+              (i32.const 10)
+              (i32.add))
+            (i32.const 20)
+            (i32.add))
+
+          (func $br_table (param i32 i32 i32 i64 i32 i64 i64) (result i32)
+            (global.set $target (local.get 3))
+            (global.set $kind (local.get 4))
+            (local.get 0))
+
+          (func (export "target") (result i64) (global.get $target))
+          (func (export "kind") (result i32) (global.get $kind))
+          (export "main" (func $main)))"#;
+
+        let wasm_bytes = wat::parse_str(BR_TABLE_PROGRAM).unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+
+        let main = 0_u32.into();
+        let function = wasm_module.function(main);
+        let code = function.code().unwrap();
+        let high_level_body: HighLevelBody =
+            (&wasm_module, function, code, &main).try_into().unwrap();
+        let transformed = Target::BrTable(1_u32.into()).transform(
+            &high_level_body,
+            &mut wasm_module,
+            &Sites::default(),
+        );
+        let LowLevelBody(low_level_body) = LowLevelBody::from(transformed);
+        wasm_module.function_mut(main).code_mut().unwrap().body = low_level_body;
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let module = Module::new(&engine, wasm_module.to_bytes().unwrap()).unwrap();
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let main = instance
+            .get_typed_func::<i32, i32>(&mut store, "main")
+            .unwrap();
+        let target = instance
+            .get_typed_func::<(), i64>(&mut store, "target")
+            .unwrap();
+        let kind = instance
+            .get_typed_func::<(), i32>(&mut store, "kind")
+            .unwrap();
+
+        for (input, output, target_instr_index, target_kind) in [
+            (0, 60, 1, LabelKind::Block),
+            (1, 50, 0, LabelKind::Block),
+            (2, 30, -1, LabelKind::Function),
+            (9, 30, -1, LabelKind::Function), // default
+        ] {
+            assert_eq!(main.call(&mut store, input).unwrap(), output);
+            assert_eq!(target.call(&mut store, ()).unwrap(), target_instr_index);
+            assert_eq!(kind.call(&mut store, ()).unwrap(), target_kind as i32);
+        }
+    }
+
     #[test]
     fn test_target() {
         let target = Target::IfThen(0_usize.into());
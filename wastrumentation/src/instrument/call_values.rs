@@ -0,0 +1,218 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parse_nesting::{
+    BodyInner, HighLevelBody, HighLevelInstr as Instr, TypedHighLevelInstr,
+};
+use crate::site_predicate::Sites;
+use crate::stack_library::ModuleLinkedStackHooks;
+use wasabi_wasm::{Function, FunctionType, Idx, LocalOp, Module, Val, ValType};
+
+use super::reference_handles::ReferenceHandles;
+use super::stack_values;
+
+/// The signatures of the functions that are called directly from `target_indices`.
+pub fn called_types(
+    module: &Module,
+    target_indices: &HashSet<Idx<Function>>,
+) -> HashSet<FunctionType> {
+    target_indices
+        .iter()
+        .filter_map(|index| module.function(*index).code())
+        .flat_map(|code| &code.body)
+        .filter_map(|instr| match instr {
+            wasabi_wasm::Instr::Call(callee) => Some(module.function(*callee).type_),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Spills the arguments and results of every direct `call` into a stack library
+/// buffer of the callee's signature, so the analysis can inspect and replace
+/// them. Unlike the generic apply hook, only the call sites in the targeted
+/// functions pay for this.
+pub struct CallValues<'a> {
+    pub pre_trap_idx: Option<Idx<Function>>,
+    pub post_trap_idx: Option<Idx<Function>>,
+    pub pre_sites: Sites<'a>,
+    pub post_sites: Sites<'a>,
+    pub signature_import_links: &'a HashMap<FunctionType, ModuleLinkedStackHooks>,
+    pub reference_handles: &'a ReferenceHandles,
+}
+
+impl CallValues<'_> {
+    pub fn transform(
+        &self,
+        high_level_body: &HighLevelBody,
+        function_index: Idx<Function>,
+        module: &mut Module,
+    ) -> HighLevelBody {
+        let HighLevelBody(body) = high_level_body;
+        HighLevelBody(self.transform_inner(body, function_index, module))
+    }
+
+    fn transform_inner(
+        &self,
+        body: &BodyInner,
+        function_index: Idx<Function>,
+        module: &mut Module,
+    ) -> BodyInner {
+        let mut result = Vec::new();
+
+        for typed_instr @ TypedHighLevelInstr { instr, .. } in body {
+            match instr {
+                Instr::Call(callee)
+                    if typed_instr.is_uninstrumented()
+                        && (self.pre_trap_idx(typed_instr).is_some()
+                            || self.post_trap_idx(typed_instr).is_some()) =>
+                {
+                    result.extend(self.instrument_call(
+                        typed_instr,
+                        *callee,
+                        function_index,
+                        module,
+                    ));
+                }
+                // DEFAULT TRAVERSAL
+                Instr::If(type_, then, else_) => {
+                    result.push(
+                        typed_instr.place_untouched(Instr::If(
+                            *type_,
+                            self.transform_inner(then, function_index, module),
+                            else_
+                                .as_ref()
+                                .map(|else_| self.transform_inner(else_, function_index, module)),
+                        )),
+                    );
+                }
+                Instr::Loop(type_, body) => {
+                    result.push(typed_instr.place_untouched(Instr::Loop(
+                        *type_,
+                        self.transform_inner(body, function_index, module),
+                    )));
+                }
+                Instr::Block(type_, body) => {
+                    result.push(typed_instr.place_untouched(Instr::Block(
+                        *type_,
+                        self.transform_inner(body, function_index, module),
+                    )));
+                }
+                instr => result.push(typed_instr.place_untouched(instr.clone())),
+            }
+        }
+        result
+    }
+
+    fn pre_trap_idx(&self, typed_instr: &TypedHighLevelInstr) -> Option<Idx<Function>> {
+        self.pre_trap_idx
+            .filter(|_| self.pre_sites.selects(typed_instr))
+    }
+
+    fn post_trap_idx(&self, typed_instr: &TypedHighLevelInstr) -> Option<Idx<Function>> {
+        self.post_trap_idx
+            .filter(|_| self.post_sites.selects(typed_instr))
+    }
+
+    fn instrument_call(
+        &self,
+        typed_instr: &TypedHighLevelInstr,
+        callee: Idx<Function>,
+        function_index: Idx<Function>,
+        module: &mut Module,
+    ) -> BodyInner {
+        use wasabi_wasm::Instr::{Call, Const, Local};
+
+        let callee_type = module.function(callee).type_;
+        let (args, results) = (callee_type.inputs(), callee_type.results());
+        let stack_hooks = self
+            .signature_import_links
+            .get(&callee_type)
+            .expect("Imported");
+        let arg_locals = stack_values::fresh_locals(module, function_index, args);
+        let result_locals = stack_values::fresh_locals(module, function_index, results);
+        let [values_buffer, types_buffer] = [ValType::I32; 2]
+            .map(|type_| module.function_mut(function_index).add_fresh_local(type_));
+        let all_types: Vec<ValType> = args.iter().chain(results).copied().collect();
+        let (save_handles, restore_handles) =
+            stack_values::save_handles(module, function_index, &all_types, self.reference_handles);
+
+        let runtime_values = || {
+            vec![
+                Const(Val::I32(i32::try_from(callee.to_u32()).unwrap())),
+                Const(Val::I32(i32::try_from(args.len()).unwrap())),
+                Const(Val::I32(i32::try_from(results.len()).unwrap())),
+                Local(LocalOp::Get, values_buffer),
+                Local(LocalOp::Get, types_buffer),
+            ]
+        };
+
+        // STACK: [args]
+        let mut store_args = stack_values::spill(&arg_locals);
+        // STACK: []
+        store_args.extend(save_handles);
+        store_args.extend(stack_values::push_for_stack(
+            &arg_locals,
+            args,
+            self.reference_handles,
+        ));
+        store_args.extend([
+            // STACK: [args]
+            Call(stack_hooks.allocate_values_buffer),
+            // STACK: [values_buffer]
+            Local(LocalOp::Set, values_buffer),
+            Call(stack_hooks.allocate_types_buffer),
+            Local(LocalOp::Set, types_buffer),
+            // STACK: []
+        ]);
+        let load_args = stack_values::load_from_stack(
+            values_buffer,
+            &stack_hooks.arg_load_n,
+            args,
+            self.reference_handles,
+        );
+
+        // STACK: [results]
+        let mut store_results = stack_values::spill(&result_locals);
+        store_results.push(Local(LocalOp::Get, values_buffer));
+        store_results.extend(stack_values::push_for_stack(
+            &result_locals,
+            results,
+            self.reference_handles,
+        ));
+        // STACK: [values_buffer, results]
+        store_results.push(Call(stack_hooks.ret_store_all));
+        // STACK: []
+        let mut load_results = stack_values::load_from_stack(
+            values_buffer,
+            &stack_hooks.ret_load_n,
+            results,
+            self.reference_handles,
+        );
+        // STACK: [results]
+        load_results.extend([
+            Local(LocalOp::Get, values_buffer),
+            Call(stack_hooks.free_values_buffer),
+            Local(LocalOp::Get, types_buffer),
+            Call(stack_hooks.free_types_buffer),
+        ]);
+        load_results.extend(restore_handles);
+
+        let mut instrumentation = stack_values::instrument_with(typed_instr, store_args);
+        if let Some(pre_trap_idx) = self.pre_trap_idx(typed_instr) {
+            // STACK: [f_tgt, argc, resc, sigv, sigtypv]
+            instrumentation.extend(stack_values::instrument_with(typed_instr, runtime_values()));
+            instrumentation.extend_from_slice(&typed_instr.to_trap_call(&pre_trap_idx));
+        }
+        instrumentation.extend(stack_values::instrument_with(typed_instr, load_args));
+        // STACK: [args]
+        instrumentation.push(typed_instr.place_original(Instr::Call(callee)));
+        // STACK: [results]
+        instrumentation.extend(stack_values::instrument_with(typed_instr, store_results));
+        if let Some(post_trap_idx) = self.post_trap_idx(typed_instr) {
+            // STACK: [f_tgt, argc, resc, sigv, sigtypv]
+            instrumentation.extend(stack_values::instrument_with(typed_instr, runtime_values()));
+            instrumentation.extend_from_slice(&typed_instr.to_trap_call(&post_trap_idx));
+        }
+        instrumentation.extend(stack_values::instrument_with(typed_instr, load_results));
+        instrumentation
+    }
+}
@@ -0,0 +1,605 @@
+// Resolves the function a `call_indirect` is about to call, without calling it.
+// Function references cannot be compared, so every funcref table the module
+// defines gets a shadow table in which the slot of a function `f` holds a thunk
+// that answers `f` when asked for the type of the call site, and `-1` otherwise.
+//
+// The shadow tables follow the original ones: element segments are copied into
+// them, and every `table.init`, `table.copy`, `table.grow` and `elem.drop` is
+// repeated on them. A function reference written by `table.set`, `table.fill`
+// or `table.grow` gets its thunk written along when the straight-line code
+// before the mutation shows where it comes from: a `ref.func`, or a `table.get`
+// of a shadowed table whose shadow slot is copied. Function references cannot be
+// compared at runtime, so one of any other origin cannot be told apart, and its
+// slot is nulled in the shadow table instead; it resolves to `-1`. So do the
+// slots of an imported table and those the host writes into an exported table.
+
+use std::collections::HashMap;
+
+use wasabi_wasm::Instr::{
+    Binary, Call, CallIndirect, Const, Drop, ElemDrop, Else, End, Global, If, Local, Nop, RefFunc,
+    RefIsNull, RefNull, TableCopy, TableFill, TableGet, TableGrow, TableInit, TableSet, TableSize,
+    Unary,
+};
+use wasabi_wasm::{
+    BinaryOp, Element, ElementMode, Function, FunctionType, GlobalOp, Idx, Instr, Limits, LocalOp,
+    Module, RefType, Table, Val, ValType,
+};
+
+use super::{stack_values, Instrumentable};
+
+/// The resolved function index of a slot that is null, holds a function of
+/// another type than the call site expects, or cannot be resolved.
+pub const UNRESOLVED: i32 = -1;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+struct ShadowTable {
+    table: Idx<Table>,
+    resolve: Idx<Function>,
+}
+
+#[derive(PartialEq, Eq, Default, Debug)]
+pub struct CalleeResolution {
+    shadow_tables: HashMap<Idx<Table>, ShadowTable>,
+    shadow_elements: HashMap<Idx<Element>, Idx<Element>>,
+    thunks: HashMap<Idx<Function>, Idx<Function>>,
+    type_ids: HashMap<FunctionType, i32>,
+}
+
+/// Where the function reference a table mutation writes comes from.
+enum Origin {
+    Function(Idx<Function>),
+    /// A `table.get` of the slot the instruction pushes, which still holds
+    /// the same reference at the mutation.
+    Slot(Idx<Table>, Instr),
+}
+
+impl CalleeResolution {
+    /// Must be installed before instrumentation adds tables and element
+    /// segments of its own.
+    pub fn install(module: &mut Module) -> Self {
+        let mut resolution = Self::default();
+        let thunk_type = FunctionType::new(&[ValType::I32], &[ValType::I32]);
+
+        let referenced_by_code = module
+            .functions()
+            .filter_map(|(_, function)| function.code())
+            .flat_map(|code| &code.body)
+            .filter_map(|instr| match instr {
+                RefFunc(function_idx) => Some(*function_idx),
+                _ => None,
+            });
+        let referenced: Vec<Idx<Function>> = module
+            .elements
+            .iter()
+            .filter(|element| element.typ == RefType::FuncRef)
+            .flat_map(|element| &element.init)
+            .filter_map(|init| match init.as_slice() {
+                [RefFunc(function_idx), End] => Some(*function_idx),
+                _ => None,
+            })
+            .chain(referenced_by_code)
+            .collect();
+        for function_idx in referenced {
+            if resolution.thunks.contains_key(&function_idx) {
+                continue;
+            }
+            let function_type = module.function(function_idx).type_;
+            let next_type_id = i32::try_from(resolution.type_ids.len()).unwrap();
+            let type_id = *resolution
+                .type_ids
+                .entry(function_type)
+                .or_insert(next_type_id);
+            let thunk = module.add_function(
+                thunk_type,
+                vec![],
+                vec![
+                    // [expected_type_id]
+                    Local(LocalOp::Get, 0_u32.into()),
+                    Const(Val::I32(type_id)),
+                    Binary(BinaryOp::I32Eq),
+                    // [type_matches]
+                    If(FunctionType::new(&[], &[ValType::I32])),
+                    Const(Val::I32(i32::try_from(function_idx.to_u32()).unwrap())),
+                    Else,
+                    Const(Val::I32(UNRESOLVED)),
+                    End,
+                    // [resolved]
+                    End,
+                ],
+            );
+            module.name_generated(thunk, &format!("resolve_thunk_{}", function_idx.to_u32()));
+            resolution.thunks.insert(function_idx, thunk);
+        }
+
+        let defined_function_tables: Vec<(Idx<Table>, Limits)> = module
+            .tables
+            .iter()
+            .enumerate()
+            .filter(|(_, table)| table.ref_type == RefType::FuncRef && table.import.is_none())
+            .map(|(index, table)| (index.into(), table.limits.clone()))
+            .collect();
+        for (table_idx, limits) in defined_function_tables {
+            let shadow = Idx::from(module.tables.len());
+            module.tables.push(Table {
+                limits,
+                import: None,
+                ref_type: RefType::FuncRef,
+                export: vec![],
+            });
+            let resolve = module.add_function(
+                FunctionType::new(&[ValType::I32, ValType::I32], &[ValType::I32]),
+                vec![],
+                vec![
+                    // []
+                    Local(LocalOp::Get, 0_u32.into()), // slot
+                    TableSize(shadow),
+                    Binary(BinaryOp::I32LtU),
+                    // [in_bounds]
+                    If(FunctionType::new(&[], &[ValType::I32])),
+                    Local(LocalOp::Get, 0_u32.into()), // slot
+                    TableGet(shadow),
+                    RefIsNull,
+                    // [is_null]
+                    If(FunctionType::new(&[], &[ValType::I32])),
+                    Const(Val::I32(UNRESOLVED)),
+                    Else,
+                    Local(LocalOp::Get, 1_u32.into()), // expected_type_id
+                    Local(LocalOp::Get, 0_u32.into()), // slot
+                    CallIndirect(thunk_type, shadow),
+                    End,
+                    Else,
+                    Const(Val::I32(UNRESOLVED)),
+                    End,
+                    // [resolved]
+                    End,
+                ],
+            );
+            module.name_generated(resolve, &format!("resolve_table_{}", table_idx.to_u32()));
+            resolution.shadow_tables.insert(
+                table_idx,
+                ShadowTable {
+                    table: shadow,
+                    resolve,
+                },
+            );
+        }
+
+        let function_elements: Vec<(Idx<Element>, Element)> = module
+            .elements
+            .iter()
+            .enumerate()
+            .filter(|(_, element)| element.typ == RefType::FuncRef)
+            .map(|(index, element)| (index.into(), element.clone()))
+            .collect();
+        for (element_idx, element) in function_elements {
+            let mode = match element.mode {
+                ElementMode::Passive => ElementMode::Passive,
+                ElementMode::Active { table, offset } => match resolution.shadow_tables.get(&table)
+                {
+                    Some(ShadowTable { table, .. }) => ElementMode::Active {
+                        table: *table,
+                        offset,
+                    },
+                    None => continue,
+                },
+                ElementMode::Declarative => continue,
+            };
+            let init = element
+                .init
+                .iter()
+                .map(|init| match init.as_slice() {
+                    [RefFunc(function_idx), End] => {
+                        vec![RefFunc(resolution.thunks[function_idx]), End]
+                    }
+                    _ => vec![RefNull(RefType::FuncRef), End],
+                })
+                .collect();
+            let shadow = Idx::from(module.elements.len());
+            module.elements.push(Element {
+                typ: RefType::FuncRef,
+                init,
+                mode,
+            });
+            resolution.shadow_elements.insert(element_idx, shadow);
+        }
+
+        // Declares the thunks, so `mirror` may write them with `ref.func`
+        if !resolution.thunks.is_empty() {
+            let mut declared: Vec<Idx<Function>> = resolution.thunks.values().copied().collect();
+            declared.sort_by_key(|thunk| thunk.to_u32());
+            module.elements.push(Element {
+                typ: RefType::FuncRef,
+                init: declared
+                    .into_iter()
+                    .map(|thunk| vec![RefFunc(thunk), End])
+                    .collect(),
+                mode: ElementMode::Declarative,
+            });
+        }
+
+        resolution
+    }
+
+    /// STACK: [slot] -> [resolved], the index of the function in `slot` of
+    /// `table` if it is of `function_type`, [`UNRESOLVED`] otherwise.
+    pub fn resolve(&self, table: &Idx<Table>, function_type: &FunctionType) -> Vec<Instr> {
+        match (
+            self.shadow_tables.get(table),
+            self.type_ids.get(function_type),
+        ) {
+            (Some(ShadowTable { resolve, .. }), Some(type_id)) => {
+                vec![Const(Val::I32(*type_id)), Call(*resolve)]
+            }
+            // No function in an element segment of the module has this type
+            _ => vec![Drop, Const(Val::I32(UNRESOLVED))],
+        }
+    }
+
+    /// Repeats every mutation of a shadowed table on its shadow table, in all
+    /// functions of `module`. Runs last, so the mutations performed by the base
+    /// shims the instrumentation injects are followed as well.
+    ///
+    /// Returns, per function it rewrote, the index in the previous body of
+    /// every instruction of the rewritten body.
+    pub fn mirror_table_mutations(
+        &self,
+        module: &mut Module,
+    ) -> HashMap<Idx<Function>, Vec<usize>> {
+        let functions_with_code: Vec<Idx<Function>> = module
+            .functions()
+            .filter(|(_index, function)| function.code().is_some())
+            .map(|(index, _)| index)
+            .collect();
+
+        let mut origins = HashMap::new();
+        for function_index in functions_with_code {
+            let body = std::mem::take(
+                &mut module
+                    .function_mut(function_index)
+                    .code_mut()
+                    .expect("Has code")
+                    .body,
+            );
+            let mut mirrored_body = Vec::with_capacity(body.len());
+            let mut body_origins = Vec::with_capacity(body.len());
+            let mut mirrored_any = false;
+            for (index, instr) in body.iter().enumerate() {
+                match self.mirror(instr, &body[..index], function_index, module) {
+                    Some(mirrored) => {
+                        mirrored_any = true;
+                        body_origins.resize(body_origins.len() + mirrored.len(), index);
+                        mirrored_body.extend(mirrored);
+                    }
+                    None => {
+                        body_origins.push(index);
+                        mirrored_body.push(instr.clone());
+                    }
+                }
+            }
+            module
+                .function_mut(function_index)
+                .code_mut()
+                .expect("Has code")
+                .body = mirrored_body;
+            if mirrored_any {
+                origins.insert(function_index, body_origins);
+            }
+        }
+        origins
+    }
+
+    fn shadow_table(&self, table: &Idx<Table>) -> Option<Idx<Table>> {
+        self.shadow_tables.get(table).map(|shadow| shadow.table)
+    }
+
+    /// STACK: [] -> [thunk], the thunk of the function reference that comes
+    /// from `origin`, null if it is unknown.
+    fn thunk(&self, origin: Option<Origin>) -> Vec<Instr> {
+        let thunk = match origin {
+            Some(Origin::Function(function)) => self
+                .thunks
+                .get(&function)
+                .map(|thunk| vec![RefFunc(*thunk)]),
+            Some(Origin::Slot(table, slot)) => self
+                .shadow_table(&table)
+                .map(|shadow| vec![slot, TableGet(shadow)]),
+            None => None,
+        };
+        thunk.unwrap_or_else(|| vec![RefNull(RefType::FuncRef)])
+    }
+
+    /// `instr` followed by its counterpart on the shadow tables, if it mutates
+    /// a shadowed table. `preceding` are the instructions of the body before
+    /// `instr`.
+    fn mirror(
+        &self,
+        instr: &Instr,
+        preceding: &[Instr],
+        function_index: Idx<Function>,
+        module: &mut Module,
+    ) -> Option<Vec<Instr>> {
+        let get = |local| Local(LocalOp::Get, local);
+        let funcref = ValType::Ref(RefType::FuncRef);
+
+        match instr {
+            TableInit(table, element) => {
+                let shadow_table = self.shadow_table(table)?;
+                let shadow_element = *self.shadow_elements.get(element)?;
+                let operands = [ValType::I32; 3];
+                let [destination, source, length] = fresh_locals(module, function_index, operands);
+                Some(replay(
+                    instr,
+                    &[destination, source, length],
+                    [
+                        get(destination),
+                        get(source),
+                        get(length),
+                        TableInit(shadow_table, shadow_element),
+                    ],
+                ))
+            }
+            TableCopy(destination_table, source_table) => {
+                let shadow_destination = self.shadow_table(destination_table)?;
+                let shadow_source = self.shadow_table(source_table)?;
+                let operands = [ValType::I32; 3];
+                let [destination, source, length] = fresh_locals(module, function_index, operands);
+                Some(replay(
+                    instr,
+                    &[destination, source, length],
+                    [
+                        get(destination),
+                        get(source),
+                        get(length),
+                        TableCopy(shadow_destination, shadow_source),
+                    ],
+                ))
+            }
+            ElemDrop(element) => {
+                let shadow_element = *self.shadow_elements.get(element)?;
+                Some(vec![instr.clone(), ElemDrop(shadow_element)])
+            }
+            TableGrow(table) => {
+                let shadow_table = self.shadow_table(table)?;
+                let thunk = self.thunk(origin(module, preceding, preceding.len(), 1));
+                let [value, delta] = fresh_locals(module, function_index, [funcref, ValType::I32]);
+                // [previous_size]
+                let mut shadow = thunk;
+                shadow.extend([get(delta), TableGrow(shadow_table), Drop]);
+                Some(replay(instr, &[value, delta], shadow))
+            }
+            TableSet(table) => {
+                let shadow_table = self.shadow_table(table)?;
+                let thunk = self.thunk(origin(module, preceding, preceding.len(), 0));
+                let [slot, value] = fresh_locals(module, function_index, [ValType::I32, funcref]);
+                let mut shadow = vec![get(slot)];
+                shadow.extend(thunk);
+                shadow.push(TableSet(shadow_table));
+                Some(replay(instr, &[slot, value], shadow))
+            }
+            TableFill(table) => {
+                let shadow_table = self.shadow_table(table)?;
+                let thunk = self.thunk(origin(module, preceding, preceding.len(), 1));
+                let [slot, value, length] = fresh_locals(
+                    module,
+                    function_index,
+                    [ValType::I32, funcref, ValType::I32],
+                );
+                let mut shadow = vec![get(slot)];
+                shadow.extend(thunk);
+                shadow.extend([get(length), TableFill(shadow_table)]);
+                Some(replay(instr, &[slot, value, length], shadow))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The origin of the value `depth` values below the top of the stack after
+/// the first `before` instructions of `preceding`, as far as the straight-line
+/// code up to the end of `preceding` tells.
+fn origin(module: &Module, preceding: &[Instr], before: usize, depth: usize) -> Option<Origin> {
+    let index = producer(module, &preceding[..before], depth)?;
+    match &preceding[index] {
+        RefFunc(function) => Some(Origin::Function(*function)),
+        Local(LocalOp::Tee, _) => origin(module, preceding, index, 0),
+        Local(LocalOp::Get, local) => {
+            let assignment = preceding[..index].iter().rposition(|instr| {
+                matches!(instr, Local(LocalOp::Set | LocalOp::Tee, assigned) if assigned == local)
+            })?;
+            // Only when the local is assigned in the same straight-line code
+            let straight_line = preceding[assignment + 1..index]
+                .iter()
+                .all(|instr| stack_effect(module, instr).is_some());
+            if !straight_line {
+                return None;
+            }
+            origin(module, preceding, assignment, 0)
+        }
+        TableGet(table) => {
+            let slot_index = producer(module, &preceding[..index], 0)?;
+            let slot = &preceding[slot_index];
+            let after_slot = &preceding[slot_index + 1..];
+            // The table may only change through calls, `mirror` does not walk
+            // past any other mutation
+            let unchanged = !preceding[index + 1..]
+                .iter()
+                .any(|instr| matches!(instr, Call(_)))
+                && match slot {
+                    Const(_) => true,
+                    Local(LocalOp::Get, local) => !after_slot.iter().any(|instr| {
+                        matches!(instr, Local(LocalOp::Set | LocalOp::Tee, assigned) if assigned == local)
+                    }),
+                    _ => false,
+                };
+            unchanged.then(|| Origin::Slot(*table, slot.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// The index of the instruction among `preceding` that pushed the value
+/// `depth` values below the top of the stack, if only straight-line code of
+/// known stack effect follows it.
+fn producer(module: &Module, preceding: &[Instr], mut depth: usize) -> Option<usize> {
+    for (index, instr) in preceding.iter().enumerate().rev() {
+        let (pops, pushes) = stack_effect(module, instr)?;
+        if depth < pushes {
+            return Some(index);
+        }
+        depth = depth - pushes + pops;
+    }
+    None
+}
+
+/// The number of values `instr` pops and pushes, for the instructions that
+/// neither branch nor mutate a table.
+fn stack_effect(module: &Module, instr: &Instr) -> Option<(usize, usize)> {
+    let effect = match instr {
+        Nop => (0, 0),
+        Const(_) | RefFunc(_) | RefNull(_) | TableSize(_) => (0, 1),
+        Local(LocalOp::Get, _) | Global(GlobalOp::Get, _) => (0, 1),
+        Drop | Local(LocalOp::Set, _) | Global(GlobalOp::Set, _) => (1, 0),
+        Local(LocalOp::Tee, _) | TableGet(_) | RefIsNull | Unary(_) => (1, 1),
+        Binary(_) => (2, 1),
+        Call(function) => {
+            let function_type = module.function(*function).type_;
+            (function_type.inputs().len(), function_type.results().len())
+        }
+        _ => return None,
+    };
+    Some(effect)
+}
+
+fn fresh_locals<const N: usize>(
+    module: &mut Module,
+    function_index: Idx<Function>,
+    types: [ValType; N],
+) -> [Idx<wasabi_wasm::Local>; N] {
+    types.map(|type_| module.function_mut(function_index).add_fresh_local(type_))
+}
+
+/// Stashes the operands of `instr` in `operands`, performs `instr` and then
+/// `shadow`, which may read the operands back from their locals.
+fn replay(
+    instr: &Instr,
+    operands: &[Idx<wasabi_wasm::Local>],
+    shadow: impl IntoIterator<Item = Instr>,
+) -> Vec<Instr> {
+    let mut replayed = stack_values::spill(operands);
+    replayed.extend(operands.iter().map(|operand| Local(LocalOp::Get, *operand)));
+    replayed.push(instr.clone());
+    replayed.extend(shadow);
+    replayed
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::{Engine, Instance, Module, Store};
+
+    use crate::parse_nesting::{HighLevelBody, LowLevelBody};
+    use crate::site_predicate::Sites;
+
+    use super::super::function_call_indirect::Target;
+    use super::{CalleeResolution, UNRESOLVED};
+
+    const INDIRECT_PROGRAM: &str = r#"
+    (module
+      (type $unary (func (param i32) (result i32)))
+      (table $functions 3 funcref)
+      (elem (table $functions) (i32.const 0) func $double $triple)
+      (elem $late func $triple)
+      (global $resolved (mut i32) (i32.const -2))
+
+      (func $main (param $slot i32) (result i32)
+        (call_indirect (type $unary) (i32.const 5) (local.get $slot)))
+      (func $double (param i32) (result i32)
+        (i32.mul (local.get 0) (i32.const 2)))
+      (func $triple (param i32) (result i32)
+        (i32.mul (local.get 0) (i32.const 3)))
+
+      (func $post (param i32 i32 i64 i64)
+        (global.set $resolved (local.get 1)))
+
+      (func (export "late_init")
+        (table.init $functions $late (i32.const 2) (i32.const 0) (i32.const 1)))
+      (func (export "overwrite")
+        (table.set $functions (i32.const 0) (ref.func $triple)))
+      (func (export "copy") (param $to i32) (param $from i32)
+        (table.set $functions (local.get $to) (table.get $functions (local.get $from))))
+      (func $passed (param $to i32) (param $function funcref)
+        (table.set $functions (local.get $to) (local.get $function)))
+      (func (export "pass") (param $to i32)
+        (call $passed (local.get $to) (ref.func $triple)))
+      (func (export "resolved") (result i32) (global.get $resolved))
+      (export "main" (func $main)))"#;
+
+    #[test]
+    fn test_resolves_callee_through_table_mutations() {
+        let wasm_bytes = wat::parse_str(INDIRECT_PROGRAM).unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+
+        let (main, double, triple, post) = (0_u32, 1_u32, 2_u32, 3_u32);
+        let resolution = CalleeResolution::install(&mut wasm_module);
+        let main_idx = main.into();
+        let function = wasm_module.function(main_idx);
+        let code = function.code().unwrap();
+        let high_level_body: HighLevelBody = (&wasm_module, function, code, &main_idx)
+            .try_into()
+            .unwrap();
+        let target = Target::IndirectPost(post.into(), &resolution);
+        let transformed =
+            high_level_body.transform_call_indirect(target, &mut wasm_module, &Sites::default());
+        let LowLevelBody(low_level_body) = LowLevelBody::from(transformed);
+        wasm_module.function_mut(main_idx).code_mut().unwrap().body = low_level_body;
+        resolution.mirror_table_mutations(&mut wasm_module);
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let module = Module::new(&engine, wasm_module.to_bytes().unwrap()).unwrap();
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let main = instance
+            .get_typed_func::<i32, i32>(&mut store, "main")
+            .unwrap();
+        let resolved = instance
+            .get_typed_func::<(), i32>(&mut store, "resolved")
+            .unwrap();
+        let late_init = instance
+            .get_typed_func::<(), ()>(&mut store, "late_init")
+            .unwrap();
+        let overwrite = instance
+            .get_typed_func::<(), ()>(&mut store, "overwrite")
+            .unwrap();
+        let copy = instance
+            .get_typed_func::<(i32, i32), ()>(&mut store, "copy")
+            .unwrap();
+        let pass = instance
+            .get_typed_func::<i32, ()>(&mut store, "pass")
+            .unwrap();
+
+        assert_eq!(main.call(&mut store, 0).unwrap(), 10);
+        assert_eq!(resolved.call(&mut store, ()).unwrap(), double as i32);
+        assert_eq!(main.call(&mut store, 1).unwrap(), 15);
+        assert_eq!(resolved.call(&mut store, ()).unwrap(), triple as i32);
+
+        // A null slot traps the call
+        assert!(main.call(&mut store, 2).is_err());
+        late_init.call(&mut store, ()).unwrap();
+        assert_eq!(main.call(&mut store, 2).unwrap(), 15);
+        assert_eq!(resolved.call(&mut store, ()).unwrap(), triple as i32);
+
+        // A reference written by `table.set` resolves if it is a copy of a slot
+        copy.call(&mut store, (2, 0)).unwrap();
+        assert_eq!(main.call(&mut store, 2).unwrap(), 10);
+        assert_eq!(resolved.call(&mut store, ()).unwrap(), double as i32);
+
+        // or a `ref.func`
+        overwrite.call(&mut store, ()).unwrap();
+        assert_eq!(main.call(&mut store, 0).unwrap(), 15);
+        assert_eq!(resolved.call(&mut store, ()).unwrap(), triple as i32);
+
+        // but not if it comes from anywhere else
+        pass.call(&mut store, 1).unwrap();
+        assert_eq!(main.call(&mut store, 1).unwrap(), 15);
+        assert_eq!(resolved.call(&mut store, ()).unwrap(), UNRESOLVED);
+    }
+}
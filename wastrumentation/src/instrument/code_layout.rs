@@ -0,0 +1,154 @@
+// Where a module encodes its functions and their instructions. The parsed
+// module does not keep this, though source locations and DWARF refer to code
+// by it.
+
+use std::collections::HashMap;
+
+use wasmparser::{Parser, Payload, TypeRef};
+
+use crate::error::InstrumentationError;
+
+/// Offsets are from the start of the code section, i.e. the byte after its
+/// size, which is how DWARF addresses code in WebAssembly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionLayout {
+    /// The size of the body, which the body starts with
+    pub start: u32,
+    /// The declarations of the locals, after the size
+    pub locals: u32,
+    /// Every instruction, in order of index
+    pub instructions: Vec<u32>,
+    /// Just past the body
+    pub end: u32,
+}
+
+pub struct CodeLayout<'a> {
+    imported_functions: u32,
+    /// Per function with code, in order of index
+    functions: Vec<FunctionLayout>,
+    /// The size of the code section
+    pub size: u32,
+    /// The custom sections holding DWARF, by name, e.g. `.debug_line`
+    pub debug_sections: HashMap<&'a str, &'a [u8]>,
+}
+
+impl<'a> CodeLayout<'a> {
+    pub fn of(module: &'a [u8]) -> Result<Self, InstrumentationError> {
+        let mut layout = Self {
+            imported_functions: 0,
+            functions: Vec::new(),
+            size: 0,
+            debug_sections: HashMap::new(),
+        };
+        let mut code_section_start = 0;
+        // Where the next body starts, its size included
+        let mut next_start = 0;
+        let offset = |position: usize, code_section_start: usize| {
+            u32::try_from(position - code_section_start).unwrap()
+        };
+
+        for payload in Parser::new(0).parse_all(module) {
+            match payload.map_err(InstrumentationError::ReadCodeOffsetsError)? {
+                Payload::ImportSection(imports) => {
+                    for import in imports {
+                        let import = import.map_err(InstrumentationError::ReadCodeOffsetsError)?;
+                        if matches!(import.ty, TypeRef::Func(_)) {
+                            layout.imported_functions += 1;
+                        }
+                    }
+                }
+                Payload::CodeSectionStart { range, size, .. } => {
+                    code_section_start = range.start;
+                    // The number of bodies precedes the first one, possibly padded
+                    let count_length = module[range.start..]
+                        .iter()
+                        .position(|byte| byte & 0x80 == 0)
+                        .map_or(0, |position| position + 1);
+                    next_start = range.start + count_length;
+                    layout.size = size;
+                }
+                Payload::CodeSectionEntry(body) => {
+                    let mut operators = body
+                        .get_operators_reader()
+                        .map_err(InstrumentationError::ReadCodeOffsetsError)?;
+                    let mut instructions = Vec::new();
+                    while !operators.eof() {
+                        let (_operator, position) = operators
+                            .read_with_offset()
+                            .map_err(InstrumentationError::ReadCodeOffsetsError)?;
+                        instructions.push(offset(position, code_section_start));
+                    }
+                    let range = body.range();
+                    layout.functions.push(FunctionLayout {
+                        start: offset(next_start, code_section_start),
+                        locals: offset(range.start, code_section_start),
+                        instructions,
+                        end: offset(range.end, code_section_start),
+                    });
+                    next_start = range.end;
+                }
+                Payload::CustomSection(section) if section.name().starts_with(".debug_") => {
+                    layout.debug_sections.insert(section.name(), section.data());
+                }
+                _ => {}
+            }
+        }
+        Ok(layout)
+    }
+
+    /// The layout of the function at `function_index`, if it has code.
+    pub fn function(&self, function_index: u32) -> Option<&FunctionLayout> {
+        let position = function_index.checked_sub(self.imported_functions)?;
+        self.nth(usize::try_from(position).ok()?)
+    }
+
+    /// The layout of the `ordinal`-th function with code.
+    pub fn nth(&self, ordinal: usize) -> Option<&FunctionLayout> {
+        self.functions.get(ordinal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CodeLayout, FunctionLayout};
+
+    // The code section holds the number of bodies, then per body its size, the
+    // number of local declarations and its instructions.
+    const PROGRAM: &str = r#"
+    (module
+      (import "host" "f" (func))
+      (func (result i32)
+        i32.const 1
+        i32.const 2
+        i32.add)
+      (func
+        nop))"#;
+
+    #[test]
+    fn test_code_layout() {
+        let wasm_bytes = wat::parse_str(PROGRAM).unwrap();
+        let layout = CodeLayout::of(&wasm_bytes).unwrap();
+
+        assert!(layout.function(0).is_none());
+        assert_eq!(
+            layout.function(1),
+            Some(&FunctionLayout {
+                start: 1,
+                locals: 2,
+                instructions: vec![3, 5, 7, 8],
+                end: 9,
+            })
+        );
+        assert_eq!(
+            layout.function(2),
+            Some(&FunctionLayout {
+                start: 9,
+                locals: 10,
+                instructions: vec![11, 12],
+                end: 13,
+            })
+        );
+        assert_eq!(layout.size, 13);
+        assert!(layout.debug_sections.is_empty());
+    }
+}
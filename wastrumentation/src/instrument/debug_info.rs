@@ -0,0 +1,639 @@
+// DWARF addresses code by its offset from the start of the code section. The
+// instrumentation grows the bodies it rewrites and moves the original code of
+// a function into a new function when wrapping it, so the DWARF of the input
+// program no longer matches the instrumented module as is.
+//
+// Every instruction of a rewritten body is traced back to the instruction of
+// the input program it originates from, an injected one to the instruction it
+// instruments. An address of the input program then maps onto the first
+// instruction that originates from it, so a breakpoint on a line stops before
+// the hooks of its instructions run.
+//
+// The DWARF is converted with gimli, which only translates the start of a line
+// sequence, so the line rows, the extents of entries and their range lists are
+// rewritten here. Accelerator tables, e.g. `.debug_aranges`, are dropped. The
+// first module of a merge keeps its DWARF, see `Wastrumenter::merge`.
+//
+// Rewriting is opt in, as it fails on DWARF gimli cannot convert. Otherwise the
+// DWARF is stripped, see `strip`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use gimli::write::{self, Address, EndianVec, Sections};
+use gimli::{EndianSlice, LittleEndian, Reader};
+use wasabi_wasm::{Function, Idx, Module, RawCustomSection};
+
+use super::code_layout::{CodeLayout, FunctionLayout};
+use super::owned_memory::OwnedMemory;
+use crate::error::InstrumentationError;
+
+const DEBUG_SECTION_PREFIX: &str = ".debug_";
+
+type DwarfReader<'a> = EndianSlice<'a, LittleEndian>;
+
+/// Removes the DWARF of the input program from `module`, as it no longer
+/// matches the code once instrumented.
+pub fn strip(module: &mut Module) {
+    module
+        .custom_sections
+        .retain(|section| !section.name.starts_with(DEBUG_SECTION_PREFIX));
+}
+
+pub struct DebugInfo<'a> {
+    layout: CodeLayout<'a>,
+    /// Per function holding code of the input program, the function of the
+    /// input program and, per instruction of the body it holds, the index of
+    /// the instruction in that function it originates from
+    holders: HashMap<Idx<Function>, (Idx<Function>, Vec<usize>)>,
+}
+
+impl<'a> DebugInfo<'a> {
+    /// The DWARF of the input program, if it has any. Every function of
+    /// `module`, as parsed from the input program, holds its own code.
+    pub fn of(
+        input_program: &'a [u8],
+        module: &Module,
+    ) -> Result<Option<Self>, InstrumentationError> {
+        let layout = CodeLayout::of(input_program)?;
+        if !layout.debug_sections.contains_key(".debug_info") {
+            return Ok(None);
+        }
+        let holders = module
+            .functions()
+            .filter_map(|(index, function)| {
+                let code = function.code()?;
+                Some((index, (index, (0..code.body.len()).collect())))
+            })
+            .collect();
+        Ok(Some(Self { layout, holders }))
+    }
+
+    /// Records that the body of the function at `function_idx` was rewritten,
+    /// every instruction originating from the instruction at its index in
+    /// `origins` in the previous body.
+    pub fn rewritten(&mut self, function_idx: Idx<Function>, origins: &[usize]) {
+        if let Some((_original, previous)) = self.holders.get_mut(&function_idx) {
+            let composed: Vec<usize> = origins
+                .iter()
+                .map(|origin| {
+                    previous
+                        .get(*origin)
+                        .or(previous.last())
+                        .copied()
+                        .unwrap_or(0)
+                })
+                .collect();
+            *previous = composed;
+        }
+    }
+
+    /// Records that the code of the function at `from` moved to `to`.
+    pub fn moved(&mut self, from: Idx<Function>, to: Idx<Function>) {
+        if let Some(holder) = self.holders.remove(&from) {
+            self.holders.insert(to, holder);
+        }
+    }
+
+    /// Encodes `module` with `owned_memory` attached, along with the DWARF of
+    /// the input program, rewritten to address the code where it ended up.
+    pub fn encode(
+        &self,
+        module: &mut Module,
+        owned_memory: &OwnedMemory,
+    ) -> Result<Vec<u8>, InstrumentationError> {
+        strip(module);
+        let encoded = module
+            .to_bytes()
+            .map_err(InstrumentationError::EncodeError)?;
+        // Attaching rewrites the code, so it is laid out as attached
+        let encoded = owned_memory.attach(&encoded)?;
+        let encoded_layout = CodeLayout::of(&encoded)?;
+
+        // Functions with code are encoded in order, after the imported ones
+        let ordinals: HashMap<Idx<Function>, usize> = module
+            .functions()
+            .filter(|(_index, function)| function.code().is_some())
+            .enumerate()
+            .map(|(ordinal, (index, _function))| (index, ordinal))
+            .collect();
+        let mut addresses = Addresses::new(self.layout.size);
+        for (holder, (original, origins)) in &self.holders {
+            let old = self.layout.function(original.to_u32());
+            let new = ordinals
+                .get(holder)
+                .and_then(|ordinal| encoded_layout.nth(*ordinal));
+            if let (Some(old), Some(new)) = (old, new) {
+                addresses.insert(old, new, origins);
+            }
+        }
+
+        for (name, content) in addresses.rewrite(&self.layout.debug_sections)? {
+            module.custom_sections.push(RawCustomSection {
+                name,
+                content,
+                previous_section: None,
+            });
+        }
+        let encoded = module
+            .to_bytes()
+            .map_err(InstrumentationError::EncodeError)?;
+        owned_memory.attach(&encoded)
+    }
+}
+
+/// Maps addresses in the code of the input program onto the instrumented one.
+struct Addresses {
+    /// The size of the code section of the input program
+    size: u32,
+    /// The start of every function and instruction, onto the instrumented one
+    anchors: BTreeMap<u64, u64>,
+    /// Per function, by its start, where it ends
+    functions: BTreeMap<u64, u64>,
+    /// The end of every function, onto the instrumented one
+    ends: HashMap<u64, u64>,
+}
+
+impl Addresses {
+    fn new(size: u32) -> Self {
+        Self {
+            size,
+            anchors: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            ends: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, old: &FunctionLayout, new: &FunctionLayout, origins: &[usize]) {
+        let mut first_originating = vec![None; old.instructions.len()];
+        for (offset, origin) in new.instructions.iter().zip(origins) {
+            if let Some(first) = first_originating.get_mut(*origin) {
+                first.get_or_insert(*offset);
+            }
+        }
+
+        self.anchors.insert(old.start.into(), new.start.into());
+        self.anchors.insert(old.locals.into(), new.locals.into());
+        // Instrumentation may place an instruction before the one it follows
+        let mut previous = new.locals;
+        for (old_offset, new_offset) in old.instructions.iter().zip(first_originating) {
+            previous = new_offset.unwrap_or(previous).max(previous);
+            self.anchors.insert((*old_offset).into(), previous.into());
+        }
+        self.functions.insert(old.start.into(), old.end.into());
+        self.ends.insert(old.end.into(), new.end.into());
+    }
+
+    /// Where the code at `address` ended up. Addresses outside of the code,
+    /// e.g. of code the linker discarded, are left as they are.
+    fn convert(&self, address: u64) -> u64 {
+        if address >= u64::from(self.size) {
+            return address;
+        }
+        match self.anchors.range(..=address).next_back() {
+            Some((old, new)) => new + (address - old),
+            None => address,
+        }
+    }
+
+    /// Where the code that ends at `address` ended up, which differs from
+    /// [`Addresses::convert`] when a function ends there.
+    fn convert_end(&self, address: u64) -> u64 {
+        match self.ends.get(&address) {
+            Some(end) => *end,
+            None => self.convert(address),
+        }
+    }
+
+    /// The start of the function of the input program holding `address`.
+    fn function_at(&self, address: u64) -> Option<u64> {
+        let (start, end) = self.functions.range(..=address).next_back()?;
+        (address < *end).then_some(*start)
+    }
+
+    /// The DWARF in `debug_sections`, rewritten. Yields the new sections.
+    fn rewrite(
+        &self,
+        debug_sections: &HashMap<&str, &[u8]>,
+    ) -> Result<Vec<(String, Vec<u8>)>, InstrumentationError> {
+        let section = |id: gimli::SectionId| debug_sections.get(id.name()).copied().unwrap_or(&[]);
+        let dwarf = load(section)?;
+        // Converted without their rows, which are rewritten below
+        let headers = line_program_headers(section(gimli::SectionId::DebugLine), &dwarf)
+            .map_err(InstrumentationError::DwarfError)?;
+        let header_dwarf = load(|id| match id {
+            gimli::SectionId::DebugLine => headers.as_slice(),
+            id => section(id),
+        })?;
+
+        let convert_address = |address| Some(Address::Constant(self.convert(address)));
+        let mut converted = write::Dwarf::from(&header_dwarf, &convert_address)
+            .map_err(InstrumentationError::DwarfConvertError)?;
+
+        let (mut units, mut header_units) = (dwarf.units(), header_dwarf.units());
+        let mut index = 0;
+        while let (Some(header), Some(header_only)) = (
+            units.next().map_err(InstrumentationError::DwarfError)?,
+            header_units
+                .next()
+                .map_err(InstrumentationError::DwarfError)?,
+        ) {
+            let unit = dwarf
+                .unit(header)
+                .map_err(InstrumentationError::DwarfError)?;
+            let header_only = header_dwarf
+                .unit(header_only)
+                .map_err(InstrumentationError::DwarfError)?;
+            let converted_unit = converted.units.get_mut(converted.units.id(index));
+            index += 1;
+
+            self.rewrite_entries(&dwarf, &unit, converted_unit)
+                .map_err(InstrumentationError::DwarfError)?;
+
+            let (Some(program), Some(program_header)) =
+                (unit.line_program, header_only.line_program)
+            else {
+                continue;
+            };
+            let (mut line_program, files) = write::LineProgram::from(
+                program_header,
+                &header_dwarf,
+                &mut converted.line_strings,
+                &mut converted.strings,
+                &convert_address,
+            )
+            .map_err(InstrumentationError::DwarfConvertError)?;
+            self.rewrite_rows(program, &mut line_program, &files)?;
+            converted_unit.line_program = line_program;
+        }
+
+        let mut sections = Sections::new(EndianVec::new(LittleEndian));
+        converted
+            .write(&mut sections)
+            .map_err(InstrumentationError::DwarfWriteError)?;
+        let mut rewritten = Vec::new();
+        sections
+            .for_each(|id, data| {
+                if !data.slice().is_empty() {
+                    rewritten.push((id.name().to_string(), data.slice().to_vec()));
+                }
+                Ok::<_, write::Error>(())
+            })
+            .map_err(InstrumentationError::DwarfWriteError)?;
+        Ok(rewritten)
+    }
+
+    /// Fixes the extents and range lists of the entries of `converted`, which
+    /// holds the entries of `unit` in the same order. gimli converts their
+    /// addresses but not the length of an extent, and cannot tell the end of
+    /// a function from the start of the next one.
+    fn rewrite_entries(
+        &self,
+        dwarf: &gimli::Dwarf<DwarfReader>,
+        unit: &gimli::Unit<DwarfReader>,
+        converted: &mut write::Unit,
+    ) -> Result<(), gimli::Error> {
+        let mut extents = Vec::new();
+        let mut entries = unit.entries();
+        while let Some((_depth, entry)) = entries.next_dfs()? {
+            let low_pc = match entry.attr_value(gimli::DW_AT_low_pc)? {
+                Some(gimli::AttributeValue::Addr(address)) => Some(address),
+                _ => None,
+            };
+            let length = entry
+                .attr_value(gimli::DW_AT_high_pc)?
+                .and_then(|value| value.udata_value());
+            let ranges = match entry.attr(gimli::DW_AT_ranges)? {
+                Some(_) => {
+                    let mut ranges = dwarf.die_ranges(unit, entry)?;
+                    let mut collected = Vec::new();
+                    while let Some(range) = ranges.next()? {
+                        collected.push(range);
+                    }
+                    Some(collected)
+                }
+                None => None,
+            };
+            extents.push((low_pc.zip(length), ranges));
+        }
+
+        // In the same order as `next_dfs`
+        let mut stack = vec![converted.root()];
+        for (extent, ranges) in extents {
+            let Some(id) = stack.pop() else {
+                break;
+            };
+            stack.extend(converted.get(id).children().rev().copied());
+            if let Some((low_pc, length)) = extent {
+                let start = self.convert(low_pc);
+                let end = self.convert_end(low_pc + length);
+                converted.get_mut(id).set(
+                    gimli::DW_AT_high_pc,
+                    write::AttributeValue::Udata(end.saturating_sub(start)),
+                );
+            }
+            if let Some(ranges) = ranges {
+                let ranges = ranges
+                    .iter()
+                    .map(|range| write::Range::StartEnd {
+                        begin: Address::Constant(self.convert(range.begin)),
+                        end: Address::Constant(self.convert_end(range.end)),
+                    })
+                    .collect();
+                let ranges = converted.ranges.add(write::RangeList(ranges));
+                converted.get_mut(id).set(
+                    gimli::DW_AT_ranges,
+                    write::AttributeValue::RangeListRef(ranges),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the rows of `program` to `line_program`, one sequence per function
+    /// of the input program, as the functions need not stay in order.
+    fn rewrite_rows(
+        &self,
+        program: gimli::IncompleteLineProgram<DwarfReader>,
+        line_program: &mut write::LineProgram,
+        files: &[write::FileId],
+    ) -> Result<(), InstrumentationError> {
+        // Up to DWARF 4, files are numbered from 1
+        let first_file = u64::from(program.header().version() <= 4);
+        let mut rows = program.rows();
+        // The start of the sequence being written, and of the function it is in
+        let mut sequence: Option<(u64, Option<u64>)> = None;
+        let mut last_address = 0;
+
+        while let Some((_header, row)) =
+            rows.next_row().map_err(InstrumentationError::DwarfError)?
+        {
+            let address = row.address();
+            if row.end_sequence() {
+                if let Some((start, _function)) = sequence.take() {
+                    line_program.end_sequence(self.convert_end(address).saturating_sub(start));
+                }
+                continue;
+            }
+
+            let function = self.function_at(address);
+            if let Some((start, sequence_function)) = sequence {
+                if sequence_function != function {
+                    let end = match sequence_function {
+                        Some(function_start) => self.convert_end(self.functions[&function_start]),
+                        None => self.convert(address),
+                    };
+                    line_program.end_sequence(end.saturating_sub(start));
+                    sequence = None;
+                }
+            }
+            let new_address = self.convert(address);
+            let start = match sequence {
+                Some((start, _function)) => start,
+                None => {
+                    line_program.begin_sequence(Some(Address::Constant(new_address)));
+                    sequence = Some((new_address, function));
+                    new_address
+                }
+            };
+            last_address = new_address;
+
+            let file = row
+                .file_index()
+                .checked_sub(first_file)
+                .and_then(|index| files.get(usize::try_from(index).ok()?))
+                .copied()
+                .ok_or(InstrumentationError::DwarfConvertError(
+                    write::ConvertError::InvalidFileIndex,
+                ))?;
+            let new_row = line_program.row();
+            new_row.address_offset = new_address.saturating_sub(start);
+            new_row.file = file;
+            new_row.line = row.line().map_or(0, |line| line.get());
+            new_row.column = match row.column() {
+                gimli::ColumnType::LeftEdge => 0,
+                gimli::ColumnType::Column(column) => column.get(),
+            };
+            new_row.discriminator = row.discriminator();
+            new_row.is_statement = row.is_stmt();
+            new_row.basic_block = row.basic_block();
+            new_row.prologue_end = row.prologue_end();
+            new_row.epilogue_begin = row.epilogue_begin();
+            line_program.generate_row();
+        }
+
+        // A sequence the input program left open
+        if let Some((start, _function)) = sequence {
+            line_program.end_sequence(last_address.saturating_sub(start));
+        }
+        Ok(())
+    }
+}
+
+fn load<'b>(
+    section: impl Fn(gimli::SectionId) -> &'b [u8],
+) -> Result<gimli::Dwarf<DwarfReader<'b>>, InstrumentationError> {
+    gimli::Dwarf::load(|id| Ok::<_, gimli::Error>(EndianSlice::new(section(id), LittleEndian)))
+        .map_err(InstrumentationError::DwarfError)
+}
+
+/// `.debug_line` with the line program of every unit in `dwarf` cut off after
+/// its header, so gimli converts the header without the rows.
+fn line_program_headers(
+    debug_line: &[u8],
+    dwarf: &gimli::Dwarf<DwarfReader>,
+) -> Result<Vec<u8>, gimli::Error> {
+    let mut headers = debug_line.to_vec();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let Some(program) = &unit.line_program else {
+            continue;
+        };
+        let header = program.header();
+        let offset = header.offset().0;
+        let rows_start = header
+            .raw_program_buf()
+            .offset_from(&EndianSlice::new(debug_line, LittleEndian));
+        // The unit length follows the 64-bit marker in 64-bit DWARF
+        let (length_start, length_size) = match header.format() {
+            gimli::Format::Dwarf32 => (offset, 4),
+            gimli::Format::Dwarf64 => (offset + 4, 8),
+        };
+        let length = u64::try_from(rows_start - (length_start + length_size)).unwrap();
+        headers[length_start..length_start + length_size]
+            .copy_from_slice(&length.to_le_bytes()[..length_size]);
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use gimli::write::{
+        Address, AttributeValue, Dwarf, EndianVec, LineProgram, LineString, Sections, Unit,
+    };
+    use gimli::{Encoding, Format, LineEncoding, LittleEndian};
+    use wasabi_wasm::{Function, Idx, Module, RawCustomSection};
+
+    use super::{strip, DebugInfo};
+    use crate::instrument::code_layout::CodeLayout;
+    use crate::instrument::owned_memory::OwnedMemory;
+    use crate::parse_nesting::{HighLevelBody, HighLevelInstr, LowLevelBody};
+
+    const PROGRAM: &str = r#"
+    (module
+      (func $first (result i32)
+        i32.const 1
+        i32.const 2
+        i32.add)
+      (func $second
+        nop))"#;
+
+    /// DWARF with a row on line `line` for every `(address, line)`, in a single
+    /// sequence up to `end`, and a subprogram spanning `subprogram`.
+    fn dwarf(rows: &[(u64, u64)], end: u64, subprogram: (u64, u64)) -> Vec<(String, Vec<u8>)> {
+        let encoding = Encoding {
+            format: Format::Dwarf32,
+            version: 4,
+            address_size: 4,
+        };
+        let mut dwarf = Dwarf::new();
+        let mut program = LineProgram::new(
+            encoding,
+            LineEncoding::default(),
+            LineString::String(b"/src".to_vec()),
+            LineString::String(b"lib.rs".to_vec()),
+            None,
+        );
+        let directory = program.default_directory();
+        let file = program.add_file(LineString::String(b"lib.rs".to_vec()), directory, None);
+        program.begin_sequence(Some(Address::Constant(rows[0].0)));
+        for (address, line) in rows {
+            program.row().address_offset = address - rows[0].0;
+            program.row().file = file;
+            program.row().line = *line;
+            program.generate_row();
+        }
+        program.end_sequence(end - rows[0].0);
+
+        let mut unit = Unit::new(encoding, program);
+        let subprogram_id = unit.add(unit.root(), gimli::DW_TAG_subprogram);
+        let entry = unit.get_mut(subprogram_id);
+        entry.set(
+            gimli::DW_AT_low_pc,
+            AttributeValue::Address(Address::Constant(subprogram.0)),
+        );
+        entry.set(
+            gimli::DW_AT_high_pc,
+            AttributeValue::Udata(subprogram.1 - subprogram.0),
+        );
+        dwarf.units.add(unit);
+
+        let mut sections = Sections::new(EndianVec::new(LittleEndian));
+        dwarf.write(&mut sections).unwrap();
+        let mut named = Vec::new();
+        sections
+            .for_each(|id, data| {
+                named.push((id.name().to_string(), data.slice().to_vec()));
+                Ok::<_, gimli::write::Error>(())
+            })
+            .unwrap();
+        named
+    }
+
+    #[test]
+    fn test_rewrite_line_rows() {
+        let mut wasm_bytes = wat::parse_str(PROGRAM).unwrap();
+        // The instructions of `$first` are at 3, 5, 7 and 8, `$second` ends at 13
+        let (mut module, _, _) = Module::from_bytes(&wasm_bytes).unwrap();
+        for (name, content) in dwarf(&[(3, 10), (5, 11), (7, 12), (11, 20)], 13, (1, 9)) {
+            module.custom_sections.push(RawCustomSection {
+                name,
+                content,
+                previous_section: None,
+            });
+        }
+        wasm_bytes = module.to_bytes().unwrap();
+        let (mut module, _, _) = Module::from_bytes(&wasm_bytes).unwrap();
+        let mut debug_info = DebugInfo::of(&wasm_bytes, &module).unwrap().unwrap();
+
+        // Precede the `i32.add` of `$first` with two `nop`s
+        let first: Idx<Function> = 0_usize.into();
+        let code = module.function(first).code().unwrap();
+        let high_level_body: HighLevelBody = (&module, module.function(first), code, &first)
+            .try_into()
+            .unwrap();
+        let HighLevelBody(mut body) = high_level_body;
+        let add = body.pop().unwrap();
+        body.push(add.instrument_with(HighLevelInstr::Nop));
+        body.push(add.instrument_with(HighLevelInstr::Nop));
+        body.push(add);
+        let (LowLevelBody(low_level_body), origins) =
+            LowLevelBody::with_origins(HighLevelBody(body));
+        module.function_mut(first).code_mut().unwrap().body = low_level_body;
+        debug_info.rewritten(first, &origins);
+
+        let instrumented = debug_info
+            .encode(&mut module, &OwnedMemory::default())
+            .unwrap();
+        assert!(wasmtime::Module::validate(&wasmtime::Engine::default(), &instrumented).is_ok());
+        let layout = CodeLayout::of(&instrumented).unwrap();
+        // `$first` is two bytes longer, the `nop`s are at 7 and 8
+        assert_eq!(layout.nth(0).unwrap().instructions, [3, 5, 7, 8, 9, 10]);
+
+        let dwarf = gimli::Dwarf::load(|id| {
+            let data = layout.debug_sections.get(id.name()).copied().unwrap_or(&[]);
+            Ok::<_, gimli::Error>(gimli::EndianSlice::new(data, LittleEndian))
+        })
+        .unwrap();
+        let header = dwarf.units().next().unwrap().unwrap();
+        let unit = dwarf.unit(header).unwrap();
+
+        let mut rows = unit.line_program.clone().unwrap().rows();
+        let (mut lines, mut sequence_ends) = (Vec::new(), Vec::new());
+        while let Some((_, row)) = rows.next_row().unwrap() {
+            if row.end_sequence() {
+                sequence_ends.push(row.address());
+            } else {
+                lines.push((row.address(), row.line().unwrap().get()));
+            }
+        }
+        // The `i32.add` on line 12 starts at the first `nop` instrumenting it
+        assert_eq!(lines, [(3, 10), (5, 11), (7, 12), (13, 20)]);
+        // One sequence per function, each up to where the function now ends
+        assert_eq!(sequence_ends, [11, 15]);
+
+        let mut entries = unit.entries();
+        entries.next_dfs().unwrap();
+        let (_, subprogram) = entries.next_dfs().unwrap().unwrap();
+        assert_eq!(
+            subprogram.attr_value(gimli::DW_AT_low_pc).unwrap(),
+            Some(gimli::AttributeValue::Addr(1))
+        );
+        assert_eq!(
+            subprogram
+                .attr_value(gimli::DW_AT_high_pc)
+                .unwrap()
+                .and_then(|value| value.udata_value()),
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn test_strip() {
+        let (mut module, _, _) = Module::from_bytes(&wat::parse_str(PROGRAM).unwrap()).unwrap();
+        for name in [".debug_info", ".debug_line", "producers"] {
+            module.custom_sections.push(RawCustomSection {
+                name: name.to_string(),
+                content: vec![],
+                previous_section: None,
+            });
+        }
+        strip(&mut module);
+        let names: Vec<_> = module
+            .custom_sections
+            .iter()
+            .map(|section| section.name.as_str())
+            .collect();
+        assert_eq!(names, ["producers"]);
+    }
+}
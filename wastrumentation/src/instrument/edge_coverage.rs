@@ -0,0 +1,216 @@
+// Edge coverage in the style of AFL: upon entering a basic block, the counter of
+// the edge from the previously entered block into it is incremented, in a map of
+// one byte per edge that the host reads and resets between runs.
+//
+// The map is the owned memory of the instrumented program, see
+// `super::owned_memory`, so the program keeps its memory 0 and cannot overwrite
+// the map. The counter is updated inline, with the location of the block a
+// constant.
+
+use wasabi_wasm::{
+    BinaryOp, FunctionType, GlobalOp, Idx, Instr as LowLevelInstr, Label, LoadOp, LocalOp, Module,
+    Mutability, StoreOp, Val, ValType,
+};
+
+use crate::error::InstrumentationError;
+use crate::parse_nesting::{HighLevelBody, HighLevelInstr as Instr, LowLevelBody};
+
+use super::basic_block::BasicBlocks;
+use super::debug_info::{self, DebugInfo};
+use super::owned_memory::{memarg, OwnedMemory};
+use super::Instrumentable;
+
+/// The memory holding the coverage map
+pub const EXPORT_COVERAGE_MAP: &str = "__wastrumentation_coverage_map";
+/// Zeroes the coverage map and forgets the previously entered block
+pub const EXPORT_COVERAGE_RESET: &str = "__wastrumentation_coverage_reset";
+
+/// The size of the coverage map in bytes, a single page
+pub const MAP_SIZE: u32 = 1 << 16;
+
+/// The location of a basic block, its id hashed onto the coverage map. The edge
+/// from block `p` into block `c` counts at `location(c) ^ (location(p) >> 1)`,
+/// the shift keeps `p -> c` and `c -> p`, as well as `c -> c`, apart.
+pub fn location(block_id: i32) -> i32 {
+    let block_id = u32::try_from(block_id).unwrap();
+    // Fibonacci hashing, keeping as many of the upper bits as the map needs
+    let hash = block_id.wrapping_mul(0x9E37_79B9) >> (32 - MAP_SIZE.trailing_zeros());
+    i32::try_from(hash).unwrap()
+}
+
+/// Counts the entry of every basic block in every function with code in the
+/// coverage map, which the module exports along with a function to reset it.
+/// The basic blocks are described in a custom section, see
+/// [`super::basic_block::BASIC_BLOCKS_SECTION`].
+pub fn instrument(module: &[u8], debug_info: bool) -> Result<Vec<u8>, InstrumentationError> {
+    let input_program = module;
+    let (mut module, _offsets, _issue) =
+        Module::from_bytes(module).map_err(InstrumentationError::ParseModuleError)?;
+    let basic_blocks = BasicBlocks::of(&module)?;
+    let mut debug_info = if debug_info {
+        DebugInfo::of(input_program, &module)?
+    } else {
+        None
+    };
+
+    let target_indices: Vec<_> = module
+        .functions()
+        .filter(|(_index, f)| f.code().is_some())
+        .map(|(idx, _)| idx)
+        .collect();
+    let mut owned_memory = OwnedMemory::exported(EXPORT_COVERAGE_MAP);
+    let map = owned_memory.reserve(MAP_SIZE);
+    let previous = module.add_global(
+        ValType::I32,
+        Mutability::Mut,
+        vec![LowLevelInstr::Const(Val::I32(0)), LowLevelInstr::End],
+    );
+
+    for target_function_idx in target_indices {
+        let target_function = module.function(target_function_idx);
+        let code = target_function
+            .code()
+            .ok_or(InstrumentationError::AttemptInnerInstrumentImport)?;
+        let high_level_body: HighLevelBody = (&module, target_function, code, &target_function_idx)
+            .try_into()
+            .map_err(|e| InstrumentationError::LowToHighError { low_to_high_err: e })?;
+        let transformed =
+            basic_blocks.transform_with(&high_level_body, target_function_idx, |anchor, id| {
+                let location = location(id);
+                let edge = [
+                    Instr::Const(Val::I32(location)),
+                    Instr::Global(GlobalOp::Get, previous),
+                    Instr::Binary(BinaryOp::I32Xor),
+                ];
+                // STACK: []
+                let mut instrumentation: Vec<_> = edge
+                    .iter()
+                    .chain(&edge)
+                    .map(|instr| anchor.instrument_with(instr.clone()))
+                    .collect();
+                // STACK: [edge, edge]
+                instrumentation.extend([
+                    anchor.instrument_with(Instr::Load(LoadOp::I32Load8U, memarg(map))),
+                    anchor.instrument_with(Instr::Const(Val::I32(1))),
+                    anchor.instrument_with(Instr::Binary(BinaryOp::I32Add)),
+                    // STACK: [edge, count]
+                    anchor.instrument_with(Instr::Store(StoreOp::I32Store8, memarg(map))),
+                    // STACK: []
+                    anchor.instrument_with(Instr::Const(Val::I32(location >> 1))),
+                    anchor.instrument_with(Instr::Global(GlobalOp::Set, previous)),
+                ]);
+                instrumentation
+            });
+        let (LowLevelBody(transformed_low_level_body), origins) =
+            LowLevelBody::with_origins(transformed);
+        if let Some(debug_info) = &mut debug_info {
+            debug_info.rewritten(target_function_idx, &origins);
+        }
+        module
+            .function_mut(target_function_idx)
+            .code_mut()
+            .ok_or(InstrumentationError::AttemptInnerInstrumentImport)?
+            .body = transformed_low_level_body;
+    }
+    add_reset(&mut module, map, previous);
+    basic_blocks.install_side_table(&mut module);
+
+    match debug_info {
+        Some(debug_info) => debug_info.encode(&mut module, &owned_memory),
+        None => {
+            debug_info::strip(&mut module);
+            owned_memory.attach(
+                &module
+                    .to_bytes()
+                    .map_err(InstrumentationError::EncodeError)?,
+            )
+        }
+    }
+}
+
+/// Exports a function that zeroes the map at `map` and resets `previous`.
+fn add_reset(module: &mut Module, map: u32, previous: Idx<wasabi_wasm::Global>) {
+    use LowLevelInstr::{Binary, BrIf, Const, End, Global, Local, Loop, Store};
+    const WORD: i32 = 8;
+    let address = 0_u32.into();
+    let reset = module.add_function(
+        FunctionType::new(&[], &[]),
+        vec![ValType::I32],
+        vec![
+            Loop(FunctionType::new(&[], &[])),
+            // Zeroes the word at `address`
+            Local(LocalOp::Get, address),
+            Const(Val::I64(0)),
+            Store(StoreOp::I64Store, memarg(map)),
+            Local(LocalOp::Get, address),
+            Const(Val::I32(WORD)),
+            Binary(BinaryOp::I32Add),
+            Local(LocalOp::Tee, address),
+            Const(Val::I32(i32::try_from(MAP_SIZE).unwrap())),
+            Binary(BinaryOp::I32LtU),
+            BrIf(Label::from(0_usize)),
+            End,
+            Const(Val::I32(0)),
+            Global(GlobalOp::Set, previous),
+            End,
+        ],
+    );
+    module.export_generated(reset, EXPORT_COVERAGE_RESET);
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::{Engine, Instance, Module, Store};
+
+    use super::{instrument, location, EXPORT_COVERAGE_MAP, EXPORT_COVERAGE_RESET};
+
+    const BRANCHING_PROGRAM: &str = r#"
+    (module
+      (func (export "main") (param $a i32) (result i32)
+        (if (result i32) (local.get $a)
+          (then (i32.const 1))
+          (else (i32.const 2)))))"#;
+
+    #[test]
+    fn test_counts_edges_in_map() {
+        let wasm_bytes = wat::parse_str(BRANCHING_PROGRAM).unwrap();
+        let instrumented = instrument(&wasm_bytes, false).unwrap();
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let module = Module::new(&engine, instrumented).unwrap();
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let main = instance
+            .get_typed_func::<i32, i32>(&mut store, "main")
+            .unwrap();
+        let reset = instance
+            .get_typed_func::<(), ()>(&mut store, EXPORT_COVERAGE_RESET)
+            .unwrap();
+        let map = instance
+            .get_memory(&mut store, EXPORT_COVERAGE_MAP)
+            .unwrap();
+
+        // Blocks: 0 -> `if`, 1 -> then-arm, 2 -> else-arm, 3 -> after the `if`
+        let edge = |from: Option<i32>, to: i32| {
+            let previous = from.map_or(0, |from| location(from) >> 1);
+            usize::try_from(location(to) ^ previous).unwrap()
+        };
+        assert_eq!(main.call(&mut store, 1).unwrap(), 1);
+        assert_eq!(main.call(&mut store, 1).unwrap(), 1);
+        assert_eq!(main.call(&mut store, 0).unwrap(), 2);
+        let counts = map.data(&store);
+        assert_eq!(counts[edge(None, 0)], 1);
+        assert_eq!(counts[edge(Some(0), 1)], 2);
+        assert_eq!(counts[edge(Some(1), 3)], 2);
+        assert_eq!(counts[edge(Some(3), 0)], 2);
+        assert_eq!(counts[edge(Some(0), 2)], 1);
+        assert_eq!(counts[edge(Some(2), 3)], 1);
+        assert_eq!(counts.iter().map(|count| u32::from(*count)).sum::<u32>(), 9);
+
+        // Forgets the previous block along with the counts
+        reset.call(&mut store, ()).unwrap();
+        assert!(map.data(&store).iter().all(|count| *count == 0));
+        assert_eq!(main.call(&mut store, 0).unwrap(), 2);
+        assert_eq!(map.data(&store)[edge(None, 0)], 1);
+    }
+}
@@ -1,7 +1,6 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use crate::compiler::{LibGeneratable, Library};
-use crate::stack_library::StackLibrary;
+use crate::stack_library::ModuleLinkedStackHooks;
 use wasabi_wasm::ElementMode;
 use wasabi_wasm::Function;
 use wasabi_wasm::FunctionType;
@@ -19,7 +18,8 @@ use wasabi_wasm::{Code, Element, ImportOrPresent};
 
 use crate::analysis::{WasmExport, WasmImport};
 
-use super::FunctionTypeConvertible;
+use super::reference_handles::ReferenceHandles;
+use super::{FunctionTypeConvertible, Instrumentable};
 
 pub const INSTRUMENTATION_STACK_MODULE: &str = "wastrumentation_stack";
 pub const INSTRUMENTATION_ANALYSIS_MODULE: &str = "WASTRUMENTATION_ANALYSIS";
@@ -28,40 +28,45 @@ pub const INSTRUMENTATION_INSTRUMENTED_MODULE: &str = "instrumented_input";
 pub const CODE_IS_PRESENT: i32 = 0;
 pub const CODE_IS_IMPORT: i32 = 1;
 
+/// Returns, per function with code, the function its code moved to.
 #[allow(clippy::too_many_lines)]
-pub fn instrument<InstrumentationLanguage: LibGeneratable>(
+pub fn instrument(
     module: &mut Module,
     pre_instrumentation_function_indices: &HashSet<Idx<Function>>,
+    signature_import_links: &HashMap<FunctionType, ModuleLinkedStackHooks>,
     wasp_exported_generic_apply_trap: &WasmExport,
     wasp_imported_generic_apply_base: &WasmImport,
-) -> Library<InstrumentationLanguage> {
+) -> HashMap<Idx<Function>, Idx<Function>> {
     // 0. GENERATE GENERIC APPLY
     let generic_apply_index = module.add_function_import(
         wasp_exported_generic_apply_trap.as_function_type(),
         INSTRUMENTATION_ANALYSIS_MODULE.into(),
         wasp_exported_generic_apply_trap.name.to_string(),
     );
+    module.name_generated(generic_apply_index, &wasp_exported_generic_apply_trap.name);
 
-    // 1. GENERATE IMPORTS FOR INSTRUMENTATION STACK LIBRARY
-    let StackLibrary {
-        library,
-        signature_import_links,
-    } = StackLibrary::<InstrumentationLanguage>::from_module(
-        module,
-        pre_instrumentation_function_indices,
-    );
+    // 1. IMPORTS FOR INSTRUMENTATION STACK LIBRARY ARE GENERATED BY THE CALLER
 
     // 2. Generate function instrumentation functionality
-    let apply_table_index = module.tables.len();
     let mut apply_table_funs = vec![];
+    let mut reference_handles = ReferenceHandles::default();
+    let mut uninstrumented_indices = HashMap::new();
 
     for function_index in pre_instrumentation_function_indices {
         let target_function_type = module.function(*function_index).type_;
+        let used_ref_types = reference_handles.install_for(module, &target_function_type);
 
         let stack_library_for_target = signature_import_links
             .get(&target_function_type)
             .expect("Imported");
 
+        // The generated functions are named after the function they wrap
+        let target_name = module
+            .function(*function_index)
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("function_{}", function_index.to_u32()));
+
         // 1. Generate "uninstrumented" function
         let target_code_is_present = module.function(*function_index).code().is_some();
         let uninstrumented_index = match &module.function(*function_index).code {
@@ -85,6 +90,13 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
                 )
             }
         };
+        module.name_generated(
+            uninstrumented_index,
+            &format!("{target_name}_uninstrumented"),
+        );
+        if target_code_is_present {
+            uninstrumented_indices.insert(*function_index, uninstrumented_index);
+        }
 
         // 2. Generate "base apply" function
         let signature_buffer_pointer_type = ValType::I32;
@@ -95,21 +107,44 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
         let call_stack_store_rets: Instr = Call(stack_library_for_target.ret_store_all);
 
         let mut apply_instructions: Vec<Instr> = Vec::new();
+        let mut apply_locals = vec![signature_buffer_pointer_type];
 
         apply_instructions.push(local_get_stack_ptr());
 
-        for load_call in &stack_library_for_target.arg_load_n {
+        for (load_call, arg_type) in stack_library_for_target
+            .arg_load_n
+            .iter()
+            .zip(target_function_type.inputs())
+        {
             apply_instructions.push(local_get_stack_ptr());
             apply_instructions.push(Call(*load_call));
+            apply_instructions.extend(reference_handles.from_handle(arg_type));
         }
 
-        apply_instructions.extend_from_slice(&[call_base, call_stack_store_rets, End]);
+        apply_instructions.push(call_base);
 
-        let apply_index = module.add_function(
-            apply_type,
-            vec![signature_buffer_pointer_type],
-            apply_instructions,
-        );
+        let results = target_function_type.results();
+        if results
+            .iter()
+            .any(|ret_type| matches!(ret_type, ValType::Ref(_)))
+        {
+            // Spill the results, so the references among them can be turned into handles
+            let first_result_local = apply_type.inputs().len() + apply_locals.len();
+            apply_locals.extend_from_slice(results);
+            let result_local = |index: usize| Idx::from(first_result_local + index);
+            for index in (0..results.len()).rev() {
+                apply_instructions.push(Local(LocalOp::Set, result_local(index)));
+            }
+            for (index, ret_type) in results.iter().enumerate() {
+                apply_instructions.push(Local(LocalOp::Get, result_local(index)));
+                apply_instructions.extend(reference_handles.to_handle(ret_type));
+            }
+        }
+
+        apply_instructions.extend_from_slice(&[call_stack_store_rets, End]);
+
+        let apply_index = module.add_function(apply_type, apply_locals, apply_instructions);
+        module.name_generated(apply_index, &format!("{target_name}_apply"));
 
         let apply_table_index = apply_table_funs.len();
         apply_table_funs.push(apply_index);
@@ -119,12 +154,25 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
         original_function.code = ImportOrPresent::Present(Code::new()); // clear out / install body
         let stack_ptr_local = original_function.add_fresh_local(ValType::I32);
         let stack_ptr_types_local = original_function.add_fresh_local(ValType::I32);
+        let saved_handles: Vec<_> = used_ref_types
+            .iter()
+            .map(|ref_type| {
+                let handle_table = reference_handles.table(ref_type);
+                (
+                    handle_table,
+                    original_function.add_fresh_local(ValType::I32),
+                )
+            })
+            .collect();
 
         let push_args_on_stack: Vec<Instr> = target_function_type
             .inputs()
             .iter()
             .enumerate()
-            .map(|(index, _int_type)| Local(LocalOp::Get, index.into()))
+            .flat_map(|(index, arg_type)| {
+                std::iter::once(Local(LocalOp::Get, index.into()))
+                    .chain(reference_handles.to_handle(arg_type))
+            })
             .collect();
         let call_allocate_values_buffer = Call(stack_library_for_target.allocate_values_buffer);
         let local_set_values_buffer_ptr = Local(LocalOp::Set, stack_ptr_local);
@@ -152,6 +200,9 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
         }));
 
         let mut instrumented_body = Vec::new();
+        for (handle_table, saved_handle_local) in &saved_handles {
+            instrumented_body.extend(handle_table.save(*saved_handle_local));
+        }
         instrumented_body.extend(push_args_on_stack);
         instrumented_body.push(call_allocate_values_buffer);
         instrumented_body.push(local_set_values_buffer_ptr);
@@ -169,15 +220,23 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
             call_generic_apply,
         ]);
 
-        for load_call in &stack_library_for_target.ret_load_n {
+        for (load_call, ret_type) in stack_library_for_target
+            .ret_load_n
+            .iter()
+            .zip(target_function_type.results())
+        {
             instrumented_body.push(local_get_stack_ptr());
             instrumented_body.push(Call(*load_call));
+            instrumented_body.extend(reference_handles.from_handle(ret_type));
         }
 
         instrumented_body.push(local_get_stack_ptr());
         instrumented_body.push(call_free_values_buffer);
         instrumented_body.push(local_get_stack_types_ptr());
         instrumented_body.push(call_free_types_buffer);
+        for (handle_table, saved_handle_local) in &saved_handles {
+            instrumented_body.extend(handle_table.restore(*saved_handle_local));
+        }
         instrumented_body.push(End);
         original_function.code_mut().unwrap().body = instrumented_body;
     }
@@ -217,16 +276,12 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
             Local(LocalOp::Get, 0_usize.into()), // sigv
             CallIndirect(
                 FunctionType::new(&[ValType::I32], &[]),
-                apply_table_index.into(),
+                apply_table_idx.into(),
             ),
             End,
         ],
     );
 
-    module
-        .function_mut(call_base_idx)
-        .export
-        .push(wasp_imported_generic_apply_base.name.to_string());
-
-    library
+    module.export_generated(call_base_idx, &wasp_imported_generic_apply_base.name);
+    uninstrumented_indices
 }
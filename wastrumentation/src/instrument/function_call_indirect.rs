@@ -1,38 +1,65 @@
 use crate::parse_nesting::{
     BodyInner, HighLevelBody, HighLevelInstr as Instr, TypedHighLevelInstr,
 };
-use wasabi_wasm::{Function, Idx, Module, Val};
+use wasabi_wasm::{Function, FunctionType, Idx, Local, LocalOp, Module, Table, Val, ValType};
 
+use super::callee_resolution::CalleeResolution;
+use super::stack_values;
 use super::TransformationStrategy;
+use crate::site_predicate::Sites;
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
-pub enum Target {
+pub enum Target<'a> {
     Pre(Idx<Function>),
     Post(Idx<Function>),
-    IndirectPre(Idx<Function>),
-    IndirectPost(Idx<Function>),
+    IndirectPre(Idx<Function>, &'a CalleeResolution),
+    IndirectPost(Idx<Function>, &'a CalleeResolution),
 }
 
-impl TransformationStrategy for Target {
-    fn transform(&self, high_level_body: &HighLevelBody, _: &mut Module) -> HighLevelBody {
-        high_level_body.transform_call_indirect(*self)
+impl TransformationStrategy for Target<'_> {
+    fn transform(
+        &self,
+        high_level_body: &HighLevelBody,
+        module: &mut Module,
+        sites: &Sites,
+    ) -> HighLevelBody {
+        high_level_body.transform_call_indirect(*self, module, sites)
     }
 }
 
 impl HighLevelBody {
     #[must_use]
-    pub fn transform_call_indirect(&self, target: Target) -> Self {
+    pub fn transform_call_indirect(
+        &self,
+        target: Target,
+        module: &mut Module,
+        sites: &Sites,
+    ) -> Self {
         let Self(body) = self;
-        let transformed_body = transform(body, target);
+        let transformed_body = transform(body, target, sites, module);
         Self(transformed_body)
     }
 }
 
-fn transform(body: &BodyInner, target: Target) -> BodyInner {
+/// Instructions that push the resolved function index of the slot in
+/// `slot_local`, see [`CalleeResolution::resolve`].
+fn resolve(
+    typed_instr: &TypedHighLevelInstr,
+    resolution: &CalleeResolution,
+    slot_local: Idx<Local>,
+    function_type: &FunctionType,
+    table_index: &Idx<Table>,
+) -> BodyInner {
+    let mut instrs = vec![wasabi_wasm::Instr::Local(LocalOp::Get, slot_local)];
+    instrs.extend(resolution.resolve(table_index, function_type));
+    stack_values::instrument_with(typed_instr, instrs)
+}
+
+fn transform(body: &BodyInner, target: Target, sites: &Sites, module: &mut Module) -> BodyInner {
     let mut result = Vec::new();
 
     for typed_instr @ TypedHighLevelInstr { instr, .. } in body {
-        if typed_instr.is_uninstrumented() {
+        if typed_instr.is_uninstrumented() && sites.selects(typed_instr) {
             match (target, instr) {
                 (Target::Pre(call_pre_idx), Instr::Call(index)) => {
                     // STACK: [type_in]
@@ -61,14 +88,28 @@ fn transform(body: &BodyInner, target: Target) -> BodyInner {
                     continue;
                 }
                 (
-                    Target::IndirectPre(call_pre_idx),
-                    Instr::CallIndirect(_function_type, table_index),
+                    Target::IndirectPre(call_pre_idx, resolution),
+                    Instr::CallIndirect(function_type, table_index),
                 ) => {
-                    // STACK: [type_in, table_function_index]
-                    result.push(typed_instr.instrument_with(Instr::Const(Val::I32(
-                        i32::try_from(table_index.to_u32()).unwrap(),
-                    ))));
-                    // STACK: [type_in, table_function_index, table_index]
+                    let slot_local = module
+                        .function_mut(typed_instr.funct_index.into())
+                        .add_fresh_local(ValType::I32);
+                    result.extend_from_slice(&[
+                        // STACK: [type_in, table_function_index]
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Tee, slot_local)),
+                        typed_instr.instrument_with(Instr::Const(Val::I32(
+                            i32::try_from(table_index.to_u32()).unwrap(),
+                        ))),
+                        // STACK: [type_in, table_function_index, table_index]
+                    ]);
+                    result.extend(resolve(
+                        typed_instr,
+                        resolution,
+                        slot_local,
+                        function_type,
+                        table_index,
+                    ));
+                    // STACK: [type_in, table_function_index, table_index, resolved_index]
                     result.extend_from_slice(&typed_instr.to_trap_call(&call_pre_idx));
                     // STACK: [type_in, table_function_index]
                     result.push(typed_instr.place_original(instr.clone()));
@@ -76,17 +117,36 @@ fn transform(body: &BodyInner, target: Target) -> BodyInner {
                     continue;
                 }
                 (
-                    Target::IndirectPost(call_post_idx),
-                    Instr::CallIndirect(_function_type, table_index),
+                    Target::IndirectPost(call_post_idx, resolution),
+                    Instr::CallIndirect(function_type, table_index),
                 ) => {
+                    // Resolved before the call, which may change the table
+                    let [slot_local, resolved_local] = [ValType::I32; 2].map(|type_| {
+                        module
+                            .function_mut(typed_instr.funct_index.into())
+                            .add_fresh_local(type_)
+                    });
+                    // STACK: [type_in, table_function_index]
+                    result
+                        .push(typed_instr.instrument_with(Instr::Local(LocalOp::Tee, slot_local)));
+                    result.extend(resolve(
+                        typed_instr,
+                        resolution,
+                        slot_local,
+                        function_type,
+                        table_index,
+                    ));
                     result.extend_from_slice(&[
+                        // STACK: [type_in, table_function_index, resolved_index]
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Set, resolved_local)),
                         // STACK: [type_in, table_function_index]
                         typed_instr.place_original(instr.clone()),
                         // STACK: [type_out]
                         typed_instr.instrument_with(Instr::Const(Val::I32(
                             i32::try_from(table_index.to_u32()).unwrap(),
                         ))),
-                        // STACK: [type_out, table_index]
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Get, resolved_local)),
+                        // STACK: [type_out, table_index, resolved_index]
                     ]);
                     result.extend_from_slice(&typed_instr.to_trap_call(&call_post_idx));
                     // STACK: [type_out]
@@ -101,25 +161,31 @@ fn transform(body: &BodyInner, target: Target) -> BodyInner {
             (target, Instr::If(type_, then, None)) => {
                 result.push(typed_instr.place_untouched(Instr::If(
                     *type_,
-                    transform(then, target),
+                    transform(then, target, sites, module),
                     None,
                 )));
             }
             (target, Instr::If(type_, then, Some(else_))) => {
                 result.push(typed_instr.place_untouched(Instr::If(
                     *type_,
-                    transform(then, target),
-                    Some(transform(else_, target)),
+                    transform(then, target, sites, module),
+                    Some(transform(else_, target, sites, module)),
                 )))
             }
             (target, Instr::Loop(type_, body)) => {
                 result.push(
-                    typed_instr.place_untouched(Instr::Loop(*type_, transform(body, target))),
+                    typed_instr.place_untouched(Instr::Loop(
+                        *type_,
+                        transform(body, target, sites, module),
+                    )),
                 );
             }
             (target, Instr::Block(type_, body)) => {
                 result.push(
-                    typed_instr.place_untouched(Instr::Block(*type_, transform(body, target))),
+                    typed_instr.place_untouched(Instr::Block(
+                        *type_,
+                        transform(body, target, sites, module),
+                    )),
                 );
             }
             (_, instr) => result.push(typed_instr.place_untouched(instr.clone())),
@@ -0,0 +1,666 @@
+use std::collections::HashMap;
+
+use crate::parse_nesting::{
+    BodyInner, HighLevelBody, HighLevelInstr as Instr, TypedHighLevelInstr,
+};
+use crate::site_predicate::Sites;
+use crate::stack_library::ModuleLinkedStackHooks;
+use wasabi_wasm::types::InferredInstructionType;
+use wasabi_wasm::{
+    BinaryOp, Function, FunctionType, Idx, Label, Local, LocalOp, Module, Val, ValType,
+};
+
+use super::reference_handles::ReferenceHandles;
+use super::return_values::returned_type;
+use super::stack_values;
+
+/// The signature the stack library stores the arguments of `function_type` with.
+pub fn entered_type(function_type: &FunctionType) -> FunctionType {
+    FunctionType::new(function_type.inputs(), &[])
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Hook {
+    Enter,
+    Exit,
+}
+
+/// Hands the arguments of a function to the analysis when it is entered, and its
+/// results on every path that exits it. They travel through stack library
+/// buffers of the signatures [`entered_type`] and [`returned_type`], so the
+/// analysis may overwrite them.
+pub struct Target<'a> {
+    pub hook: Hook,
+    pub trap_idx: Idx<Function>,
+    /// The functions to hook, see [`Sites::selects_function`]
+    pub sites: Sites<'a>,
+    pub signature_import_links: &'a HashMap<FunctionType, ModuleLinkedStackHooks>,
+    pub reference_handles: &'a ReferenceHandles,
+}
+
+impl Target<'_> {
+    /// Unlike the [`super::TransformationStrategy`] targets, entering and exiting
+    /// is a property of the function rather than of one of its instructions.
+    /// The function index is passed explicitly, as an empty body has no
+    /// instruction to take it from.
+    pub fn transform(
+        &self,
+        high_level_body: &HighLevelBody,
+        function_index: Idx<Function>,
+        module: &mut Module,
+    ) -> HighLevelBody {
+        if !self.sites.selects_function(function_index.to_u32()) {
+            return high_level_body.clone();
+        }
+        let HighLevelBody(body) = high_level_body;
+
+        let transformed_body = match self.hook {
+            Hook::Enter => {
+                let anchor = anchor(function_index, 0);
+                let mut transformed_body = pass_arguments(
+                    &anchor,
+                    self.trap_idx,
+                    function_index,
+                    module,
+                    self.signature_import_links,
+                    self.reference_handles,
+                );
+                transformed_body.extend_from_slice(body);
+                transformed_body
+            }
+            Hook::Exit => {
+                let mut transformed_body = instrument_exit_branches(
+                    body,
+                    function_index,
+                    module,
+                    &mut |typed_instr, module| {
+                        Some(self.pass_results(typed_instr, function_index, module))
+                    },
+                );
+                // Exit by falling through the end of the body
+                let anchor = anchor(function_index, end_index(module, function_index));
+                transformed_body.extend(self.pass_results(&anchor, function_index, module));
+                transformed_body
+            }
+        };
+        HighLevelBody(transformed_body)
+    }
+
+    fn pass_results(
+        &self,
+        typed_instr: &TypedHighLevelInstr,
+        function_index: Idx<Function>,
+        module: &mut Module,
+    ) -> BodyInner {
+        pass_results(
+            typed_instr,
+            self.trap_idx,
+            function_index,
+            module,
+            self.signature_import_links,
+            self.reference_handles,
+        )
+    }
+}
+
+/// The index of the `end` instruction that closes the body of `function_index`.
+pub fn end_index(module: &Module, function_index: Idx<Function>) -> usize {
+    module
+        .function(function_index)
+        .code()
+        .expect("Target functions have code")
+        .body
+        .len()
+        - 1
+}
+
+/// A site to instrument with where the function has no instruction of its own,
+/// i.e. its start and the closing `end`.
+pub fn anchor(function_index: Idx<Function>, instr_index: usize) -> TypedHighLevelInstr {
+    TypedHighLevelInstr::new_uninstrumented(
+        function_index.to_u32(),
+        instr_index,
+        InferredInstructionType::Unreachable, // Never inspected, only used to instrument with
+        Instr::Nop,
+    )
+}
+
+/// Moves the arguments of the function through the analysis by calling
+/// `trap_idx`, replacing them with the values the analysis decided on.
+pub fn pass_arguments(
+    typed_instr: &TypedHighLevelInstr,
+    trap_idx: Idx<Function>,
+    function_index: Idx<Function>,
+    module: &mut Module,
+    signature_import_links: &HashMap<FunctionType, ModuleLinkedStackHooks>,
+    reference_handles: &ReferenceHandles,
+) -> BodyInner {
+    use wasabi_wasm::Instr::{Call, Const, Local};
+
+    let entered_type = entered_type(&module.function(function_index).type_);
+    let args = entered_type.inputs();
+    let stack_hooks = signature_import_links.get(&entered_type).expect("Imported");
+    // The parameters are the first locals of the function
+    let arg_locals: Vec<Idx<wasabi_wasm::Local>> = (0..args.len())
+        .map(|index| u32::try_from(index).unwrap().into())
+        .collect();
+    let [values_buffer, types_buffer] =
+        [ValType::I32; 2].map(|type_| module.function_mut(function_index).add_fresh_local(type_));
+    let (save_handles, restore_handles) =
+        stack_values::save_handles(module, function_index, args, reference_handles);
+
+    // STACK: []
+    let mut store = save_handles;
+    store.extend(stack_values::push_for_stack(
+        &arg_locals,
+        args,
+        reference_handles,
+    ));
+    store.extend([
+        // STACK: [args]
+        Call(stack_hooks.allocate_values_buffer),
+        // STACK: [values_buffer]
+        Local(LocalOp::Set, values_buffer),
+        Call(stack_hooks.allocate_types_buffer),
+        Local(LocalOp::Set, types_buffer),
+        Const(Val::I32(i32::try_from(args.len()).unwrap())),
+        Local(LocalOp::Get, values_buffer),
+        Local(LocalOp::Get, types_buffer),
+        // STACK: [argc, argv, argtypv]
+    ]);
+
+    // STACK: []
+    let mut reload = stack_values::load_from_stack(
+        values_buffer,
+        &stack_hooks.arg_load_n,
+        args,
+        reference_handles,
+    );
+    // STACK: [args]
+    reload.extend(stack_values::spill(&arg_locals));
+    // STACK: []
+    reload.extend([
+        Local(LocalOp::Get, values_buffer),
+        Call(stack_hooks.free_values_buffer),
+        Local(LocalOp::Get, types_buffer),
+        Call(stack_hooks.free_types_buffer),
+    ]);
+    reload.extend(restore_handles);
+
+    let mut instrumentation = stack_values::instrument_with(typed_instr, store);
+    instrumentation.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+    instrumentation.extend(stack_values::instrument_with(typed_instr, reload));
+    instrumentation
+}
+
+/// Moves the results on top of the stack through the analysis by calling
+/// `trap_idx`, leaving the values the analysis decided on in their place.
+pub fn pass_results(
+    typed_instr: &TypedHighLevelInstr,
+    trap_idx: Idx<Function>,
+    function_index: Idx<Function>,
+    module: &mut Module,
+    signature_import_links: &HashMap<FunctionType, ModuleLinkedStackHooks>,
+    reference_handles: &ReferenceHandles,
+) -> BodyInner {
+    use wasabi_wasm::Instr::{Call, Const, Local};
+
+    let returned_type = returned_type(&module.function(function_index).type_);
+    let results = returned_type.results();
+    let stack_hooks = signature_import_links
+        .get(&returned_type)
+        .expect("Imported");
+    let result_locals = stack_values::fresh_locals(module, function_index, results);
+    let [values_buffer, types_buffer] =
+        [ValType::I32; 2].map(|type_| module.function_mut(function_index).add_fresh_local(type_));
+    let (save_handles, restore_handles) =
+        stack_values::save_handles(module, function_index, results, reference_handles);
+
+    // STACK: [results]
+    let mut store = stack_values::spill(&result_locals);
+    // STACK: []
+    store.extend(save_handles);
+    store.extend([
+        Call(stack_hooks.allocate_values_buffer),
+        Local(LocalOp::Set, values_buffer),
+        Local(LocalOp::Get, values_buffer),
+    ]);
+    // STACK: [values_buffer]
+    store.extend(stack_values::push_for_stack(
+        &result_locals,
+        results,
+        reference_handles,
+    ));
+    // STACK: [values_buffer, results]
+    store.extend([
+        Call(stack_hooks.ret_store_all),
+        // STACK: []
+        Call(stack_hooks.allocate_types_buffer),
+        Local(LocalOp::Set, types_buffer),
+        Const(Val::I32(i32::try_from(results.len()).unwrap())),
+        Local(LocalOp::Get, values_buffer),
+        Local(LocalOp::Get, types_buffer),
+        // STACK: [resc, resv, restypv]
+    ]);
+
+    // STACK: []
+    let mut reload = stack_values::load_from_stack(
+        values_buffer,
+        &stack_hooks.ret_load_n,
+        results,
+        reference_handles,
+    );
+    // STACK: [results]
+    reload.extend([
+        Local(LocalOp::Get, values_buffer),
+        Call(stack_hooks.free_values_buffer),
+        Local(LocalOp::Get, types_buffer),
+        Call(stack_hooks.free_types_buffer),
+    ]);
+    reload.extend(restore_handles);
+
+    let mut instrumentation = stack_values::instrument_with(typed_instr, store);
+    instrumentation.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+    instrumentation.extend(stack_values::instrument_with(typed_instr, reload));
+    instrumentation
+}
+
+fn fresh_local(module: &mut Module, function_index: Idx<Function>, type_: ValType) -> Idx<Local> {
+    module.function_mut(function_index).add_fresh_local(type_)
+}
+
+fn exits(label: &Label, depth: u32) -> bool {
+    label.to_u32() == depth
+}
+
+/// Whether `instr` leaves the function, when `depth` labels enclose it.
+fn exits_function(instr: &Instr, depth: u32) -> bool {
+    match instr {
+        Instr::Return => true,
+        Instr::Br(label) | Instr::BrIf(label) => exits(label, depth),
+        Instr::BrTable { table, default } => table.iter().chain([default]).any(|l| exits(l, depth)),
+        _ => false,
+    }
+}
+
+/// Places the instructions `on_exit` generates on every path that exits the
+/// function body, except for falling through its end: at every `return` and at
+/// every branch to the label of the function body. These find the results of
+/// the function on top of the stack and must leave them there. Paths for which
+/// `on_exit` returns `None` are left untouched.
+pub fn instrument_exit_branches(
+    body: &BodyInner,
+    function_index: Idx<Function>,
+    module: &mut Module,
+    on_exit: &mut dyn FnMut(&TypedHighLevelInstr, &mut Module) -> Option<BodyInner>,
+) -> BodyInner {
+    transform(body, 0, function_index, module, on_exit)
+}
+
+/// `depth` is the number of labels enclosing `body`, i.e. the label that
+/// targets the function body itself.
+fn transform(
+    body: &BodyInner,
+    depth: u32,
+    function_index: Idx<Function>,
+    module: &mut Module,
+    on_exit: &mut dyn FnMut(&TypedHighLevelInstr, &mut Module) -> Option<BodyInner>,
+) -> BodyInner {
+    let mut result = Vec::new();
+
+    for typed_instr @ TypedHighLevelInstr { instr, .. } in body {
+        if typed_instr.is_uninstrumented() && exits_function(instr, depth) {
+            if let Some(instrumentation) = on_exit(typed_instr, module) {
+                result.extend(instrument_exit(
+                    typed_instr,
+                    instrumentation,
+                    depth,
+                    function_index,
+                    module,
+                ));
+                continue;
+            }
+        }
+
+        // DEFAULT TRAVERSAL
+        match instr {
+            Instr::If(type_, then, None) => {
+                result.push(typed_instr.place_untouched(Instr::If(
+                    *type_,
+                    transform(then, depth + 1, function_index, module, on_exit),
+                    None,
+                )));
+            }
+            Instr::If(type_, then, Some(else_)) => {
+                result.push(typed_instr.place_untouched(Instr::If(
+                    *type_,
+                    transform(then, depth + 1, function_index, module, on_exit),
+                    Some(transform(else_, depth + 1, function_index, module, on_exit)),
+                )))
+            }
+            Instr::Loop(type_, body) => {
+                result.push(typed_instr.place_untouched(Instr::Loop(
+                    *type_,
+                    transform(body, depth + 1, function_index, module, on_exit),
+                )));
+            }
+            Instr::Block(type_, body) => {
+                result.push(typed_instr.place_untouched(Instr::Block(
+                    *type_,
+                    transform(body, depth + 1, function_index, module, on_exit),
+                )));
+            }
+            instr => result.push(typed_instr.place_untouched(instr.clone())),
+        }
+    }
+    result
+}
+
+/// Places `instrumentation` before `typed_instr`, which may exit the function.
+/// A conditional exit only performs it when the branch is taken.
+fn instrument_exit(
+    typed_instr: &TypedHighLevelInstr,
+    instrumentation: BodyInner,
+    depth: u32,
+    function_index: Idx<Function>,
+    module: &mut Module,
+) -> BodyInner {
+    let TypedHighLevelInstr { instr, .. } = typed_instr;
+    let results = module.function(function_index).type_.results().to_vec();
+    // Takes the results and leaves them, as `instrumentation` does
+    let passing_results = FunctionType::new(&results, &results);
+    let mut result = Vec::new();
+
+    match instr {
+        Instr::Return | Instr::Br(_) => {
+            // STACK: [type_out]
+            result.extend(instrumentation);
+            // STACK: [type_out]
+        }
+        Instr::BrIf(_) => {
+            let condition = fresh_local(module, function_index, ValType::I32);
+            let get_condition =
+                || typed_instr.instrument_with(Instr::Local(LocalOp::Get, condition));
+            // STACK: [type_out, condition]
+            result.extend([
+                typed_instr.instrument_with(Instr::Local(LocalOp::Set, condition)),
+                // STACK: [type_out]
+                get_condition(),
+                // STACK: [type_out, condition]
+                typed_instr.instrument_with(Instr::if_then(passing_results, instrumentation)),
+                // STACK: [type_out]
+                get_condition(),
+            ]);
+            // STACK: [type_out, condition]
+        }
+        Instr::BrTable { table, default } => {
+            let target = fresh_local(module, function_index, ValType::I32);
+            let get_target = || typed_instr.instrument_with(Instr::Local(LocalOp::Get, target));
+            let constant = |value: usize| {
+                typed_instr.instrument_with(Instr::Const(Val::I32(value.try_into().unwrap())))
+            };
+            let binary = |op| typed_instr.instrument_with(Instr::Binary(op));
+
+            // STACK: [type_out, table_target_index]
+            result.extend([
+                typed_instr.instrument_with(Instr::Local(LocalOp::Set, target)),
+                // STACK: [type_out]
+                constant(0), // false
+            ]);
+            // STACK: [type_out, exits]
+            for (index, _) in table.iter().enumerate().filter(|(_, l)| exits(l, depth)) {
+                result.extend([
+                    get_target(),
+                    constant(index),
+                    binary(BinaryOp::I32Eq),
+                    binary(BinaryOp::I32Or),
+                ]);
+            }
+            if exits(default, depth) {
+                result.extend([
+                    get_target(),
+                    constant(table.len()),
+                    binary(BinaryOp::I32GeU),
+                    binary(BinaryOp::I32Or),
+                ]);
+            }
+            // STACK: [type_out, exits]
+            result.push(
+                typed_instr.instrument_with(Instr::if_then(passing_results, instrumentation)),
+            );
+            // STACK: [type_out]
+            result.push(get_target());
+            // STACK: [type_out, table_target_index]
+        }
+        _ => unreachable!("Only instructions that exit the function are instrumented"),
+    }
+    result.push(typed_instr.place_original(instr.clone()));
+    result
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+
+    use wasabi_wasm::ValType::{I32, I64};
+    use wasabi_wasm::{Function, FunctionType, Idx};
+    use wasmtime::{Caller, Engine, Linker, Module, Store};
+
+    use crate::instrument::function_application::INSTRUMENTATION_STACK_MODULE;
+    use crate::instrument::reference_handles::ReferenceHandles;
+    use crate::instrument::return_values::returned_type;
+    use crate::parse_nesting::{HighLevelBody, LowLevelBody};
+    use crate::site_predicate::Sites;
+    use crate::stack_library::ModuleLinkedStackHooks;
+    use crate::wasm_constructs::{Signature, SignatureSide, WasmType};
+
+    use super::{entered_type, Hook, Target};
+
+    pub(crate) const EXIT_PATHS_PROGRAM: &str = r#"
+    (module
+      ;; 0 -> br_if to the function label
+      ;; 1 -> return
+      ;; 2 -> br_table to $inner, fall through
+      ;; 3 -> br_table to the function label
+      ;; 4 -> br_table default to the function label
+      (func $main (param $a i32) (result i32)
+        (block $inner (result i32)
+          (br_if 1 (i32.const 10) (i32.eqz (local.get $a)))
+          (drop)
+          (if (i32.eq (local.get $a) (i32.const 1))
+            (then (return (i32.const 11))))
+          (br_table $inner 1 1 (i32.const 12) (i32.sub (local.get $a) (i32.const 2))))
+        (i32.const 1)
+        (i32.add))
+      (export "main" (func $main)))"#;
+
+    /// The result of `$main` in [`EXIT_PATHS_PROGRAM`] for each of its inputs
+    pub(crate) const EXIT_PATHS: [(i32, i32); 5] = [(0, 10), (1, 11), (2, 13), (3, 12), (4, 12)];
+
+    /// Stands in for both the stack library, of which every buffer holds a
+    /// single value, and the analysis.
+    #[derive(Default)]
+    pub(crate) struct Analysis {
+        pub(crate) buffers: Vec<i32>,
+        pub(crate) entered: Vec<i32>,
+        pub(crate) exited: Vec<i32>,
+    }
+
+    /// Imports the stack library for the arguments and the results of `function`.
+    pub(crate) fn import_stack_hooks(
+        module: &mut wasabi_wasm::Module,
+        function: Idx<Function>,
+    ) -> HashMap<FunctionType, ModuleLinkedStackHooks> {
+        let function_type = module.function(function).type_;
+        [entered_type(&function_type), returned_type(&function_type)]
+            .into_iter()
+            .map(|function_type| {
+                let stack_hooks =
+                    ModuleLinkedStackHooks::from_function_type_module(function_type, module);
+                (function_type, stack_hooks)
+            })
+            .collect()
+    }
+
+    /// Links the stack library for a function of the type `[i32] -> [i32]`.
+    pub(crate) fn link_stack_library(linker: &mut Linker<Analysis>) {
+        let entered = Signature {
+            return_types: vec![],
+            argument_types: vec![WasmType::I32],
+        };
+        let returned = Signature {
+            return_types: vec![WasmType::I32],
+            argument_types: vec![],
+        };
+        let module = INSTRUMENTATION_STACK_MODULE;
+        let load =
+            |caller: Caller<'_, Analysis>, buffer: i32| caller.data().buffers[buffer as usize];
+        let store = |mut caller: Caller<'_, Analysis>, buffer: i32, value: i32| {
+            caller.data_mut().buffers[buffer as usize] = value;
+        };
+
+        linker
+            .func_wrap(
+                module,
+                &entered.generate_allocate_values_buffer_name(),
+                |mut caller: Caller<'_, Analysis>, argument: i32| {
+                    let buffers = &mut caller.data_mut().buffers;
+                    buffers.push(argument);
+                    i32::try_from(buffers.len() - 1).unwrap()
+                },
+            )
+            .unwrap();
+        linker
+            .func_wrap(
+                module,
+                &returned.generate_allocate_values_buffer_name(),
+                |mut caller: Caller<'_, Analysis>| {
+                    let buffers = &mut caller.data_mut().buffers;
+                    buffers.push(0);
+                    i32::try_from(buffers.len() - 1).unwrap()
+                },
+            )
+            .unwrap();
+        for (signature, signature_side) in [
+            (&entered, SignatureSide::Argument),
+            (&returned, SignatureSide::Return),
+        ] {
+            linker
+                .func_wrap(
+                    module,
+                    &signature.generate_load_name(signature_side, 0),
+                    load,
+                )
+                .unwrap();
+            linker
+                .func_wrap(
+                    module,
+                    &signature.generate_store_name(signature_side, 0),
+                    store,
+                )
+                .unwrap();
+        }
+        linker
+            .func_wrap(module, &entered.generate_store_rets_name(), |_: i32| {})
+            .unwrap();
+        linker
+            .func_wrap(module, &returned.generate_store_rets_name(), store)
+            .unwrap();
+        for signature in [&entered, &returned] {
+            linker
+                .func_wrap(
+                    module,
+                    &signature.generate_allocate_types_buffer_name(),
+                    || 0,
+                )
+                .unwrap();
+            for name in [
+                signature.generate_free_values_buffer_name(),
+                signature.generate_free_types_buffer_name(),
+            ] {
+                linker.func_wrap(module, &name, |_: i32| {}).unwrap();
+            }
+        }
+    }
+
+    /// Records the single result and adds 1000 to it.
+    pub(crate) fn overwrite_result(
+        mut caller: Caller<'_, Analysis>,
+        resc: i32,
+        resv: i32,
+        _restypv: i32,
+        _fidx: i64,
+        _iidx: i64,
+    ) {
+        assert_eq!(resc, 1);
+        let analysis = caller.data_mut();
+        analysis.exited.push(analysis.buffers[resv as usize]);
+        analysis.buffers[resv as usize] += 1000;
+    }
+
+    #[test]
+    fn test_enter_exit_on_every_path() {
+        let wasm_bytes = wat::parse_str(EXIT_PATHS_PROGRAM).unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+
+        let main = 0_u32.into();
+        let signature_import_links = import_stack_hooks(&mut wasm_module, main);
+        let reference_handles = ReferenceHandles::default();
+        let trap_type = FunctionType::new(&[I32, I32, I32, I64, I64], &[]);
+        let targets = [(Hook::Enter, "enter"), (Hook::Exit, "exit")].map(|(hook, name)| Target {
+            hook,
+            trap_idx: wasm_module.add_function_import(trap_type, "analysis".into(), name.into()),
+            sites: Sites::default(),
+            signature_import_links: &signature_import_links,
+            reference_handles: &reference_handles,
+        });
+
+        let function = wasm_module.function(main);
+        let code = function.code().unwrap();
+        let high_level_body: HighLevelBody =
+            (&wasm_module, function, code, &main).try_into().unwrap();
+        let transformed = targets.iter().fold(high_level_body, |body, target| {
+            target.transform(&body, main, &mut wasm_module)
+        });
+        let LowLevelBody(low_level_body) = LowLevelBody::from(transformed);
+        wasm_module.function_mut(main).code_mut().unwrap().body = low_level_body;
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, Analysis::default());
+        let mut linker = Linker::new(&engine);
+        link_stack_library(&mut linker);
+        linker
+            .func_wrap(
+                "analysis",
+                "enter",
+                |mut caller: Caller<'_, Analysis>, argc: i32, argv: i32, _: i32, _: i64, _: i64| {
+                    assert_eq!(argc, 1);
+                    let analysis = caller.data_mut();
+                    let argument = analysis.buffers[argv as usize];
+                    analysis.entered.push(argument);
+                    if argument == 7 {
+                        analysis.buffers[argv as usize] = 1;
+                    }
+                },
+            )
+            .unwrap();
+        linker
+            .func_wrap("analysis", "exit", overwrite_result)
+            .unwrap();
+        let module = Module::new(&engine, wasm_module.to_bytes().unwrap()).unwrap();
+        let instance = linker.instantiate(&mut store, &module).unwrap();
+        let main = instance
+            .get_typed_func::<i32, i32>(&mut store, "main")
+            .unwrap();
+
+        // The analysis takes the path of input 1 for input 7
+        for (input, output) in EXIT_PATHS.into_iter().chain([(7, 11)]) {
+            assert_eq!(main.call(&mut store, input).unwrap(), output + 1000);
+            let Analysis {
+                entered, exited, ..
+            } = store.data_mut();
+            assert_eq!(std::mem::take(entered), [input]);
+            assert_eq!(std::mem::take(exited), [output], "Input {input}");
+        }
+    }
+}
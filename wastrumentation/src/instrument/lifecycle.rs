@@ -0,0 +1,375 @@
+use wasabi_wasm::{
+    BinaryOp, DataMode, ElementMode, Function, FunctionType, GlobalOp, Idx, Instr, LocalOp, Module,
+    Mutability, UnaryOp, Val, ValType,
+};
+
+use super::Instrumentable;
+
+/// The functions the input program exports to its host, before any exports
+/// for the analysis are added by the instrumentation.
+pub fn exported_functions(module: &Module) -> Vec<Idx<Function>> {
+    module
+        .functions()
+        .filter(|(_index, f)| !f.export.is_empty())
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// Runs the `module_init` trap before the start function of the input program.
+/// The engine applies active segments before any code executes, so these are
+/// made passive and applied right after the trap instead, in the order the
+/// engine would: element segments first, data segments next. A data segment
+/// for a memory other than the first is left active, as `memory.init` cannot
+/// address it.
+pub fn inject_module_init(module: &mut Module, trap_idx: Idx<Function>) {
+    use wasabi_wasm::Instr::{Call, End};
+
+    let mut body = vec![Call(trap_idx)];
+    body.extend(apply_active_elements(module));
+    body.extend(apply_active_data(module));
+    if let Some(original_start) = module.start {
+        body.push(Call(original_start));
+    }
+    body.push(End);
+
+    let start = module.add_function(FunctionType::new(&[], &[]), vec![], body);
+    module.name_generated(start, "module_init");
+    module.start = Some(start);
+}
+
+/// The instructions of a constant expression, without its closing `end`.
+fn evaluate(offset: &[Instr]) -> impl Iterator<Item = Instr> + '_ {
+    offset
+        .iter()
+        .filter(|instr| !matches!(instr, Instr::End))
+        .cloned()
+}
+
+fn length(length: usize) -> Instr {
+    Instr::Const(Val::I32(i32::try_from(length).unwrap()))
+}
+
+/// Makes the active element segments passive, returning the instructions
+/// that apply them.
+fn apply_active_elements(module: &mut Module) -> Vec<Instr> {
+    use wasabi_wasm::Instr::{Const, ElemDrop, TableInit};
+
+    let mut apply = vec![];
+    for (index, element) in module.elements.iter_mut().enumerate() {
+        let ElementMode::Active { table, offset } = &element.mode else {
+            continue;
+        };
+        let element_idx = Idx::from(index);
+        // [destination:i32]
+        apply.extend(evaluate(offset));
+        apply.extend([
+            // [destination:i32, source:i32]
+            Const(Val::I32(0)),
+            // [destination:i32, source:i32, length:i32]
+            length(element.init.len()),
+            TableInit(*table, element_idx),
+            // []
+            ElemDrop(element_idx),
+        ]);
+        element.mode = ElementMode::Passive;
+    }
+    apply
+}
+
+/// Makes the active data segments of the first memory passive, returning the
+/// instructions that apply them.
+fn apply_active_data(module: &mut Module) -> Vec<Instr> {
+    use wasabi_wasm::Instr::{Const, DataDrop, MemoryInit};
+
+    let mut apply = vec![];
+    for (index, data) in module.data.iter_mut().enumerate() {
+        let DataMode::Active { memory, offset } = &data.mode else {
+            continue;
+        };
+        if memory.to_u32() != 0 {
+            continue;
+        }
+        let data_idx = Idx::from(index);
+        // [destination:i32]
+        apply.extend(evaluate(offset));
+        apply.extend([
+            // [destination:i32, source:i32]
+            Const(Val::I32(0)),
+            // [destination:i32, source:i32, length:i32]
+            length(data.init.len()),
+            MemoryInit(data_idx),
+            // []
+            DataDrop(data_idx),
+        ]);
+        data.mode = DataMode::Passive;
+    }
+    apply
+}
+
+/// Runs the `module_finalize` trap whenever a call from the host into one of
+/// `exported` returns. Exports that are re-entered through an import while an
+/// outer export is still running do not finalize, only the outermost does.
+/// A call that traps never returns, so it cannot tell that it left, and later
+/// calls would count as re-entered. A host that catches a trap out of the
+/// module calls the exported `module_finalize_reset` before calling it again.
+pub fn inject_module_finalize(
+    module: &mut Module,
+    trap_idx: Idx<Function>,
+    exported: &[Idx<Function>],
+) {
+    use wasabi_wasm::Instr::{Binary, Call, Const, End, Global, If, Local, Unary};
+
+    let depth = module.add_global(ValType::I32, Mutability::Mut, vec![Const(Val::I32(0)), End]);
+    let reset = module.add_function(
+        FunctionType::new(&[], &[]),
+        vec![],
+        vec![Const(Val::I32(0)), Global(GlobalOp::Set, depth), End],
+    );
+    module.export_generated(reset, "module_finalize_reset");
+
+    for exported_idx in exported {
+        let function_type = module.function(*exported_idx).type_;
+
+        let mut body = vec![
+            // []
+            Global(GlobalOp::Get, depth),
+            Const(Val::I32(1)),
+            Binary(BinaryOp::I32Add),
+            Global(GlobalOp::Set, depth),
+        ];
+        // [args]
+        body.extend((0..function_type.inputs().len()).map(|arg| Local(LocalOp::Get, arg.into())));
+        body.extend([
+            Call(*exported_idx),
+            // [results]
+            Global(GlobalOp::Get, depth),
+            Const(Val::I32(1)),
+            Binary(BinaryOp::I32Sub),
+            Global(GlobalOp::Set, depth),
+            Global(GlobalOp::Get, depth),
+            // [results, depth]
+            Unary(UnaryOp::I32Eqz),
+            // [results, returns_to_host]
+            If(FunctionType::new(&[], &[])),
+            Const(Val::I32(i32::try_from(exported_idx.to_u32()).unwrap())),
+            Call(trap_idx),
+            End,
+            // [results]
+            End,
+        ]);
+
+        let wrapper_idx = module.add_function(function_type, vec![], body);
+        let wrapped_name = module
+            .function(*exported_idx)
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("function_{}", exported_idx.to_u32()));
+        module.name_generated(wrapper_idx, &format!("{wrapped_name}_finalize"));
+        let exports = std::mem::take(&mut module.function_mut(*exported_idx).export);
+        module.function_mut(wrapper_idx).export = exports;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::{Engine, Instance, Module, Store};
+
+    use super::{inject_module_finalize, inject_module_init};
+
+    const LIFECYCLE_PROGRAM: &str = r#"
+    (module
+      (global $started (mut i32) (i32.const 0))
+      (global $started_before_init (mut i32) (i32.const -1))
+      (global $finalized (mut i32) (i32.const 0))
+      (global $last_finalized (mut i32) (i32.const -1))
+
+      (func $main (param $a i32) (result i32)
+        (i32.add (call $inner (local.get $a)) (i32.const 1)))
+      (func $inner (param $a i32) (result i32)
+        (i32.mul (local.get $a) (i32.const 2)))
+      (func $start
+        (global.set $started (i32.const 1)))
+
+      (func $init
+        (global.set $started_before_init (global.get $started)))
+      (func $finalize (param i32)
+        (global.set $finalized (i32.add (global.get $finalized) (i32.const 1)))
+        (global.set $last_finalized (local.get 0)))
+
+      (func (export "started_before_init") (result i32) (global.get $started_before_init))
+      (func (export "finalized") (result i32) (global.get $finalized))
+      (func (export "last_finalized") (result i32) (global.get $last_finalized))
+      (export "main" (func $main))
+      (export "inner" (func $inner))
+      (start $start))"#;
+
+    #[test]
+    fn test_init_before_start_and_finalize_on_return_to_host() {
+        let wasm_bytes = wat::parse_str(LIFECYCLE_PROGRAM).unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+
+        let (main, inner, init, finalize) = (0_u32, 1_u32, 3_u32, 4_u32);
+        inject_module_init(&mut wasm_module, init.into());
+        inject_module_finalize(
+            &mut wasm_module,
+            finalize.into(),
+            &[main.into(), inner.into()],
+        );
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let module = Module::new(&engine, wasm_module.to_bytes().unwrap()).unwrap();
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let get = |store: &mut Store<()>, name| {
+            instance
+                .get_typed_func::<(), i32>(&mut *store, name)
+                .unwrap()
+                .call(store, ())
+                .unwrap()
+        };
+        let call = |store: &mut Store<()>, name, input| {
+            instance
+                .get_typed_func::<i32, i32>(&mut *store, name)
+                .unwrap()
+                .call(store, input)
+                .unwrap()
+        };
+
+        assert_eq!(get(&mut store, "started_before_init"), 0);
+        assert_eq!(get(&mut store, "finalized"), 0);
+
+        // The call from `main` into `inner` does not return to the host
+        assert_eq!(call(&mut store, "main", 3), 7);
+        assert_eq!(get(&mut store, "finalized"), 1);
+        assert_eq!(get(&mut store, "last_finalized"), main as i32);
+
+        assert_eq!(call(&mut store, "inner", 3), 6);
+        assert_eq!(get(&mut store, "finalized"), 2);
+        assert_eq!(get(&mut store, "last_finalized"), inner as i32);
+    }
+
+    #[test]
+    fn test_generated_functions_are_named() {
+        let wasm_bytes = wat::parse_str(LIFECYCLE_PROGRAM).unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+
+        let (main, init, finalize) = (0_u32, 3_u32, 4_u32);
+        inject_module_init(&mut wasm_module, init.into());
+        inject_module_finalize(&mut wasm_module, finalize.into(), &[main.into()]);
+
+        // The name section is rewritten along with the functions
+        let (wasm_module, _, _) =
+            wasabi_wasm::Module::from_bytes(&wasm_module.to_bytes().unwrap()).unwrap();
+        let names: Vec<Option<&str>> = wasm_module
+            .functions()
+            .map(|(_index, f)| f.name.as_deref())
+            .collect();
+        assert_eq!(names[0], Some("main"));
+        assert_eq!(names[4], Some("finalize"));
+        assert_eq!(
+            names[8..],
+            [
+                Some("module_init"),
+                Some("module_finalize_reset"),
+                Some("main_finalize")
+            ]
+        );
+    }
+
+    const SEGMENTS_PROGRAM: &str = r#"
+    (module
+      (global $data_before_init (mut i32) (i32.const -1))
+      (global $table_empty_before_init (mut i32) (i32.const -1))
+      (memory 1)
+      (table 1 funcref)
+
+      (func $init
+        (global.set $data_before_init (i32.load8_u (i32.const 16)))
+        (global.set $table_empty_before_init (ref.is_null (table.get 0 (i32.const 0)))))
+      (func $answer (result i32) (i32.const 42))
+
+      (func (export "data_before_init") (result i32) (global.get $data_before_init))
+      (func (export "table_empty_before_init") (result i32) (global.get $table_empty_before_init))
+      (func (export "data") (result i32) (i32.load8_u (i32.const 16)))
+      (func (export "call_table") (result i32) (call_indirect (result i32) (i32.const 0)))
+      (elem (i32.const 0) $answer)
+      (data (i32.const 16) "\2a"))"#;
+
+    #[test]
+    fn test_init_before_active_segments() {
+        let wasm_bytes = wat::parse_str(SEGMENTS_PROGRAM).unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+        inject_module_init(&mut wasm_module, 0_u32.into());
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let module = Module::new(&engine, wasm_module.to_bytes().unwrap()).unwrap();
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let mut get = |name| {
+            instance
+                .get_typed_func::<(), i32>(&mut store, name)
+                .unwrap()
+                .call(&mut store, ())
+                .unwrap()
+        };
+
+        assert_eq!(get("data_before_init"), 0);
+        assert_eq!(get("table_empty_before_init"), 1);
+        assert_eq!(get("data"), 42);
+        assert_eq!(get("call_table"), 42);
+    }
+
+    const TRAPPING_PROGRAM: &str = r#"
+    (module
+      (global $finalized (mut i32) (i32.const 0))
+
+      (func $fail (unreachable))
+      (func $succeed)
+      (func $finalize (param i32)
+        (global.set $finalized (i32.add (global.get $finalized) (i32.const 1))))
+
+      (func (export "finalized") (result i32) (global.get $finalized))
+      (export "fail" (func $fail))
+      (export "succeed" (func $succeed)))"#;
+
+    #[test]
+    fn test_finalize_after_trap() {
+        let wasm_bytes = wat::parse_str(TRAPPING_PROGRAM).unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+
+        let (fail, succeed, finalize) = (0_u32, 1_u32, 2_u32);
+        inject_module_finalize(
+            &mut wasm_module,
+            finalize.into(),
+            &[fail.into(), succeed.into()],
+        );
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let module = Module::new(&engine, wasm_module.to_bytes().unwrap()).unwrap();
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let call = |store: &mut Store<()>, name| {
+            instance
+                .get_typed_func::<(), ()>(&mut *store, name)
+                .unwrap()
+                .call(store, ())
+        };
+        let finalized = |store: &mut Store<()>| {
+            instance
+                .get_typed_func::<(), i32>(&mut *store, "finalized")
+                .unwrap()
+                .call(store, ())
+                .unwrap()
+        };
+
+        call(&mut store, "succeed").unwrap();
+        assert_eq!(finalized(&mut store), 1);
+        assert!(call(&mut store, "fail").is_err());
+        assert_eq!(finalized(&mut store), 1);
+
+        // Without a reset, the call after the trap would count as re-entered
+        call(&mut store, "module_finalize_reset").unwrap();
+        call(&mut store, "succeed").unwrap();
+        assert_eq!(finalized(&mut store), 2);
+    }
+}
@@ -1,17 +1,29 @@
-use super::TransformationStrategy;
+use super::{Instrumentable, TransformationStrategy};
 use crate::parse_nesting::{
     BodyInner, HighLevelBody, HighLevelInstr as Instr, TypedHighLevelInstr,
 };
+use crate::site_predicate::Sites;
 
 use wasabi_wasm::types::InferredInstructionType;
 use wasabi_wasm::{
-    Function, FunctionType, GlobalOp, Idx, LoadOp, LocalOp, Memarg, Module, StoreOp, Val, ValType,
+    Code, Data, Function, FunctionType, GlobalOp, Idx, LoadOp, LocalOp, Memarg, Module, StoreOp,
+    Val, ValType,
 };
 
+/// The memory every load, store, `memory.fill`, `memory.copy` and `memory.init`
+/// addresses. `wasabi_wasm` carries no memory index for these instructions, the
+/// ones it parses always target memory 0. Their traps report it, and the base
+/// load and store shims trap on any other memory index, until one can be chosen.
+const IMPLICIT_MEMORY: u32 = 0;
+
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum Target {
     MemorySize(Idx<Function>),
     MemoryGrow(Idx<Function>),
+    MemoryFill(Idx<Function>),
+    MemoryCopy(Idx<Function>),
+    MemoryInit(Idx<Function>),
+    DataDrop(Idx<Function>),
 
     // Local: Get / Set / Tee
     // - I32
@@ -53,18 +65,23 @@ pub enum Target {
 }
 
 impl TransformationStrategy for Target {
-    fn transform(&self, high_level_body: &HighLevelBody, _: &mut Module) -> HighLevelBody {
+    fn transform(
+        &self,
+        high_level_body: &HighLevelBody,
+        _: &mut Module,
+        sites: &Sites,
+    ) -> HighLevelBody {
         let HighLevelBody(body) = high_level_body;
-        let transformed_body = transform(body, *self);
+        let transformed_body = transform(body, *self, sites);
         HighLevelBody(transformed_body)
     }
 }
 
-fn transform(body: &BodyInner, target: Target) -> BodyInner {
+fn transform(body: &BodyInner, target: Target, sites: &Sites) -> BodyInner {
     let mut result = Vec::new();
 
     for typed_instr @ TypedHighLevelInstr { instr, .. } in body {
-        if typed_instr.is_uninstrumented() {
+        if typed_instr.is_uninstrumented() && sites.selects(typed_instr) {
             match (target, instr) {
                 (Target::MemorySize(trap_idx), Instr::MemorySize(idx)) => {
                     result.extend_from_slice(&[
@@ -89,6 +106,52 @@ fn transform(body: &BodyInner, target: Target) -> BodyInner {
 
                     continue;
                 }
+                (Target::MemoryFill(trap_idx), Instr::MemoryFill) => {
+                    result.extend_from_slice(&[
+                        // [destination:I32,value:I32,length:I32]   // Push memory index on stack
+                        typed_instr.instrument_with(Instr::Const(Val::I64(IMPLICIT_MEMORY.into()))),
+                        // [destination:I32,value:I32,length:I32,index:I64]
+                    ]);
+                    result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    // []
+                    continue;
+                }
+                (Target::MemoryCopy(trap_idx), Instr::MemoryCopy) => {
+                    result.extend_from_slice(&[
+                        // [destination:I32,source:I32,length:I32]  // Push destination memory index on stack
+                        typed_instr.instrument_with(Instr::Const(Val::I64(IMPLICIT_MEMORY.into()))),
+                        // [destination:I32,source:I32,length:I32,destination-index:I64]
+                        typed_instr.instrument_with(Instr::Const(Val::I64(IMPLICIT_MEMORY.into()))),
+                        // [destination:I32,source:I32,length:I32,destination-index:I64,source-index:I64]
+                    ]);
+                    result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    // []
+                    continue;
+                }
+                (Target::MemoryInit(trap_idx), Instr::MemoryInit(data_idx)) => {
+                    result.extend_from_slice(&[
+                        // [destination:I32,offset:I32,length:I32]  // Push data segment index on stack
+                        typed_instr
+                            .instrument_with(Instr::Const(Val::I64(data_idx.to_u32().into()))),
+                        // [destination:I32,offset:I32,length:I32,data-index:I64]
+                        typed_instr.instrument_with(Instr::Const(Val::I64(IMPLICIT_MEMORY.into()))),
+                        // [destination:I32,offset:I32,length:I32,data-index:I64,index:I64]
+                    ]);
+                    result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    // []
+                    continue;
+                }
+                (Target::DataDrop(trap_idx), Instr::DataDrop(data_idx)) => {
+                    result.extend_from_slice(&[
+                        // []                                       // Push data segment index on stack
+                        typed_instr
+                            .instrument_with(Instr::Const(Val::I64(data_idx.to_u32().into()))),
+                        // [data-index:I64]
+                    ]);
+                    result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    // []
+                    continue;
+                }
                 _ => (),
             }
 
@@ -221,6 +284,8 @@ fn transform(body: &BodyInner, target: Target) -> BodyInner {
                                     // [i32: index to write to, F32: value to write to, U32 as I64: Offset]
                                     typed_instr.instrument_with(Instr::Const(Val::I32($store_op.serialize()))),
                                     // [i32: index to write to, F32: value to write to, U32 as I64: Offset, i32: serialized operation]
+                                    typed_instr.instrument_with(Instr::Const(Val::I64(IMPLICIT_MEMORY.into()))),
+                                    // [i32: index to write to, F32: value to write to, U32 as I64: Offset, i32: serialized operation, I64: memory index]
                                 ]);
                                 result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
                                 continue;
@@ -244,6 +309,8 @@ fn transform(body: &BodyInner, target: Target) -> BodyInner {
                                     // [i32: index to load from,  U32as I64: Offset]
                                     typed_instr.instrument_with(Instr::Const(Val::I32($load_op.serialize()))),
                                     // [i32: index to load from,  U32as I64: Offset, i32: serialized operation]
+                                    typed_instr.instrument_with(Instr::Const(Val::I64(IMPLICIT_MEMORY.into()))),
+                                    // [i32: index to load from,  U32as I64: Offset, i32: serialized operation, I64: memory index]
                                 ]);
                                 result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
                                 continue;
@@ -302,25 +369,27 @@ fn transform(body: &BodyInner, target: Target) -> BodyInner {
             (target, Instr::If(type_, then, None)) => {
                 result.push(typed_instr.place_untouched(Instr::If(
                     *type_,
-                    transform(then, target),
+                    transform(then, target, sites),
                     None,
                 )));
             }
             (target, Instr::If(type_, then, Some(else_))) => {
                 result.push(typed_instr.place_untouched(Instr::If(
                     *type_,
-                    transform(then, target),
-                    Some(transform(else_, target)),
+                    transform(then, target, sites),
+                    Some(transform(else_, target, sites)),
                 )))
             }
             (target, Instr::Loop(type_, body)) => {
                 result.push(
-                    typed_instr.place_untouched(Instr::Loop(*type_, transform(body, target))),
+                    typed_instr
+                        .place_untouched(Instr::Loop(*type_, transform(body, target, sites))),
                 );
             }
             (target, Instr::Block(type_, body)) => {
                 result.push(
-                    typed_instr.place_untouched(Instr::Block(*type_, transform(body, target))),
+                    typed_instr
+                        .place_untouched(Instr::Block(*type_, transform(body, target, sites))),
                 );
             }
             (_, instr) => result.push(typed_instr.place_untouched(instr.clone())),
@@ -370,6 +439,40 @@ impl Serialize for LoadOp {
     }
 }
 
+/// Generates a chain of `if`s that compares the `i32` in local `index_local`
+/// against each of `indices`, running the instructions `on_index` generates for
+/// the first match. Any other index hits `unreachable`.
+pub(super) fn dispatch_on_index(
+    indices: impl IntoIterator<Item = u32>,
+    index_local: u32,
+    results: &[ValType],
+    on_index: impl Fn(u32) -> Vec<wasabi_wasm::Instr>,
+) -> Vec<wasabi_wasm::Instr> {
+    use wasabi_wasm::BinaryOp::I32Eq;
+    use wasabi_wasm::Instr::{Binary, Const, Else, End, If, Local, Unreachable};
+
+    let mut body = vec![];
+    let mut nesting = 0;
+    for index in indices {
+        body.extend_from_slice(&[
+            // []
+            Local(LocalOp::Get, index_local.into()),
+            // [runtime_index]
+            Const(Val::I32(index as i32)),
+            // [runtime_index, index]
+            Binary(I32Eq),
+            // [is_index]
+            If(FunctionType::new(&[], results)),
+        ]);
+        body.extend(on_index(index));
+        body.push(Else);
+        nesting += 1;
+    }
+    body.push(Unreachable);
+    body.extend(std::iter::repeat_n(End, nesting));
+    body
+}
+
 pub fn inject_memory_loads(module: &mut Module) {
     use wasabi_wasm::Instr::{Binary, Const, End, Load, Local};
     use wasabi_wasm::LoadOp::{F32Load, F64Load, I32Load, I64Load}; // Regular
@@ -384,8 +487,6 @@ pub fn inject_memory_loads(module: &mut Module) {
         I64Load8U,
     };
 
-    assert!(module.memories.len() <= 1);
-
     for (name, load_op, load_type) in [
         // Regular
         ("instrumented_base_load_i32", I32Load, ValType::I32),
@@ -405,7 +506,8 @@ pub fn inject_memory_loads(module: &mut Module) {
         ("instrumented_base_load_i64_32S", I64Load32S, ValType::I64),
         ("instrumented_base_load_i64_32U", I64Load32U, ValType::I64),
     ] {
-        let function_type = FunctionType::new(&[ValType::I32, ValType::I32], &[load_type]);
+        let function_type =
+            FunctionType::new(&[ValType::I32, ValType::I32, ValType::I32], &[load_type]);
         let body = if module.memories.is_empty() {
             vec![
                 // []
@@ -414,25 +516,25 @@ pub fn inject_memory_loads(module: &mut Module) {
                 End,
             ]
         } else {
-            vec![
-                // []
-                Local(LocalOp::Get, 0_u32.into()),
-                // [ptr]
-                Local(LocalOp::Get, 1_u32.into()),
-                // [ptr, offset]
-                Binary(wasabi_wasm::BinaryOp::I32Add),
-                // [ptr + offset]
-                Load(load_op, Memarg::default(load_op)),
-                // [value]
-                End,
-            ]
+            let mut body = dispatch_on_index([IMPLICIT_MEMORY], 2, &[load_type], |_memory| {
+                vec![
+                    // []
+                    Local(LocalOp::Get, 0_u32.into()),
+                    // [ptr]
+                    Local(LocalOp::Get, 1_u32.into()),
+                    // [ptr, offset]
+                    Binary(wasabi_wasm::BinaryOp::I32Add),
+                    // [ptr + offset]
+                    Load(load_op, Memarg::default(load_op)),
+                    // [value]
+                ]
+            });
+            body.push(End);
+            body
         };
 
         let memory_function_idx = module.add_function(function_type, vec![], body);
-        module
-            .function_mut(memory_function_idx)
-            .export
-            .push(name.to_string());
+        module.export_generated(memory_function_idx, name);
     }
 }
 
@@ -442,8 +544,6 @@ pub fn inject_memory_stores(module: &mut Module) {
     use wasabi_wasm::StoreOp::{I32Store16, I32Store8}; // I32 Specialized
     use wasabi_wasm::StoreOp::{I64Store16, I64Store32, I64Store8}; // I64 Specialized
 
-    assert!(module.memories.len() <= 1);
-
     for (name, store_op, store_type) in [
         // Regular
         ("instrumented_base_store_i32", I32Store, ValType::I32),
@@ -458,37 +558,37 @@ pub fn inject_memory_stores(module: &mut Module) {
         ("instrumented_base_store_i64_16", I64Store16, ValType::I64),
         ("instrumented_base_store_i64_32", I64Store32, ValType::I64),
     ] {
-        let function_type = FunctionType::new(&[ValType::I32, store_type, ValType::I32], &[]);
+        let function_type =
+            FunctionType::new(&[ValType::I32, store_type, ValType::I32, ValType::I32], &[]);
         let body = if module.memories.is_empty() {
             vec![End]
         } else {
-            vec![
-                // []
-                Local(LocalOp::Get, 0_u32.into()),
-                // [ptr]
-                Local(LocalOp::Get, 2_u32.into()),
-                // [ptr, offset]
-                Binary(wasabi_wasm::BinaryOp::I32Add),
-                // [ptr + offset]
-                Local(LocalOp::Get, 1_u32.into()),
-                // [ptr + offset, value]
-                Store(store_op, Memarg::default(store_op)),
-                // []
-                End,
-            ]
+            let mut body = dispatch_on_index([IMPLICIT_MEMORY], 3, &[], |_memory| {
+                vec![
+                    // []
+                    Local(LocalOp::Get, 0_u32.into()),
+                    // [ptr]
+                    Local(LocalOp::Get, 2_u32.into()),
+                    // [ptr, offset]
+                    Binary(wasabi_wasm::BinaryOp::I32Add),
+                    // [ptr + offset]
+                    Local(LocalOp::Get, 1_u32.into()),
+                    // [ptr + offset, value]
+                    Store(store_op, Memarg::default(store_op)),
+                    // []
+                ]
+            });
+            body.push(End);
+            body
         };
 
         let memory_function_idx: Idx<Function> = module.add_function(function_type, vec![], body);
-        module
-            .function_mut(memory_function_idx)
-            .export
-            .push(name.to_string());
+        module.export_generated(memory_function_idx, name);
     }
 }
 
 pub fn inject_memory_grow(module: &mut Module) {
     use wasabi_wasm::Instr::{Const, End, Local, MemoryGrow};
-    assert!(module.memories.len() <= 1);
 
     let function_type = FunctionType::new(&[ValType::I32, ValType::I32], &[ValType::I32]);
     let body = if module.memories.is_empty() {
@@ -499,26 +599,26 @@ pub fn inject_memory_grow(module: &mut Module) {
             End,
         ]
     } else {
-        vec![
-            // []
-            Local(LocalOp::Get, 0_u32.into()),
-            // [amount:i32]
-            MemoryGrow(0_u32.into()),
-            // [delta_or_neg_1:i32]
-            End,
-        ]
+        let memories = 0..module.memories.len() as u32;
+        let mut body = dispatch_on_index(memories, 1, &[ValType::I32], |memory| {
+            vec![
+                // []
+                Local(LocalOp::Get, 0_u32.into()),
+                // [amount:i32]
+                MemoryGrow(memory.into()),
+                // [delta_or_neg_1:i32]
+            ]
+        });
+        body.push(End);
+        body
     };
 
     let memory_function_idx: Idx<Function> = module.add_function(function_type, vec![], body);
-    module
-        .function_mut(memory_function_idx)
-        .export
-        .push("instrumented_memory_grow".to_string());
+    module.export_generated(memory_function_idx, "instrumented_memory_grow");
 }
 
 pub fn inject_memory_size(module: &mut Module) {
     use wasabi_wasm::Instr::{Const, End, MemorySize};
-    assert!(module.memories.len() <= 1);
 
     let function_type = FunctionType::new(&[ValType::I32], &[ValType::I32]);
     let body = if module.memories.is_empty() {
@@ -528,18 +628,158 @@ pub fn inject_memory_size(module: &mut Module) {
             // [value]
             End,
         ]
+    } else {
+        let memories = 0..module.memories.len() as u32;
+        let mut body = dispatch_on_index(memories, 0, &[ValType::I32], |memory| {
+            vec![
+                // []
+                MemorySize(memory.into()),
+                // [size_in_pages:i32]
+            ]
+        });
+        body.push(End);
+        body
+    };
+
+    let memory_function_idx: Idx<Function> = module.add_function(function_type, vec![], body);
+    module.export_generated(memory_function_idx, "instrumented_memory_size");
+}
+
+pub fn inject_memory_fill(module: &mut Module) {
+    use wasabi_wasm::Instr::{End, Local, MemoryFill};
+
+    let function_type = FunctionType::new(
+        &[ValType::I32, ValType::I32, ValType::I32, ValType::I32],
+        &[],
+    );
+    let body = if module.memories.is_empty() {
+        vec![End]
+    } else {
+        vec![
+            // []
+            Local(LocalOp::Get, 0_u32.into()),
+            // [destination:i32]
+            Local(LocalOp::Get, 1_u32.into()),
+            // [destination:i32, value:i32]
+            Local(LocalOp::Get, 2_u32.into()),
+            // [destination:i32, value:i32, length:i32]
+            MemoryFill,
+            // []
+            End,
+        ]
+    };
+
+    let memory_function_idx: Idx<Function> = module.add_function(function_type, vec![], body);
+    module.export_generated(memory_function_idx, "instrumented_base_memory_fill");
+}
+
+pub fn inject_memory_copy(module: &mut Module) {
+    use wasabi_wasm::Instr::{End, Local, MemoryCopy};
+
+    let function_type = FunctionType::new(
+        &[
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+        ],
+        &[],
+    );
+    let body = if module.memories.is_empty() {
+        vec![End]
     } else {
         vec![
             // []
-            MemorySize(0_u32.into()),
-            // [size_in_pages:i32]
+            Local(LocalOp::Get, 0_u32.into()),
+            // [destination:i32]
+            Local(LocalOp::Get, 1_u32.into()),
+            // [destination:i32, source:i32]
+            Local(LocalOp::Get, 2_u32.into()),
+            // [destination:i32, source:i32, length:i32]
+            MemoryCopy,
+            // []
             End,
         ]
     };
 
     let memory_function_idx: Idx<Function> = module.add_function(function_type, vec![], body);
-    module
-        .function_mut(memory_function_idx)
-        .export
-        .push("instrumented_memory_size".to_string());
+    module.export_generated(memory_function_idx, "instrumented_base_memory_copy");
+}
+
+/// The data segments that are referred to by a `memory.init` or `data.drop`
+/// in `module`. Since these instructions take the data segment as immediate,
+/// the base shims can only dispatch on segments that occur in the module.
+/// Must be gathered before instrumentation replaces the instructions.
+pub fn referred_data_segments(module: &Module) -> Vec<Idx<Data>> {
+    use wasabi_wasm::Instr::{DataDrop, MemoryInit};
+
+    let mut data_segments: Vec<Idx<Data>> = module
+        .functions()
+        .filter_map(|(_index, function)| function.code())
+        .flat_map(|Code { body, .. }| body.iter())
+        .filter_map(|instr| match instr {
+            MemoryInit(data_idx) | DataDrop(data_idx) => Some(*data_idx),
+            _ => None,
+        })
+        .collect();
+    data_segments.sort_by_key(|data_idx| data_idx.to_u32());
+    data_segments.dedup();
+    data_segments
+}
+
+pub fn inject_memory_init(module: &mut Module, data_segments: &[Idx<Data>]) {
+    use wasabi_wasm::Instr::{End, Local, MemoryInit};
+
+    let function_type = FunctionType::new(
+        &[
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+        ],
+        &[],
+    );
+    let body = if module.memories.is_empty() {
+        vec![End]
+    } else {
+        let data_segments = data_segments.iter().map(|data_idx| data_idx.to_u32());
+        let mut body = dispatch_on_index(data_segments, 3, &[], |data_idx| {
+            vec![
+                // []
+                Local(LocalOp::Get, 0_u32.into()),
+                // [destination:i32]
+                Local(LocalOp::Get, 1_u32.into()),
+                // [destination:i32, offset:i32]
+                Local(LocalOp::Get, 2_u32.into()),
+                // [destination:i32, offset:i32, length:i32]
+                MemoryInit(data_idx.into()),
+                // []
+            ]
+        });
+        body.push(End);
+        body
+    };
+
+    let memory_function_idx: Idx<Function> = module.add_function(function_type, vec![], body);
+    module.export_generated(memory_function_idx, "instrumented_base_memory_init");
+}
+
+pub fn inject_data_drop(module: &mut Module, data_segments: &[Idx<Data>]) {
+    use wasabi_wasm::Instr::{DataDrop, End};
+
+    let function_type = FunctionType::new(&[ValType::I32], &[]);
+    let data_segments = data_segments.iter().map(|data_idx| data_idx.to_u32());
+    let mut body = dispatch_on_index(data_segments, 0, &[], |data_idx| {
+        vec![
+            // []
+            DataDrop(data_idx.into()),
+            // []
+        ]
+    });
+    body.push(End);
+
+    let memory_function_idx: Idx<Function> = module.add_function(function_type, vec![], body);
+    module.export_generated(memory_function_idx, "instrumented_base_data_drop");
 }
@@ -4,6 +4,7 @@ use wasabi_wasm::Code;
 use wasabi_wasm::FunctionType;
 use wasabi_wasm::ImportOrPresent;
 use wasabi_wasm::Module;
+use wasabi_wasm::RefType;
 use wasabi_wasm::ValType;
 
 use crate::compiler::{LibGeneratable, Library};
@@ -14,23 +15,52 @@ use crate::analysis::{AnalysisInterface, WasmExport, WasmImport, WasmType};
 use crate::error::InstrumentationError;
 use crate::parse_nesting::HighLevelBody;
 use crate::parse_nesting::LowLevelBody;
+use crate::site_predicate::Sites;
+use crate::stack_library::StackLibrary;
+use crate::target_selector;
+use crate::Configuration;
 
-use self::block_loop::Target::{BlockPost, BlockPre, LoopPost, LoopPre, Select};
+use self::basic_block::BasicBlocks;
+use self::block_loop::Target::{BlockPost, BlockPre, LoopIteration, LoopPost, LoopPre, Select};
 use self::branch_if::Target::{Br, BrIf, BrTable, IfThen, IfThenElse, IfThenElsePost, IfThenPost};
+use self::call_values::CallValues;
+use self::callee_resolution::CalleeResolution;
+use self::debug_info::DebugInfo;
 use self::function_application::INSTRUMENTATION_ANALYSIS_MODULE;
 use self::function_call_indirect::Target::{
     IndirectPost as CallIndirectPost, IndirectPre as CallIndirectPre, Post as CallPost,
     Pre as CallPre,
 };
 use self::memory::Target::*;
+use self::owned_memory::OwnedMemory;
+use self::reference_handles::ReferenceHandles;
+use self::return_values::ReturnValues;
 use self::simple_operations::Target::*;
+use self::site_id::SiteIds;
+use self::source_location::SourceLocations;
+use self::table::Target::*;
 
+pub mod basic_block;
 pub mod block_loop;
 pub mod branch_if;
+pub mod call_values;
+pub mod callee_resolution;
+mod code_layout;
+mod debug_info;
+pub mod edge_coverage;
 pub mod function_application;
 pub mod function_call_indirect;
+pub mod function_enter_exit;
+pub mod lifecycle;
 pub mod memory;
+mod owned_memory;
+pub mod reference_handles;
+pub mod return_values;
 pub mod simple_operations;
+pub mod site_id;
+pub mod source_location;
+pub mod stack_values;
+pub mod table;
 
 pub struct Instrumented<InstrumentationLanguage: LibGeneratable> {
     pub module: Vec<u8>,
@@ -40,8 +70,17 @@ pub struct Instrumented<InstrumentationLanguage: LibGeneratable> {
 pub fn instrument<InstrumentationLanguage: LibGeneratable>(
     module: &[u8],
     analysis_interface: &AnalysisInterface,
-    target_indices: &Option<Vec<u32>>,
+    configuration: &Configuration,
 ) -> Result<Instrumented<InstrumentationLanguage>, InstrumentationError> {
+    let Configuration {
+        target_indices,
+        target_selectors,
+        site_predicate,
+        source_locations,
+        site_ids,
+        debug_info,
+        primary_selection: _,
+    } = configuration;
     let AnalysisInterface {
         generic_interface,
         if_then_else_trap,
@@ -54,14 +93,32 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
         post_trap_call,
         pre_trap_call_indirect,
         post_trap_call_indirect,
+        call_values_pre,
+        call_values_post,
+        function_enter,
+        function_exit,
+        module_init,
+        module_finalize,
+        basic_block,
         br_table_trap,
         pre_block,
         post_block,
         pre_loop,
         post_loop,
+        loop_iteration,
         select,
         drop_trap,
         return_trap,
+        return_values,
+        drop_i32,
+        drop_f32,
+        drop_i64,
+        drop_f64,
+        unreachable_trap,
+        nop_trap,
+        ref_null,
+        ref_is_null,
+        ref_func,
         const_i32_trap,
         const_f32_trap,
         const_i64_trap,
@@ -91,6 +148,18 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
         binary_f64_f64_to_f64,
         memory_size,
         memory_grow,
+        memory_fill,
+        memory_copy,
+        memory_init,
+        data_drop,
+        table_get,
+        table_set,
+        table_size,
+        table_grow,
+        table_fill,
+        table_copy,
+        table_init,
+        elem_drop,
         local_get_i32,
         local_set_i32,
         local_tee_i32,
@@ -121,32 +190,45 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
         i64_load,
     } = analysis_interface;
 
+    let input_program = module;
     let (mut module, _offsets, _issue) =
         Module::from_bytes(module).map_err(InstrumentationError::ParseModuleError)?;
 
+    let referred_data_segments = memory::referred_data_segments(&module);
+    let referred_table_inits = table::referred_table_inits(&module);
+    let referred_element_segments = table::referred_element_segments(&module);
+    let exported_functions = lifecycle::exported_functions(&module);
+    // Traces the code of the input program as it is rewritten, see `debug_info`
+    let mut debug_info = if *debug_info {
+        DebugInfo::of(input_program, &module)?
+    } else {
+        None
+    };
+
     let target_indices_including_imports: HashSet<Idx<Function>> = module
         .functions()
-        .filter(|(_index, f)| !uses_reference_types(f))
-        .map(|(idx, _)| idx)
-        .filter(|index| {
-            target_indices
-                .as_ref()
-                .is_none_or(|ts| ts.contains(&index.to_u32()))
+        .filter(|(index, f)| {
+            target_selector::is_target(*index, f, target_indices, target_selectors)
         })
+        .map(|(idx, _)| idx)
         .collect();
 
     let target_indices: HashSet<Idx<Function>> = module
         .functions()
         .filter(|(_index, f)| f.code().is_some())
-        .filter(|(_index, f)| !uses_reference_types(f))
-        .map(|(idx, _)| idx)
-        .filter(|index| {
-            target_indices
-                .as_ref()
-                .is_none_or(|ts| ts.contains(&index.to_u32()))
+        .filter(|(index, f)| {
+            target_selector::is_target(*index, f, target_indices, target_selectors)
         })
+        .map(|(idx, _)| idx)
         .collect();
 
+    // Read from the input program as encoded, see `source_location`
+    let source_locations = (*source_locations)
+        .then(|| SourceLocations::of(input_program, &target_indices))
+        .transpose()?;
+    // Computed from the input program, see `site_id`
+    let site_ids = (*site_ids).then(|| SiteIds::of(&module, &target_indices));
+
     // For each function, generate high-level typed AST
     let target_high_level_functions: Vec<HighLevelBody> = target_indices
         .iter()
@@ -161,6 +243,37 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
         })
         .collect::<Result<Vec<HighLevelBody>, InstrumentationError>>()?;
 
+    // Built from the input program, so the block ids are not affected by the
+    // functions the instrumentation adds
+    let basic_blocks = match basic_block {
+        Some(export) => Some((
+            BasicBlocks::of(&module)?,
+            module.install(export),
+            Sites::new(site_predicate.as_ref(), &export.name),
+        )),
+        None => None,
+    };
+
+    // The callee of a `call_indirect` is resolved through tables shadowing the
+    // function tables of the input program, see `callee_resolution`
+    let callee_resolution = (pre_trap_call_indirect.is_some() || post_trap_call_indirect.is_some())
+        .then(|| CalleeResolution::install(&mut module));
+
+    // The references table instructions read or write reach the analysis as
+    // handles, see `reference_handles`
+    let table_handles = [table_get, table_set, table_grow, table_fill]
+        .iter()
+        .any(|export| export.is_some())
+        .then(|| {
+            let ref_types: Vec<RefType> =
+                module.tables.iter().map(|table| table.ref_type).collect();
+            let mut table_handles = ReferenceHandles::default();
+            for ref_type in ref_types {
+                table_handles.install(&mut module, ref_type);
+            }
+            table_handles
+        });
+
     //  Install all tarps
     type TFn = fn(Idx<Function>) -> Box<dyn TransformationStrategy>;
     let traps_target_generators = [
@@ -168,11 +281,10 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
         (post_block, (|i| Box::new(BlockPost(i)))),
         (pre_loop, (|i| Box::new(LoopPre(i)))),
         (post_loop, (|i| Box::new(LoopPost(i)))),
+        (loop_iteration, (|i| Box::new(LoopIteration(i)))),
         (select, (|i| Box::new(Select(i)))),
         (pre_trap_call, (|i| Box::new(CallPre(i)))),
         (post_trap_call, (|i| Box::new(CallPost(i)))),
-        (pre_trap_call_indirect, (|i| Box::new(CallIndirectPre(i)))),
-        (post_trap_call_indirect, (|i| Box::new(CallIndirectPost(i)))),
         (if_then_trap, (|i| Box::new(IfThen(i)))),
         (if_then_post_trap, (|i| Box::new(IfThenPost(i)))),
         (if_then_else_trap, (|i| Box::new(IfThenElse(i)))),
@@ -182,6 +294,15 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
         (br_table_trap, (|i| Box::new(BrTable(i)))),
         (drop_trap, (|i| Box::new(Drop(i)))),
         (return_trap, (|i| Box::new(Return(i)))),
+        (drop_i32, (|i| Box::new(DropI32(i)))),
+        (drop_f32, (|i| Box::new(DropF32(i)))),
+        (drop_i64, (|i| Box::new(DropI64(i)))),
+        (drop_f64, (|i| Box::new(DropF64(i)))),
+        (unreachable_trap, (|i| Box::new(Unreachable(i)))),
+        (nop_trap, (|i| Box::new(Nop(i)))),
+        (ref_null, (|i| Box::new(RefNull(i)))),
+        (ref_is_null, (|i| Box::new(RefIsNull(i)))),
+        (ref_func, (|i| Box::new(RefFunc(i)))),
         (const_i32_trap, (|i| Box::new(ConstI32(i)))),
         (const_f32_trap, (|i| Box::new(ConstF32(i)))),
         (const_i64_trap, (|i| Box::new(ConstI64(i)))),
@@ -211,6 +332,14 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
         (binary_f64_f64_to_f64, (|i| Box::new(BinaryF64F64toF64(i)))),
         (memory_size, (|i| Box::new(MemorySize(i)))),
         (memory_grow, (|i| Box::new(MemoryGrow(i)))),
+        (memory_fill, (|i| Box::new(MemoryFill(i)))),
+        (memory_copy, (|i| Box::new(MemoryCopy(i)))),
+        (memory_init, (|i| Box::new(MemoryInit(i)))),
+        (data_drop, (|i| Box::new(DataDrop(i)))),
+        (table_size, (|i| Box::new(TableSize(i)))),
+        (table_copy, (|i| Box::new(TableCopy(i)))),
+        (table_init, (|i| Box::new(TableInit(i)))),
+        (elem_drop, (|i| Box::new(ElemDrop(i)))),
         (local_get_i32, (|i| Box::new(LocalGetI32(i)))),
         (local_set_i32, (|i| Box::new(LocalSetI32(i)))),
         (local_tee_i32, (|i| Box::new(LocalTeeI32(i)))),
@@ -239,29 +368,203 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
         (f64_load, (|i| Box::new(F64Load(i)))),
         (i32_load, (|i| Box::new(I32Load(i)))),
         (i64_load, (|i| Box::new(I64Load(i)))),
-    ] as [(&Option<WasmExport>, TFn); 75];
+    ] as [(&Option<WasmExport>, TFn); 91];
 
-    let targets: Vec<Box<dyn TransformationStrategy>> = traps_target_generators
+    // Each target only places its hook at the sites the predicate selects
+    let site_predicate = site_predicate.as_ref();
+    let mut targets: Vec<(Sites, Box<dyn TransformationStrategy + '_>)> = traps_target_generators
         .into_iter()
         .filter_map(|(export, target_gen)| {
-            export
-                .as_ref()
-                .map(|export| module.install(export))
-                .map(target_gen)
+            export.as_ref().map(|export| {
+                (
+                    Sites::new(site_predicate, &export.name),
+                    target_gen(module.install(export)),
+                )
+            })
         })
         .collect();
 
+    if let Some(callee_resolution) = &callee_resolution {
+        type TFnIndirect<'a> =
+            fn(Idx<Function>, &'a CalleeResolution) -> function_call_indirect::Target<'a>;
+        for (export, target_gen) in [
+            (pre_trap_call_indirect, CallIndirectPre as TFnIndirect),
+            (post_trap_call_indirect, CallIndirectPost as TFnIndirect),
+        ] {
+            if let Some(export) = export {
+                let trap_idx = module.install(export);
+                targets.push((
+                    Sites::new(site_predicate, &export.name),
+                    Box::new(target_gen(trap_idx, callee_resolution)),
+                ));
+            }
+        }
+    }
+
+    if let Some(table_handles) = &table_handles {
+        type TFnTable<'a> = fn(Idx<Function>, &'a ReferenceHandles) -> table::Target<'a>;
+        for (export, target_gen) in [
+            (table_get, TableGet as TFnTable),
+            (table_set, TableSet as TFnTable),
+            (table_grow, TableGrow as TFnTable),
+            (table_fill, TableFill as TFnTable),
+        ] {
+            if let Some(export) = export {
+                let trap_idx = module.install(export);
+                targets.push((
+                    Sites::new(site_predicate, &export.name),
+                    Box::new(target_gen(trap_idx, table_handles)),
+                ));
+            }
+        }
+    }
+
+    // Values the analysis inspects as `RuntimeValues` travel through the stack library
+    let mut value_function_types: HashSet<FunctionType> = HashSet::new();
+    if return_values.is_some() {
+        value_function_types.extend(
+            target_indices
+                .iter()
+                .map(|index| return_values::returned_type(&module.function(*index).type_)),
+        );
+    }
+    if call_values_pre.is_some() || call_values_post.is_some() {
+        value_function_types.extend(call_values::called_types(&module, &target_indices));
+    }
+    if function_enter.is_some() {
+        value_function_types.extend(
+            target_indices
+                .iter()
+                .map(|index| function_enter_exit::entered_type(&module.function(*index).type_)),
+        );
+    }
+    if function_exit.is_some() {
+        value_function_types.extend(
+            target_indices
+                .iter()
+                .map(|index| return_values::returned_type(&module.function(*index).type_)),
+        );
+    }
+    let mut value_handles = ReferenceHandles::default();
+    for function_type in &value_function_types {
+        value_handles.install_for(&mut module, function_type);
+    }
+
+    let mut stack_function_types = value_function_types;
+    if generic_interface.is_some() {
+        stack_function_types.extend(
+            target_indices_including_imports
+                .iter()
+                .map(|index| module.function(*index).type_),
+        );
+    }
+    let passes_values = return_values.is_some()
+        || call_values_pre.is_some()
+        || call_values_post.is_some()
+        || function_enter.is_some()
+        || function_exit.is_some();
+    let stack_library = (generic_interface.is_some() || passes_values).then(|| {
+        StackLibrary::<InstrumentationLanguage>::from_function_types(
+            &mut module,
+            &stack_function_types,
+        )
+    });
+
+    let call_values_target = stack_library
+        .as_ref()
+        .filter(|_| call_values_pre.is_some() || call_values_post.is_some())
+        .map(|stack_library| CallValues {
+            pre_trap_idx: call_values_pre
+                .as_ref()
+                .map(|export| module.install(export)),
+            post_trap_idx: call_values_post
+                .as_ref()
+                .map(|export| module.install(export)),
+            pre_sites: call_values_pre
+                .as_ref()
+                .map(|export| Sites::new(site_predicate, &export.name))
+                .unwrap_or_default(),
+            post_sites: call_values_post
+                .as_ref()
+                .map(|export| Sites::new(site_predicate, &export.name))
+                .unwrap_or_default(),
+            signature_import_links: &stack_library.signature_import_links,
+            reference_handles: &value_handles,
+        });
+    let return_values_target =
+        return_values
+            .as_ref()
+            .zip(stack_library.as_ref())
+            .map(|(export, stack_library)| ReturnValues {
+                trap_idx: module.install(export),
+                sites: Sites::new(site_predicate, &export.name),
+                signature_import_links: &stack_library.signature_import_links,
+                reference_handles: &value_handles,
+            });
+
+    // Installed after the other traps, so they enclose every other trap in the body
+    let enter_exit_targets: Vec<function_enter_exit::Target> = [
+        (function_enter, function_enter_exit::Hook::Enter),
+        (function_exit, function_enter_exit::Hook::Exit),
+    ]
+    .into_iter()
+    .filter_map(|(export, hook)| {
+        export
+            .as_ref()
+            .zip(stack_library.as_ref())
+            .map(|(export, stack_library)| function_enter_exit::Target {
+                hook,
+                trap_idx: module.install(export),
+                sites: Sites::new(site_predicate, &export.name),
+                signature_import_links: &stack_library.signature_import_links,
+                reference_handles: &value_handles,
+            })
+    })
+    .collect();
+
     let transformed_bodies: Vec<HighLevelBody> = target_high_level_functions
         .into_iter()
-        .map(|high_level_body| {
-            targets.iter().fold(high_level_body, |transformed, target| {
-                target.transform(&transformed, &mut module)
-            })
+        .zip(target_indices.iter())
+        .map(|(high_level_body, target_function_idx)| {
+            // Placed first, so a block is entered before the traps of its instructions
+            let transformed = match &basic_blocks {
+                Some((basic_blocks, trap_idx, sites))
+                    if sites.selects_function(target_function_idx.to_u32()) =>
+                {
+                    basic_blocks.transform(&high_level_body, *target_function_idx, *trap_idx)
+                }
+                _ => high_level_body,
+            };
+            let transformed = targets
+                .iter()
+                .fold(transformed, |transformed, (sites, target)| {
+                    target.transform(&transformed, &mut module, sites)
+                });
+            let transformed = call_values_target
+                .iter()
+                .fold(transformed, |transformed, target| {
+                    target.transform(&transformed, *target_function_idx, &mut module)
+                });
+            let transformed =
+                return_values_target
+                    .iter()
+                    .fold(transformed, |transformed, target| {
+                        target.transform(&transformed, *target_function_idx, &mut module)
+                    });
+            enter_exit_targets
+                .iter()
+                .fold(transformed, |transformed, target| {
+                    target.transform(&transformed, *target_function_idx, &mut module)
+                })
         })
         .collect();
 
     for (target_function_idx, transformed_body) in target_indices.iter().zip(transformed_bodies) {
-        let LowLevelBody(transformed_low_level_body) = transformed_body.into();
+        let (LowLevelBody(transformed_low_level_body), origins) =
+            LowLevelBody::with_origins(transformed_body);
+        if let Some(debug_info) = &mut debug_info {
+            debug_info.rewritten(*target_function_idx, &origins);
+        }
         let locals = module
             .function(*target_function_idx)
             .code()
@@ -274,58 +577,123 @@ pub fn instrument<InstrumentationLanguage: LibGeneratable>(
         });
     }
 
-    let instrumentation_library =
-        generic_interface
-            .as_ref()
-            .map(|(generic_import, generic_export)| {
-                function_application::instrument::<InstrumentationLanguage>(
-                    &mut module,
-                    &target_indices_including_imports,
-                    generic_import,
-                    generic_export,
-                )
-            });
+    if let (Some((generic_import, generic_export)), Some(stack_library)) =
+        (generic_interface, &stack_library)
+    {
+        // The generic apply wraps the functions the predicate selects
+        let sites = Sites::new(site_predicate, &generic_import.name);
+        let applied_indices: HashSet<Idx<Function>> = target_indices_including_imports
+            .iter()
+            .copied()
+            .filter(|index| sites.selects_function(index.to_u32()))
+            .collect();
+        let uninstrumented_indices = function_application::instrument(
+            &mut module,
+            &applied_indices,
+            &stack_library.signature_import_links,
+            generic_import,
+            generic_export,
+        );
+        if let Some(debug_info) = &mut debug_info {
+            for (function_idx, uninstrumented_idx) in uninstrumented_indices {
+                debug_info.moved(function_idx, uninstrumented_idx);
+            }
+        }
+    }
+    let instrumentation_library = stack_library.map(|StackLibrary { library, .. }| library);
 
     memory::inject_memory_loads(&mut module);
     memory::inject_memory_stores(&mut module);
     memory::inject_memory_grow(&mut module);
     memory::inject_memory_size(&mut module);
+    memory::inject_memory_fill(&mut module);
+    memory::inject_memory_copy(&mut module);
+    memory::inject_memory_init(&mut module, &referred_data_segments);
+    memory::inject_data_drop(&mut module, &referred_data_segments);
+    table::inject_table_copy(&mut module);
+    table::inject_table_init(&mut module, &referred_table_inits);
+    table::inject_elem_drop(&mut module, &referred_element_segments);
 
-    Ok(Instrumented {
-        module: module
-            .to_bytes()
-            .map_err(InstrumentationError::EncodeError)?,
-        instrumentation_library,
-    })
-}
-
-fn uses_reference_types(f: &Function) -> bool {
-    for ty_ in f.type_.inputs() {
-        match ty_ {
-            ValType::I32 | ValType::I64 | ValType::F32 | ValType::F64 => continue,
-            ValType::Ref(_) => return true,
+    if let Some(callee_resolution) = &callee_resolution {
+        let mirrored = callee_resolution.mirror_table_mutations(&mut module);
+        if let Some(debug_info) = &mut debug_info {
+            for (function_idx, origins) in mirrored {
+                debug_info.rewritten(function_idx, &origins);
+            }
         }
     }
-    for ty_ in f.type_.results() {
-        match ty_ {
-            ValType::I32 | ValType::I64 | ValType::F32 | ValType::F64 => continue,
-            ValType::Ref(_) => return true,
-        }
+
+    // After mirroring, which would mirror the active segments it applies a second time
+    if let Some(module_init) = module_init {
+        let trap_idx = module.install(module_init);
+        lifecycle::inject_module_init(&mut module, trap_idx);
+    }
+    if let Some(module_finalize) = module_finalize {
+        let trap_idx = module.install(module_finalize);
+        lifecycle::inject_module_finalize(&mut module, trap_idx, &exported_functions);
     }
-    false
+
+    if let Some((basic_blocks, _, _)) = &basic_blocks {
+        basic_blocks.install_side_table(&mut module);
+    }
+
+    let mut owned_memory = OwnedMemory::default();
+    if let Some(source_locations) = &source_locations {
+        source_locations.install_side_table(&mut module);
+        source_locations.inject_lookups(&mut module, &mut owned_memory);
+    }
+
+    if let Some(site_ids) = &site_ids {
+        site_ids.install_side_table(&mut module);
+        site_ids.inject_lookup(&mut module, &mut owned_memory);
+    }
+
+    let module = match debug_info {
+        Some(debug_info) => debug_info.encode(&mut module, &owned_memory)?,
+        None => {
+            debug_info::strip(&mut module);
+            owned_memory.attach(
+                &module
+                    .to_bytes()
+                    .map_err(InstrumentationError::EncodeError)?,
+            )?
+        }
+    };
+    Ok(Instrumented {
+        module,
+        instrumentation_library,
+    })
 }
 
 trait Instrumentable {
     fn install(&mut self, export: &WasmExport) -> Idx<Function>;
+    /// Names a function the instrumentation generated in the name section,
+    /// so that it reads well in stack traces and profiles.
+    fn name_generated(&mut self, function_idx: Idx<Function>, name: &str);
+    /// Names a generated function and exports it under that same name.
+    fn export_generated(&mut self, function_idx: Idx<Function>, name: &str);
 }
 
 impl Instrumentable for Module {
     fn install(&mut self, export: &WasmExport) -> Idx<Function> {
-        self.add_function_import(
+        let trap_idx = self.add_function_import(
             export.as_function_type(),
             INSTRUMENTATION_ANALYSIS_MODULE.to_string(),
             export.name.to_string(),
-        )
+        );
+        self.name_generated(trap_idx, &export.name);
+        trap_idx
+    }
+
+    fn name_generated(&mut self, function_idx: Idx<Function>, name: &str) {
+        self.function_mut(function_idx).name = Some(name.to_string());
+    }
+
+    fn export_generated(&mut self, function_idx: Idx<Function>, name: &str) {
+        self.name_generated(function_idx, name);
+        self.function_mut(function_idx)
+            .export
+            .push(name.to_string());
     }
 }
 
@@ -376,42 +744,11 @@ impl FunctionTypeConvertible for WasmImport {
 }
 
 pub trait TransformationStrategy {
-    fn transform(&self, high_level_body: &HighLevelBody, module: &mut Module) -> HighLevelBody;
-}
-
-#[cfg(test)]
-mod tests {
-    use wasabi_wasm::RefType::{ExternRef, FuncRef};
-    use wasabi_wasm::ValType::{self, Ref, F32, F64, I32, I64};
-    use wasabi_wasm::{Code, Function, FunctionType};
-
-    use super::uses_reference_types;
-
-    #[test]
-    fn test_uses_reference_types() {
-        let assertions: &[(&[ValType], &[ValType], bool)] = &[
-            (&[], &[], false),
-            (&[F32, F64], &[I32, I64], false),
-            (&[I32, I32], &[], false),
-            (&[], &[I32, I32], false),
-            (&[Ref(FuncRef)], &[], true),
-            (&[], &[Ref(FuncRef)], true),
-            (&[Ref(ExternRef)], &[], true),
-            (&[], &[Ref(ExternRef)], true),
-            (&[F32, F32, F32, Ref(FuncRef)], &[], true),
-            (&[], &[F64, F64, F64, Ref(FuncRef)], true),
-            (&[I32, I32, I32, Ref(FuncRef)], &[], true),
-            (&[], &[I64, I64, I64, Ref(FuncRef)], true),
-            (
-                &[I32, I32, I32, Ref(ExternRef)],
-                &[I64, I64, I64, Ref(ExternRef)],
-                true,
-            ),
-        ];
-        for (inputs, results, uses_reference) in assertions {
-            let ft = FunctionType::new(inputs, results);
-            let fc = Function::new(ft, Code::new(), vec![]);
-            assert_eq!(uses_reference_types(&fc), *uses_reference)
-        }
-    }
+    /// Places the hook only at the instructions `sites` selects.
+    fn transform(
+        &self,
+        high_level_body: &HighLevelBody,
+        module: &mut Module,
+        sites: &Sites,
+    ) -> HighLevelBody;
 }
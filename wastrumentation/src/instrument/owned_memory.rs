@@ -0,0 +1,462 @@
+// A memory of the instrumentation's own, for state the program must not be
+// able to overwrite, e.g. the coverage map or the tables lookups search. It is
+// added after the memories of the program, so these keep their indices.
+//
+// The loads and stores `wasabi_wasm` emits cannot address a memory other than
+// memory 0. Those meant for the owned memory carry a marker alignment instead,
+// one no access has, and `OwnedMemory::attach` points them at the owned memory
+// once the module is encoded. Only the section framing and the code section are
+// read, the module is otherwise untouched.
+
+use std::collections::BTreeMap;
+
+use wasabi_wasm::Instr::{
+    Binary, Block, Br, BrIf, Const, Else, End, If, Load, Local, Loop, Return, Unary,
+};
+use wasabi_wasm::{
+    BinaryOp, Function, FunctionType, Idx, LoadOp, LocalOp, Memarg, Module, UnaryOp, Val, ValType,
+};
+use wasmparser::{Operator, Parser, Payload, TypeRef};
+
+use crate::error::InstrumentationError;
+
+const HEADER_LENGTH: usize = 8; // magic number and version
+const PAGE_SIZE: u32 = 1 << 16;
+/// The alignment exponent of a memarg that addresses the owned memory. No
+/// access is aligned to more than 2^3 bytes, but it still parses.
+const MARKER_ALIGNMENT: u8 = 0x3F;
+/// The alignment flags of a rewritten memarg: byte alignment, followed by the
+/// index of the memory
+const MEMORY_INDEX_FLAG: u8 = 0x40;
+
+const MEMORY_SECTION_ID: u8 = 5;
+const EXPORT_SECTION_ID: u8 = 7;
+const CODE_SECTION_ID: u8 = 10;
+const DATA_SECTION_ID: u8 = 11;
+const DATA_COUNT_SECTION_ID: u8 = 12;
+/// The order of the known sections in a module, by id
+const SECTION_ORDER: [u8; 13] = [1, 2, 3, 4, 5, 13, 6, 7, 8, 9, 12, 10, 11];
+/// A record of a lookup table: the key as `i64`, then the value in 8 bytes
+const RECORD_SIZE: u32 = 16;
+
+/// A memarg for an access at `offset` into the owned memory.
+pub fn memarg(offset: u32) -> Memarg {
+    Memarg {
+        alignment_exp: MARKER_ALIGNMENT,
+        offset,
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OwnedMemory {
+    export: Option<String>,
+    /// The number of bytes reserved so far
+    size: u32,
+    /// The initial contents, by address
+    segments: Vec<(u32, Vec<u8>)>,
+}
+
+impl OwnedMemory {
+    /// An owned memory the module exports to its host under `name`.
+    pub fn exported(name: &str) -> Self {
+        Self {
+            export: Some(name.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Reserves `length` zeroed bytes, aligned to 8, yielding their address.
+    pub fn reserve(&mut self, length: u32) -> u32 {
+        let address = self.size.next_multiple_of(8);
+        self.size = address + length;
+        address
+    }
+
+    /// Reserves room for `bytes` and initializes it with them, yielding their
+    /// address.
+    pub fn place(&mut self, bytes: Vec<u8>) -> u32 {
+        let address = self.reserve(u32::try_from(bytes.len()).unwrap());
+        if !bytes.is_empty() {
+            self.segments.push((address, bytes));
+        }
+        address
+    }
+
+    /// Adds a function `(function_index: i32, instr_index: i32) -> value` to
+    /// `module`, which binary searches `records`, placed in the memory sorted
+    /// by key. It answers `unknown` for an instruction without a record. The
+    /// values are of the type of `unknown`, an `i32` or an `i64`.
+    pub fn add_lookup(
+        &mut self,
+        module: &mut Module,
+        records: &BTreeMap<(u32, u32), Val>,
+        unknown: Val,
+    ) -> Idx<Function> {
+        let (value_type, value_load) = match unknown {
+            Val::I64(_) => (ValType::I64, LoadOp::I64Load),
+            _ => (ValType::I32, LoadOp::I32Load),
+        };
+        let function_type = FunctionType::new(&[ValType::I32, ValType::I32], &[value_type]);
+        if records.is_empty() {
+            return module.add_function(function_type, vec![], vec![Const(unknown), End]);
+        }
+
+        let mut table = Vec::with_capacity(records.len() * usize::try_from(RECORD_SIZE).unwrap());
+        for ((function_index, instr_index), value) in records {
+            let key = (u64::from(*function_index) << 32) | u64::from(*instr_index);
+            table.extend(key.to_le_bytes());
+            match value {
+                Val::I64(value) => table.extend(value.to_le_bytes()),
+                Val::I32(value) => table.extend(i64::from(*value).to_le_bytes()),
+                _ => unreachable!("Lookups answer integers"),
+            }
+        }
+        let table = self.place(table);
+        let count = i32::try_from(records.len()).unwrap();
+
+        let [function_index, instr_index] = [0_u32, 1].map(Idx::from);
+        let [key, low, high, middle, record] = [2_u32, 3, 4, 5, 6].map(Idx::from);
+        let get = |local| Local(LocalOp::Get, local);
+        let set = |local| Local(LocalOp::Set, local);
+        let body = vec![
+            // [] key = function_index << 32 | instr_index
+            get(function_index),
+            Unary(UnaryOp::I64ExtendI32U),
+            Const(Val::I64(32)),
+            Binary(BinaryOp::I64Shl),
+            get(instr_index),
+            Unary(UnaryOp::I64ExtendI32U),
+            Binary(BinaryOp::I64Or),
+            set(key),
+            // [] The key is in `low..high`, if in the table at all
+            Const(Val::I32(count)),
+            set(high),
+            Block(FunctionType::empty()),
+            Loop(FunctionType::empty()),
+            get(low),
+            get(high),
+            Binary(BinaryOp::I32GeU),
+            BrIf(0_usize.into()),
+            // [] middle = (low + high) / 2
+            get(low),
+            get(high),
+            Binary(BinaryOp::I32Add),
+            Const(Val::I32(1)),
+            Binary(BinaryOp::I32ShrU),
+            Local(LocalOp::Tee, middle),
+            Const(Val::I32(i32::try_from(RECORD_SIZE).unwrap())),
+            Binary(BinaryOp::I32Mul),
+            set(record),
+            // [] Found
+            get(record),
+            Load(LoadOp::I64Load, memarg(table)),
+            get(key),
+            Binary(BinaryOp::I64Eq),
+            If(FunctionType::empty()),
+            get(record),
+            Load(value_load, memarg(table + 8)),
+            Return,
+            End,
+            // [] Continues in the half that holds the key
+            get(record),
+            Load(LoadOp::I64Load, memarg(table)),
+            get(key),
+            Binary(BinaryOp::I64LtU),
+            If(FunctionType::empty()),
+            get(middle),
+            Const(Val::I32(1)),
+            Binary(BinaryOp::I32Add),
+            set(low),
+            Else,
+            get(middle),
+            set(high),
+            End,
+            Br(0_usize.into()),
+            End,
+            End,
+            Const(unknown),
+            End,
+        ];
+        let locals = vec![
+            ValType::I64,
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+        ];
+        module.add_function(function_type, locals, body)
+    }
+
+    /// Adds the memory to the encoded `module`, and points every memarg
+    /// created with [`memarg`] at it. A module that reserved nothing is left
+    /// as it is.
+    pub fn attach(&self, module: &[u8]) -> Result<Vec<u8>, InstrumentationError> {
+        if self.size == 0 {
+            return Ok(module.to_vec());
+        }
+        let Scan {
+            memories,
+            bodies,
+            marked,
+        } = Scan::of(module)?;
+
+        let mut sections: Vec<(u8, Vec<u8>)> = sections(module)
+            .map(|(id, content)| (id, content.to_vec()))
+            .collect();
+        let needed = [
+            (MEMORY_SECTION_ID, true),
+            (EXPORT_SECTION_ID, self.export.is_some()),
+            (DATA_SECTION_ID, !self.segments.is_empty()),
+        ];
+        for (id, needed) in needed {
+            if needed && !sections.iter().any(|(present, _)| *present == id) {
+                let position = sections
+                    .iter()
+                    .position(|(present, _)| order(*present).is_some_and(|other| other > order(id)))
+                    .unwrap_or(sections.len());
+                sections.insert(position, (id, vec![0]));
+            }
+        }
+
+        for (id, content) in &mut sections {
+            *content = match *id {
+                MEMORY_SECTION_ID => {
+                    // Sized once, never grown
+                    let pages = self.size.div_ceil(PAGE_SIZE);
+                    let mut memory = vec![0x01];
+                    write_u32(&mut memory, pages);
+                    write_u32(&mut memory, pages);
+                    append(content, 1, &memory)
+                }
+                EXPORT_SECTION_ID => match &self.export {
+                    Some(name) => {
+                        let mut export = Vec::new();
+                        write_u32(&mut export, u32::try_from(name.len()).unwrap());
+                        export.extend_from_slice(name.as_bytes());
+                        export.push(0x02);
+                        write_u32(&mut export, memories);
+                        append(content, 1, &export)
+                    }
+                    None => continue,
+                },
+                DATA_SECTION_ID => {
+                    let mut segments = Vec::new();
+                    for (address, bytes) in &self.segments {
+                        // Active, in the memory at the index that follows
+                        segments.push(0x02);
+                        write_u32(&mut segments, memories);
+                        segments.push(0x41); // i32.const
+                        write_i32(&mut segments, i32::try_from(*address).unwrap());
+                        segments.push(0x0B); // end
+                        write_u32(&mut segments, u32::try_from(bytes.len()).unwrap());
+                        segments.extend_from_slice(bytes);
+                    }
+                    append(content, self.segments.len(), &segments)
+                }
+                DATA_COUNT_SECTION_ID => append(content, self.segments.len(), &[]),
+                CODE_SECTION_ID => {
+                    let mut code = Vec::new();
+                    write_u32(&mut code, u32::try_from(bodies.len()).unwrap());
+                    for body in &bodies {
+                        let mut rewritten = Vec::with_capacity(body.len());
+                        let mut copied = body.start;
+                        for position in marked.iter().filter(|position| body.contains(*position)) {
+                            // The opcode, then the alignment and the offset
+                            rewritten.extend_from_slice(&module[copied..=*position]);
+                            let (_alignment, offset_start) =
+                                read_u32(module, position + 1).expect("Read before by the scan");
+                            rewritten.push(MEMORY_INDEX_FLAG);
+                            write_u32(&mut rewritten, memories);
+                            copied = offset_start;
+                        }
+                        rewritten.extend_from_slice(&module[copied..body.end]);
+                        write_u32(&mut code, u32::try_from(rewritten.len()).unwrap());
+                        code.extend(rewritten);
+                    }
+                    code
+                }
+                _ => continue,
+            };
+        }
+
+        let mut attached = module[..HEADER_LENGTH].to_vec();
+        for (id, content) in sections {
+            attached.push(id);
+            write_u32(&mut attached, u32::try_from(content.len()).unwrap());
+            attached.extend(content);
+        }
+        Ok(attached)
+    }
+}
+
+/// What `attach` needs to know of the module before rewriting it.
+struct Scan {
+    /// The number of memories the module imports or defines
+    memories: u32,
+    /// The range of every function body, its locals included
+    bodies: Vec<std::ops::Range<usize>>,
+    /// The position of every load and store with a marked memarg
+    marked: Vec<usize>,
+}
+
+impl Scan {
+    fn of(module: &[u8]) -> Result<Self, InstrumentationError> {
+        let read_error = InstrumentationError::AttachOwnedMemoryError;
+        let mut scan = Self {
+            memories: 0,
+            bodies: Vec::new(),
+            marked: Vec::new(),
+        };
+        for payload in Parser::new(0).parse_all(module) {
+            match payload.map_err(read_error)? {
+                Payload::ImportSection(imports) => {
+                    for import in imports {
+                        if matches!(import.map_err(read_error)?.ty, TypeRef::Memory(_)) {
+                            scan.memories += 1;
+                        }
+                    }
+                }
+                Payload::MemorySection(memories) => scan.memories += memories.count(),
+                Payload::CodeSectionEntry(body) => {
+                    let mut operators = body.get_operators_reader().map_err(read_error)?;
+                    while !operators.eof() {
+                        let (operator, position) =
+                            operators.read_with_offset().map_err(read_error)?;
+                        let alignment = match operator {
+                            Operator::I32Load { memarg }
+                            | Operator::I64Load { memarg }
+                            | Operator::I32Load8U { memarg }
+                            | Operator::I32Store { memarg }
+                            | Operator::I64Store { memarg }
+                            | Operator::I32Store8 { memarg } => memarg.align,
+                            _ => continue,
+                        };
+                        if alignment == MARKER_ALIGNMENT {
+                            scan.marked.push(position);
+                        }
+                    }
+                    scan.bodies.push(body.range());
+                }
+                _ => {}
+            }
+        }
+        Ok(scan)
+    }
+}
+
+fn order(id: u8) -> Option<usize> {
+    SECTION_ORDER.iter().position(|known| *known == id)
+}
+
+/// The id and content of every section in `module`, in order. Stops at the
+/// first malformed section.
+fn sections(module: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut offset = HEADER_LENGTH.min(module.len());
+    std::iter::from_fn(move || {
+        let id = *module.get(offset)?;
+        let (size, content_start) = read_u32(module, offset + 1)?;
+        let end = content_start.checked_add(usize::try_from(size).ok()?)?;
+        offset = end;
+        Some((id, module.get(content_start..end)?))
+    })
+}
+
+/// The `content` of a vector section with `added` more entries, `entries`.
+fn append(content: &[u8], added: usize, entries: &[u8]) -> Vec<u8> {
+    let (count, entries_start) = read_u32(content, 0).unwrap_or((0, content.len()));
+    let mut appended = Vec::with_capacity(content.len() + entries.len() + 5);
+    write_u32(&mut appended, count + u32::try_from(added).unwrap());
+    appended.extend_from_slice(&content[entries_start..]);
+    appended.extend_from_slice(entries);
+    appended
+}
+
+/// Reads an unsigned LEB128 at `offset`, yielding it and the offset after it.
+fn read_u32(bytes: &[u8], mut offset: usize) -> Option<(u32, usize)> {
+    let mut value: u32 = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = *bytes.get(offset)?;
+        offset += 1;
+        value |= u32::from(byte & 0x7F).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some((value, offset));
+        }
+    }
+    None
+}
+
+fn write_u32(bytes: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = u8::try_from(value & 0x7F).unwrap();
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn write_i32(bytes: &mut Vec<u8>, mut value: i32) {
+    loop {
+        let byte = u8::try_from(value & 0x7F).unwrap();
+        value >>= 7;
+        let sign_bit_clear = byte & 0x40 == 0;
+        if (value == 0 && sign_bit_clear) || (value == -1 && !sign_bit_clear) {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasabi_wasm::{Instr, LoadOp, StoreOp, Val};
+    use wasmtime::{Engine, Instance, Module, Store};
+
+    use super::{memarg, OwnedMemory};
+
+    #[test]
+    fn test_accesses_owned_memory() {
+        let program = r#"
+        (module
+          (memory (export "program") 1)
+          (func (export "moved") (result i32) (i32.const 0)))"#;
+        let wasm_bytes = wat::parse_str(program).unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+
+        let mut owned = OwnedMemory::exported("owned");
+        let first = owned.place(vec![7, 0, 0, 0]);
+        let second = owned.reserve(4);
+        // Moves the placed value to the reserved bytes, leaving memory 0 be
+        wasm_module
+            .function_mut(0_u32.into())
+            .code_mut()
+            .unwrap()
+            .body = vec![
+            Instr::Const(Val::I32(0)),
+            Instr::Const(Val::I32(0)),
+            Instr::Load(LoadOp::I32Load, memarg(first)),
+            Instr::Store(StoreOp::I32Store, memarg(second)),
+            Instr::Const(Val::I32(0)),
+            Instr::Load(LoadOp::I32Load, memarg(second)),
+            Instr::End,
+        ];
+        let attached = owned.attach(&wasm_module.to_bytes().unwrap()).unwrap();
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let module = Module::new(&engine, attached).unwrap();
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let moved = instance
+            .get_typed_func::<(), i32>(&mut store, "moved")
+            .unwrap();
+        assert_eq!(moved.call(&mut store, ()).unwrap(), 7);
+
+        let owned = instance.get_memory(&mut store, "owned").unwrap();
+        let program = instance.get_memory(&mut store, "program").unwrap();
+        assert_eq!(owned.data(&store)[8..12], [7, 0, 0, 0]);
+        assert!(program.data(&store).iter().all(|byte| *byte == 0));
+    }
+}
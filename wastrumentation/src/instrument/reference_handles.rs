@@ -0,0 +1,338 @@
+// Reference values cannot cross into the stack library, which keeps the values
+// it is handed in linear memory. The instrumented module instead stores them in
+// a table of its own and passes the slot they occupy, an `i32` handle, along.
+//
+// Handles are allocated stack-wise: an instrumented function remembers the next
+// free handle on entry and releases the handles it allocated on exit, clearing
+// their slots so the table does not keep the references alive. The table thus
+// only grows as deep as handles are nested. As references cannot be compared,
+// the same reference may occupy several slots. An analysis must therefore not
+// hold on to a handle beyond the call it observed. Handle 0 always refers to
+// `ref.null`.
+
+use std::collections::HashMap;
+
+use wasabi_wasm::Instr::{
+    Binary, Call, Const, Drop, Else, End, Global, If, Local, RefIsNull, RefNull, TableFill,
+    TableGet, TableGrow, TableSet, TableSize,
+};
+use wasabi_wasm::{
+    BinaryOp, Function, FunctionType, GlobalOp, Idx, Instr, Limits, LocalOp, Module, Mutability,
+    RefType, Table, Val, ValType,
+};
+
+use super::Instrumentable;
+
+pub const NULL_HANDLE: i32 = 0;
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct HandleTable {
+    ref_type: RefType,
+    table: Idx<Table>,
+    next_handle: Idx<wasabi_wasm::Global>,
+    to_handle: Idx<Function>,
+    from_handle: Idx<Function>,
+}
+
+impl HandleTable {
+    fn install(module: &mut Module, ref_type: RefType) -> Self {
+        let table = Idx::from(module.tables.len());
+        module.tables.push(Table {
+            limits: Limits {
+                initial_size: 1, // The null handle
+                max_size: None,
+            },
+            import: None,
+            ref_type,
+            export: vec![],
+        });
+
+        let next_handle = module.add_global(
+            ValType::I32,
+            Mutability::Mut,
+            vec![Const(Val::I32(NULL_HANDLE + 1)), End],
+        );
+
+        let to_handle = module.add_function(
+            FunctionType::new(&[ValType::Ref(ref_type)], &[ValType::I32]),
+            vec![],
+            vec![
+                // []
+                Local(LocalOp::Get, 0_u32.into()),
+                RefIsNull,
+                // [is_null]
+                If(FunctionType::new(&[], &[ValType::I32])),
+                Const(Val::I32(NULL_HANDLE)),
+                Else,
+                Global(GlobalOp::Get, next_handle),
+                // [handle]
+                TableSize(table),
+                // [handle, size]
+                Binary(BinaryOp::I32Eq),
+                // [table_is_full]
+                If(FunctionType::new(&[], &[])),
+                Local(LocalOp::Get, 0_u32.into()),
+                Const(Val::I32(1)),
+                TableGrow(table),
+                Drop,
+                Else,
+                Global(GlobalOp::Get, next_handle),
+                Local(LocalOp::Get, 0_u32.into()),
+                TableSet(table),
+                End,
+                // []
+                Global(GlobalOp::Get, next_handle),
+                // [handle]
+                Global(GlobalOp::Get, next_handle),
+                Const(Val::I32(1)),
+                Binary(BinaryOp::I32Add),
+                Global(GlobalOp::Set, next_handle),
+                End,
+                // [handle]
+                End,
+            ],
+        );
+
+        let ref_type_name = match ref_type {
+            RefType::FuncRef => "funcref",
+            RefType::ExternRef => "externref",
+        };
+        module.name_generated(to_handle, &format!("to_handle_{ref_type_name}"));
+
+        let from_handle = module.add_function(
+            FunctionType::new(&[ValType::I32], &[ValType::Ref(ref_type)]),
+            vec![],
+            vec![
+                // []
+                Local(LocalOp::Get, 0_u32.into()),
+                // [handle]
+                TableGet(table),
+                // [reference]
+                End,
+            ],
+        );
+        module.name_generated(from_handle, &format!("from_handle_{ref_type_name}"));
+
+        Self {
+            ref_type,
+            table,
+            next_handle,
+            to_handle,
+            from_handle,
+        }
+    }
+
+    /// Remembers the next free handle in `local`.
+    pub fn save(&self, local: Idx<wasabi_wasm::Local>) -> [Instr; 2] {
+        [
+            Global(GlobalOp::Get, self.next_handle),
+            Local(LocalOp::Set, local),
+        ]
+    }
+
+    /// Releases every handle allocated since `save` was performed on `local`.
+    pub fn restore(&self, local: Idx<wasabi_wasm::Local>) -> [Instr; 9] {
+        [
+            // []                           // Clear the released slots
+            Local(LocalOp::Get, local),
+            RefNull(self.ref_type),
+            Global(GlobalOp::Get, self.next_handle),
+            Local(LocalOp::Get, local),
+            Binary(BinaryOp::I32Sub),
+            // [saved, null, released]
+            TableFill(self.table),
+            // []
+            Local(LocalOp::Get, local),
+            Global(GlobalOp::Set, self.next_handle),
+        ]
+    }
+}
+
+#[derive(PartialEq, Eq, Default, Debug)]
+pub struct ReferenceHandles(HashMap<RefType, HandleTable>);
+
+impl ReferenceHandles {
+    /// Installs the handle table for references of `ref_type`, unless present.
+    pub fn install(&mut self, module: &mut Module, ref_type: RefType) {
+        let Self(tables) = self;
+        tables
+            .entry(ref_type)
+            .or_insert_with(|| HandleTable::install(module, ref_type));
+    }
+
+    /// Installs the handle tables the references in `function_type` require,
+    /// returning the reference types it uses.
+    pub fn install_for(
+        &mut self,
+        module: &mut Module,
+        function_type: &FunctionType,
+    ) -> Vec<RefType> {
+        let used_ref_types: Vec<RefType> = [RefType::FuncRef, RefType::ExternRef]
+            .into_iter()
+            .filter(|ref_type| {
+                function_type
+                    .inputs()
+                    .iter()
+                    .chain(function_type.results())
+                    .any(|val_type| *val_type == ValType::Ref(*ref_type))
+            })
+            .collect();
+        for ref_type in &used_ref_types {
+            self.install(module, *ref_type);
+        }
+        used_ref_types
+    }
+
+    pub fn table(&self, ref_type: &RefType) -> &HandleTable {
+        let Self(tables) = self;
+        tables.get(ref_type).expect("Installed")
+    }
+
+    /// Converts a value of `val_type` on top of the stack into its handle,
+    /// `None` if `val_type` is no reference type.
+    pub fn to_handle(&self, val_type: &ValType) -> Option<Instr> {
+        match val_type {
+            ValType::Ref(ref_type) => Some(Call(self.table(ref_type).to_handle)),
+            _ => None,
+        }
+    }
+
+    /// Converts a handle on top of the stack back into a value of `val_type`,
+    /// `None` if `val_type` is no reference type.
+    pub fn from_handle(&self, val_type: &ValType) -> Option<Instr> {
+        match val_type {
+            ValType::Ref(ref_type) => Some(Call(self.table(ref_type).from_handle)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasabi_wasm::Instr::{Binary, BrIf, Const, Drop, End, Local, Loop, TableSize};
+    use wasabi_wasm::RefType::{ExternRef, FuncRef};
+    use wasabi_wasm::ValType::{self, Ref, F32, F64, I32, I64};
+    use wasabi_wasm::{BinaryOp, FunctionType, LocalOp, Module, RefType, Val};
+    use wasmtime::{Engine, ExternRef as HostExternRef, Func, Instance, Rooted, Store};
+
+    use super::{ReferenceHandles, NULL_HANDLE};
+
+    #[test]
+    fn test_install_for() {
+        let assertions: &[(&[ValType], &[ValType], &[RefType])] = &[
+            (&[], &[], &[]),
+            (&[F32, F64], &[I32, I64], &[]),
+            (&[Ref(FuncRef)], &[], &[FuncRef]),
+            (&[], &[Ref(ExternRef)], &[ExternRef]),
+            (&[I32, I32, I32, Ref(FuncRef)], &[], &[FuncRef]),
+            (
+                &[I32, I32, I32, Ref(ExternRef)],
+                &[I64, I64, I64, Ref(FuncRef)],
+                &[FuncRef, ExternRef],
+            ),
+        ];
+        for (inputs, results, expected_ref_types) in assertions {
+            let wasm_bytes = wat::parse_str("(module)").unwrap();
+            let (mut module, _, _) = Module::from_bytes(&wasm_bytes).unwrap();
+            let mut reference_handles = ReferenceHandles::default();
+            let function_type = FunctionType::new(inputs, results);
+            let used_ref_types = reference_handles.install_for(&mut module, &function_type);
+            assert_eq!(&used_ref_types, expected_ref_types);
+            assert_eq!(module.tables.len(), expected_ref_types.len());
+
+            // Installing for the same signature twice reuses the tables
+            reference_handles.install_for(&mut module, &function_type);
+            assert_eq!(module.tables.len(), expected_ref_types.len());
+        }
+    }
+
+    #[test]
+    fn test_null_reference_is_null_handle() {
+        let wasm_bytes = wat::parse_str("(module)").unwrap();
+        let (mut module, _, _) = Module::from_bytes(&wasm_bytes).unwrap();
+        let mut reference_handles = ReferenceHandles::default();
+        let function_type = FunctionType::new(&[Ref(FuncRef), Ref(ExternRef)], &[]);
+        reference_handles.install_for(&mut module, &function_type);
+        for (name, ref_type) in [("funcref_handle", FuncRef), ("externref_handle", ExternRef)] {
+            let to_handle = reference_handles.to_handle(&Ref(ref_type)).unwrap();
+            let handle_of = module.add_function(
+                FunctionType::new(&[Ref(ref_type)], &[I32]),
+                vec![],
+                vec![Local(LocalOp::Get, 0_u32.into()), to_handle, End],
+            );
+            module.function_mut(handle_of).export.push(name.to_string());
+        }
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let wasm_module = wasmtime::Module::new(&engine, module.to_bytes().unwrap()).unwrap();
+        let instance = Instance::new(&mut store, &wasm_module, &[]).unwrap();
+        let funcref_handle = instance
+            .get_typed_func::<Option<Func>, i32>(&mut store, "funcref_handle")
+            .unwrap();
+        let externref_handle = instance
+            .get_typed_func::<Option<Rooted<HostExternRef>>, i32>(&mut store, "externref_handle")
+            .unwrap();
+
+        let function = instance.get_func(&mut store, "funcref_handle").unwrap();
+        assert_eq!(funcref_handle.call(&mut store, None).unwrap(), NULL_HANDLE);
+        assert_eq!(funcref_handle.call(&mut store, Some(function)).unwrap(), 1);
+        assert_eq!(funcref_handle.call(&mut store, None).unwrap(), NULL_HANDLE);
+        assert_eq!(funcref_handle.call(&mut store, Some(function)).unwrap(), 2);
+        assert_eq!(
+            externref_handle.call(&mut store, None).unwrap(),
+            NULL_HANDLE
+        );
+    }
+
+    #[test]
+    fn test_released_handles_are_reused() {
+        let wasm_bytes = wat::parse_str("(module)").unwrap();
+        let (mut module, _, _) = Module::from_bytes(&wasm_bytes).unwrap();
+        let mut reference_handles = ReferenceHandles::default();
+        let function_type = FunctionType::new(&[Ref(FuncRef)], &[]);
+        reference_handles.install_for(&mut module, &function_type);
+        let handle_table = reference_handles.table(&FuncRef);
+        let to_handle = reference_handles.to_handle(&Ref(FuncRef)).unwrap();
+
+        // Converts the reference 100 times, releasing every handle again, and
+        // yields the size of the handle table
+        let [reference, iteration, saved] = [0_u32, 1, 2].map(Into::into);
+        let mut body = vec![Loop(FunctionType::empty())];
+        body.extend(handle_table.save(saved));
+        body.extend([Local(LocalOp::Get, reference), to_handle, Drop]);
+        body.extend(handle_table.restore(saved));
+        body.extend([
+            Local(LocalOp::Get, iteration),
+            Const(Val::I32(1)),
+            Binary(BinaryOp::I32Add),
+            Local(LocalOp::Tee, iteration),
+            Const(Val::I32(100)),
+            Binary(BinaryOp::I32LtU),
+            BrIf(0_usize.into()),
+            End,
+            TableSize(0_u32.into()),
+            End,
+        ]);
+        let convert = module.add_function(
+            FunctionType::new(&[Ref(FuncRef)], &[I32]),
+            vec![I32, I32],
+            body,
+        );
+        module
+            .function_mut(convert)
+            .export
+            .push("convert".to_string());
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let wasm_module = wasmtime::Module::new(&engine, module.to_bytes().unwrap()).unwrap();
+        let instance = Instance::new(&mut store, &wasm_module, &[]).unwrap();
+        let convert = instance
+            .get_typed_func::<Option<Func>, i32>(&mut store, "convert")
+            .unwrap();
+        let function = instance.get_func(&mut store, "convert").unwrap();
+        // The null handle and the one handle in use at a time
+        assert_eq!(convert.call(&mut store, Some(function)).unwrap(), 2);
+    }
+}
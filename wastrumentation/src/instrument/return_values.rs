@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use crate::parse_nesting::{BodyInner, HighLevelBody, TypedHighLevelInstr};
+use crate::site_predicate::Sites;
+use crate::stack_library::ModuleLinkedStackHooks;
+use wasabi_wasm::{Function, FunctionType, Idx, Module};
+
+use super::function_enter_exit;
+use super::reference_handles::ReferenceHandles;
+
+/// The signature the stack library stores the results of `function_type` with.
+pub fn returned_type(function_type: &FunctionType) -> FunctionType {
+    FunctionType::new(&[], function_type.results())
+}
+
+/// Hands the values a function returns to the analysis, through a stack library
+/// buffer of the signature [`returned_type`]. The analysis may overwrite them.
+///
+/// The values are observed on every path that exits the function: at every
+/// `return`, at every branch to the label of the function body and when
+/// falling through its end.
+pub struct ReturnValues<'a> {
+    pub trap_idx: Idx<Function>,
+    pub sites: Sites<'a>,
+    pub signature_import_links: &'a HashMap<FunctionType, ModuleLinkedStackHooks>,
+    pub reference_handles: &'a ReferenceHandles,
+}
+
+impl ReturnValues<'_> {
+    pub fn transform(
+        &self,
+        high_level_body: &HighLevelBody,
+        function_index: Idx<Function>,
+        module: &mut Module,
+    ) -> HighLevelBody {
+        let HighLevelBody(body) = high_level_body;
+        let end_index = function_enter_exit::end_index(module, function_index);
+
+        let mut transformed_body = function_enter_exit::instrument_exit_branches(
+            body,
+            function_index,
+            module,
+            &mut |typed_instr, module| {
+                self.sites
+                    .selects(typed_instr)
+                    .then(|| self.call_trap(typed_instr, function_index, module))
+            },
+        );
+        // Return by falling through the end of the body
+        if self
+            .sites
+            .selects_at(function_index.to_u32(), end_index, "end")
+        {
+            let anchor = function_enter_exit::anchor(function_index, end_index);
+            transformed_body.extend(self.call_trap(&anchor, function_index, module));
+        }
+        HighLevelBody(transformed_body)
+    }
+
+    /// Moves the results on top of the stack through the analysis, leaving the
+    /// values the analysis decided on in their place.
+    fn call_trap(
+        &self,
+        typed_instr: &TypedHighLevelInstr,
+        function_index: Idx<Function>,
+        module: &mut Module,
+    ) -> BodyInner {
+        function_enter_exit::pass_results(
+            typed_instr,
+            self.trap_idx,
+            function_index,
+            module,
+            self.signature_import_links,
+            self.reference_handles,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasabi_wasm::FunctionType;
+    use wasabi_wasm::ValType::{I32, I64};
+    use wasmtime::{Engine, Linker, Module, Store};
+
+    use crate::instrument::function_enter_exit::tests::{
+        import_stack_hooks, link_stack_library, overwrite_result, Analysis, EXIT_PATHS,
+        EXIT_PATHS_PROGRAM,
+    };
+    use crate::instrument::reference_handles::ReferenceHandles;
+    use crate::parse_nesting::{HighLevelBody, LowLevelBody};
+    use crate::site_predicate::Sites;
+
+    use super::ReturnValues;
+
+    #[test]
+    fn test_overwrite_returned_values() {
+        let wasm_bytes = wat::parse_str(EXIT_PATHS_PROGRAM).unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+
+        let main = 0_u32.into();
+        let signature_import_links = import_stack_hooks(&mut wasm_module, main);
+        let reference_handles = ReferenceHandles::default();
+        let trap_type = FunctionType::new(&[I32, I32, I32, I64, I64], &[]);
+        let return_values = ReturnValues {
+            trap_idx: wasm_module.add_function_import(
+                trap_type,
+                "analysis".into(),
+                "return_values".into(),
+            ),
+            sites: Sites::new(None, "return_values"),
+            signature_import_links: &signature_import_links,
+            reference_handles: &reference_handles,
+        };
+
+        let function = wasm_module.function(main);
+        let code = function.code().unwrap();
+        let high_level_body: HighLevelBody =
+            (&wasm_module, function, code, &main).try_into().unwrap();
+        let transformed = return_values.transform(&high_level_body, main, &mut wasm_module);
+        let LowLevelBody(low_level_body) = LowLevelBody::from(transformed);
+        wasm_module.function_mut(main).code_mut().unwrap().body = low_level_body;
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, Analysis::default());
+        let mut linker = Linker::new(&engine);
+        link_stack_library(&mut linker);
+        linker
+            .func_wrap("analysis", "return_values", overwrite_result)
+            .unwrap();
+        let module = Module::new(&engine, wasm_module.to_bytes().unwrap()).unwrap();
+        let instance = linker.instantiate(&mut store, &module).unwrap();
+        let main = instance
+            .get_typed_func::<i32, i32>(&mut store, "main")
+            .unwrap();
+
+        for (input, output) in EXIT_PATHS {
+            assert_eq!(main.call(&mut store, input).unwrap(), output + 1000);
+            let returned = std::mem::take(&mut store.data_mut().exited);
+            assert_eq!(returned, [output], "Input {input}");
+        }
+    }
+}
@@ -1,16 +1,29 @@
 use crate::parse_nesting::{
     BodyInner, HighLevelBody, HighLevelInstr as Instr, TypedHighLevelInstr,
 };
-use wasabi_wasm::{BinaryOp, Function, Idx, Module, UnaryOp, Val};
+use wasabi_wasm::types::InferredInstructionType;
+use wasabi_wasm::{BinaryOp, Function, Idx, Module, RefType, UnaryOp, Val, ValType};
 
 use super::TransformationStrategy;
+use crate::site_predicate::Sites;
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum Target {
     Drop(Idx<Function>), // Optional: drop-generic / drop-specific
+    DropI32(Idx<Function>),
+    DropF32(Idx<Function>),
+    DropI64(Idx<Function>),
+    DropF64(Idx<Function>),
 
     Return(Idx<Function>),
 
+    Unreachable(Idx<Function>),
+    Nop(Idx<Function>),
+
+    RefNull(Idx<Function>),
+    RefIsNull(Idx<Function>),
+    RefFunc(Idx<Function>),
+
     ConstI32(Idx<Function>),
     ConstF32(Idx<Function>),
     ConstI64(Idx<Function>),
@@ -43,9 +56,14 @@ pub enum Target {
 }
 
 impl TransformationStrategy for Target {
-    fn transform(&self, high_level_body: &HighLevelBody, _: &mut Module) -> HighLevelBody {
+    fn transform(
+        &self,
+        high_level_body: &HighLevelBody,
+        _: &mut Module,
+        sites: &Sites,
+    ) -> HighLevelBody {
         let HighLevelBody(body) = high_level_body;
-        let transformed_body = transform(body, *self);
+        let transformed_body = transform(body, *self, sites);
         HighLevelBody(transformed_body)
     }
 }
@@ -121,6 +139,16 @@ impl WastrumentationSerializable for UnaryOp {
     }
 }
 
+impl WastrumentationSerializable for RefType {
+    // Cfr. `wasm_constructs::WasmType::runtime_enum_value`
+    fn serialize(&self) -> i32 {
+        match self {
+            RefType::FuncRef => 4,
+            RefType::ExternRef => 5,
+        }
+    }
+}
+
 impl WastrumentationSerializable for BinaryOp {
     fn serialize(&self) -> i32 {
         match self {
@@ -246,11 +274,11 @@ macro_rules! transformation_strategy {
     };
 }
 
-fn transform(body: &BodyInner, target: Target) -> BodyInner {
+fn transform(body: &BodyInner, target: Target, sites: &Sites) -> BodyInner {
     let mut result = Vec::new();
 
     for typed_instr @ TypedHighLevelInstr { instr, .. } in body {
-        if typed_instr.is_uninstrumented() {
+        if typed_instr.is_uninstrumented() && sites.selects(typed_instr) {
             if let (Target::Return(trap_idx), Instr::Return) = (target, instr) {
                 // Inject call
                 result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
@@ -271,6 +299,70 @@ fn transform(body: &BodyInner, target: Target) -> BodyInner {
                 continue;
             }
 
+            let typed_drop = match target {
+                Target::DropI32(trap_idx) => Some((trap_idx, ValType::I32)),
+                Target::DropF32(trap_idx) => Some((trap_idx, ValType::F32)),
+                Target::DropI64(trap_idx) => Some((trap_idx, ValType::I64)),
+                Target::DropF64(trap_idx) => Some((trap_idx, ValType::F64)),
+                _ => None,
+            };
+            if let (
+                Some((trap_idx, dropped_type)),
+                Instr::Drop,
+                InferredInstructionType::Reachable(type_),
+            ) = (typed_drop, instr, &typed_instr.type_)
+            {
+                if type_.inputs() == [dropped_type] {
+                    // STACK: [value]
+                    result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    // STACK: [], the trap takes the place of the drop
+                    continue;
+                }
+            }
+
+            if let (Target::Unreachable(trap_idx), Instr::Unreachable)
+            | (Target::Nop(trap_idx), Instr::Nop) = (target, instr)
+            {
+                // Inject call
+                result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                result.extend_from_slice(&[
+                    // Inject original instruction after
+                    typed_instr.place_original(instr.clone()),
+                ]);
+                continue;
+            }
+
+            if let (Target::RefNull(trap_idx), Instr::RefNull(ref_type)) = (target, instr) {
+                // STACK: []
+                result.push(
+                    typed_instr.instrument_with(Instr::Const(Val::I32(ref_type.serialize()))),
+                );
+                // STACK: [ref_type]
+                result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                // STACK: []
+                result.push(typed_instr.place_original(instr.clone()));
+                // STACK: [ref]
+                continue;
+            }
+
+            if let (Target::RefFunc(trap_idx), Instr::RefFunc(index)) = (target, instr) {
+                // STACK: []
+                result.push(typed_instr.instrument_with(Instr::Const(Val::I32(
+                    i32::try_from(index.to_u32()).unwrap(),
+                ))));
+                // STACK: [f_idx]
+                result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                // STACK: []
+                result.push(typed_instr.place_original(instr.clone()));
+                // STACK: [ref]
+                continue;
+            }
+
+            transformation_strategy!(
+                typed_instr, target, instr, result,
+                RefIsNull for Instr::RefIsNull
+            );
+
             transformation_strategy!(
                 typed_instr, target, instr, result,
                 ConstI32 for Instr::Const(Val::I32(_))
@@ -328,25 +420,27 @@ fn transform(body: &BodyInner, target: Target) -> BodyInner {
             (target, Instr::If(type_, then, None)) => {
                 result.push(typed_instr.place_untouched(Instr::If(
                     *type_,
-                    transform(then, target),
+                    transform(then, target, sites),
                     None,
                 )));
             }
             (target, Instr::If(type_, then, Some(else_))) => {
                 result.push(typed_instr.place_untouched(Instr::If(
                     *type_,
-                    transform(then, target),
-                    Some(transform(else_, target)),
+                    transform(then, target, sites),
+                    Some(transform(else_, target, sites)),
                 )))
             }
             (target, Instr::Loop(type_, body)) => {
                 result.push(
-                    typed_instr.place_untouched(Instr::Loop(*type_, transform(body, target))),
+                    typed_instr
+                        .place_untouched(Instr::Loop(*type_, transform(body, target, sites))),
                 );
             }
             (target, Instr::Block(type_, body)) => {
                 result.push(
-                    typed_instr.place_untouched(Instr::Block(*type_, transform(body, target))),
+                    typed_instr
+                        .place_untouched(Instr::Block(*type_, transform(body, target, sites))),
                 );
             }
             (_, instr) => result.push(typed_instr.place_untouched(instr.clone())),
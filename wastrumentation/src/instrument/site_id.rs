@@ -0,0 +1,581 @@
+// Traps identify an instruction by the index of its function and its index in
+// the body of that function. Both are positional: an instruction added early in
+// a function renumbers every later one, so results of two builds of a program
+// cannot be compared. Site ids identify an instruction by what it is and where
+// it sits instead.
+//
+// A function is keyed by its name in the name section, or, without one, by its
+// type and how many functions without a name and of that type precede it, e.g.
+// `unnamed:(i32)->(i64)#2`. Such a key only changes when such a function is
+// added or removed before it. An instruction is located by its path through
+// the blocks enclosing it, every step naming an opcode and how many instructions
+// with that opcode precede it in the same block, e.g. `loop#0/if#1/else/call#2`.
+// Adding an instruction thus only changes the paths of the later instructions
+// with the same opcode in its block, and of what those enclose. The id of a site
+// is the FNV-1a hash of the key and the path, without its sign bit.
+//
+// The manifest mapping every instruction index onto its path and id is kept in a
+// custom section, the ids are answered at runtime by a function the module
+// exports, for analyses to import. An instruction added early in a block still
+// changes the ids of some later ones, so the ids of two builds are matched up
+// by aligning the opcodes of the sites of each function, see `SiteIds::map_onto`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Deserializer};
+use wasabi_wasm::{Function, FunctionType, Idx, Instr, Module, RawCustomSection};
+use wasabi_wasm::{RefType, Val, ValType};
+use wasmparser::{Parser, Payload};
+
+use super::owned_memory::OwnedMemory;
+use super::source_location::json_string;
+use super::Instrumentable;
+use crate::error::InstrumentationError;
+use crate::parse_nesting::HighLevelInstr;
+use crate::site_predicate::opcode;
+
+/// The custom section holding the manifest, a JSON object like
+/// `{"functions":[{"function":1,"key":"name:main","sites":[{"instr":0,"path":"local.get#0","id":"4c3f9a1b0e2d7a65"}]}]}`.
+/// Per function, `sites` holds the path and the id, in hexadecimal, of every
+/// instruction in order of index.
+pub const SITE_IDS_SECTION: &str = "wastrumentation:site_ids";
+
+/// `(function_index: i32, instr_index: i32) -> i64`, the site id
+pub const EXPORT_SITE_ID: &str = "instrumented_site_id";
+
+/// What the exported function answers for instructions without a site id
+pub const UNKNOWN: i64 = -1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Site {
+    /// Through the blocks enclosing the instruction, e.g. `block#0/call#1`
+    pub path: String,
+    pub id: u64,
+}
+
+pub struct SiteIds {
+    /// Per function index, the key of the function and the site of every
+    /// instruction in its body
+    functions: BTreeMap<u32, (String, Vec<Site>)>,
+}
+
+impl SiteIds {
+    /// The sites of the instructions of `targets`, as parsed from the input
+    /// program before the instrumentation rewrites them.
+    pub fn of(module: &Module, targets: &HashSet<Idx<Function>>) -> Self {
+        let mut keys = function_keys(module);
+        let functions = targets
+            .iter()
+            .filter_map(|target| {
+                let body = &module.function(*target).code()?.body;
+                let key = keys.remove(target)?;
+                let sites = paths(body)
+                    .into_iter()
+                    .map(|path| Site {
+                        id: site_id(&key, &path),
+                        path,
+                    })
+                    .collect();
+                Some((target.to_u32(), (key, sites)))
+            })
+            .collect();
+        Self { functions }
+    }
+
+    /// The sites of the functions of `program`, read from its manifest if it
+    /// was instrumented with site ids, see [`SITE_IDS_SECTION`].
+    ///
+    /// # Errors
+    /// Errors upon failing to parse the program or its manifest.
+    pub fn of_program(program: &[u8]) -> Result<Self, InstrumentationError> {
+        // A malformed program is left for the parser to report
+        for payload in Parser::new(0).parse_all(program) {
+            let Ok(payload) = payload else {
+                break;
+            };
+            if let Payload::CustomSection(reader) = payload {
+                if reader.name() == SITE_IDS_SECTION {
+                    return Self::from_manifest(reader.data());
+                }
+            }
+        }
+        let (module, _offsets, _issue) =
+            Module::from_bytes(program).map_err(InstrumentationError::ParseModuleError)?;
+        let functions = module
+            .functions()
+            .filter(|(_index, function)| function.code().is_some())
+            .map(|(index, _function)| index)
+            .collect();
+        Ok(Self::of(&module, &functions))
+    }
+
+    /// The sites in `manifest`, the content of a [`SITE_IDS_SECTION`].
+    ///
+    /// # Errors
+    /// Errors upon a malformed manifest.
+    pub fn from_manifest(manifest: &[u8]) -> Result<Self, InstrumentationError> {
+        #[derive(Deserialize)]
+        struct Manifest {
+            functions: Vec<ManifestFunction>,
+        }
+        #[derive(Deserialize)]
+        struct ManifestFunction {
+            function: u32,
+            key: String,
+            sites: Vec<ManifestSite>,
+        }
+        #[derive(Deserialize)]
+        struct ManifestSite {
+            path: String,
+            #[serde(deserialize_with = "hexadecimal")]
+            id: u64,
+        }
+        fn hexadecimal<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+            let id = String::deserialize(deserializer)?;
+            u64::from_str_radix(&id, 16).map_err(serde::de::Error::custom)
+        }
+
+        let manifest: Manifest = serde_json::from_slice(manifest)
+            .map_err(InstrumentationError::ReadSiteIdsManifestError)?;
+        let functions = manifest
+            .functions
+            .into_iter()
+            .map(
+                |ManifestFunction {
+                     function,
+                     key,
+                     sites,
+                 }| {
+                    let sites = sites
+                        .into_iter()
+                        .map(|ManifestSite { path, id }| Site { path, id })
+                        .collect();
+                    (function, (key, sites))
+                },
+            )
+            .collect();
+        Ok(Self { functions })
+    }
+
+    /// Maps the id of every site of `self`, from an earlier build, onto the id
+    /// of the same site in `new`. The sites of a function, matched up by key,
+    /// are aligned along the longest common subsequence of their opcodes. A
+    /// site that was removed, or whose function was renamed, is not mapped.
+    pub fn map_onto(&self, new: &SiteIds) -> BTreeMap<u64, u64> {
+        let new_functions: HashMap<&str, &[Site]> = new
+            .functions
+            .values()
+            .map(|(key, sites)| (key.as_str(), sites.as_slice()))
+            .collect();
+        self.functions
+            .values()
+            .filter_map(|(key, old)| Some((old, *new_functions.get(key.as_str())?)))
+            .flat_map(|(old, new)| {
+                align(old, new)
+                    .into_iter()
+                    .map(move |(old_index, new_index)| (old[old_index].id, new[new_index].id))
+            })
+            .collect()
+    }
+
+    pub fn get(&self, function_index: u32, instr_index: usize) -> Option<&Site> {
+        self.functions.get(&function_index)?.1.get(instr_index)
+    }
+
+    pub fn install_side_table(&self, module: &mut Module) {
+        let functions: Vec<String> = self
+            .functions
+            .iter()
+            .map(|(function_index, (key, sites))| {
+                let sites: Vec<String> = sites
+                    .iter()
+                    .enumerate()
+                    .map(|(instr_index, Site { path, id })| {
+                        format!(
+                            r#"{{"instr":{instr_index},"path":{},"id":"{id:016x}"}}"#,
+                            json_string(path)
+                        )
+                    })
+                    .collect();
+                format!(
+                    r#"{{"function":{function_index},"key":{},"sites":[{}]}}"#,
+                    json_string(key),
+                    sites.join(",")
+                )
+            })
+            .collect();
+
+        module.custom_sections.push(RawCustomSection {
+            name: SITE_IDS_SECTION.to_string(),
+            content: format!(r#"{{"functions":[{}]}}"#, functions.join(",")).into_bytes(),
+            previous_section: None,
+        });
+    }
+
+    /// Adds and exports [`EXPORT_SITE_ID`], placing its table in
+    /// `owned_memory`.
+    pub fn inject_lookup(&self, module: &mut Module, owned_memory: &mut OwnedMemory) {
+        let records = self
+            .functions
+            .iter()
+            .flat_map(|(function_index, (_key, sites))| {
+                sites.iter().enumerate().map(move |(instr_index, site)| {
+                    let instr_index = u32::try_from(instr_index).unwrap();
+                    let id = i64::try_from(site.id).unwrap();
+                    ((*function_index, instr_index), Val::I64(id))
+                })
+            })
+            .collect();
+        let lookup_idx = owned_memory.add_lookup(module, &records, Val::I64(UNKNOWN));
+        module.export_generated(lookup_idx, EXPORT_SITE_ID);
+    }
+}
+
+/// The key of every function with code in `module`.
+fn function_keys(module: &Module) -> HashMap<Idx<Function>, String> {
+    // Per type, how many functions without a name precede
+    let mut unnamed: HashMap<String, usize> = HashMap::new();
+    module
+        .functions()
+        .filter(|(_index, function)| function.code().is_some())
+        .map(|(index, function)| {
+            let key = match &function.name {
+                Some(name) => format!("name:{name}"),
+                None => {
+                    let signature = signature(&function.type_);
+                    let position = unnamed.entry(signature.clone()).or_default();
+                    let key = format!("unnamed:{signature}#{position}");
+                    *position += 1;
+                    key
+                }
+            };
+            (index, key)
+        })
+        .collect()
+}
+
+/// E.g. `(i32 i32)->(i64)`
+fn signature(function_type: &FunctionType) -> String {
+    let names = |types: &[ValType]| {
+        let names: Vec<&str> = types
+            .iter()
+            .map(|val_type| match val_type {
+                ValType::I32 => "i32",
+                ValType::I64 => "i64",
+                ValType::F32 => "f32",
+                ValType::F64 => "f64",
+                ValType::Ref(RefType::FuncRef) => "funcref",
+                ValType::Ref(RefType::ExternRef) => "externref",
+            })
+            .collect();
+        names.join(" ")
+    };
+    format!(
+        "({})->({})",
+        names(function_type.inputs()),
+        names(function_type.results())
+    )
+}
+
+/// Pairs the indices of the sites of `old` and `new` with the same opcode,
+/// along the longest common subsequence of their opcodes. The sites before
+/// and after the first and last difference are paired up front, as a change
+/// to a function is usually local.
+fn align(old: &[Site], new: &[Site]) -> Vec<(usize, usize)> {
+    let same = |old_index: usize, new_index: usize| {
+        step_opcode(&old[old_index].path) == step_opcode(&new[new_index].path)
+    };
+    let shortest = old.len().min(new.len());
+    let prefix = (0..shortest)
+        .take_while(|index| same(*index, *index))
+        .count();
+    let suffix = (0..shortest - prefix)
+        .take_while(|index| same(old.len() - 1 - index, new.len() - 1 - index))
+        .count();
+    let (old_len, new_len) = (old.len() - prefix - suffix, new.len() - prefix - suffix);
+
+    // The length of the longest common subsequence of what follows in between
+    let mut lengths = vec![vec![0_usize; new_len + 1]; old_len + 1];
+    for i in (0..old_len).rev() {
+        for j in (0..new_len).rev() {
+            lengths[i][j] = if same(prefix + i, prefix + j) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|index| (index, index)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < old_len && j < new_len {
+        if same(prefix + i, prefix + j) {
+            pairs.push((prefix + i, prefix + j));
+            (i, j) = (i + 1, j + 1);
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs.extend((0..suffix).map(|index| (old.len() - suffix + index, new.len() - suffix + index)));
+    pairs
+}
+
+/// The opcode of the last step of `path`, e.g. `call` for `block#0/call#1`
+fn step_opcode(path: &str) -> &str {
+    let step = path.rsplit('/').next().unwrap_or(path);
+    step.split('#').next().unwrap_or(step)
+}
+
+/// The path of every instruction in `body`. An `else` and an `end` are steps
+/// of the block they belong to.
+fn paths(body: &[Instr]) -> Vec<String> {
+    struct Frame {
+        /// The path of the block
+        path: String,
+        /// Where its instructions are, after its `else` they are in `{path}/else`
+        prefix: String,
+        /// Per opcode, how many of its instructions so far have it
+        counts: HashMap<&'static str, usize>,
+    }
+    let join = |prefix: &str, step: &str| {
+        if prefix.is_empty() {
+            step.to_string()
+        } else {
+            format!("{prefix}/{step}")
+        }
+    };
+
+    let mut frames = vec![Frame {
+        path: String::new(),
+        prefix: String::new(),
+        counts: HashMap::new(),
+    }];
+    let mut paths = Vec::with_capacity(body.len());
+    for instr in body {
+        let Some(frame) = frames.last_mut() else {
+            break;
+        };
+        match instr {
+            Instr::Else => {
+                frame.prefix = join(&frame.path, "else");
+                frame.counts.clear();
+                paths.push(frame.prefix.clone());
+            }
+            Instr::End => {
+                paths.push(join(&frame.path, "end"));
+                frames.pop();
+            }
+            instr => {
+                let opcode = low_level_opcode(instr);
+                let count = frame.counts.entry(opcode).or_default();
+                let path = join(&frame.prefix, &format!("{opcode}#{count}"));
+                *count += 1;
+                paths.push(path.clone());
+                if matches!(instr, Instr::Block(_) | Instr::Loop(_) | Instr::If(_)) {
+                    frames.push(Frame {
+                        prefix: path.clone(),
+                        path,
+                        counts: HashMap::new(),
+                    });
+                }
+            }
+        }
+    }
+    paths
+}
+
+fn low_level_opcode(instr: &Instr) -> &'static str {
+    match instr {
+        Instr::Block(_) => "block",
+        Instr::Loop(_) => "loop",
+        Instr::If(_) => "if",
+        Instr::Else => "else",
+        Instr::End => "end",
+        instr => HighLevelInstr::try_from(instr.clone()).map_or("", |instr| opcode(&instr)),
+    }
+}
+
+fn site_id(key: &str, path: &str) -> u64 {
+    fnv1a(format!("{key}/{path}").as_bytes()) & (u64::MAX >> 1)
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use wasabi_wasm::Module;
+    use wasmtime::{Engine, Instance, Store};
+
+    use super::{paths, SiteIds, EXPORT_SITE_ID, UNKNOWN};
+    use crate::instrument::owned_memory::OwnedMemory;
+
+    const PROGRAM: &str = r#"
+    (module
+      (func $main (param i32) (result i32)
+        (block
+          (br_if 0 (local.get 0))
+          (call $main (i32.const 0))
+          drop)
+        (if (result i32) (local.get 0)
+          (then (i32.const 1))
+          (else (call $main (i32.const 1))))))"#;
+
+    // `PROGRAM` with a `nop` added before the block
+    const CHANGED_PROGRAM: &str = r#"
+    (module
+      (func $main (param i32) (result i32)
+        nop
+        (block
+          (br_if 0 (local.get 0))
+          (call $main (i32.const 0))
+          drop)
+        (if (result i32) (local.get 0)
+          (then (i32.const 1))
+          (else (call $main (i32.const 1))))))"#;
+
+    // `PROGRAM` with a `local.get` added before the block
+    const SHIFTED_PROGRAM: &str = r#"
+    (module
+      (func $main (param i32) (result i32)
+        (drop (local.get 0))
+        (block
+          (br_if 0 (local.get 0))
+          (call $main (i32.const 0))
+          drop)
+        (if (result i32) (local.get 0)
+          (then (i32.const 1))
+          (else (call $main (i32.const 1))))))"#;
+
+    fn parse(program: &str) -> (Module, SiteIds) {
+        let (module, _, _) = Module::from_bytes(&wat::parse_str(program).unwrap()).unwrap();
+        let site_ids = SiteIds::of(&module, &HashSet::from([0_u32.into()]));
+        (module, site_ids)
+    }
+
+    #[test]
+    fn test_paths() {
+        let (module, _) = parse(PROGRAM);
+        let body = &module.function(0_u32.into()).code().unwrap().body;
+        assert_eq!(
+            paths(body),
+            [
+                "block#0",
+                "block#0/local.get#0",
+                "block#0/br_if#0",
+                "block#0/i32.const#0",
+                "block#0/call#0",
+                "block#0/drop#0",
+                "block#0/end",
+                "local.get#0",
+                "if#0",
+                "if#0/i32.const#0",
+                "if#0/else",
+                "if#0/else/i32.const#0",
+                "if#0/else/call#0",
+                "if#0/end",
+                "end",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ids_survive_added_instructions() {
+        let (_, site_ids) = parse(PROGRAM);
+        let (_, changed_site_ids) = parse(CHANGED_PROGRAM);
+        let id = |site_ids: &SiteIds, instr_index| site_ids.get(0, instr_index).unwrap().id;
+
+        // The calls, shifted by one instruction
+        assert_eq!(id(&site_ids, 4), id(&changed_site_ids, 5));
+        assert_eq!(id(&site_ids, 12), id(&changed_site_ids, 13));
+        assert_ne!(id(&site_ids, 4), id(&site_ids, 12));
+        assert!(site_ids.get(0, 15).is_none());
+    }
+
+    #[test]
+    fn test_keys_unnamed_functions() {
+        let program = r#"
+        (module
+          (func (result i32) (i32.const 0))
+          (func (param i32))
+          (func (result i32) (i32.const 1)))"#;
+        let (module, _, _) = Module::from_bytes(&wat::parse_str(program).unwrap()).unwrap();
+        let targets = HashSet::from([0_u32.into(), 1_u32.into(), 2_u32.into()]);
+        let site_ids = SiteIds::of(&module, &targets);
+        let keys: Vec<&str> = site_ids
+            .functions
+            .values()
+            .map(|(key, _sites)| key.as_str())
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "unnamed:()->(i32)#0",
+                "unnamed:(i32)->()#0",
+                "unnamed:()->(i32)#1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_map_ids_onto_changed_program() {
+        let (mut module, site_ids) = parse(PROGRAM);
+        site_ids.install_side_table(&mut module);
+        // Read from the manifest of the earlier build
+        let old = SiteIds::of_program(&module.to_bytes().unwrap()).unwrap();
+        assert_eq!(old.functions, site_ids.functions);
+
+        let changed = SiteIds::of_program(&wat::parse_str(CHANGED_PROGRAM).unwrap()).unwrap();
+        let mapping = old.map_onto(&changed);
+        // Every site keeps its id, the `nop` is new
+        assert_eq!(mapping.len(), 15);
+        assert!(mapping.iter().all(|(old_id, new_id)| old_id == new_id));
+
+        let shifted = SiteIds::of_program(&wat::parse_str(SHIFTED_PROGRAM).unwrap()).unwrap();
+        let mapping = old.map_onto(&shifted);
+        let id = |site_ids: &SiteIds, instr_index| site_ids.get(0, instr_index).unwrap().id;
+        // The `local.get` the `if` tests became the second one in its block
+        assert_eq!(shifted.get(0, 9).unwrap().path, "local.get#1");
+        assert_ne!(id(&old, 7), id(&shifted, 9));
+        assert_eq!(mapping[&id(&old, 7)], id(&shifted, 9));
+        assert_eq!(mapping[&id(&old, 4)], id(&shifted, 6));
+        assert_eq!(mapping.len(), 15);
+    }
+
+    #[test]
+    fn test_lookup_at_runtime() {
+        let (mut module, site_ids) = parse(PROGRAM);
+        let mut owned_memory = OwnedMemory::default();
+        site_ids.inject_lookup(&mut module, &mut owned_memory);
+        site_ids.install_side_table(&mut module);
+        let instrumented = owned_memory.attach(&module.to_bytes().unwrap()).unwrap();
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let wasm_module = wasmtime::Module::new(&engine, instrumented).unwrap();
+        let instance = Instance::new(&mut store, &wasm_module, &[]).unwrap();
+        let lookup = instance
+            .get_typed_func::<(i32, i32), i64>(&mut store, EXPORT_SITE_ID)
+            .unwrap();
+
+        let call_id = i64::try_from(site_ids.get(0, 4).unwrap().id).unwrap();
+        assert_eq!(lookup.call(&mut store, (0, 4)).unwrap(), call_id);
+        assert_eq!(lookup.call(&mut store, (0, 15)).unwrap(), UNKNOWN);
+        assert_eq!(lookup.call(&mut store, (1, 0)).unwrap(), UNKNOWN);
+
+        let manifest = module
+            .custom_sections
+            .iter()
+            .find(|section| section.name == super::SITE_IDS_SECTION)
+            .unwrap();
+        let manifest = String::from_utf8_lossy(&manifest.content);
+        assert!(manifest.starts_with(r#"{"functions":[{"function":0,"key":"name:main","sites":[{"instr":0,"path":"block#0","id":""#));
+    }
+}
@@ -0,0 +1,325 @@
+// Traps identify an instruction by its function index and its index in the body
+// of that function. Source locations map those onto where the instruction was
+// encoded in the input program: the byte offset from the start of the code
+// section, the address DWARF uses for WebAssembly. When the input program
+// carries DWARF line information, the offset is resolved further to a file,
+// line and column.
+//
+// The locations are kept in a custom section for tools that read the
+// instrumented module, and are answered at runtime by functions the module
+// exports, for analyses to import. These search a table in the memory the
+// instrumentation owns, see `super::owned_memory`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use addr2line::gimli::{self, EndianSlice, LittleEndian};
+use wasabi_wasm::{Function, Idx, Module, RawCustomSection, Val};
+
+use super::code_layout::CodeLayout;
+use super::owned_memory::OwnedMemory;
+use super::Instrumentable;
+use crate::error::InstrumentationError;
+
+/// The custom section holding the source locations, a JSON object like
+/// `{"files":["src/lib.rs"],"functions":[{"function":1,"offsets":[34,36,37],"lines":[[0,12,5],[0,12,9],null]}]}`.
+/// Per function, `offsets` holds the offset of every instruction in order of
+/// index. `lines` holds, if any instruction of the function could be resolved,
+/// the index into `files`, the line and the column of every instruction.
+pub const SOURCE_LOCATIONS_SECTION: &str = "wastrumentation:source_locations";
+
+/// `(function_index: i32, instr_index: i32) -> i32`, the code section offset
+pub const EXPORT_SOURCE_OFFSET: &str = "instrumented_source_offset";
+/// `(function_index: i32, instr_index: i32) -> i32`, the line in the source
+pub const EXPORT_SOURCE_LINE: &str = "instrumented_source_line";
+/// `(function_index: i32, instr_index: i32) -> i32`, the column in the source
+pub const EXPORT_SOURCE_COLUMN: &str = "instrumented_source_column";
+
+/// What the exported functions answer for instructions without the location
+pub const UNKNOWN: i32 = -1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// From the start of the code section, i.e. the byte after its size
+    pub offset: u32,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+pub struct SourceLocations {
+    /// Per function index, the location of every instruction in its body
+    functions: BTreeMap<u32, Vec<SourceLocation>>,
+}
+
+impl SourceLocations {
+    /// Reads the locations of the instructions of `targets` from the input
+    /// program as it was encoded, before the instrumentation rewrites it.
+    pub fn of(
+        input_program: &[u8],
+        targets: &HashSet<Idx<Function>>,
+    ) -> Result<Self, InstrumentationError> {
+        let layout = CodeLayout::of(input_program)?;
+        let offsets: BTreeMap<u32, &[u32]> = targets
+            .iter()
+            .filter_map(|target| {
+                let function_index = target.to_u32();
+                let function_layout = layout.function(function_index)?;
+                Some((function_index, function_layout.instructions.as_slice()))
+            })
+            .collect();
+
+        let lines = Lines::of(&layout.debug_sections)?;
+        let functions = offsets
+            .into_iter()
+            .map(|(function_index, offsets)| {
+                let locations = offsets
+                    .iter()
+                    .map(|offset| lines.locate(*offset))
+                    .collect::<Result<_, _>>()?;
+                Ok((function_index, locations))
+            })
+            .collect::<Result<_, InstrumentationError>>()?;
+        Ok(Self { functions })
+    }
+
+    pub fn get(&self, function_index: u32, instr_index: usize) -> Option<&SourceLocation> {
+        self.functions.get(&function_index)?.get(instr_index)
+    }
+
+    pub fn install_side_table(&self, module: &mut Module) {
+        let mut files: Vec<String> = Vec::new();
+        let mut file_index = |file: &str| match files.iter().position(|known| known == file) {
+            Some(index) => index,
+            None => {
+                files.push(file.to_string());
+                files.len() - 1
+            }
+        };
+
+        let functions: Vec<String> = self
+            .functions
+            .iter()
+            .map(|(function_index, locations)| {
+                let offsets: Vec<String> = locations
+                    .iter()
+                    .map(|location| location.offset.to_string())
+                    .collect();
+                let lines: Vec<String> = locations
+                    .iter()
+                    .map(|location| match location {
+                        SourceLocation {
+                            file: Some(file),
+                            line: Some(line),
+                            column,
+                            ..
+                        } => format!("[{},{line},{}]", file_index(file), column.unwrap_or(0)),
+                        _ => "null".to_string(),
+                    })
+                    .collect();
+                let lines = if lines.iter().all(|line| line == "null") {
+                    String::new()
+                } else {
+                    format!(r#","lines":[{}]"#, lines.join(","))
+                };
+                format!(
+                    r#"{{"function":{function_index},"offsets":[{}]{lines}}}"#,
+                    offsets.join(",")
+                )
+            })
+            .collect();
+        let files: Vec<String> = files.iter().map(|file| json_string(file)).collect();
+
+        module.custom_sections.push(RawCustomSection {
+            name: SOURCE_LOCATIONS_SECTION.to_string(),
+            content: format!(
+                r#"{{"files":[{}],"functions":[{}]}}"#,
+                files.join(","),
+                functions.join(",")
+            )
+            .into_bytes(),
+            previous_section: None,
+        });
+    }
+
+    /// Adds and exports [`EXPORT_SOURCE_OFFSET`], [`EXPORT_SOURCE_LINE`] and
+    /// [`EXPORT_SOURCE_COLUMN`], placing their tables in `owned_memory`.
+    pub fn inject_lookups(&self, module: &mut Module, owned_memory: &mut OwnedMemory) {
+        type Field = fn(&SourceLocation) -> Option<u32>;
+        let lookups: [(&str, Field); 3] = [
+            (EXPORT_SOURCE_OFFSET, |location| Some(location.offset)),
+            (EXPORT_SOURCE_LINE, |location| location.line),
+            (EXPORT_SOURCE_COLUMN, |location| location.column),
+        ];
+        for (name, field) in lookups {
+            let records = self
+                .functions
+                .iter()
+                .flat_map(|(function_index, locations)| {
+                    locations
+                        .iter()
+                        .enumerate()
+                        .filter_map(move |(instr_index, location)| {
+                            let value = i32::try_from(field(location)?).ok()?;
+                            let instr_index = u32::try_from(instr_index).unwrap();
+                            Some(((*function_index, instr_index), Val::I32(value)))
+                        })
+                })
+                .collect();
+            let lookup_idx = owned_memory.add_lookup(module, &records, Val::I32(UNKNOWN));
+            module.export_generated(lookup_idx, name);
+        }
+    }
+}
+
+/// The line information of the input program, if it has any.
+struct Lines<'a> {
+    context: Option<addr2line::Context<EndianSlice<'a, LittleEndian>>>,
+}
+
+impl<'a> Lines<'a> {
+    fn of(debug_sections: &HashMap<&str, &'a [u8]>) -> Result<Self, InstrumentationError> {
+        if !debug_sections.contains_key(".debug_line") {
+            return Ok(Self { context: None });
+        }
+        let dwarf = gimli::Dwarf::load(|section: gimli::SectionId| {
+            let data = debug_sections.get(section.name()).copied().unwrap_or(&[]);
+            Ok::<_, gimli::Error>(EndianSlice::new(data, LittleEndian))
+        })
+        .map_err(InstrumentationError::DwarfError)?;
+        let context =
+            addr2line::Context::from_dwarf(dwarf).map_err(InstrumentationError::DwarfError)?;
+        Ok(Self {
+            context: Some(context),
+        })
+    }
+
+    fn locate(&self, offset: u32) -> Result<SourceLocation, InstrumentationError> {
+        let location = match &self.context {
+            Some(context) => context
+                .find_location(offset.into())
+                .map_err(InstrumentationError::DwarfError)?,
+            None => None,
+        };
+        let (file, line, column) = match location {
+            Some(location) => (
+                location.file.map(ToString::to_string),
+                location.line,
+                location.column,
+            ),
+            None => (None, None, None),
+        };
+        Ok(SourceLocation {
+            offset,
+            file,
+            line,
+            column,
+        })
+    }
+}
+
+pub(super) fn json_string(string: &str) -> String {
+    let mut json = String::from('"');
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str(r#"\""#),
+            '\\' => json.push_str(r"\\"),
+            c if c.is_control() => json.push_str(&format!(r"\u{:04x}", u32::from(c))),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use wasmtime::{Engine, Instance, Store};
+
+    use super::{json_string, SourceLocations, EXPORT_SOURCE_LINE, EXPORT_SOURCE_OFFSET, UNKNOWN};
+    use crate::instrument::owned_memory::OwnedMemory;
+
+    // The code section holds the number of bodies, the size of the body, the
+    // number of local declarations and then the instructions, at offsets 3, 5,
+    // 7 and 8. The second function follows at offsets 11 and 12.
+    const PROGRAM: &str = r#"
+    (module
+      (import "host" "f" (func))
+      (func (result i32)
+        i32.const 1
+        i32.const 2
+        i32.add)
+      (func
+        nop))"#;
+
+    #[test]
+    fn test_code_section_offsets() {
+        let wasm_bytes = wat::parse_str(PROGRAM).unwrap();
+        let targets = HashSet::from([1_u32.into(), 2_u32.into()]);
+        let source_locations = SourceLocations::of(&wasm_bytes, &targets).unwrap();
+
+        let offsets = |function_index| {
+            (0..)
+                .map_while(|instr_index| source_locations.get(function_index, instr_index))
+                .map(|location| location.offset)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(offsets(1), [3, 5, 7, 8]);
+        assert_eq!(offsets(2), [11, 12]);
+        assert_eq!(source_locations.get(1, 0).unwrap().line, None);
+
+        let only_second = SourceLocations::of(&wasm_bytes, &HashSet::from([2_u32.into()])).unwrap();
+        assert!(only_second.get(1, 0).is_none());
+    }
+
+    #[test]
+    fn test_lookup_at_runtime() {
+        let wasm_bytes = wat::parse_str(PROGRAM).unwrap();
+        let targets = HashSet::from([1_u32.into(), 2_u32.into()]);
+        let source_locations = SourceLocations::of(&wasm_bytes, &targets).unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+        let mut owned_memory = OwnedMemory::default();
+        source_locations.inject_lookups(&mut wasm_module, &mut owned_memory);
+        source_locations.install_side_table(&mut wasm_module);
+        let instrumented = owned_memory
+            .attach(&wasm_module.to_bytes().unwrap())
+            .unwrap();
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let module = wasmtime::Module::new(&engine, instrumented).unwrap();
+        let host_f = wasmtime::Func::wrap(&mut store, || {});
+        let instance = Instance::new(&mut store, &module, &[host_f.into()]).unwrap();
+        let mut lookup = |name, function_index, instr_index| {
+            instance
+                .get_typed_func::<(i32, i32), i32>(&mut store, name)
+                .unwrap()
+                .call(&mut store, (function_index, instr_index))
+                .unwrap()
+        };
+
+        assert_eq!(lookup(EXPORT_SOURCE_OFFSET, 1, 2), 7);
+        assert_eq!(lookup(EXPORT_SOURCE_OFFSET, 2, 1), 12);
+        assert_eq!(lookup(EXPORT_SOURCE_OFFSET, 1, 4), UNKNOWN);
+        assert_eq!(lookup(EXPORT_SOURCE_OFFSET, 0, 0), UNKNOWN);
+        assert_eq!(lookup(EXPORT_SOURCE_OFFSET, 9, 0), UNKNOWN);
+        assert_eq!(lookup(EXPORT_SOURCE_LINE, 1, 0), UNKNOWN);
+
+        let side_table = wasm_module
+            .custom_sections
+            .iter()
+            .find(|section| section.name == super::SOURCE_LOCATIONS_SECTION)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&side_table.content),
+            r#"{"files":[],"functions":[{"function":1,"offsets":[3,5,7,8]},{"function":2,"offsets":[11,12]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string(r#"C:\src\"a".rs"#), r#""C:\\src\\\"a\".rs""#);
+        assert_eq!(json_string("a\nb"), r#""a\u000ab""#);
+    }
+}
@@ -0,0 +1,93 @@
+// Moves values between the operand stack and a buffer of the stack library, so
+// the analysis can inspect and replace them as `RuntimeValues`. References
+// travel through the buffer as handles, see [`super::reference_handles`].
+
+use crate::parse_nesting::{BodyInner, TypedHighLevelInstr};
+use wasabi_wasm::Instr::{Call, Local};
+use wasabi_wasm::{Function, Idx, Instr, LocalOp, Module, RefType, ValType};
+
+use super::reference_handles::ReferenceHandles;
+
+pub fn fresh_locals(
+    module: &mut Module,
+    function_index: Idx<Function>,
+    types: &[ValType],
+) -> Vec<Idx<wasabi_wasm::Local>> {
+    types
+        .iter()
+        .map(|type_| module.function_mut(function_index).add_fresh_local(*type_))
+        .collect()
+}
+
+/// Pops as many values as there are `locals` off the stack into them.
+pub fn spill(locals: &[Idx<wasabi_wasm::Local>]) -> Vec<Instr> {
+    locals
+        .iter()
+        .rev()
+        .map(|local| Local(LocalOp::Set, *local))
+        .collect()
+}
+
+/// Pushes the values in `locals` the way the stack library takes them.
+pub fn push_for_stack(
+    locals: &[Idx<wasabi_wasm::Local>],
+    types: &[ValType],
+    reference_handles: &ReferenceHandles,
+) -> Vec<Instr> {
+    locals
+        .iter()
+        .zip(types)
+        .flat_map(|(local, type_)| {
+            std::iter::once(Local(LocalOp::Get, *local)).chain(reference_handles.to_handle(type_))
+        })
+        .collect()
+}
+
+/// Pushes the values `loads` read from the buffer in `buffer`.
+pub fn load_from_stack(
+    buffer: Idx<wasabi_wasm::Local>,
+    loads: &[Idx<Function>],
+    types: &[ValType],
+    reference_handles: &ReferenceHandles,
+) -> Vec<Instr> {
+    loads
+        .iter()
+        .zip(types)
+        .flat_map(|(load, type_)| {
+            [Local(LocalOp::Get, buffer), Call(*load)]
+                .into_iter()
+                .chain(reference_handles.from_handle(type_))
+        })
+        .collect()
+}
+
+/// Saves the next free handle for every reference type among `types`, returning
+/// the instructions that save and those that release the handles allocated since.
+pub fn save_handles(
+    module: &mut Module,
+    function_index: Idx<Function>,
+    types: &[ValType],
+    reference_handles: &ReferenceHandles,
+) -> (Vec<Instr>, Vec<Instr>) {
+    let (mut save, mut restore) = (vec![], vec![]);
+    for ref_type in [RefType::FuncRef, RefType::ExternRef] {
+        if types.contains(&ValType::Ref(ref_type)) {
+            let handle_table = reference_handles.table(&ref_type);
+            let saved_handle_local = module
+                .function_mut(function_index)
+                .add_fresh_local(ValType::I32);
+            save.extend(handle_table.save(saved_handle_local));
+            restore.extend(handle_table.restore(saved_handle_local));
+        }
+    }
+    (save, restore)
+}
+
+pub fn instrument_with(typed_instr: &TypedHighLevelInstr, instrs: Vec<Instr>) -> BodyInner {
+    instrs
+        .into_iter()
+        .map(|instr| {
+            typed_instr.instrument_with(instr.try_into().expect("Straight-line instruction"))
+        })
+        .collect()
+}
@@ -0,0 +1,542 @@
+use super::memory::dispatch_on_index;
+use super::reference_handles::{HandleTable, ReferenceHandles};
+use super::stack_values;
+use super::{Instrumentable, TransformationStrategy};
+use crate::parse_nesting::{
+    BodyInner, HighLevelBody, HighLevelInstr as Instr, TypedHighLevelInstr,
+};
+use crate::site_predicate::Sites;
+
+use wasabi_wasm::types::InferredInstructionType;
+use wasabi_wasm::{
+    Code, Element, Function, FunctionType, Idx, Local, LocalOp, Module, Table, Val, ValType,
+};
+
+/// The instructions that read or write a reference pass it to the analysis as
+/// a handle, see `super::reference_handles`.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Target<'a> {
+    TableGet(Idx<Function>, &'a ReferenceHandles),
+    TableSet(Idx<Function>, &'a ReferenceHandles),
+    TableSize(Idx<Function>),
+    TableGrow(Idx<Function>, &'a ReferenceHandles),
+    TableFill(Idx<Function>, &'a ReferenceHandles),
+    TableCopy(Idx<Function>),
+    TableInit(Idx<Function>),
+    ElemDrop(Idx<Function>),
+}
+
+impl TransformationStrategy for Target<'_> {
+    fn transform(
+        &self,
+        high_level_body: &HighLevelBody,
+        module: &mut Module,
+        sites: &Sites,
+    ) -> HighLevelBody {
+        let HighLevelBody(body) = high_level_body;
+        let transformed_body = transform(body, *self, sites, module);
+        HighLevelBody(transformed_body)
+    }
+}
+
+/// Reference values cannot cross the analysis interface, instructions that
+/// consume one stash it in a fresh local of the instrumented function while
+/// its handle is passed instead.
+fn fresh_local(
+    module: &mut Module,
+    typed_instr: &TypedHighLevelInstr,
+    type_: ValType,
+) -> Idx<Local> {
+    module
+        .function_mut(typed_instr.funct_index.into())
+        .add_fresh_local(type_)
+}
+
+/// The handle table for references of `value_type`, along with the instructions
+/// that convert a reference into a handle and back.
+fn handles_of<'a>(
+    reference_handles: &'a ReferenceHandles,
+    value_type: &ValType,
+) -> (&'a HandleTable, wasabi_wasm::Instr, wasabi_wasm::Instr) {
+    let ValType::Ref(ref_type) = value_type else {
+        unreachable!("Tables hold references")
+    };
+    (
+        reference_handles.table(ref_type),
+        reference_handles.to_handle(value_type).unwrap(),
+        reference_handles.from_handle(value_type).unwrap(),
+    )
+}
+
+fn table_index(table_idx: &Idx<Table>) -> Instr {
+    Instr::Const(Val::I64(table_idx.to_u32().into()))
+}
+
+fn element_index(element_idx: &Idx<Element>) -> Instr {
+    Instr::Const(Val::I64(element_idx.to_u32().into()))
+}
+
+fn transform(
+    body: &BodyInner,
+    target: Target<'_>,
+    sites: &Sites,
+    module: &mut Module,
+) -> BodyInner {
+    let mut result = Vec::new();
+
+    for typed_instr @ TypedHighLevelInstr { instr, type_, .. } in body {
+        if typed_instr.is_uninstrumented() && sites.selects(typed_instr) {
+            match (target, instr, type_) {
+                (
+                    Target::TableGet(trap_idx, reference_handles),
+                    Instr::TableGet(table_idx),
+                    InferredInstructionType::Reachable(instr_type),
+                ) => {
+                    let (handle_table, to_handle, from_handle) =
+                        handles_of(reference_handles, &instr_type.results()[0]);
+                    let saved_handle_local = fresh_local(module, typed_instr, ValType::I32);
+                    let slot_local = fresh_local(module, typed_instr, ValType::I32);
+                    let handle_local = fresh_local(module, typed_instr, ValType::I32);
+                    result.extend(stack_values::instrument_with(
+                        typed_instr,
+                        handle_table.save(saved_handle_local).to_vec(),
+                    ));
+                    result.extend_from_slice(&[
+                        // [slot:I32]                   // Stash slot, perform operation
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Tee, slot_local)),
+                        typed_instr.place_original(instr.clone()),
+                        // [value:REF]
+                    ]);
+                    result.extend(stack_values::instrument_with(typed_instr, vec![to_handle]));
+                    result.extend_from_slice(&[
+                        // [value:I32]
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Set, handle_local)),
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Get, slot_local)),
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Get, handle_local)),
+                        // [slot:I32,value:I32]         // Push table index on stack
+                        typed_instr.instrument_with(table_index(table_idx)),
+                        // [slot:I32,value:I32,index:I64]
+                    ]);
+                    result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    // [value:I32]
+                    let mut release = vec![from_handle];
+                    release.extend(handle_table.restore(saved_handle_local));
+                    result.extend(stack_values::instrument_with(typed_instr, release));
+                    // [value:REF]
+                    continue;
+                }
+                (
+                    Target::TableSet(trap_idx, reference_handles),
+                    Instr::TableSet(table_idx),
+                    InferredInstructionType::Reachable(instr_type),
+                ) => {
+                    let (handle_table, to_handle, _) =
+                        handles_of(reference_handles, &instr_type.inputs()[1]);
+                    let saved_handle_local = fresh_local(module, typed_instr, ValType::I32);
+                    let value_local = fresh_local(module, typed_instr, instr_type.inputs()[1]);
+                    result.extend(stack_values::instrument_with(
+                        typed_instr,
+                        handle_table.save(saved_handle_local).to_vec(),
+                    ));
+                    result.extend_from_slice(&[
+                        // [slot:I32,value:REF]         // Stash value
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Tee, value_local)),
+                        // [slot:I32,value:REF]
+                    ]);
+                    result.extend(stack_values::instrument_with(typed_instr, vec![to_handle]));
+                    result.extend_from_slice(&[
+                        // [slot:I32,value:I32]         // Push table index on stack
+                        typed_instr.instrument_with(table_index(table_idx)),
+                        // [slot:I32,value:I32,index:I64]
+                    ]);
+                    result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    result.extend_from_slice(&[
+                        // [slot:I32]                   // Restore value
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Get, value_local)),
+                        // [slot:I32,value:REF]         // Perform operation
+                        typed_instr.place_original(instr.clone()),
+                        // []
+                    ]);
+                    result.extend(stack_values::instrument_with(
+                        typed_instr,
+                        handle_table.restore(saved_handle_local).to_vec(),
+                    ));
+                    continue;
+                }
+                (Target::TableSize(trap_idx), Instr::TableSize(table_idx), _) => {
+                    result.extend_from_slice(&[
+                        // []                           // Perform operation
+                        typed_instr.place_original(instr.clone()),
+                        // [size:I32]                   // Push table index on stack
+                        typed_instr.instrument_with(table_index(table_idx)),
+                        // [size:I32,index:I64]
+                    ]);
+                    result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    // [size:I32]
+                    continue;
+                }
+                (
+                    Target::TableGrow(trap_idx, reference_handles),
+                    Instr::TableGrow(table_idx),
+                    InferredInstructionType::Reachable(instr_type),
+                ) => {
+                    let (handle_table, to_handle, _) =
+                        handles_of(reference_handles, &instr_type.inputs()[0]);
+                    let saved_handle_local = fresh_local(module, typed_instr, ValType::I32);
+                    let init_local = fresh_local(module, typed_instr, instr_type.inputs()[0]);
+                    let delta_local = fresh_local(module, typed_instr, ValType::I32);
+                    result.extend(stack_values::instrument_with(
+                        typed_instr,
+                        handle_table.save(saved_handle_local).to_vec(),
+                    ));
+                    result.extend_from_slice(&[
+                        // [init:REF,delta:I32]         // Stash operands
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Set, delta_local)),
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Tee, init_local)),
+                        // [init:REF]
+                    ]);
+                    result.extend(stack_values::instrument_with(typed_instr, vec![to_handle]));
+                    result.extend_from_slice(&[
+                        // [init:I32]
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Get, delta_local)),
+                        // [init:I32,delta:I32]         // Push table index on stack
+                        typed_instr.instrument_with(table_index(table_idx)),
+                        // [init:I32,delta:I32,index:I64]
+                    ]);
+                    result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    result.extend_from_slice(&[
+                        // [delta:I32]                  // Restore operands
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Set, delta_local)),
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Get, init_local)),
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Get, delta_local)),
+                        // [init:REF,delta:I32]         // Perform operation
+                        typed_instr.place_original(instr.clone()),
+                        // [previous-size-or-neg-one:I32]
+                    ]);
+                    result.extend(stack_values::instrument_with(
+                        typed_instr,
+                        handle_table.restore(saved_handle_local).to_vec(),
+                    ));
+                    continue;
+                }
+                (
+                    Target::TableFill(trap_idx, reference_handles),
+                    Instr::TableFill(table_idx),
+                    InferredInstructionType::Reachable(instr_type),
+                ) => {
+                    let (handle_table, to_handle, _) =
+                        handles_of(reference_handles, &instr_type.inputs()[1]);
+                    let saved_handle_local = fresh_local(module, typed_instr, ValType::I32);
+                    let slot_local = fresh_local(module, typed_instr, ValType::I32);
+                    let value_local = fresh_local(module, typed_instr, instr_type.inputs()[1]);
+                    let length_local = fresh_local(module, typed_instr, ValType::I32);
+                    result.extend(stack_values::instrument_with(
+                        typed_instr,
+                        handle_table.save(saved_handle_local).to_vec(),
+                    ));
+                    result.extend_from_slice(&[
+                        // [slot:I32,value:REF,length:I32]  // Stash operands
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Set, length_local)),
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Set, value_local)),
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Tee, slot_local)),
+                        // [slot:I32]
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Get, value_local)),
+                        // [slot:I32,value:REF]
+                    ]);
+                    result.extend(stack_values::instrument_with(typed_instr, vec![to_handle]));
+                    result.extend_from_slice(&[
+                        // [slot:I32,value:I32]
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Get, length_local)),
+                        // [slot:I32,value:I32,length:I32]  // Push table index on stack
+                        typed_instr.instrument_with(table_index(table_idx)),
+                        // [slot:I32,value:I32,length:I32,index:I64]
+                    ]);
+                    result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    result.extend_from_slice(&[
+                        // []                           // Restore operands
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Get, slot_local)),
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Get, value_local)),
+                        typed_instr.instrument_with(Instr::Local(LocalOp::Get, length_local)),
+                        // [slot:I32,value:REF,length:I32]  // Perform operation
+                        typed_instr.place_original(instr.clone()),
+                        // []
+                    ]);
+                    result.extend(stack_values::instrument_with(
+                        typed_instr,
+                        handle_table.restore(saved_handle_local).to_vec(),
+                    ));
+                    continue;
+                }
+                (
+                    Target::TableCopy(trap_idx),
+                    Instr::TableCopy(destination_table_idx, source_table_idx),
+                    _,
+                ) => {
+                    result.extend_from_slice(&[
+                        // [destination:I32,source:I32,length:I32]  // Push destination table index on stack
+                        typed_instr.instrument_with(table_index(destination_table_idx)),
+                        // [destination:I32,source:I32,length:I32,destination-index:I64]
+                        typed_instr.instrument_with(table_index(source_table_idx)),
+                        // [destination:I32,source:I32,length:I32,destination-index:I64,source-index:I64]
+                    ]);
+                    result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    // []
+                    continue;
+                }
+                (Target::TableInit(trap_idx), Instr::TableInit(table_idx, element_idx), _) => {
+                    result.extend_from_slice(&[
+                        // [destination:I32,offset:I32,length:I32]  // Push element segment index on stack
+                        typed_instr.instrument_with(element_index(element_idx)),
+                        // [destination:I32,offset:I32,length:I32,element-index:I64]
+                        typed_instr.instrument_with(table_index(table_idx)),
+                        // [destination:I32,offset:I32,length:I32,element-index:I64,index:I64]
+                    ]);
+                    result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    // []
+                    continue;
+                }
+                (Target::ElemDrop(trap_idx), Instr::ElemDrop(element_idx), _) => {
+                    result.extend_from_slice(&[
+                        // []                                       // Push element segment index on stack
+                        typed_instr.instrument_with(element_index(element_idx)),
+                        // [element-index:I64]
+                    ]);
+                    result.extend_from_slice(&typed_instr.to_trap_call(&trap_idx));
+                    // []
+                    continue;
+                }
+                _ => (),
+            }
+        }
+
+        match (target, instr) {
+            // DEFAULT TRAVERSAL
+            (target, Instr::If(type_, then, None)) => {
+                let then = transform(then, target, sites, module);
+                result.push(typed_instr.place_untouched(Instr::If(*type_, then, None)));
+            }
+            (target, Instr::If(type_, then, Some(else_))) => {
+                let then = transform(then, target, sites, module);
+                let else_ = transform(else_, target, sites, module);
+                result.push(typed_instr.place_untouched(Instr::If(*type_, then, Some(else_))))
+            }
+            (target, Instr::Loop(type_, body)) => {
+                let body = transform(body, target, sites, module);
+                result.push(typed_instr.place_untouched(Instr::Loop(*type_, body)));
+            }
+            (target, Instr::Block(type_, body)) => {
+                let body = transform(body, target, sites, module);
+                result.push(typed_instr.place_untouched(Instr::Block(*type_, body)));
+            }
+            (_, instr) => result.push(typed_instr.place_untouched(instr.clone())),
+        }
+    }
+    result
+}
+
+/// The `(table, element segment)` pairs that are referred to by a `table.init`
+/// in `module`. Since the instruction takes both as immediate, the base shim can
+/// only dispatch on pairs that occur in the module.
+/// Must be gathered before instrumentation replaces the instructions.
+pub fn referred_table_inits(module: &Module) -> Vec<(Idx<Table>, Idx<Element>)> {
+    use wasabi_wasm::Instr::TableInit;
+
+    let mut table_inits: Vec<(Idx<Table>, Idx<Element>)> = module
+        .functions()
+        .filter_map(|(_index, function)| function.code())
+        .flat_map(|Code { body, .. }| body.iter())
+        .filter_map(|instr| match instr {
+            TableInit(table_idx, element_idx) => Some((*table_idx, *element_idx)),
+            _ => None,
+        })
+        .collect();
+    table_inits.sort_by_key(|(table_idx, element_idx)| (table_idx.to_u32(), element_idx.to_u32()));
+    table_inits.dedup();
+    table_inits
+}
+
+/// The element segments that are referred to by a `table.init` or `elem.drop`
+/// in `module`. Must be gathered before instrumentation replaces the instructions.
+pub fn referred_element_segments(module: &Module) -> Vec<Idx<Element>> {
+    use wasabi_wasm::Instr::{ElemDrop, TableInit};
+
+    let mut element_segments: Vec<Idx<Element>> = module
+        .functions()
+        .filter_map(|(_index, function)| function.code())
+        .flat_map(|Code { body, .. }| body.iter())
+        .filter_map(|instr| match instr {
+            TableInit(_table_idx, element_idx) | ElemDrop(element_idx) => Some(*element_idx),
+            _ => None,
+        })
+        .collect();
+    element_segments.sort_by_key(|element_idx| element_idx.to_u32());
+    element_segments.dedup();
+    element_segments
+}
+
+pub fn inject_table_copy(module: &mut Module) {
+    use wasabi_wasm::Instr::{End, Local, TableCopy};
+
+    let function_type = FunctionType::new(
+        &[
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+        ],
+        &[],
+    );
+    let tables = 0..module.tables.len() as u32;
+    let mut body = dispatch_on_index(tables.clone(), 3, &[], |destination_table| {
+        dispatch_on_index(tables.clone(), 4, &[], |source_table| {
+            vec![
+                // []
+                Local(LocalOp::Get, 0_u32.into()),
+                // [destination:i32]
+                Local(LocalOp::Get, 1_u32.into()),
+                // [destination:i32, source:i32]
+                Local(LocalOp::Get, 2_u32.into()),
+                // [destination:i32, source:i32, length:i32]
+                TableCopy(destination_table.into(), source_table.into()),
+                // []
+            ]
+        })
+    });
+    body.push(End);
+
+    let table_function_idx: Idx<Function> = module.add_function(function_type, vec![], body);
+    module.export_generated(table_function_idx, "instrumented_base_table_copy");
+}
+
+pub fn inject_table_init(module: &mut Module, table_inits: &[(Idx<Table>, Idx<Element>)]) {
+    use wasabi_wasm::Instr::{End, Local, TableInit};
+
+    let function_type = FunctionType::new(
+        &[
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+        ],
+        &[],
+    );
+    let mut tables: Vec<u32> = table_inits
+        .iter()
+        .map(|(table_idx, _element_idx)| table_idx.to_u32())
+        .collect();
+    tables.dedup();
+    let mut body = dispatch_on_index(tables, 4, &[], |table| {
+        let element_segments = table_inits
+            .iter()
+            .filter(|(table_idx, _element_idx)| table_idx.to_u32() == table)
+            .map(|(_table_idx, element_idx)| element_idx.to_u32());
+        dispatch_on_index(element_segments, 3, &[], |element| {
+            vec![
+                // []
+                Local(LocalOp::Get, 0_u32.into()),
+                // [destination:i32]
+                Local(LocalOp::Get, 1_u32.into()),
+                // [destination:i32, offset:i32]
+                Local(LocalOp::Get, 2_u32.into()),
+                // [destination:i32, offset:i32, length:i32]
+                TableInit(table.into(), element.into()),
+                // []
+            ]
+        })
+    });
+    body.push(End);
+
+    let table_function_idx: Idx<Function> = module.add_function(function_type, vec![], body);
+    module.export_generated(table_function_idx, "instrumented_base_table_init");
+}
+
+pub fn inject_elem_drop(module: &mut Module, element_segments: &[Idx<Element>]) {
+    use wasabi_wasm::Instr::{ElemDrop, End};
+
+    let function_type = FunctionType::new(&[ValType::I32], &[]);
+    let element_segments = element_segments
+        .iter()
+        .map(|element_idx| element_idx.to_u32());
+    let mut body = dispatch_on_index(element_segments, 0, &[], |element_idx| {
+        vec![
+            // []
+            ElemDrop(element_idx.into()),
+            // []
+        ]
+    });
+    body.push(End);
+
+    let table_function_idx: Idx<Function> = module.add_function(function_type, vec![], body);
+    module.export_generated(table_function_idx, "instrumented_base_elem_drop");
+}
+
+#[cfg(test)]
+mod tests {
+    use wasabi_wasm::RefType;
+    use wasmtime::{Engine, Instance, Module, Store};
+
+    use crate::parse_nesting::{HighLevelBody, LowLevelBody};
+    use crate::site_predicate::Sites;
+
+    use super::super::reference_handles::{ReferenceHandles, NULL_HANDLE};
+    use super::super::TransformationStrategy;
+    use super::Target;
+
+    const TABLE_GET_PROGRAM: &str = r#"
+    (module
+      (table $functions 2 funcref)
+      (elem (table $functions) (i32.const 0) func $main)
+      (global $observed (mut i32) (i32.const -1))
+
+      (func $main (param $slot i32) (result i32)
+        (ref.is_null (table.get $functions (local.get $slot))))
+
+      (func $trap (param $slot i32) (param $value i32) (param i64 i64 i64) (result i32)
+        (global.set $observed (local.get $value))
+        (i32.const 0))
+
+      (func (export "observed") (result i32) (global.get $observed))
+      (export "main" (func $main)))"#;
+
+    #[test]
+    fn test_table_get_passes_value_as_handle() {
+        let wasm_bytes = wat::parse_str(TABLE_GET_PROGRAM).unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+
+        let (main, trap) = (0_u32, 1_u32);
+        let mut reference_handles = ReferenceHandles::default();
+        reference_handles.install(&mut wasm_module, RefType::FuncRef);
+        let main_idx = main.into();
+        let function = wasm_module.function(main_idx);
+        let code = function.code().unwrap();
+        let high_level_body: HighLevelBody = (&wasm_module, function, code, &main_idx)
+            .try_into()
+            .unwrap();
+        // The analysis replaces every reference it observes with `ref.null`
+        let target = Target::TableGet(trap.into(), &reference_handles);
+        let transformed = target.transform(&high_level_body, &mut wasm_module, &Sites::default());
+        let LowLevelBody(low_level_body) = LowLevelBody::from(transformed);
+        wasm_module.function_mut(main_idx).code_mut().unwrap().body = low_level_body;
+
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let module = Module::new(&engine, wasm_module.to_bytes().unwrap()).unwrap();
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let main = instance
+            .get_typed_func::<i32, i32>(&mut store, "main")
+            .unwrap();
+        let observed = instance
+            .get_typed_func::<(), i32>(&mut store, "observed")
+            .unwrap();
+
+        // The handle of the function is released after every `table.get`
+        for _ in 0..2 {
+            assert_eq!(main.call(&mut store, 0).unwrap(), 1);
+            assert_eq!(observed.call(&mut store, ()).unwrap(), NULL_HANDLE + 1);
+        }
+        assert_eq!(main.call(&mut store, 1).unwrap(), 1);
+        assert_eq!(observed.call(&mut store, ()).unwrap(), NULL_HANDLE);
+    }
+}
@@ -1,14 +1,20 @@
 pub mod analysis;
 pub mod compiler;
+mod custom_sections;
 pub mod error;
 mod instrument;
 pub mod parse_nesting;
+pub mod site_predicate;
 mod stack_library;
+pub mod target_selector;
 pub mod wasm_constructs;
 
 use std::fmt::Debug;
 use std::marker::PhantomData;
 
+pub use crate::instrument::edge_coverage;
+pub use crate::instrument::site_id;
+pub use crate::instrument::source_location;
 use crate::instrument::Instrumented;
 use analysis::ProcessedAnalysis;
 use compiler::{Compiles, DefaultCompilerOptions, LibGeneratable, SourceCodeBound, WasmModule};
@@ -17,12 +23,15 @@ use instrument::function_application::INSTRUMENTATION_INSTRUMENTED_MODULE;
 use instrument::function_application::INSTRUMENTATION_STACK_MODULE;
 pub use stack_library::ModuleLinkedStackHooks;
 use wasm_merge::options::BulkMemory;
+use wasm_merge::options::DebugInfo;
 use wasm_merge::options::Multimemory;
 use wasm_merge::options::NoValidate;
 use wasm_merge::options::RenameExportConflicts;
 use wasm_merge::{InputModule, MergeOptions};
 
-use crate::error::Error;
+use crate::error::{Error, InstrumentationError};
+use crate::site_predicate::SitePredicate;
+use crate::target_selector::TargetSelector;
 
 #[derive(Clone)]
 pub struct Wastrumenter<
@@ -45,6 +54,19 @@ pub struct Wastrumenter<
 #[derive(Debug, Clone, Default)]
 pub struct Configuration {
     pub target_indices: Option<Vec<u32>>,
+    /// Narrows the target functions down by name, see [`TargetSelector`]
+    pub target_selectors: Option<Vec<TargetSelector>>,
+    /// Narrows the instrumentation of the target functions down to sites
+    pub site_predicate: Option<SitePredicate>,
+    /// Maps every instruction of the target functions onto its location in
+    /// the input program, see [`source_location`]
+    pub source_locations: bool,
+    /// Identifies every instruction of the target functions by its content
+    /// and place rather than its index, see [`site_id`]
+    pub site_ids: bool,
+    /// Keeps the DWARF of the input program, rewritten to match the
+    /// instrumented code, rather than stripping it
+    pub debug_info: bool,
     pub primary_selection: Option<PrimaryTarget>,
 }
 
@@ -94,8 +116,9 @@ where
         configuration: &Configuration,
     ) -> Result<WasmModule, Error<AnalysisLanguage, InstrumentationLanguage>> {
         let Configuration {
-            target_indices,
+            debug_info,
             primary_selection,
+            ..
         } = configuration;
         // 1. Compile analysis
         let ProcessedAnalysis {
@@ -115,7 +138,7 @@ where
         } = instrument::instrument::<InstrumentationLanguage>(
             input_program,
             &analysis_interface,
-            target_indices,
+            configuration,
         )
         .map_err(Error::InstrumentationError)?;
         // 3. Compile the instrumentation lib
@@ -132,19 +155,27 @@ where
         };
 
         // 4. Merge them all together
-        let instrumented_input = Self::merge(
+        let merged = Self::merge(
             primary_selection,
+            *debug_info,
             &instrumented_input,
             &analysis_wasm,
             compiled_instrumentation_lib.as_deref(),
         )?;
 
-        // 5. Yield expected result
-        Ok(instrumented_input)
+        // 5. Yield expected result, with the side tables of the instrumentation
+        //    and described as the input program was
+        let merged = custom_sections::carry_over(&instrumented_input, &merged);
+        Ok(custom_sections::carry_over(input_program, &merged))
     }
 
+    /// Merges the modules into one. With debug info enabled, binaryen keeps
+    /// the names of every module, and the DWARF of the first module it merges,
+    /// updated for where its code ends up. That is the instrumented input,
+    /// unless the analysis or the instrumentation library is the primary.
     fn merge(
         primary_selection: &Option<PrimaryTarget>,
+        debug_info: bool,
         instrumented_input: &[u8],
         compiled_analysis: &[u8],
         compiled_instrumentation_lib: Option<&[u8]>,
@@ -194,6 +225,11 @@ where
             input_modules,
             no_validation: NoValidate::Enable,
             rename_export_conflicts: RenameExportConflicts::Enable,
+            debug_info: if debug_info {
+                DebugInfo::Enable
+            } else {
+                DebugInfo::Disable
+            },
             multimemory: Multimemory::Enable,
             bulk_memory: BulkMemory::Enable,
             ..Default::default()
@@ -201,3 +237,17 @@ where
         merge_options.merge().map_err(Error::MergeError)
     }
 }
+
+/// Instruments `input_program` for edge coverage, see [`edge_coverage`]. The
+/// resulting module exports the coverage map and a function to reset it. With
+/// `debug_info`, it keeps the DWARF of the input program, see
+/// [`Configuration::debug_info`].
+///
+/// # Errors
+/// Errors upon failing to instrument.
+pub fn instrument_edge_coverage(
+    input_program: &[u8],
+    debug_info: bool,
+) -> Result<WasmModule, InstrumentationError> {
+    edge_coverage::instrument(input_program, debug_info)
+}
@@ -0,0 +1,256 @@
+use std::collections::{BTreeSet, HashSet};
+
+use wasabi_wasm::Label;
+
+use super::{BodyInner, HighLevelBody, HighLevelInstr as Instr, TypedHighLevelInstr};
+
+/// A maximal run of instructions that is only entered at its first instruction
+/// and only left after its last one. It covers the instruction indices
+/// `start..end` of the low-level body, which includes `else` and `end`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize,
+    /// The positions in [`ControlFlowGraph::blocks`] control may continue at
+    pub successors: BTreeSet<usize>,
+}
+
+/// The basic blocks of a function body, ordered by their first instruction.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl ControlFlowGraph {
+    /// Builds the graph of a body as it was parsed, before any instrumentation
+    /// was placed in it: the instruction indices must match the low-level body.
+    ///
+    /// A block starts at the first instruction of the function, at every
+    /// instruction a branch may continue at and after every instruction that
+    /// may branch or call. A call falls through to the block after it once
+    /// the callee returns.
+    pub fn build(body: &HighLevelBody) -> Self {
+        let HighLevelBody(body) = body;
+        let mut builder = Builder::default();
+        // The label of the function body returns, it has no block to continue at
+        builder.visit(body, &mut vec![None]);
+        builder.finish(closing_index(body, 0) + 1)
+    }
+
+    /// The position of the block that starts at `instr_index`, if any.
+    pub fn block_starting_at(&self, instr_index: usize) -> Option<usize> {
+        self.blocks
+            .binary_search_by_key(&instr_index, |block| block.start)
+            .ok()
+    }
+}
+
+/// The index of the `else` or `end` instruction that closes `body`, given the
+/// index of its first instruction.
+pub fn closing_index(body: &BodyInner, first_index: usize) -> usize {
+    body.last().map_or(first_index, |last| last_index(last) + 1)
+}
+
+/// The index of the last low-level instruction that `typed_instr` spans.
+fn last_index(typed_instr: &TypedHighLevelInstr) -> usize {
+    let index = typed_instr.instr_index;
+    match &typed_instr.instr {
+        Instr::Block(_, body) | Instr::Loop(_, body) | Instr::If(_, body, None) => {
+            closing_index(body, index + 1)
+        }
+        Instr::If(_, then, Some(else_)) => closing_index(else_, closing_index(then, index + 1) + 1),
+        _ => index,
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    leaders: BTreeSet<usize>,
+    jumps: Vec<(usize, usize)>,
+    /// Instructions control never falls through
+    terminators: HashSet<usize>,
+}
+
+impl Builder {
+    /// `labels` holds, innermost last, the index a branch to each enclosing
+    /// label continues at.
+    fn visit(&mut self, body: &BodyInner, labels: &mut Vec<Option<usize>>) {
+        for typed_instr in body {
+            let index = typed_instr.instr_index;
+            match &typed_instr.instr {
+                Instr::Block(_, body) => {
+                    labels.push(Some(closing_index(body, index + 1) + 1));
+                    self.visit(body, labels);
+                    labels.pop();
+                }
+                Instr::Loop(_, body) => {
+                    labels.push(Some(index + 1));
+                    self.visit(body, labels);
+                    labels.pop();
+                }
+                Instr::If(_, then, else_) => {
+                    let then_end = closing_index(then, index + 1);
+                    let end = last_index(typed_instr);
+                    self.terminators.insert(index);
+                    self.jumps.push((index, index + 1));
+                    labels.push(Some(end + 1));
+                    self.visit(then, labels);
+                    if let Some(else_) = else_ {
+                        self.jumps.push((index, then_end + 1));
+                        // The `else` ends the then-arm, which continues after the `if`
+                        self.terminators.insert(then_end);
+                        self.jumps.push((then_end, end + 1));
+                        self.visit(else_, labels);
+                    } else {
+                        self.jumps.push((index, end + 1));
+                    }
+                    labels.pop();
+                }
+                Instr::Br(label) => {
+                    self.branch(index, label, labels);
+                    self.terminators.insert(index);
+                }
+                Instr::BrIf(label) => {
+                    self.branch(index, label, labels);
+                    self.leaders.insert(index + 1);
+                }
+                Instr::Call(_) | Instr::CallIndirect(_, _) => {
+                    self.leaders.insert(index + 1);
+                }
+                Instr::BrTable { table, default } => {
+                    for label in table.iter().chain([default]) {
+                        self.branch(index, label, labels);
+                    }
+                    self.terminators.insert(index);
+                }
+                Instr::Return | Instr::Unreachable => {
+                    self.terminators.insert(index);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn branch(&mut self, from: usize, label: &Label, labels: &[Option<usize>]) {
+        let depth = usize::try_from(label.to_u32()).unwrap();
+        if let Some(to) = labels[labels.len() - 1 - depth] {
+            self.jumps.push((from, to));
+        }
+    }
+
+    fn finish(self, length: usize) -> ControlFlowGraph {
+        let Builder {
+            mut leaders,
+            jumps,
+            terminators,
+        } = self;
+        leaders.insert(0);
+        leaders.extend(jumps.iter().map(|(_, to)| *to));
+        leaders.extend(terminators.iter().map(|from| from + 1));
+        leaders.retain(|leader| *leader < length);
+
+        let starts: Vec<usize> = leaders.into_iter().collect();
+        let block_of = |index: usize| starts.partition_point(|start| *start <= index) - 1;
+        let mut blocks: Vec<BasicBlock> = starts
+            .iter()
+            .zip(starts.iter().skip(1).chain([&length]))
+            .map(|(start, end)| BasicBlock {
+                start: *start,
+                end: *end,
+                successors: BTreeSet::new(),
+            })
+            .collect();
+        for (from, to) in jumps {
+            blocks[block_of(from)].successors.insert(block_of(to));
+        }
+        for (position, block) in blocks.iter_mut().enumerate() {
+            if block.end < length && !terminators.contains(&(block.end - 1)) {
+                block.successors.insert(position + 1);
+            }
+        }
+        ControlFlowGraph { blocks }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::parse_nesting::HighLevelBody;
+
+    use super::{BasicBlock, ControlFlowGraph};
+
+    fn graph_of_first_function(wat: &str) -> ControlFlowGraph {
+        let wasm_bytes = wat::parse_str(wat).unwrap();
+        let (module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+        let index = 0_usize.into();
+        let function = module.function(index);
+        let code = function.code().unwrap();
+        let body = HighLevelBody::try_from((&module, function, code, &index)).unwrap();
+        ControlFlowGraph::build(&body)
+    }
+
+    fn block(start: usize, end: usize, successors: &[usize]) -> BasicBlock {
+        BasicBlock {
+            start,
+            end,
+            successors: successors.iter().copied().collect::<BTreeSet<_>>(),
+        }
+    }
+
+    #[test]
+    fn test_blocks_of_loop_and_if() {
+        let graph = graph_of_first_function(
+            r#"
+            (module
+              (func (param $n i32) (result i32)
+                local.get $n     ;; 0
+                if (result i32)  ;; 1
+                  i32.const 1    ;; 2
+                else             ;; 3
+                  loop $again    ;; 4
+                    local.get $n ;; 5
+                    br_if $again ;; 6
+                  end            ;; 7
+                  i32.const 2    ;; 8
+                end              ;; 9
+                return))         ;; 10, 11
+            "#,
+        );
+        assert_eq!(
+            graph.blocks,
+            vec![
+                block(0, 2, &[1, 2]),
+                block(2, 4, &[5]),
+                block(4, 5, &[3]),
+                block(5, 7, &[3, 4]),
+                block(7, 10, &[5]),
+                block(10, 11, &[]),
+                // Dead, after the return
+                block(11, 12, &[]),
+            ]
+        );
+        assert_eq!(graph.block_starting_at(5), Some(3));
+        assert_eq!(graph.block_starting_at(6), None);
+    }
+
+    #[test]
+    fn test_blocks_split_after_calls() {
+        let graph = graph_of_first_function(
+            r#"
+            (module
+              (type $nullary (func))
+              (table 1 funcref)
+              (func $main
+                call $main               ;; 0
+                i32.const 0              ;; 1
+                call_indirect (type $nullary) ;; 2
+                nop))                    ;; 3, 4
+            "#,
+        );
+        assert_eq!(
+            graph.blocks,
+            vec![block(0, 1, &[1]), block(1, 3, &[2]), block(3, 5, &[])]
+        );
+    }
+}
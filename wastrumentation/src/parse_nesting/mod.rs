@@ -1,3 +1,4 @@
+mod control_flow;
 mod high_level_body;
 mod typed_high_level_body;
 mod typed_high_level_body_error;
@@ -9,6 +10,7 @@ mod tests;
 // pub use high_level_body::Body as HighLevelBody;
 // pub use high_level_body::Instr as HighLevelInstr;
 
+pub use control_flow::{closing_index, BasicBlock, ControlFlowGraph};
 pub use high_level_body::LowLevelBody;
 
 pub use typed_high_level_body::Body as HighLevelBody; // TypedIndexedHighLevelBody;
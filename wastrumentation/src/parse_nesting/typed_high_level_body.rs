@@ -372,39 +372,88 @@ impl TryFrom<(&Module, &Function, &Code, &Idx<Function>)> for Body {
 
 impl From<Body> for LowLevelBody {
     fn from(typed_high_level_body: Body) -> Self {
+        let (low_level_body, _origins) = Self::with_origins(typed_high_level_body);
+        low_level_body
+    }
+}
+
+impl LowLevelBody {
+    /// Lowers the body, along with the index of the instruction it was parsed
+    /// from for every lowered instruction. Instrumentation originates from the
+    /// instruction it instruments, the `else` and `end` of an original block
+    /// from what was right after the instructions the block held.
+    pub fn with_origins(typed_high_level_body: Body) -> (Self, Vec<usize>) {
         let Body(body_inner) = typed_high_level_body;
-        let mut low_level_body = from_recurse(body_inner);
+        let mut low_level_body = Vec::with_capacity(body_inner.len());
+        let mut origins = Vec::with_capacity(body_inner.len());
+        let end_origin = from_recurse(body_inner, 0, &mut low_level_body, &mut origins);
         low_level_body.push(wasabi_wasm::Instr::End);
-        Self(low_level_body)
+        origins.push(end_origin);
+        (Self(low_level_body), origins)
     }
 }
 
-fn from_recurse(instructions: BodyInner) -> Vec<wasabi_wasm::Instr> {
-    let mut result = Vec::with_capacity(instructions.len());
-    for TypedHighLevelInstr { instr, .. } in instructions {
+/// Lowers `instructions` into `result`, recording their origins. The first
+/// original instruction is at `start` or later. Returns the index right after
+/// the last original instruction.
+fn from_recurse(
+    instructions: BodyInner,
+    start: usize,
+    result: &mut Vec<wasabi_wasm::Instr>,
+    origins: &mut Vec<usize>,
+) -> usize {
+    let mut next = start;
+    for TypedHighLevelInstr {
+        instr,
+        instr_index,
+        instrumentation_instruction,
+        ..
+    } in instructions
+    {
+        // The origin of an `else` or `end` closing instructions up to `inner_next`
+        let closing = |inner_next: usize| {
+            if instrumentation_instruction {
+                instr_index
+            } else {
+                inner_next
+            }
+        };
         match instr {
             // interesting
             Instr::Block(type_, body_) => {
                 result.push(wasabi_wasm::Instr::Block(type_));
-                result.extend(from_recurse(body_));
+                origins.push(instr_index);
+                let inner_next = from_recurse(body_, instr_index + 1, result, origins);
                 result.push(wasabi_wasm::Instr::End);
+                origins.push(closing(inner_next));
+                next = next.max(inner_next);
             }
             Instr::Loop(type_, body_) => {
                 result.push(wasabi_wasm::Instr::Loop(type_));
-                result.extend(from_recurse(body_));
+                origins.push(instr_index);
+                let inner_next = from_recurse(body_, instr_index + 1, result, origins);
                 result.push(wasabi_wasm::Instr::End);
+                origins.push(closing(inner_next));
+                next = next.max(inner_next);
             }
             Instr::If(type_, then, Some(else_)) => {
                 result.push(wasabi_wasm::Instr::If(type_));
-                result.extend(from_recurse(then));
+                origins.push(instr_index);
+                let then_next = from_recurse(then, instr_index + 1, result, origins);
                 result.push(wasabi_wasm::Instr::Else);
-                result.extend(from_recurse(else_));
+                origins.push(closing(then_next));
+                let else_next = from_recurse(else_, then_next + 1, result, origins);
                 result.push(wasabi_wasm::Instr::End);
+                origins.push(closing(else_next));
+                next = next.max(else_next);
             }
             Instr::If(type_, then, None) => {
                 result.push(wasabi_wasm::Instr::If(type_));
-                result.extend(from_recurse(then));
+                origins.push(instr_index);
+                let inner_next = from_recurse(then, instr_index + 1, result, origins);
                 result.push(wasabi_wasm::Instr::End);
+                origins.push(closing(inner_next));
+                next = next.max(inner_next);
             }
 
             // rest is not interesting, just push in result
@@ -448,13 +497,19 @@ fn from_recurse(instructions: BodyInner) -> Vec<wasabi_wasm::Instr> {
             Instr::Unary(v) => result.push(wasabi_wasm::Instr::Unary(v)),
             Instr::Binary(v) => result.push(wasabi_wasm::Instr::Binary(v)),
         }
+        origins.resize(result.len(), instr_index);
+        if !instrumentation_instruction {
+            // Past the instruction, or past the `end` closing it
+            next = next.max(origins[origins.len() - 1] + 1);
+        }
     }
-    result
+    next
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Body;
+    use super::{Body, Instr};
+    use crate::parse_nesting::LowLevelBody;
     use wasabi_wasm::{
         types::InferredInstructionType, Code, Function, FunctionType, Idx, Module, ValType,
     };
@@ -503,4 +558,32 @@ mod tests {
         let Body(body_odd) = body_odd;
         assert_eq!(expected_type, body_odd.first().unwrap().type_);
     }
+
+    #[test]
+    fn test_lowering_origins() {
+        let if_then_else_program = wat::parse_str(EVEN_ODD_PROGRAM).unwrap();
+        let (module, _, _) = Module::from_bytes(&if_then_else_program).unwrap();
+        let function = module.function(0_usize.into());
+        let code = function.code().unwrap();
+        let body = Body::try_from((&module, function, code, &Idx::from(0_usize))).unwrap();
+
+        let (LowLevelBody(low_level_body), origins) = LowLevelBody::with_origins(body.clone());
+        assert_eq!(low_level_body, code.body);
+        assert_eq!(origins, (0..code.body.len()).collect::<Vec<_>>());
+
+        // Surround the `call $odd` at index 9, in the else branch, with a `nop`
+        let Body(mut body_inner) = body;
+        let Instr::If(_, _, Some(else_)) = &mut body_inner[3].instr else {
+            panic!("expected the if-then-else at index 3");
+        };
+        let call = else_.pop().unwrap();
+        else_.extend([
+            call.instrument_with(Instr::Nop),
+            call.clone(),
+            call.instrument_with(Instr::Nop),
+        ]);
+        let (LowLevelBody(low_level_body), origins) = LowLevelBody::with_origins(Body(body_inner));
+        assert_eq!(low_level_body.len(), code.body.len() + 2);
+        assert_eq!(origins, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 9, 9, 10, 11]);
+    }
 }
@@ -0,0 +1,324 @@
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use wasabi_wasm::{GlobalOp, LocalOp, Val};
+
+use crate::error::SitePredicateError;
+use crate::parse_nesting::{HighLevelInstr as Instr, TypedHighLevelInstr};
+
+/// Narrows instrumentation down to sites, the instructions a hook is placed at.
+/// A site is selected when every field that is present selects it, a field
+/// selects it when any of its entries does.
+///
+/// Hooks that belong to a function rather than to one of its instructions,
+/// i.e. function enter and exit, basic blocks and the generic apply, are
+/// narrowed by `functions` and `hooks` only. They have no instruction, so a
+/// predicate with `instructions` or `opcodes` does not select them. The module
+/// lifecycle is only narrowed by [`crate::Configuration::target_indices`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SitePredicate {
+    pub functions: Option<Vec<RangeInclusive<u32>>>,
+    /// The index of the instruction in the body of its function, as parsed
+    pub instructions: Option<Vec<RangeInclusive<usize>>>,
+    /// Names as in the text format, e.g. `i32.div_s`
+    pub opcodes: Option<Vec<String>>,
+    /// Names of the traps exported by the analysis, e.g. `trap_i32_load`
+    pub hooks: Option<Vec<String>>,
+}
+
+impl SitePredicate {
+    pub fn selects(
+        &self,
+        hook: &str,
+        function_index: u32,
+        instr_index: usize,
+        opcode: &str,
+    ) -> bool {
+        let Self {
+            functions,
+            instructions,
+            opcodes,
+            hooks,
+        } = self;
+        functions
+            .as_ref()
+            .is_none_or(|ranges| ranges.iter().any(|range| range.contains(&function_index)))
+            && instructions
+                .as_ref()
+                .is_none_or(|ranges| ranges.iter().any(|range| range.contains(&instr_index)))
+            && opcodes
+                .as_ref()
+                .is_none_or(|opcodes| opcodes.iter().any(|name| name == opcode))
+            && hooks
+                .as_ref()
+                .is_none_or(|hooks| hooks.iter().any(|name| name == hook))
+    }
+
+    /// Whether the hook of a function, rather than of one of its instructions,
+    /// is selected.
+    pub fn selects_function(&self, hook: &str, function_index: u32) -> bool {
+        self.instructions.is_none()
+            && self.opcodes.is_none()
+            && self
+                .functions
+                .as_ref()
+                .is_none_or(|ranges| ranges.iter().any(|range| range.contains(&function_index)))
+            && self
+                .hooks
+                .as_ref()
+                .is_none_or(|hooks| hooks.iter().any(|name| name == hook))
+    }
+}
+
+/// Parses a filter expression, clauses `key=values` joined by `&&`. The key is
+/// one of `function`, `instr`, `opcode` or `hook`, the values are separated by
+/// `,` and indices are given as `n`, `n..m` or `n..=m`. For example, the loads
+/// in function 12 between instructions 40 and 90:
+/// `function=12 && instr=40..=90 && opcode=i32.load,i64.load`.
+impl FromStr for SitePredicate {
+    type Err = SitePredicateError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let mut predicate = Self::default();
+        for clause in expression.split("&&").map(str::trim) {
+            let malformed = || SitePredicateError::MalformedClause(clause.to_string());
+            let (key, values) = clause.split_once('=').ok_or_else(malformed)?;
+            let key = key.trim();
+            let values: Vec<&str> = values.split(',').map(str::trim).collect();
+            if values.iter().any(|value| value.is_empty()) {
+                return Err(malformed());
+            }
+            match key {
+                "function" => assign(&mut predicate.functions, key, parse_ranges(&values)?)?,
+                "instr" => assign(&mut predicate.instructions, key, parse_ranges(&values)?)?,
+                "opcode" => assign(&mut predicate.opcodes, key, to_strings(&values))?,
+                "hook" => assign(&mut predicate.hooks, key, to_strings(&values))?,
+                _ => return Err(SitePredicateError::UnknownKey(key.to_string())),
+            }
+        }
+        Ok(predicate)
+    }
+}
+
+fn assign<T>(field: &mut Option<T>, key: &str, value: T) -> Result<(), SitePredicateError> {
+    if field.replace(value).is_some() {
+        return Err(SitePredicateError::DuplicateKey(key.to_string()));
+    }
+    Ok(())
+}
+
+fn to_strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(ToString::to_string).collect()
+}
+
+fn parse_ranges<T: TryFrom<u64>>(
+    values: &[&str],
+) -> Result<Vec<RangeInclusive<T>>, SitePredicateError> {
+    values.iter().map(|value| parse_range(value)).collect()
+}
+
+fn parse_range<T: TryFrom<u64>>(value: &str) -> Result<RangeInclusive<T>, SitePredicateError> {
+    let malformed = || SitePredicateError::MalformedRange(value.to_string());
+    let index = |index: &str| index.trim().parse::<u64>().map_err(|_| malformed());
+    let (start, end) = if let Some((start, end)) = value.split_once("..=") {
+        (index(start)?, index(end)?)
+    } else if let Some((start, end)) = value.split_once("..") {
+        (
+            index(start)?,
+            index(end)?.checked_sub(1).ok_or_else(malformed)?,
+        )
+    } else {
+        (index(value)?, index(value)?)
+    };
+    if start > end {
+        return Err(malformed());
+    }
+    let convert = |index: u64| T::try_from(index).map_err(|_| malformed());
+    Ok(convert(start)?..=convert(end)?)
+}
+
+/// The sites of a single hook that are selected, all of them without predicate.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Sites<'a> {
+    predicate: Option<&'a SitePredicate>,
+    hook: &'a str,
+}
+
+impl<'a> Sites<'a> {
+    pub(crate) fn new(predicate: Option<&'a SitePredicate>, hook: &'a str) -> Self {
+        Self { predicate, hook }
+    }
+
+    pub(crate) fn selects(&self, typed_instr: &TypedHighLevelInstr) -> bool {
+        self.selects_at(
+            typed_instr.funct_index,
+            typed_instr.instr_index,
+            opcode(&typed_instr.instr),
+        )
+    }
+
+    /// For hooks that belong to the function at `function_index`, see
+    /// [`SitePredicate::selects_function`].
+    pub(crate) fn selects_function(&self, function_index: u32) -> bool {
+        self.predicate
+            .is_none_or(|predicate| predicate.selects_function(self.hook, function_index))
+    }
+
+    /// For sites without an instruction of their own, e.g. the closing `end`.
+    pub(crate) fn selects_at(&self, function_index: u32, instr_index: usize, opcode: &str) -> bool {
+        self.predicate.is_none_or(|predicate| {
+            predicate.selects(self.hook, function_index, instr_index, opcode)
+        })
+    }
+}
+
+/// The name of `instr` as in the text format.
+pub(crate) fn opcode(instr: &Instr) -> &'static str {
+    match instr {
+        Instr::Unreachable => "unreachable",
+        Instr::Nop => "nop",
+        Instr::Block(..) => "block",
+        Instr::Loop(..) => "loop",
+        Instr::If(..) => "if",
+        Instr::Br(_) => "br",
+        Instr::BrIf(_) => "br_if",
+        Instr::BrTable { .. } => "br_table",
+        Instr::Return => "return",
+        Instr::Call(_) => "call",
+        Instr::CallIndirect(..) => "call_indirect",
+        Instr::RefNull(_) => "ref.null",
+        Instr::RefIsNull => "ref.is_null",
+        Instr::RefFunc(_) => "ref.func",
+        Instr::Drop => "drop",
+        Instr::Select | Instr::TypedSelect(_) => "select",
+        Instr::Local(LocalOp::Get, _) => "local.get",
+        Instr::Local(LocalOp::Set, _) => "local.set",
+        Instr::Local(LocalOp::Tee, _) => "local.tee",
+        Instr::Global(GlobalOp::Get, _) => "global.get",
+        Instr::Global(GlobalOp::Set, _) => "global.set",
+        Instr::TableGet(_) => "table.get",
+        Instr::TableSet(_) => "table.set",
+        Instr::TableSize(_) => "table.size",
+        Instr::TableGrow(_) => "table.grow",
+        Instr::TableFill(_) => "table.fill",
+        Instr::TableCopy(..) => "table.copy",
+        Instr::TableInit(..) => "table.init",
+        Instr::ElemDrop(_) => "elem.drop",
+        Instr::Load(op, _) => op.to_name(),
+        Instr::Store(op, _) => op.to_name(),
+        Instr::MemorySize(_) => "memory.size",
+        Instr::MemoryGrow(_) => "memory.grow",
+        Instr::MemoryFill => "memory.fill",
+        Instr::MemoryCopy => "memory.copy",
+        Instr::MemoryInit(_) => "memory.init",
+        Instr::DataDrop(_) => "data.drop",
+        Instr::Const(Val::I32(_)) => "i32.const",
+        Instr::Const(Val::I64(_)) => "i64.const",
+        Instr::Const(Val::F32(_)) => "f32.const",
+        Instr::Const(Val::F64(_)) => "f64.const",
+        Instr::Unary(op) => op.to_name(),
+        Instr::Binary(op) => op.to_name(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasabi_wasm::Instr;
+
+    use super::{SitePredicate, Sites};
+    use crate::error::SitePredicateError;
+    use crate::instrument::memory::Target;
+    use crate::instrument::TransformationStrategy;
+    use crate::parse_nesting::{HighLevelBody, LowLevelBody};
+
+    #[test]
+    fn test_parse_filter_expression() {
+        let predicate: SitePredicate = "function=12 && instr=40..=90,100..105 && opcode=i32.div_s"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            predicate,
+            SitePredicate {
+                functions: Some(vec![12..=12]),
+                instructions: Some(vec![40..=90, 100..=104]),
+                opcodes: Some(vec!["i32.div_s".into()]),
+                hooks: None,
+            }
+        );
+        assert!(predicate.selects("any", 12, 90, "i32.div_s"));
+        assert!(!predicate.selects("any", 12, 91, "i32.div_s"));
+        assert!(!predicate.selects("any", 12, 105, "i32.div_s"));
+        assert!(!predicate.selects("any", 11, 40, "i32.div_s"));
+        assert!(!predicate.selects("any", 12, 40, "i32.div_u"));
+    }
+
+    #[test]
+    fn test_select_functions() {
+        let predicate: SitePredicate = "function=1..3 && hook=enter".parse().unwrap();
+        assert!(predicate.selects_function("enter", 2));
+        assert!(!predicate.selects_function("enter", 3));
+        assert!(!predicate.selects_function("exit", 2));
+
+        // A function is no instruction
+        let predicate: SitePredicate = "function=2 && opcode=call".parse().unwrap();
+        assert!(!predicate.selects_function("enter", 2));
+    }
+
+    #[test]
+    fn test_reject_malformed_expressions() {
+        use SitePredicateError::*;
+        let error = |expression: &str| expression.parse::<SitePredicate>().unwrap_err();
+        assert_eq!(error("function"), MalformedClause("function".into()));
+        assert_eq!(
+            error("opcode=i32.add,"),
+            MalformedClause("opcode=i32.add,".into())
+        );
+        assert_eq!(error("func=1"), UnknownKey("func".into()));
+        assert_eq!(error("hook=a && hook=b"), DuplicateKey("hook".into()));
+        assert_eq!(error("instr=4..4"), MalformedRange("4..4".into()));
+        assert_eq!(error("function=-1"), MalformedRange("-1".into()));
+        assert_eq!(
+            error("function=4294967296"),
+            MalformedRange("4294967296".into())
+        );
+    }
+
+    #[test]
+    fn test_sites_outside_predicate_are_untouched() {
+        let wasm_bytes = wat::parse_str(
+            r#"
+            (module
+              (memory 1)
+              (func (param i32) (result i32)
+                local.get 0     ;; 0
+                i32.load        ;; 1
+                local.get 0     ;; 2
+                i32.load        ;; 3
+                i32.add))       ;; 4
+            "#,
+        )
+        .unwrap();
+        let (mut wasm_module, _, _) = wasabi_wasm::Module::from_bytes(&wasm_bytes).unwrap();
+        let index = 0_u32.into();
+        let function = wasm_module.function(index);
+        let code = function.code().unwrap();
+        let high_level_body: HighLevelBody =
+            (&wasm_module, function, code, &index).try_into().unwrap();
+
+        let trap_idx = 1_u32.into();
+        let trap_calls = |predicate: &str| {
+            let predicate: SitePredicate = predicate.parse().unwrap();
+            let sites = Sites::new(Some(&predicate), "trap_i32_load");
+            let transformed =
+                Target::I32Load(trap_idx).transform(&high_level_body, &mut wasm_module, &sites);
+            let LowLevelBody(body) = transformed.into();
+            body.iter()
+                .filter(|instr| **instr == Instr::Call(trap_idx))
+                .count()
+        };
+        assert_eq!(trap_calls("hook=trap_i32_load"), 2);
+        assert_eq!(trap_calls("instr=2..=4"), 1);
+        assert_eq!(trap_calls("opcode=i64.load"), 0);
+        assert_eq!(trap_calls("hook=trap_i64_load"), 0);
+    }
+}
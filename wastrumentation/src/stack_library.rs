@@ -21,18 +21,18 @@ pub struct StackLibrary<Language: LibGeneratable> {
 }
 
 impl<Language: LibGeneratable> StackLibrary<Language> {
-    pub fn from_module(module: &mut Module, functions: &HashSet<Idx<Function>>) -> Self {
-        let signature_import_links: HashMap<FunctionType, ModuleLinkedStackHooks> =
-            functions.iter().fold(HashMap::new(), |mut acc, index| {
-                let function_type = module.function(*index).type_;
-                if acc.contains_key(&function_type) {
-                    return acc;
-                }
+    pub fn from_function_types(
+        module: &mut Module,
+        function_types: &HashSet<FunctionType>,
+    ) -> Self {
+        let signature_import_links: HashMap<FunctionType, ModuleLinkedStackHooks> = function_types
+            .iter()
+            .map(|function_type| {
                 let stack_library =
-                    ModuleLinkedStackHooks::from_function_type_module(function_type, module);
-                acc.insert(function_type, stack_library);
-                acc
-            });
+                    ModuleLinkedStackHooks::from_function_type_module(*function_type, module);
+                (*function_type, stack_library)
+            })
+            .collect();
         let signatures: Vec<Signature> = signature_import_links
             .keys()
             .map(WasabiFunctionType)
@@ -60,6 +60,18 @@ impl WasabiFunctionType<'_> {
         }
     }
 
+    /// The type a value of type `v` has in the stack library, see
+    /// [`WasmType::stack_type`].
+    fn stack_type(v: &ValType) -> ValType {
+        match Self::val_type_to_wasm_type(*v).stack_type() {
+            WasmType::I32 => ValType::I32,
+            WasmType::I64 => ValType::I64,
+            WasmType::F32 => ValType::F32,
+            WasmType::F64 => ValType::F64,
+            WasmType::Ref(_) => unreachable!("References cross as an i32 handle"),
+        }
+    }
+
     fn convert_reftype(r: RefType) -> LibGenRefType {
         match r {
             RefType::ExternRef => LibGenRefType::ExternRef,
@@ -113,8 +125,17 @@ impl ModuleLinkedStackHooks {
 impl From<(FunctionType, &mut Module)> for ModuleLinkedStackHooks {
     fn from((function_type, module): (FunctionType, &mut Module)) -> Self {
         let lib_gen_signature: Signature = WasabiFunctionType(&function_type).into();
-        let allocate_values_buffer_type =
-            FunctionType::new(function_type.inputs(), &[ValType::I32]);
+        let stack_inputs: Vec<ValType> = function_type
+            .inputs()
+            .iter()
+            .map(WasabiFunctionType::stack_type)
+            .collect();
+        let stack_results: Vec<ValType> = function_type
+            .results()
+            .iter()
+            .map(WasabiFunctionType::stack_type)
+            .collect();
+        let allocate_values_buffer_type = FunctionType::new(&stack_inputs, &[ValType::I32]);
         let allocate_values_buffer = module.add_function_import(
             allocate_values_buffer_type,
             INSTRUMENTATION_STACK_MODULE.into(),
@@ -142,8 +163,7 @@ impl From<(FunctionType, &mut Module)> for ModuleLinkedStackHooks {
             lib_gen_signature.generate_free_types_buffer_name(),
         );
 
-        let arg_load_n = function_type
-            .inputs()
+        let arg_load_n = stack_inputs
             .iter()
             .enumerate()
             .map(|(index, val_type)| {
@@ -155,8 +175,7 @@ impl From<(FunctionType, &mut Module)> for ModuleLinkedStackHooks {
             })
             .collect();
 
-        let ret_load_n = function_type
-            .results()
+        let ret_load_n = stack_results
             .iter()
             .enumerate()
             .map(|(index, val_type)| {
@@ -168,8 +187,7 @@ impl From<(FunctionType, &mut Module)> for ModuleLinkedStackHooks {
             })
             .collect();
 
-        let arg_store_n = function_type
-            .inputs()
+        let arg_store_n = stack_inputs
             .iter()
             .enumerate()
             .map(|(index, val_type)| {
@@ -181,8 +199,7 @@ impl From<(FunctionType, &mut Module)> for ModuleLinkedStackHooks {
             })
             .collect();
 
-        let ret_store_n = function_type
-            .results()
+        let ret_store_n = stack_results
             .iter()
             .enumerate()
             .map(|(index, val_type)| {
@@ -195,7 +212,7 @@ impl From<(FunctionType, &mut Module)> for ModuleLinkedStackHooks {
             .collect();
 
         let mut store_rets_signature = vec![ValType::I32];
-        store_rets_signature.extend(function_type.results());
+        store_rets_signature.extend(&stack_results);
         let ret_store_all = module.add_function_import(
             FunctionType::new(&store_rets_signature, &[]),
             INSTRUMENTATION_STACK_MODULE.into(),
@@ -0,0 +1,221 @@
+use std::str::FromStr;
+
+use wasabi_wasm::{Function, Idx, Module};
+
+use crate::error::{InstrumentationError, TargetSelectorError};
+
+/// Selects target functions by what they are called, rather than by an index
+/// that changes whenever the input program is rebuilt.
+///
+/// A function is a target when it matches any of the selectors that include,
+/// or any function if there are none, and none of the selectors that exclude.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetSelector {
+    pub exclude: bool,
+    pub pattern: Pattern,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    Index(u32),
+    /// A glob matched against every name the function is exported as
+    Export(String),
+    /// A glob matched against the name of the function in the name section
+    Name(String),
+}
+
+impl Pattern {
+    fn matches(&self, index: Idx<Function>, function: &Function) -> bool {
+        match self {
+            Pattern::Index(target) => index.to_u32() == *target,
+            Pattern::Export(glob) => function.export.iter().any(|name| glob_matches(glob, name)),
+            Pattern::Name(glob) => function
+                .name
+                .as_ref()
+                .is_some_and(|name| glob_matches(glob, name)),
+        }
+    }
+}
+
+/// Parses `index:n`, `export:glob` or `name:glob`, optionally preceded by `!`
+/// to exclude the functions it matches, e.g. `!name:alloc::*`. A bare index
+/// is read as `index:n`. In a glob, `*` matches any run of characters and `?`
+/// any single character.
+impl FromStr for TargetSelector {
+    type Err = TargetSelectorError;
+
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        let (exclude, rest) = match selector.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, selector),
+        };
+        let parse_index = |index: &str| {
+            index
+                .parse()
+                .map_err(|_| TargetSelectorError::MalformedIndex(selector.to_string()))
+        };
+        let pattern = match rest.split_once(':') {
+            Some(("index", index)) => Pattern::Index(parse_index(index)?),
+            Some(("export", glob)) => Pattern::Export(glob.to_string()),
+            Some(("name", glob)) => Pattern::Name(glob.to_string()),
+            None if rest.starts_with(|c: char| c.is_ascii_digit()) => {
+                Pattern::Index(parse_index(rest)?)
+            }
+            _ => return Err(TargetSelectorError::UnknownSelector(selector.to_string())),
+        };
+        Ok(Self { exclude, pattern })
+    }
+}
+
+pub fn selects(selectors: &[TargetSelector], index: Idx<Function>, function: &Function) -> bool {
+    let (excluding, including): (Vec<_>, Vec<_>) =
+        selectors.iter().partition(|selector| selector.exclude);
+    let matches = |selector: &&TargetSelector| selector.pattern.matches(index, function);
+    (including.is_empty() || including.iter().any(matches)) && !excluding.iter().any(matches)
+}
+
+/// Whether the function is targeted by both the indices and the selectors.
+pub(crate) fn is_target(
+    index: Idx<Function>,
+    function: &Function,
+    target_indices: &Option<Vec<u32>>,
+    target_selectors: &Option<Vec<TargetSelector>>,
+) -> bool {
+    target_indices
+        .as_ref()
+        .is_none_or(|ts| ts.contains(&index.to_u32()))
+        && target_selectors
+            .as_ref()
+            .is_none_or(|selectors| selects(selectors, index, function))
+}
+
+/// A function of the input program that will be instrumented.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetFunction {
+    pub index: u32,
+    pub name: Option<String>,
+    pub export: Vec<String>,
+}
+
+/// The functions with code that `instrument` targets in `input_program`.
+///
+/// # Errors
+/// Errors upon failing to parse the input program.
+pub fn target_functions(
+    input_program: &[u8],
+    target_indices: &Option<Vec<u32>>,
+    target_selectors: &Option<Vec<TargetSelector>>,
+) -> Result<Vec<TargetFunction>, InstrumentationError> {
+    let (module, _offsets, _issue) =
+        Module::from_bytes(input_program).map_err(InstrumentationError::ParseModuleError)?;
+    Ok(module
+        .functions()
+        .filter(|(_index, f)| f.code().is_some())
+        .filter(|(index, f)| is_target(*index, f, target_indices, target_selectors))
+        .map(|(index, f)| TargetFunction {
+            index: index.to_u32(),
+            name: f.name.clone(),
+            export: f.export.clone(),
+        })
+        .collect())
+}
+
+fn glob_matches(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut g, mut t) = (0, 0);
+    // The position of the last `*` in the glob, and where in the text it stops
+    let mut backtrack = None;
+    while t < text.len() {
+        match glob.get(g) {
+            Some('*') => {
+                backtrack = Some((g, t));
+                g += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` cover one more character
+                Some((star, covered)) => {
+                    backtrack = Some((star, covered + 1));
+                    g = star + 1;
+                    t = covered + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_matches, target_functions, Pattern, TargetSelector};
+    use crate::error::TargetSelectorError;
+
+    const NAMED_PROGRAM: &str = r#"
+    (module
+      (func $core::fmt::write (export "run_write"))
+      (func $alloc::vec::push (export "run_push"))
+      (func $main (export "main") (export "_start")))"#;
+
+    #[test]
+    fn test_parse_selectors() {
+        let parse = |selector: &str| selector.parse::<TargetSelector>();
+        assert_eq!(
+            parse("!name:alloc::*"),
+            Ok(TargetSelector {
+                exclude: true,
+                pattern: Pattern::Name("alloc::*".into()),
+            })
+        );
+        assert_eq!(
+            parse("export:run_*").unwrap().pattern,
+            Pattern::Export("run_*".into())
+        );
+        assert_eq!(parse("index:3").unwrap().pattern, Pattern::Index(3));
+        assert_eq!(parse("3").unwrap().pattern, Pattern::Index(3));
+        assert_eq!(
+            parse("index:three"),
+            Err(TargetSelectorError::MalformedIndex("index:three".into()))
+        );
+        assert_eq!(
+            parse("run_*"),
+            Err(TargetSelectorError::UnknownSelector("run_*".into()))
+        );
+    }
+
+    #[test]
+    fn test_glob() {
+        assert!(glob_matches("core::fmt::*", "core::fmt::write"));
+        assert!(glob_matches("*::write", "core::fmt::write"));
+        assert!(glob_matches("run_?", "run_a"));
+        assert!(glob_matches("*a*b*", "xxaxxbxx"));
+        assert!(!glob_matches("run_?", "run_ab"));
+        assert!(!glob_matches("core::*", "alloc::core::fmt"));
+    }
+
+    #[test]
+    fn test_select_target_functions() {
+        let wasm_bytes = wat::parse_str(NAMED_PROGRAM).unwrap();
+        let targets = |selectors: &[&str]| {
+            let selectors = selectors.iter().map(|s| s.parse().unwrap()).collect();
+            target_functions(&wasm_bytes, &None, &Some(selectors))
+                .unwrap()
+                .iter()
+                .map(|target| target.index)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(targets(&["export:run_*"]), [0, 1]);
+        assert_eq!(targets(&["export:run_*", "!name:alloc::*"]), [0]);
+        assert_eq!(targets(&["name:core::fmt::*", "export:_start"]), [0, 2]);
+        assert_eq!(targets(&["!export:main"]), [0, 1]);
+        assert_eq!(targets(&["index:1", "name:nothing"]), [1]);
+
+        let indexed = target_functions(&wasm_bytes, &Some(vec![0, 2]), &None).unwrap();
+        assert_eq!(indexed[1].name.as_deref(), Some("main"));
+        assert_eq!(indexed[1].export, ["main", "_start"]);
+    }
+}
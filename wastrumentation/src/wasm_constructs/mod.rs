@@ -38,6 +38,18 @@ impl WasmType {
         }
     }
 
+    /// The type a value of this type has when it crosses into the stack library.
+    /// References cannot be stored in linear memory, so the instrumented module
+    /// passes them as an `i32` handle into a table it keeps instead.
+    pub fn stack_type(&self) -> WasmType {
+        match self {
+            WasmType::I32 | WasmType::Ref(_) => WasmType::I32,
+            WasmType::F32 => WasmType::F32,
+            WasmType::I64 => WasmType::I64,
+            WasmType::F64 => WasmType::F64,
+        }
+    }
+
     pub(crate) fn as_str(&self) -> &'static str {
         match self {
             WasmType::I32 => "i32",